@@ -23,6 +23,37 @@ fn main() {
         (97, 9),
         (106, 9),
         (115, 9),
+        (124, 1),
+        (125, 10),
+        (135, 1),
+        (136, 1),
+        (137, 1),
+        (138, 9),
+        (147, 1),
+        (148, 1),
+        (149, 9),
+        (158, 69),
+        (227, 1),
+        (228, 9),
+        (237, 1),
+        (238, 1),
+        (239, 1),
+        (240, 1),
+        (241, 9),
+        (250, 9),
+        (259, 1),
+        (260, 9),
+        (269, 9),
+        (278, 9),
+        (287, 9),
+        (296, 1),
+        (297, 1),
+        (298, 9),
+        (307, 1),
+        (308, 1),
+        (309, 1),
+        (310, 1),
+        (1, 68),
     ];
 
     let mut code = String::new();
@@ -35,6 +35,361 @@ pub struct BootloaderConfig {
         note = "The frame buffer is now configured through the `BootConfig` struct when creating the bootable disk image"
     )]
     pub frame_buffer: FrameBuffer,
+
+    /// Whether memory used by the bootloader should be reported as reclaimable.
+    ///
+    /// If `true`, the frames that the bootloader allocated for its own purposes (e.g. page
+    /// tables, the boot info, or the kernel stack) are reported as
+    /// [`MemoryRegionKind::BootloaderReclaimable`](crate::info::MemoryRegionKind::BootloaderReclaimable)
+    /// in the memory map. The kernel can reclaim these regions once it no longer needs any of
+    /// the bootloader-created structures. If `false`, these regions are reported as
+    /// [`MemoryRegionKind::Bootloader`](crate::info::MemoryRegionKind::Bootloader), i.e. they
+    /// stay reserved.
+    ///
+    /// The memory of the kernel ELF file and of the ramdisk is always reported as
+    /// `Bootloader`.
+    ///
+    /// Defaults to `false`.
+    pub bootloader_regions_usable: bool,
+
+    /// Whether the bootloader should additionally pass a coarse memory map to the kernel.
+    ///
+    /// If `true`, the [`coarse_memory_regions`](crate::info::BootInfo::coarse_memory_regions)
+    /// field of the boot info contains a memory map with a minimal number of regions: adjacent
+    /// usable regions are merged and everything in between (including holes in the detailed
+    /// memory map) is reported as a single
+    /// [`Reserved`](crate::info::MemoryRegionKind::Reserved) region. The detailed memory
+    /// map is always passed in [`memory_regions`](crate::info::BootInfo::memory_regions).
+    ///
+    /// Defaults to `false`.
+    pub coarse_memory_map: bool,
+
+    /// Whether the page tables of the kernel address space should be mapped read-only.
+    ///
+    /// If `true`, the bootloader maps all page table frames of the kernel address space
+    /// read-only in the [physical memory mapping](Mappings::physical_memory). Huge pages of
+    /// the physical memory mapping that contain page tables are split into 4KiB pages for
+    /// this. The kernel needs to use the [recursive mapping](Mappings::page_table_recursive)
+    /// or its own writable alias to modify its page tables.
+    ///
+    /// The [`page_tables_read_only`](crate::info::BootInfo::page_tables_read_only) field of
+    /// the boot info reports whether this option was applied.
+    ///
+    /// Defaults to `false`.
+    pub read_only_page_tables: bool,
+
+    /// Whether the bootloader should enable process-context identifiers (PCIDs).
+    ///
+    /// If `true` and the CPU supports PCIDs, the bootloader sets `CR4.PCIDE` before jumping
+    /// to the kernel. The kernel address space is loaded with PCID `0`. If the CPU doesn't
+    /// support PCIDs, they stay disabled.
+    ///
+    /// The [`pcid_enabled`](crate::info::BootInfo::pcid_enabled) field of the boot info
+    /// reports whether PCIDs were enabled.
+    ///
+    /// Defaults to `false`.
+    pub pcid: bool,
+
+    /// The size of a memory region below 1 MiB that the bootloader should reserve for a
+    /// real-mode trampoline (in bytes).
+    ///
+    /// Kernels use such a region to start application processors through the INIT-SIPI
+    /// sequence, as the SIPI vector can only point to a page-aligned address in the first MiB.
+    /// The size is rounded up to a multiple of the page size. The bootloader reports the
+    /// physical start address of the reserved region in the
+    /// [`ap_trampoline_region`](crate::info::BootInfo::ap_trampoline_region) field of the boot
+    /// info. The region is marked as bootloader memory in the memory map and identity-mapped in
+    /// the kernel address space, even if the physical memory is not mapped.
+    ///
+    /// Defaults to `None`, i.e. no region is reserved.
+    pub ap_trampoline_size: Option<u64>,
+
+    /// Whether unmapped guard pages should be kept between the loadable segments of the
+    /// kernel.
+    ///
+    /// If `true`, the bootloader doesn't place any other mappings in the virtual address range
+    /// spanned by the kernel segments, so the unmapped pages between two segments act as guard
+    /// pages. A buffer overrun at the end of one segment then results in a page fault instead
+    /// of silently modifying the next segment. Segments that are directly adjacent in virtual
+    /// memory can't be separated by a guard page without relocating them, so they are skipped
+    /// with a log message.
+    ///
+    /// Defaults to `false`.
+    pub kernel_segment_guard_pages: bool,
+
+    /// Whether the kernel segments and the physical memory mapping should be mapped as
+    /// global pages.
+    ///
+    /// If `true`, the bootloader sets the `GLOBAL` flag on these mappings and enables
+    /// `CR4.PGE` before jumping to the kernel, so that the TLB entries of these pages survive
+    /// `CR3` reloads. The kernel must map these pages identically in all address spaces. If
+    /// the CPU doesn't support global pages, `CR4.PGE` stays disabled.
+    ///
+    /// The [`global_pages_enabled`](crate::info::BootInfo::global_pages_enabled) field of the
+    /// boot info reports whether `CR4.PGE` was enabled.
+    ///
+    /// Defaults to `false`.
+    pub global_pages: bool,
+
+    /// A custom layout for the GDT that is created for the kernel.
+    ///
+    /// Only used if a GDT is requested through the [`Mappings::gdt`] option. The bootloader
+    /// loads the GDT with the given descriptors before jumping to the kernel, so that the
+    /// kernel can start with its intended segment selectors. The selectors are reported in
+    /// [`BootInfo::gdt`](crate::BootInfo::gdt). The boot fails if the layout is invalid, see
+    /// [`GdtLayout::validate`].
+    ///
+    /// Defaults to `None`, i.e. the layout of [`GdtLayout::new_default`] is used.
+    pub gdt_layout: Option<GdtLayout>,
+
+    /// How the bootloader passes the boot info to the kernel entry point.
+    ///
+    /// The [`entry_point`](crate::entry_point) macro requires the default
+    /// [`EntryConvention::SysV`]. The other conventions are meant for kernels with a custom
+    /// `_start` function.
+    ///
+    /// Defaults to [`EntryConvention::SysV`].
+    pub entry_convention: EntryConvention,
+
+    /// The highest physical address (exclusive) of memory that the kernel can use right
+    /// after boot.
+    ///
+    /// If set, usable memory at or above this address is reported as
+    /// [`MemoryRegionKind::HighMemory`](crate::info::MemoryRegionKind::HighMemory) instead of
+    /// [`MemoryRegionKind::Usable`](crate::info::MemoryRegionKind::Usable) in the memory map.
+    /// Usable regions that span the limit are split at the limit. This keeps early kernel
+    /// allocators in an addressable range, e.g. for devices that can only access 32-bit
+    /// physical addresses. The kernel can start using the high memory once it is able to.
+    ///
+    /// Defaults to `None`, i.e. all usable memory is reported as usable.
+    pub max_usable_physical_address: Option<u64>,
+
+    /// Whether the bootloader should pass a copy of the boot info on the kernel stack.
+    ///
+    /// If `true`, the bootloader copies the [`BootInfo`](crate::BootInfo) struct to the top of
+    /// the kernel stack right before jumping to the kernel and passes the address of this copy
+    /// instead of the address of the original. The copy is 16-byte aligned and the stack
+    /// pointer is placed below it according to the
+    /// [`entry_convention`](Self::entry_convention). This is useful for kernels that reclaim
+    /// all bootloader memory early. Note that the variable-length parts of the boot info,
+    /// e.g. the memory map, are still referenced from the copy and live in bootloader memory.
+    ///
+    /// Defaults to `false`.
+    pub boot_info_on_stack: bool,
+
+    /// Whether the bootloader should look up frequently used ACPI tables for the kernel.
+    ///
+    /// If `true`, the bootloader searches the FADT, MADT, MCFG, and HPET tables through the
+    /// RSDT or XSDT and reports the ones with a valid checksum in
+    /// [`BootInfo::acpi_tables`](crate::BootInfo::acpi_tables), so the kernel doesn't need to
+    /// walk the system description tables itself.
+    ///
+    /// Defaults to `false`.
+    pub acpi_table_cache: bool,
+
+    /// Whether the framebuffer should be mapped in the kernel address space.
+    ///
+    /// If `false`, the framebuffer is left unmapped, e.g. for kernels that want to map it with
+    /// their own cache policy. The physical address and the layout of the framebuffer are still
+    /// reported in [`BootInfo::framebuffer_phys`](crate::BootInfo::framebuffer_phys), but
+    /// [`BootInfo::framebuffer`](crate::BootInfo::framebuffer) is `None`.
+    ///
+    /// Defaults to `true`.
+    pub map_framebuffer_for_kernel: bool,
+
+    /// Whether the bootloader should search for the ACPI tables.
+    ///
+    /// If `false`, the bootloader skips the RSDP search and all ACPI table walks, which shortens
+    /// the boot time for kernels that don't use ACPI. In that case
+    /// [`BootInfo::rsdp_addr`](crate::BootInfo::rsdp_addr) and all information derived from the
+    /// ACPI tables, e.g. [`BootInfo::hpet`](crate::BootInfo::hpet), are `None`.
+    ///
+    /// Defaults to `true`.
+    pub detect_acpi: bool,
+
+    /// The size of a physically contiguous memory region that the bootloader should allocate
+    /// for early DMA buffers (in bytes).
+    ///
+    /// The size is rounded up to a multiple of the page size. The bootloader maps the region
+    /// writable into the kernel address space and reports its physical and virtual start
+    /// addresses in [`BootInfo::dma_region`](crate::BootInfo::dma_region). The region is
+    /// marked as bootloader memory in the memory map, so the kernel doesn't reuse it.
+    ///
+    /// Defaults to `None`, i.e. no region is allocated.
+    pub dma_region_size: Option<u64>,
+
+    /// The highest physical address (exclusive) that the DMA region may end at.
+    ///
+    /// Useful for devices that can only access 32-bit physical addresses. Only has an effect
+    /// if [`dma_region_size`](Self::dma_region_size) is set.
+    ///
+    /// Defaults to `None`, i.e. the region can be located anywhere in physical memory.
+    pub dma_region_max_addr: Option<u64>,
+
+    /// Whether the bootloader should provide a trampoline page for switching address spaces.
+    ///
+    /// If `true`, the bootloader creates a page with a small stub that loads a new level 4
+    /// page table and jumps to a target address. The page is mapped both at its identity
+    /// address and at a dynamically chosen address in the kernel address space, and both
+    /// addresses are reported in [`BootInfo::trampoline`](crate::BootInfo::trampoline). This
+    /// allows switching between an identity-mapped and a higher-half address space without each
+    /// kernel providing its own identity-mapped stub.
+    ///
+    /// Defaults to `false`.
+    pub address_switch_trampoline: bool,
+
+    /// The maximum number of entries in the memory map that is passed to the kernel.
+    ///
+    /// By default, the bootloader reserves enough entries for all regions of the firmware memory
+    /// map. If this limit is smaller and the memory map doesn't fit, the bootloader merges
+    /// adjacent regions of the same kind until it fits, starting with the smallest ones. Only
+    /// regions that touch each other are merged, so no hole or reserved memory is ever
+    /// reported as usable, but attributes such as the NUMA proximity domain might be lost.
+    /// Regions that still don't fit are dropped. The limit is at least one entry.
+    ///
+    /// Defaults to `None`, i.e. no limit.
+    pub max_memory_regions: Option<u64>,
+
+    /// The value that the bootloader writes to the `FS` base register before jumping to the
+    /// kernel.
+    ///
+    /// Must be a canonical virtual address. The bootloader warns if the address is not mapped
+    /// in the kernel address space when the kernel is started. The value is reported in
+    /// [`BootInfo::fs_base`](crate::BootInfo::fs_base).
+    ///
+    /// Defaults to `None`, i.e. the register is left unchanged.
+    pub fs_base: Option<u64>,
+
+    /// The value that the bootloader writes to the `GS` base register before jumping to the
+    /// kernel.
+    ///
+    /// This allows the entry code of the kernel to use per-CPU data right away. Must be a
+    /// canonical virtual address. The bootloader warns if the address is not mapped in the
+    /// kernel address space when the kernel is started. The value is reported in
+    /// [`BootInfo::gs_base`](crate::BootInfo::gs_base).
+    ///
+    /// Defaults to `None`, i.e. the register is left unchanged.
+    pub gs_base: Option<u64>,
+
+    /// Defers exiting the UEFI boot services to the kernel and preallocates the given number of
+    /// bytes for the memory that the bootloader needs to set up the kernel.
+    ///
+    /// The kernel is started while the boot services are still active, so that it can call them
+    /// once more, e.g. to load another file. It must then call `ExitBootServices` itself, using
+    /// the system table and image handle reported in
+    /// [`BootInfo::deferred_exit`](crate::BootInfo::deferred_exit). This is easy to misuse:
+    ///
+    /// - All bootloader allocations, including the page tables, the kernel stack, and the boot
+    ///   info, are served from the preallocated pool. The bootloader panics if it's too small.
+    /// - Only the unused parts of the pool are reported as `Usable`. All other memory is still
+    ///   owned by the firmware, so free memory is reported as
+    ///   [`UnknownUefi(7)`](crate::info::MemoryRegionKind::UnknownUefi) and must not be used
+    ///   before the kernel has called `ExitBootServices` and requested the final memory map.
+    /// - The firmware expects physical memory to be identity-mapped, so the
+    ///   [`physical_memory`](Mappings::physical_memory) mapping must be set to
+    ///   `Mapping::FixedAddress(0)`. The bootloader maps it executable in this case, as the
+    ///   firmware code runs from it. The firmware might also access memory-mapped devices that
+    ///   aren't covered by this mapping.
+    /// - Interrupts are disabled when the kernel is started. Firmware services that wait for
+    ///   timer events might hang.
+    ///
+    /// Only supported on UEFI. The BIOS bootloader can't return to real mode to call the BIOS
+    /// from the kernel, so it ignores this option.
+    pub defer_exit_boot_services: Option<u64>,
+
+    /// How the bootloader fills the kernel stack before starting the kernel.
+    ///
+    /// Filling the stack makes bugs in the kernel entry code that read uninitialized stack memory
+    /// reproducible. It costs a memset proportional to the
+    /// [`kernel_stack_size`](Self::kernel_stack_size).
+    ///
+    /// Defaults to [`StackFill::Unchanged`].
+    pub kernel_stack_fill: StackFill,
+
+    /// Whether the framebuffer should additionally be identity-mapped in the kernel address space.
+    ///
+    /// If enabled, the framebuffer is mapped both at its physical address and at the virtual
+    /// address chosen by [`Mappings::framebuffer`](crate::config::Mappings::framebuffer), so that
+    /// early kernel code can use the physical address before switching to the higher-half
+    /// mapping. The identity address is reported in
+    /// [`BootInfo::framebuffer_identity_addr`](crate::BootInfo::framebuffer_identity_addr).
+    ///
+    /// Both aliases map the same physical frames, so they must use the same cache attributes. The
+    /// bootloader maps both with the default cache policy; a kernel that changes the cache policy
+    /// of one alias (e.g. to write-combining) must change the other one as well or unmap it.
+    ///
+    /// Has no effect if [`map_framebuffer_for_kernel`](Self::map_framebuffer_for_kernel) is
+    /// disabled. If the physical memory is mapped at offset zero, the framebuffer is already
+    /// identity-mapped by that mapping. Defaults to `false`.
+    pub identity_map_framebuffer: bool,
+
+    /// A kernel-chosen magic value that the bootloader passes through to the kernel.
+    ///
+    /// The bootloader copies this value to
+    /// [`BootInfo::handoff_magic`](crate::BootInfo::handoff_magic), so the kernel entry point can
+    /// compare it against the value it was compiled with. A mismatch indicates a corrupted handoff
+    /// or a bootloader that was built for a different kernel. Unlike the API version check, the
+    /// value is chosen by the kernel, e.g. to tie a kernel to a specific bootloader build.
+    ///
+    /// Defaults to `None`.
+    pub handoff_magic: Option<u64>,
+
+    /// How the frame at physical address zero is reported in the memory map.
+    ///
+    /// The bootloader never allocates the null frame, because Rust assumes that references can't
+    /// point to address zero. If the firmware reports the null frame as usable, it is reported as
+    /// bootloader memory by default. Kernels that want to keep the null page unmapped as a trap for
+    /// null pointer accesses can report it as reserved or leave it out of the memory map.
+    ///
+    /// Defaults to [`NullFrame::Bootloader`].
+    pub null_frame: NullFrame,
+
+    /// Whether the bootloader should enable supervisor-mode execution prevention (SMEP).
+    ///
+    /// If `true` and the CPU supports SMEP, the bootloader sets `CR4.SMEP` before jumping to the
+    /// kernel, so that the kernel can't execute code on user-accessible pages. All mappings created
+    /// by the bootloader are supervisor mappings, so this doesn't affect the bootloader itself. If
+    /// the CPU doesn't support SMEP, it stays disabled.
+    ///
+    /// The [`smep_enabled`](crate::info::BootInfo::smep_enabled) field of the boot info reports
+    /// whether SMEP was enabled.
+    ///
+    /// Defaults to `false`.
+    pub smep: bool,
+
+    /// Whether the bootloader should enable supervisor-mode access prevention (SMAP).
+    ///
+    /// If `true` and the CPU supports SMAP, the bootloader sets `CR4.SMAP` before jumping to the
+    /// kernel. The kernel then can't access user-accessible pages unless it sets `RFLAGS.AC`
+    /// first, e.g. using the `stac` and `clac` instructions. All mappings created by the
+    /// bootloader are supervisor mappings, so this doesn't affect the bootloader itself. If the
+    /// CPU doesn't support SMAP, it stays disabled.
+    ///
+    /// Firmware code called by the kernel, e.g. UEFI runtime services, might not expect SMAP to be
+    /// enabled.
+    ///
+    /// The [`smap_enabled`](crate::info::BootInfo::smap_enabled) field of the boot info reports
+    /// whether SMAP was enabled.
+    ///
+    /// Defaults to `false`.
+    pub smap: bool,
+
+    /// The cache policy for the page table frames of the kernel address space.
+    ///
+    /// If set to a policy other than [`PageTableCachePolicy::WriteBack`], the bootloader maps all
+    /// page table frames of the kernel address space with the given policy in the
+    /// [physical memory mapping](Mappings::physical_memory) and sets the same policy in the
+    /// entries that point to the level 3, level 2, and level 1 tables, so that the page walks of
+    /// the CPU use it too. Huge pages of the physical memory mapping that contain page tables are
+    /// split into 4KiB pages for this. The level 4 table is accessed with the cache policy of
+    /// `CR3`, which the bootloader leaves at write-back.
+    ///
+    /// Uncached or write-through page tables are slow and only needed on hardware with unusual
+    /// coherency requirements, so this is opt-in. Only applied if the physical memory mapping is
+    /// enabled.
+    ///
+    /// Defaults to [`PageTableCachePolicy::WriteBack`].
+    pub page_table_cache_policy: PageTableCachePolicy,
 }
 
 impl BootloaderConfig {
@@ -43,18 +398,74 @@ impl BootloaderConfig {
         0x3D,
     ];
     #[doc(hidden)]
-    pub const SERIALIZED_LEN: usize = 124;
+    pub const SERIALIZED_LEN: usize = 311;
 
     /// Creates a new default configuration with the following values:
     ///
     /// - `kernel_stack_size`: 80kiB
     /// - `mappings`: See [`Mappings::new_default()`]
+    /// - `bootloader_regions_usable`: `false`
+    /// - `coarse_memory_map`: `false`
+    /// - `read_only_page_tables`: `false`
+    /// - `pcid`: `false`
+    /// - `ap_trampoline_size`: `None`
+    /// - `kernel_segment_guard_pages`: `false`
+    /// - `global_pages`: `false`
+    /// - `gdt_layout`: `None`
+    /// - `entry_convention`: [`EntryConvention::SysV`]
+    /// - `max_usable_physical_address`: `None`
+    /// - `boot_info_on_stack`: `false`
+    /// - `acpi_table_cache`: `false`
+    /// - `map_framebuffer_for_kernel`: `true`
+    /// - `detect_acpi`: `true`
+    /// - `dma_region_size`: `None`
+    /// - `dma_region_max_addr`: `None`
+    /// - `address_switch_trampoline`: `false`
+    /// - `max_memory_regions`: `None`
+    /// - `fs_base`: `None`
+    /// - `gs_base`: `None`
+    /// - `defer_exit_boot_services`: `None`
+    /// - `kernel_stack_fill`: [`StackFill::Unchanged`]
+    /// - `identity_map_framebuffer`: `false`
+    /// - `handoff_magic`: `None`
+    /// - `null_frame`: [`NullFrame::Bootloader`]
+    /// - `smep`: `false`
+    /// - `smap`: `false`
+    /// - `page_table_cache_policy`: [`PageTableCachePolicy::WriteBack`]
     pub const fn new_default() -> Self {
         Self {
             kernel_stack_size: 80 * 1024,
             version: ApiVersion::new_default(),
             mappings: Mappings::new_default(),
             frame_buffer: FrameBuffer::new_default(),
+            bootloader_regions_usable: false,
+            coarse_memory_map: false,
+            read_only_page_tables: false,
+            pcid: false,
+            ap_trampoline_size: Option::None,
+            kernel_segment_guard_pages: false,
+            global_pages: false,
+            gdt_layout: Option::None,
+            entry_convention: EntryConvention::new_default(),
+            max_usable_physical_address: Option::None,
+            boot_info_on_stack: false,
+            acpi_table_cache: false,
+            map_framebuffer_for_kernel: true,
+            detect_acpi: true,
+            dma_region_size: Option::None,
+            dma_region_max_addr: Option::None,
+            address_switch_trampoline: false,
+            max_memory_regions: Option::None,
+            fs_base: Option::None,
+            gs_base: Option::None,
+            defer_exit_boot_services: Option::None,
+            kernel_stack_fill: StackFill::new_default(),
+            identity_map_framebuffer: false,
+            handoff_magic: Option::None,
+            null_frame: NullFrame::new_default(),
+            smep: false,
+            smap: false,
+            page_table_cache_policy: PageTableCachePolicy::new_default(),
         }
     }
 
@@ -68,6 +479,34 @@ impl BootloaderConfig {
             mappings,
             kernel_stack_size,
             frame_buffer,
+            bootloader_regions_usable,
+            coarse_memory_map,
+            read_only_page_tables,
+            pcid,
+            ap_trampoline_size,
+            kernel_segment_guard_pages,
+            global_pages,
+            gdt_layout,
+            entry_convention,
+            max_usable_physical_address,
+            boot_info_on_stack,
+            acpi_table_cache,
+            map_framebuffer_for_kernel,
+            detect_acpi,
+            dma_region_size,
+            dma_region_max_addr,
+            address_switch_trampoline,
+            max_memory_regions,
+            fs_base,
+            gs_base,
+            defer_exit_boot_services,
+            kernel_stack_fill,
+            identity_map_framebuffer,
+            handoff_magic,
+            null_frame,
+            smep,
+            smap,
+            page_table_cache_policy,
         } = self;
         let ApiVersion {
             version_major,
@@ -85,6 +524,8 @@ impl BootloaderConfig {
             dynamic_range_start,
             dynamic_range_end,
             ramdisk_memory,
+            gdt,
+            identity_map_limit,
         } = mappings;
         let FrameBuffer {
             minimum_framebuffer_height,
@@ -143,13 +584,145 @@ impl BootloaderConfig {
             },
         );
 
-        concat_115_9(
+        let buf = concat_115_9(
             buf,
             match minimum_framebuffer_width {
                 Option::None => [0; 9],
                 Option::Some(addr) => concat_1_8([1], addr.to_le_bytes()),
             },
-        )
+        );
+
+        let buf = concat_124_1(buf, [(*bootloader_regions_usable) as u8]);
+
+        let buf = concat_125_10(
+            buf,
+            match gdt {
+                Option::None => [0; 10],
+                Option::Some(m) => concat_1_9([1], m.serialize()),
+            },
+        );
+
+        let buf = concat_135_1(buf, [(*coarse_memory_map) as u8]);
+
+        let buf = concat_136_1(buf, [(*read_only_page_tables) as u8]);
+
+        let buf = concat_137_1(buf, [(*pcid) as u8]);
+
+        let buf = concat_138_9(
+            buf,
+            match ap_trampoline_size {
+                Option::None => [0; 9],
+                Option::Some(size) => concat_1_8([1], size.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_147_1(buf, [(*kernel_segment_guard_pages) as u8]);
+
+        let buf = concat_148_1(buf, [(*global_pages) as u8]);
+
+        let buf = concat_149_9(
+            buf,
+            match identity_map_limit {
+                Option::None => [0; 9],
+                Option::Some(limit) => concat_1_8([1], limit.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_158_69(
+            buf,
+            match gdt_layout {
+                Option::None => [0; 69],
+                Option::Some(layout) => concat_1_68([1], layout.serialize()),
+            },
+        );
+
+        let buf = concat_227_1(buf, [entry_convention.serialize()]);
+
+        let buf = concat_228_9(
+            buf,
+            match max_usable_physical_address {
+                Option::None => [0; 9],
+                Option::Some(addr) => concat_1_8([1], addr.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_237_1(buf, [(*boot_info_on_stack) as u8]);
+
+        let buf = concat_238_1(buf, [(*acpi_table_cache) as u8]);
+
+        let buf = concat_239_1(buf, [(*map_framebuffer_for_kernel) as u8]);
+
+        let buf = concat_240_1(buf, [(*detect_acpi) as u8]);
+
+        let buf = concat_241_9(
+            buf,
+            match dma_region_size {
+                Option::None => [0; 9],
+                Option::Some(size) => concat_1_8([1], size.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_250_9(
+            buf,
+            match dma_region_max_addr {
+                Option::None => [0; 9],
+                Option::Some(addr) => concat_1_8([1], addr.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_259_1(buf, [(*address_switch_trampoline) as u8]);
+
+        let buf = concat_260_9(
+            buf,
+            match max_memory_regions {
+                Option::None => [0; 9],
+                Option::Some(max) => concat_1_8([1], max.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_269_9(
+            buf,
+            match fs_base {
+                Option::None => [0; 9],
+                Option::Some(addr) => concat_1_8([1], addr.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_278_9(
+            buf,
+            match gs_base {
+                Option::None => [0; 9],
+                Option::Some(addr) => concat_1_8([1], addr.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_287_9(
+            buf,
+            match defer_exit_boot_services {
+                Option::None => [0; 9],
+                Option::Some(value) => concat_1_8([1], value.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_296_1(buf, [kernel_stack_fill.serialize()]);
+
+        let buf = concat_297_1(buf, [(*identity_map_framebuffer) as u8]);
+
+        let buf = concat_298_9(
+            buf,
+            match handoff_magic {
+                Option::None => [0; 9],
+                Option::Some(value) => concat_1_8([1], value.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_307_1(buf, [null_frame.serialize()]);
+
+        let buf = concat_308_1(buf, [(*smep) as u8]);
+
+        let buf = concat_309_1(buf, [(*smap) as u8]);
+
+        concat_310_1(buf, [page_table_cache_policy.serialize()])
     }
 
     /// Tries to deserialize a config byte array that was created using [`Self::serialize`].
@@ -194,7 +767,7 @@ impl BootloaderConfig {
 
         let (&kernel_stack_size, s) = split_array_ref(s);
 
-        let (mappings, s) = {
+        let (mut mappings, s) = {
             let (&kernel_stack, s) = split_array_ref(s);
             let (&boot_info, s) = split_array_ref(s);
             let (&framebuffer, s) = split_array_ref(s);
@@ -239,6 +812,9 @@ impl BootloaderConfig {
                     _ => return Err("invalid dynamic range end value"),
                 },
                 ramdisk_memory: Mapping::deserialize(&ramdisk_memory)?,
+                // stored after the other fields for compatibility, see below
+                gdt: Option::None,
+                identity_map_limit: Option::None,
             };
             (mappings, s)
         };
@@ -264,6 +840,212 @@ impl BootloaderConfig {
             (frame_buffer, s)
         };
 
+        let (&[bootloader_regions_usable], s) = split_array_ref(s);
+        let bootloader_regions_usable = match bootloader_regions_usable {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid bootloader regions usable value"),
+        };
+
+        let (&gdt_some, s) = split_array_ref(s);
+        let (&gdt, s) = split_array_ref(s);
+        mappings.gdt = match gdt_some {
+            [0] if gdt == [0; 9] => Option::None,
+            [1] => Option::Some(Mapping::deserialize(&gdt)?),
+            _ => return Err("invalid gdt value"),
+        };
+
+        let (&[coarse_memory_map], s) = split_array_ref(s);
+        let coarse_memory_map = match coarse_memory_map {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid coarse memory map value"),
+        };
+
+        let (&[read_only_page_tables], s) = split_array_ref(s);
+        let read_only_page_tables = match read_only_page_tables {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid read only page tables value"),
+        };
+
+        let (&[pcid], s) = split_array_ref(s);
+        let pcid = match pcid {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid pcid value"),
+        };
+
+        let (&ap_trampoline_size_some, s) = split_array_ref(s);
+        let (&ap_trampoline_size, s) = split_array_ref(s);
+        let ap_trampoline_size = match ap_trampoline_size_some {
+            [0] if ap_trampoline_size == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(ap_trampoline_size)),
+            _ => return Err("invalid ap trampoline size value"),
+        };
+
+        let (&[kernel_segment_guard_pages], s) = split_array_ref(s);
+        let kernel_segment_guard_pages = match kernel_segment_guard_pages {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid kernel segment guard pages value"),
+        };
+
+        let (&[global_pages], s) = split_array_ref(s);
+        let global_pages = match global_pages {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid global pages value"),
+        };
+
+        let (&identity_map_limit_some, s) = split_array_ref(s);
+        let (&identity_map_limit, s) = split_array_ref(s);
+        mappings.identity_map_limit = match identity_map_limit_some {
+            [0] if identity_map_limit == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(identity_map_limit)),
+            _ => return Err("invalid identity map limit value"),
+        };
+
+        let (&gdt_layout_some, s) = split_array_ref(s);
+        let (&gdt_layout, s) = split_array_ref(s);
+        let gdt_layout = match gdt_layout_some {
+            [0] if gdt_layout == [0; 68] => Option::None,
+            [1] => Option::Some(GdtLayout::deserialize(&gdt_layout)?),
+            _ => return Err("invalid gdt layout value"),
+        };
+
+        let (&[entry_convention], s) = split_array_ref(s);
+        let entry_convention = EntryConvention::deserialize(entry_convention)?;
+
+        let (&max_usable_physical_address_some, s) = split_array_ref(s);
+        let (&max_usable_physical_address, s) = split_array_ref(s);
+        let max_usable_physical_address = match max_usable_physical_address_some {
+            [0] if max_usable_physical_address == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(max_usable_physical_address)),
+            _ => return Err("invalid max usable physical address value"),
+        };
+
+        let (&[boot_info_on_stack], s) = split_array_ref(s);
+        let boot_info_on_stack = match boot_info_on_stack {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid boot info on stack value"),
+        };
+
+        let (&[acpi_table_cache], s) = split_array_ref(s);
+        let acpi_table_cache = match acpi_table_cache {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid ACPI table cache value"),
+        };
+
+        let (&[map_framebuffer_for_kernel], s) = split_array_ref(s);
+        let map_framebuffer_for_kernel = match map_framebuffer_for_kernel {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid map framebuffer for kernel value"),
+        };
+
+        let (&[detect_acpi], s) = split_array_ref(s);
+        let detect_acpi = match detect_acpi {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid detect acpi value"),
+        };
+
+        let (&dma_region_size_some, s) = split_array_ref(s);
+        let (&dma_region_size, s) = split_array_ref(s);
+        let dma_region_size = match dma_region_size_some {
+            [0] if dma_region_size == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(dma_region_size)),
+            _ => return Err("invalid DMA region size value"),
+        };
+
+        let (&dma_region_max_addr_some, s) = split_array_ref(s);
+        let (&dma_region_max_addr, s) = split_array_ref(s);
+        let dma_region_max_addr = match dma_region_max_addr_some {
+            [0] if dma_region_max_addr == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(dma_region_max_addr)),
+            _ => return Err("invalid DMA region max address value"),
+        };
+
+        let (&[address_switch_trampoline], s) = split_array_ref(s);
+        let address_switch_trampoline = match address_switch_trampoline {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid address switch trampoline value"),
+        };
+
+        let (&max_memory_regions_some, s) = split_array_ref(s);
+        let (&max_memory_regions, s) = split_array_ref(s);
+        let max_memory_regions = match max_memory_regions_some {
+            [0] if max_memory_regions == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(max_memory_regions)),
+            _ => return Err("invalid max memory regions value"),
+        };
+
+        let (&fs_base_some, s) = split_array_ref(s);
+        let (&fs_base, s) = split_array_ref(s);
+        let fs_base = match fs_base_some {
+            [0] if fs_base == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(fs_base)),
+            _ => return Err("invalid FS base value"),
+        };
+
+        let (&gs_base_some, s) = split_array_ref(s);
+        let (&gs_base, s) = split_array_ref(s);
+        let gs_base = match gs_base_some {
+            [0] if gs_base == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(gs_base)),
+            _ => return Err("invalid GS base value"),
+        };
+
+        let (&defer_exit_boot_services_some, s) = split_array_ref(s);
+        let (&defer_exit_boot_services, s) = split_array_ref(s);
+        let defer_exit_boot_services = match defer_exit_boot_services_some {
+            [0] if defer_exit_boot_services == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(defer_exit_boot_services)),
+            _ => return Err("invalid deferred exit pool size value"),
+        };
+
+        let (&[kernel_stack_fill], s) = split_array_ref(s);
+        let kernel_stack_fill = StackFill::deserialize(kernel_stack_fill)?;
+
+        let (&[identity_map_framebuffer], s) = split_array_ref(s);
+        let identity_map_framebuffer = match identity_map_framebuffer {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid identity map framebuffer value"),
+        };
+
+        let (&handoff_magic_some, s) = split_array_ref(s);
+        let (&handoff_magic, s) = split_array_ref(s);
+        let handoff_magic = match handoff_magic_some {
+            [0] if handoff_magic == [0; 8] => Option::None,
+            [1] => Option::Some(u64::from_le_bytes(handoff_magic)),
+            _ => return Err("invalid handoff magic value"),
+        };
+
+        let (&[null_frame], s) = split_array_ref(s);
+        let null_frame = NullFrame::deserialize(null_frame)?;
+
+        let (&[smep], s) = split_array_ref(s);
+        let smep = match smep {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid SMEP value"),
+        };
+
+        let (&[smap], s) = split_array_ref(s);
+        let smap = match smap {
+            1 => true,
+            0 => false,
+            _ => return Err("invalid SMAP value"),
+        };
+
+        let (&[page_table_cache_policy], s) = split_array_ref(s);
+        let page_table_cache_policy = PageTableCachePolicy::deserialize(page_table_cache_policy)?;
+
         if !s.is_empty() {
             return Err("unexpected rest");
         }
@@ -273,6 +1055,34 @@ impl BootloaderConfig {
             kernel_stack_size: u64::from_le_bytes(kernel_stack_size),
             mappings,
             frame_buffer,
+            bootloader_regions_usable,
+            coarse_memory_map,
+            read_only_page_tables,
+            pcid,
+            ap_trampoline_size,
+            kernel_segment_guard_pages,
+            global_pages,
+            gdt_layout,
+            entry_convention,
+            max_usable_physical_address,
+            boot_info_on_stack,
+            acpi_table_cache,
+            map_framebuffer_for_kernel,
+            detect_acpi,
+            dma_region_size,
+            dma_region_max_addr,
+            address_switch_trampoline,
+            max_memory_regions,
+            fs_base,
+            gs_base,
+            defer_exit_boot_services,
+            kernel_stack_fill,
+            identity_map_framebuffer,
+            handoff_magic,
+            null_frame,
+            smep,
+            smap,
+            page_table_cache_policy,
         })
     }
 
@@ -283,6 +1093,74 @@ impl BootloaderConfig {
             mappings: Mappings::random(),
             kernel_stack_size: rand::random(),
             frame_buffer: FrameBuffer::random(),
+            bootloader_regions_usable: rand::random(),
+            coarse_memory_map: rand::random(),
+            read_only_page_tables: rand::random(),
+            pcid: rand::random(),
+            ap_trampoline_size: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            kernel_segment_guard_pages: rand::random(),
+            global_pages: rand::random(),
+            gdt_layout: if rand::random() {
+                Option::Some(GdtLayout::random())
+            } else {
+                Option::None
+            },
+            entry_convention: EntryConvention::random(),
+            max_usable_physical_address: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            boot_info_on_stack: rand::random(),
+            acpi_table_cache: rand::random(),
+            map_framebuffer_for_kernel: rand::random(),
+            detect_acpi: rand::random(),
+            dma_region_size: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            dma_region_max_addr: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            address_switch_trampoline: rand::random(),
+            max_memory_regions: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            fs_base: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            gs_base: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            defer_exit_boot_services: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            kernel_stack_fill: StackFill::random(),
+            identity_map_framebuffer: rand::random(),
+            handoff_magic: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            null_frame: NullFrame::random(),
+            smep: rand::random(),
+            smap: rand::random(),
+            page_table_cache_policy: PageTableCachePolicy::random(),
         }
     }
 }
@@ -404,6 +1282,31 @@ pub struct Mappings {
     /// Virtual address to map ramdisk image, if present on disk
     /// Defaults to dynamic
     pub ramdisk_memory: Mapping,
+    /// Instructs the bootloader to set up a GDT and a TSS for the bootstrap processor and
+    /// to map them to the given virtual address.
+    ///
+    /// The region also contains a stack for each interrupt stack table (IST) entry of the TSS.
+    /// One of them is reserved for the double fault handler, see
+    /// [`GdtInfo::double_fault_ist_index`](crate::info::GdtInfo::double_fault_ist_index).
+    /// The bootloader loads the GDT and TSS before jumping to the kernel and reports the
+    /// location of the region in [`BootInfo::gdt`](crate::BootInfo::gdt), so that the kernel
+    /// can use them for handling its first interrupts and exceptions.
+    ///
+    /// Defaults to `None`, i.e. only a minimal GDT without a TSS is set up.
+    pub gdt: Option<Mapping>,
+    /// Restricts the [physical memory mapping](Self::physical_memory) to the physical
+    /// addresses below the given limit.
+    ///
+    /// This is useful for kernels that manage their own paging early on and only need the
+    /// low memory to be mapped, e.g. an identity mapping of the first few MiB created by
+    /// combining this option with `physical_memory: Some(Mapping::FixedAddress(0))`. The limit
+    /// is rounded up to a multiple of 2 MiB. The kernel ELF file and the framebuffer must lie
+    /// below the limit, otherwise the boot fails. The usable memory above the limit is
+    /// reported in [`BootInfo::unmapped_usable`](crate::BootInfo::unmapped_usable).
+    ///
+    /// Has no effect if no physical memory mapping is set up. Defaults to `None`, i.e. all
+    /// physical memory is mapped.
+    pub identity_map_limit: Option<u64>,
 }
 
 impl Mappings {
@@ -421,6 +1324,8 @@ impl Mappings {
             dynamic_range_start: None,
             dynamic_range_end: None,
             ramdisk_memory: Mapping::new_default(),
+            gdt: Option::None,
+            identity_map_limit: Option::None,
         }
     }
 
@@ -454,6 +1359,16 @@ impl Mappings {
                 Option::None
             },
             ramdisk_memory: Mapping::random(),
+            gdt: if rand::random() {
+                Option::Some(Mapping::random())
+            } else {
+                Option::None
+            },
+            identity_map_limit: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
         }
     }
 }
@@ -519,6 +1434,238 @@ impl Default for Mapping {
     }
 }
 
+/// Specifies how the bootloader passes the boot info to the kernel entry point.
+///
+/// With all conventions, the bootloader jumps to the entry point with interrupts disabled,
+/// `RBP` set to zero, and a zero return address at `[RSP]`, so that stack traces terminate
+/// there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EntryConvention {
+    /// Pass the boot info pointer as first argument according to the System V ABI.
+    ///
+    /// `RDI` contains the boot info pointer and `RSP + 8` is 16-byte aligned, as if the
+    /// entry point was invoked by a `call` instruction. This is the convention of an
+    /// `extern "C" fn(&'static mut BootInfo) -> !` entry point.
+    SysV,
+    /// Pass the boot info pointer on the stack.
+    ///
+    /// `[RSP + 8]` contains the boot info pointer and `RSP + 8` is 16-byte aligned, as if the
+    /// entry point was invoked by a `call` instruction with the pointer pushed as a stack
+    /// argument. For compatibility, `RDI` contains the boot info pointer as well.
+    Stack,
+}
+
+impl EntryConvention {
+    /// Creates a new [`EntryConvention::SysV`].
+    ///
+    /// This function has identical results as [`Default::default`], the only difference is
+    /// that this is a `const` function.
+    pub const fn new_default() -> Self {
+        Self::SysV
+    }
+
+    #[cfg(test)]
+    fn random() -> EntryConvention {
+        if rand::random() {
+            Self::SysV
+        } else {
+            Self::Stack
+        }
+    }
+
+    const fn serialize(&self) -> u8 {
+        match self {
+            EntryConvention::SysV => 0,
+            EntryConvention::Stack => 1,
+        }
+    }
+
+    fn deserialize(serialized: u8) -> Result<Self, &'static str> {
+        match serialized {
+            0 => Ok(EntryConvention::SysV),
+            1 => Ok(EntryConvention::Stack),
+            _ => Err("invalid entry convention value"),
+        }
+    }
+}
+
+impl Default for EntryConvention {
+    fn default() -> Self {
+        Self::new_default()
+    }
+}
+
+/// Specifies how the bootloader fills the kernel stack before starting the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StackFill {
+    /// Leave the stack frames unchanged, so they contain whatever was stored in them before.
+    Unchanged,
+    /// Fill the stack with zeros.
+    Zero,
+    /// Fill the stack with the byte [`StackFill::POISON`], so that reads of uninitialized
+    /// stack memory stand out.
+    Poison,
+}
+
+impl StackFill {
+    /// The byte that [`StackFill::Poison`] fills the stack with.
+    pub const POISON: u8 = 0xaa;
+
+    /// Creates a new [`StackFill::Unchanged`].
+    ///
+    /// This function has identical results as [`Default::default`], the only difference is
+    /// that this is a `const` function.
+    pub const fn new_default() -> Self {
+        Self::Unchanged
+    }
+
+    #[cfg(test)]
+    fn random() -> StackFill {
+        match rand::random::<u8>() % 3 {
+            0 => Self::Unchanged,
+            1 => Self::Zero,
+            _ => Self::Poison,
+        }
+    }
+
+    const fn serialize(&self) -> u8 {
+        match self {
+            StackFill::Unchanged => 0,
+            StackFill::Zero => 1,
+            StackFill::Poison => 2,
+        }
+    }
+
+    fn deserialize(serialized: u8) -> Result<Self, &'static str> {
+        match serialized {
+            0 => Ok(StackFill::Unchanged),
+            1 => Ok(StackFill::Zero),
+            2 => Ok(StackFill::Poison),
+            _ => Err("invalid kernel stack fill value"),
+        }
+    }
+}
+
+impl Default for StackFill {
+    fn default() -> Self {
+        Self::new_default()
+    }
+}
+
+/// Specifies how the frame at physical address zero is reported in the memory map.
+///
+/// Only applies if the firmware reports the null frame as usable memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum NullFrame {
+    /// Report the null frame like the other frames that the bootloader used, i.e. as
+    /// [`Bootloader`](crate::info::MemoryRegionKind::Bootloader) or
+    /// [`BootloaderReclaimable`](crate::info::MemoryRegionKind::BootloaderReclaimable) memory.
+    Bootloader,
+    /// Report the null frame as [`Reserved`](crate::info::MemoryRegionKind::Reserved) memory.
+    Reserved,
+    /// Leave the null frame out of the memory map.
+    Omitted,
+}
+
+impl NullFrame {
+    /// Creates a new [`NullFrame::Bootloader`].
+    ///
+    /// This function has identical results as [`Default::default`], the only difference is
+    /// that this is a `const` function.
+    pub const fn new_default() -> Self {
+        Self::Bootloader
+    }
+
+    #[cfg(test)]
+    fn random() -> NullFrame {
+        match rand::random::<u8>() % 3 {
+            0 => Self::Bootloader,
+            1 => Self::Reserved,
+            _ => Self::Omitted,
+        }
+    }
+
+    const fn serialize(&self) -> u8 {
+        match self {
+            NullFrame::Bootloader => 0,
+            NullFrame::Reserved => 1,
+            NullFrame::Omitted => 2,
+        }
+    }
+
+    fn deserialize(serialized: u8) -> Result<Self, &'static str> {
+        match serialized {
+            0 => Ok(NullFrame::Bootloader),
+            1 => Ok(NullFrame::Reserved),
+            2 => Ok(NullFrame::Omitted),
+            _ => Err("invalid null frame value"),
+        }
+    }
+}
+
+impl Default for NullFrame {
+    fn default() -> Self {
+        Self::new_default()
+    }
+}
+
+/// Specifies the cache policy for the page table frames of the kernel address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PageTableCachePolicy {
+    /// Normal cacheable memory.
+    WriteBack,
+    /// Write-through caching, i.e. the `WRITE_THROUGH` flag is set.
+    WriteThrough,
+    /// Uncached memory, i.e. the `NO_CACHE` and `WRITE_THROUGH` flags are set.
+    Uncached,
+}
+
+impl PageTableCachePolicy {
+    /// Creates a new [`PageTableCachePolicy::WriteBack`].
+    ///
+    /// This function has identical results as [`Default::default`], the only difference is
+    /// that this is a `const` function.
+    pub const fn new_default() -> Self {
+        Self::WriteBack
+    }
+
+    #[cfg(test)]
+    fn random() -> PageTableCachePolicy {
+        match rand::random::<u8>() % 3 {
+            0 => Self::WriteBack,
+            1 => Self::WriteThrough,
+            _ => Self::Uncached,
+        }
+    }
+
+    const fn serialize(&self) -> u8 {
+        match self {
+            PageTableCachePolicy::WriteBack => 0,
+            PageTableCachePolicy::WriteThrough => 1,
+            PageTableCachePolicy::Uncached => 2,
+        }
+    }
+
+    fn deserialize(serialized: u8) -> Result<Self, &'static str> {
+        match serialized {
+            0 => Ok(PageTableCachePolicy::WriteBack),
+            1 => Ok(PageTableCachePolicy::WriteThrough),
+            2 => Ok(PageTableCachePolicy::Uncached),
+            _ => Err("invalid page table cache policy value"),
+        }
+    }
+}
+
+impl Default for PageTableCachePolicy {
+    fn default() -> Self {
+        Self::new_default()
+    }
+}
+
 /// Configuration for the frame buffer used for graphical output.
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
 #[non_exhaustive]
@@ -559,6 +1706,161 @@ impl FrameBuffer {
     }
 }
 
+/// A custom layout of the GDT that the bootloader creates for the kernel.
+///
+/// Each entry is a raw 8-byte segment descriptor. The bootloader fills in the 16-byte TSS
+/// descriptor at [`tss_index`](Self::tss_index), all other entries are loaded as given.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub struct GdtLayout {
+    /// The segment descriptors of the GDT.
+    ///
+    /// Only the first [`len`](Self::len) entries are used. The first entry must be the null
+    /// descriptor. The two entries starting at [`tss_index`](Self::tss_index) are overwritten
+    /// with the TSS descriptor.
+    pub entries: [u64; Self::MAX_ENTRIES],
+    /// The number of used entries.
+    pub len: u8,
+    /// The index of the kernel code segment descriptor, which is loaded into `CS`.
+    ///
+    /// Must be a present 64-bit code segment with privilege level 0.
+    pub code_index: u8,
+    /// The index of the kernel data segment descriptor, which is loaded into `DS`, `ES`, and
+    /// `SS`.
+    ///
+    /// Must be a present writable data segment with privilege level 0.
+    pub data_index: u8,
+    /// The index of the first of the two entries that hold the TSS descriptor.
+    pub tss_index: u8,
+}
+
+impl GdtLayout {
+    /// The maximum number of GDT entries.
+    pub const MAX_ENTRIES: usize = 8;
+
+    /// The descriptor of a 64-bit kernel code segment.
+    pub const KERNEL_CODE_SEGMENT: u64 = 0x00af_9b00_0000_ffff;
+    /// The descriptor of a kernel data segment.
+    pub const KERNEL_DATA_SEGMENT: u64 = 0x00cf_9300_0000_ffff;
+    /// The descriptor of a 64-bit user code segment.
+    pub const USER_CODE_SEGMENT: u64 = 0x00af_fb00_0000_ffff;
+    /// The descriptor of a user data segment.
+    pub const USER_DATA_SEGMENT: u64 = 0x00cf_f300_0000_ffff;
+
+    const PRESENT: u64 = 1 << 47;
+    const PRIVILEGE_LEVEL: u64 = 3 << 45;
+    const USER_SEGMENT: u64 = 1 << 44;
+    const EXECUTABLE: u64 = 1 << 43;
+    const WRITABLE: u64 = 1 << 41;
+    const LONG_MODE: u64 = 1 << 53;
+    const DEFAULT_SIZE: u64 = 1 << 54;
+
+    /// Creates the default layout, which consists of the null descriptor, the kernel code
+    /// segment, the kernel data segment, and the TSS descriptor, in this order.
+    pub const fn new_default() -> Self {
+        let mut entries = [0; Self::MAX_ENTRIES];
+        entries[1] = Self::KERNEL_CODE_SEGMENT;
+        entries[2] = Self::KERNEL_DATA_SEGMENT;
+        Self {
+            entries,
+            len: 5,
+            code_index: 1,
+            data_index: 2,
+            tss_index: 3,
+        }
+    }
+
+    /// Checks that the layout can be loaded before jumping to the kernel.
+    ///
+    /// The indices must be in bounds and must not overlap, the first entry must be the null
+    /// descriptor, and the code and data segment descriptors must be valid long mode kernel
+    /// segments. Otherwise, loading the segment registers or jumping to the kernel would
+    /// fault.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let len = usize::from(self.len);
+        let [code, data, tss] = [self.code_index, self.data_index, self.tss_index].map(usize::from);
+        if len > Self::MAX_ENTRIES {
+            return Err("too many GDT entries");
+        }
+        if self.entries[0] != 0 {
+            return Err("the first GDT entry must be the null descriptor");
+        }
+        if code == 0 || data == 0 || tss == 0 {
+            return Err("the null descriptor can't be used as a segment");
+        }
+        if code >= len || data >= len || tss + 1 >= len {
+            return Err("GDT index out of bounds");
+        }
+        if code == data || code == tss || code == tss + 1 || data == tss || data == tss + 1 {
+            return Err("GDT indices overlap");
+        }
+
+        let code = self.entries[code];
+        let code_required = Self::PRESENT | Self::USER_SEGMENT | Self::EXECUTABLE | Self::LONG_MODE;
+        if code & code_required != code_required
+            || code & (Self::PRIVILEGE_LEVEL | Self::DEFAULT_SIZE) != 0
+        {
+            return Err("the code segment is not a 64-bit kernel code segment");
+        }
+        let data = self.entries[data];
+        let data_required = Self::PRESENT | Self::USER_SEGMENT | Self::WRITABLE;
+        if data & data_required != data_required
+            || data & (Self::PRIVILEGE_LEVEL | Self::EXECUTABLE) != 0
+        {
+            return Err("the data segment is not a writable kernel data segment");
+        }
+        Ok(())
+    }
+
+    const fn serialize(&self) -> [u8; 68] {
+        let mut buf = [0; 68];
+        let mut i = 0;
+        while i < Self::MAX_ENTRIES {
+            let bytes = self.entries[i].to_le_bytes();
+            let mut j = 0;
+            while j < bytes.len() {
+                buf[i * 8 + j] = bytes[j];
+                j += 1;
+            }
+            i += 1;
+        }
+        buf[64] = self.len;
+        buf[65] = self.code_index;
+        buf[66] = self.data_index;
+        buf[67] = self.tss_index;
+        buf
+    }
+
+    fn deserialize(serialized: &[u8; 68]) -> Result<Self, &'static str> {
+        let (entries, s) = serialized.split_at(Self::MAX_ENTRIES * 8);
+        let mut layout = Self {
+            entries: [0; Self::MAX_ENTRIES],
+            len: s[0],
+            code_index: s[1],
+            data_index: s[2],
+            tss_index: s[3],
+        };
+        for (entry, bytes) in layout.entries.iter_mut().zip(entries.chunks_exact(8)) {
+            *entry = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        if usize::from(layout.len) > Self::MAX_ENTRIES {
+            return Err("invalid gdt layout len");
+        }
+        Ok(layout)
+    }
+
+    #[cfg(test)]
+    fn random() -> GdtLayout {
+        Self {
+            entries: rand::random(),
+            len: rand::random::<u8>() % (Self::MAX_ENTRIES as u8 + 1),
+            code_index: rand::random(),
+            data_index: rand::random(),
+            tss_index: rand::random(),
+        }
+    }
+}
+
 /// Taken from https://github.com/rust-lang/rust/blob/e100ec5bc7cd768ec17d75448b29c9ab4a39272b/library/core/src/slice/mod.rs#L1673-L1677
 ///
 /// TODO replace with `split_array` feature in stdlib as soon as it's stabilized,
@@ -581,6 +1883,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn default_gdt_layout_is_valid() {
+        assert_eq!(GdtLayout::new_default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn gdt_layout_with_invalid_code_segment() {
+        let mut layout = GdtLayout::new_default();
+        layout.entries[1] = GdtLayout::USER_CODE_SEGMENT;
+        assert!(layout.validate().is_err());
+        // a 32-bit code segment
+        layout.entries[1] = GdtLayout::KERNEL_CODE_SEGMENT & !(1 << 53) | 1 << 54;
+        assert!(layout.validate().is_err());
+    }
+
     #[test]
     fn config_serde() {
         for _ in 0..10000 {
@@ -0,0 +1,118 @@
+//! The blob consists of a sequence of entries without any header or padding. Each entry is
+//! encoded as:
+//!
+//! | Offset      | Size       | Content                                   |
+//! |-------------|------------|-------------------------------------------|
+//! | 0           | 2          | key length `k` as little-endian `u16`     |
+//! | 2           | `k`        | key bytes                                 |
+//! | 2 + `k`     | 4          | value length `v` as little-endian `u32`   |
+//! | 6 + `k`     | `v`        | value bytes                               |
+//!
+//! Keys and values are arbitrary bytes. By convention, keys are UTF-8 strings. The same key
+//! might appear multiple times, in which case the interpretation is up to the kernel.
+
+/// Returns an iterator over the key-value entries of the given config blob.
+pub fn entries(blob: &[u8]) -> Entries<'_> {
+    Entries { remaining: blob }
+}
+
+/// Returns the number of bytes that [`encode`] needs for the given entries.
+pub fn encoded_len(entries: &[(&[u8], &[u8])]) -> usize {
+    entries
+        .iter()
+        .map(|(key, value)| 2 + key.len() + 4 + value.len())
+        .sum()
+}
+
+/// Encodes the given key-value entries into `buffer` and returns the number of written bytes.
+///
+/// Returns an error if a key or value is too long or if `buffer` is too small.
+pub fn encode(entries: &[(&[u8], &[u8])], buffer: &mut [u8]) -> Result<usize, &'static str> {
+    let mut offset = 0;
+    let mut write = |bytes: &[u8]| {
+        let dst = buffer
+            .get_mut(offset..offset + bytes.len())
+            .ok_or("buffer too small for the config blob")?;
+        dst.copy_from_slice(bytes);
+        offset += bytes.len();
+        Ok(())
+    };
+    for (key, value) in entries {
+        let key_len = u16::try_from(key.len()).map_err(|_| "config blob key too long")?;
+        let value_len = u32::try_from(value.len()).map_err(|_| "config blob value too long")?;
+        write(&key_len.to_le_bytes())?;
+        write(key)?;
+        write(&value_len.to_le_bytes())?;
+        write(value)?;
+    }
+    Ok(offset)
+}
+
+/// An iterator over the key-value entries of a config blob.
+///
+/// Yields `Err` and stops if the blob is truncated.
+#[derive(Debug, Clone)]
+pub struct Entries<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> Entries<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        if self.remaining.len() < len {
+            self.remaining = &[];
+            return Err("truncated config blob entry");
+        }
+        let (bytes, rest) = self.remaining.split_at(len);
+        self.remaining = rest;
+        Ok(bytes)
+    }
+
+    fn next_entry(&mut self) -> Result<(&'a [u8], &'a [u8]), &'static str> {
+        let key_len = u16::from_le_bytes(self.take(2)?.try_into().unwrap());
+        let key = self.take(usize::from(key_len))?;
+        let value_len = u32::from_le_bytes(self.take(4)?.try_into().unwrap());
+        let value = self.take(value_len as usize)?;
+        Ok((key, value))
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<(&'a [u8], &'a [u8]), &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.is_empty() {
+            None
+        } else {
+            Some(self.next_entry())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_blob_round_trip() {
+        let input: [(&[u8], &[u8]); 3] = [
+            (b"root", b"/dev/sda1"),
+            (b"quiet", b""),
+            (b"log_level", b"debug"),
+        ];
+        let mut buffer = [0; 64];
+        let len = encode(&input, &mut buffer).unwrap();
+        assert_eq!(len, encoded_len(&input));
+
+        let mut parsed = entries(&buffer[..len]);
+        for expected in input {
+            assert_eq!(parsed.next(), Some(Ok(expected)));
+        }
+        assert_eq!(parsed.next(), None);
+
+        assert!(encode(&input, &mut buffer[..len - 1]).is_err());
+        assert_eq!(
+            entries(&buffer[..len - 1]).last(),
+            Some(Err("truncated config blob entry"))
+        );
+    }
+}
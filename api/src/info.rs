@@ -30,6 +30,10 @@ pub struct BootInfo {
     /// used by the kernel.
     pub memory_regions: MemoryRegions,
     /// Information about the framebuffer for screen output if available.
+    ///
+    /// `None` if the framebuffer is not mapped in the kernel address space because
+    /// [`BootloaderConfig::map_framebuffer_for_kernel`](crate::BootloaderConfig::map_framebuffer_for_kernel)
+    /// is disabled. Use [`framebuffer_phys`](Self::framebuffer_phys) in this case.
     pub framebuffer: Optional<FrameBuffer>,
     /// The virtual address at which the mapping of the physical memory starts.
     ///
@@ -60,8 +64,219 @@ pub struct BootInfo {
     pub kernel_addr: u64,
     /// Size of the kernel ELF in memory.
     pub kernel_len: u64,
-    /// Virtual address of the loaded kernel image.
+    /// The offset that the bootloader added to the virtual addresses of the kernel ELF file.
+    ///
+    /// Same as [`kernel_load_bias`](Self::kernel_load_bias). Zero for non-PIE kernels.
     pub kernel_image_offset: u64,
+    /// The GDT and TSS set up for the bootstrap processor.
+    ///
+    /// Only available if the `gdt` mapping config option is enabled.
+    pub gdt: Optional<GdtInfo>,
+    /// A coarse view of the memory map with a minimal number of regions.
+    ///
+    /// Covers the same physical address range as [`memory_regions`](Self::memory_regions),
+    /// but only distinguishes between [`Usable`](MemoryRegionKind::Usable) and
+    /// [`Reserved`](MemoryRegionKind::Reserved) memory. The regions are sorted by their
+    /// start address.
+    ///
+    /// Only available if the `coarse_memory_map` config option is enabled.
+    pub coarse_memory_regions: Optional<MemoryRegions>,
+    /// Whether the page tables of the kernel address space are mapped read-only.
+    ///
+    /// If `true`, all page table frames are mapped read-only in the physical memory mapping,
+    /// so they need to be modified through the recursive mapping or another writable alias.
+    /// Set if the `read_only_page_tables` config option is enabled.
+    pub page_tables_read_only: bool,
+    /// The linear framebuffer video modes that are supported by the graphics hardware.
+    ///
+    /// Queried from the VBE BIOS (for BIOS) or the Graphics Output Protocol (for UEFI). At
+    /// most [`VideoModes::CAPACITY`] modes are reported. The list is empty if no framebuffer
+    /// is available.
+    pub available_video_modes: VideoModes,
+    /// The number of memory regions that did not fit into [`memory_regions`](Self::memory_regions).
+    ///
+    /// If this is non-zero, the memory map is incomplete. The kernel should treat all memory
+    /// that is not covered by the memory map as unusable.
+    pub dropped_memory_regions: u64,
+    /// Whether process-context identifiers (PCIDs) are enabled, i.e. whether `CR4.PCIDE` is set.
+    ///
+    /// If `true`, the kernel address space is loaded with PCID `0`. Set if the `pcid` config
+    /// option is enabled and the CPU supports PCIDs.
+    pub pcid_enabled: bool,
+    /// The local APIC ID of the bootstrap processor.
+    ///
+    /// This is the x2APIC ID if the CPU supports it and the 8-bit initial APIC ID otherwise.
+    /// `None` if the CPU has no local APIC.
+    pub bsp_apic_id: Optional<u32>,
+    /// The physical base address of the local APIC registers, as reported by the
+    /// `IA32_APIC_BASE` model-specific register.
+    ///
+    /// Usually `0xfee00000`, but the firmware might have relocated it. `None` if the CPU has
+    /// no local APIC.
+    pub lapic_base: Optional<u64>,
+    /// Whether the local APIC of the bootstrap processor is in x2APIC mode.
+    pub x2apic_enabled: bool,
+    /// The log messages of the bootloader.
+    ///
+    /// Contains all messages up to the creation of the boot info, independent of the
+    /// framebuffer and serial logging settings. Only the most recent messages are kept if
+    /// the log is too large.
+    pub boot_log: Optional<BootLog>,
+    /// The wall-clock time at boot, as reported by the firmware.
+    ///
+    /// Read from the CMOS real-time clock (for BIOS) or through the `GetTime` runtime service
+    /// (for UEFI). The time zone is unknown; most systems keep the clock in local time or UTC.
+    pub boot_time: Optional<BootTime>,
+    /// The physical start address of the low memory region reserved for a real-mode
+    /// trampoline.
+    ///
+    /// Only set if a region was requested through
+    /// [`BootloaderConfig::ap_trampoline_size`](crate::BootloaderConfig::ap_trampoline_size).
+    /// The region is page-aligned, located below 1 MiB, and is marked as bootloader memory in
+    /// the memory map. It is identity-mapped as writable and executable in the kernel address
+    /// space, so that the startup code of the application processors can keep running from it
+    /// after loading the kernel page table. `None` if no region was requested or if no suitable
+    /// memory was found.
+    pub ap_trampoline_region: Optional<u64>,
+    /// Whether global pages are enabled, i.e. whether `CR4.PGE` is set.
+    ///
+    /// If `true`, the kernel segments and the physical memory mapping are mapped with the
+    /// `GLOBAL` flag. Set if the `global_pages` config option is enabled and the CPU supports
+    /// global pages.
+    pub global_pages_enabled: bool,
+    /// The usable memory regions that are not covered by the physical memory mapping.
+    ///
+    /// Lists the parts of the [`Usable`](MemoryRegionKind::Usable) regions of
+    /// [`memory_regions`](Self::memory_regions) that the kernel can't access through
+    /// [`physical_memory_offset`](Self::physical_memory_offset), so it has to map them itself
+    /// before using them. If no physical memory mapping is set up, this contains all usable
+    /// regions. The regions are sorted by their start address.
+    pub unmapped_usable: MemoryRegions,
+    /// The location of the high precision event timer (HPET), as described by the ACPI
+    /// `HPET` table.
+    ///
+    /// The bootloader doesn't configure the HPET. `None` if no [`rsdp_addr`](Self::rsdp_addr)
+    /// is known, if there is no HPET table, or if the HPET registers are not memory-mapped.
+    pub hpet: Optional<HpetInfo>,
+    /// The raw VBE information blocks of the graphics card, for kernels that include a VBE
+    /// driver.
+    ///
+    /// Only available when booted through BIOS. `None` on UEFI or if the VBE BIOS didn't
+    /// report the information.
+    pub vbe_info: Optional<VbeInfo>,
+    /// The timeline of the boot process, in chronological order.
+    ///
+    /// Each stage of the bootloader records [`BootEvent`]s with the value of the time stamp
+    /// counter, which allows analyzing where the boot time is spent. The events are stored in
+    /// a memory region that is reported as bootloader memory in the memory map. At most
+    /// [`BootEvents::CAPACITY`] events are recorded, later events are dropped.
+    pub boot_events: BootEvents,
+    /// The location of frequently used ACPI tables.
+    ///
+    /// Only filled if
+    /// [`BootloaderConfig::acpi_table_cache`](crate::BootloaderConfig::acpi_table_cache) is
+    /// enabled. Contains the FADT, MADT, MCFG, and HPET tables, in this order, if they are
+    /// present and have a valid checksum. Absent tables are omitted.
+    pub acpi_tables: AcpiTables,
+    /// The physical location and the layout of the framebuffer.
+    ///
+    /// Reported independently of whether the framebuffer is mapped in the kernel address
+    /// space (see
+    /// [`BootloaderConfig::map_framebuffer_for_kernel`](crate::BootloaderConfig::map_framebuffer_for_kernel)).
+    /// `None` if there is no framebuffer.
+    pub framebuffer_phys: Optional<PhysicalFrameBuffer>,
+    /// The IOMMU description table, if the ACPI tables describe an IOMMU.
+    ///
+    /// The bootloader doesn't configure the IOMMU, it only reports the table, so that the
+    /// kernel can choose its DMA strategy early. `None` if there is no DMAR or IVRS table with
+    /// a valid checksum.
+    pub iommu: Optional<IommuInfo>,
+    /// The flattened device tree blob, if one was loaded.
+    ///
+    /// The bootloader loads the `device-tree.dtb` file from the boot partition (or the TFTP
+    /// server) like the ramdisk, without parsing it. `None` if there is no such file.
+    pub device_tree: Optional<DeviceTree>,
+    /// The physically contiguous memory region for early DMA buffers.
+    ///
+    /// Only set if a region was requested through
+    /// [`BootloaderConfig::dma_region_size`](crate::BootloaderConfig::dma_region_size). `None`
+    /// if no region was requested or if no suitable memory was found.
+    pub dma_region: Optional<DmaRegion>,
+    /// The trampoline page for switching address spaces.
+    ///
+    /// Only set if requested through
+    /// [`BootloaderConfig::address_switch_trampoline`](crate::BootloaderConfig::address_switch_trampoline).
+    pub trampoline: Optional<Trampoline>,
+    /// Information about the memory encryption support of the CPU.
+    ///
+    /// `None` if the CPU supports neither AMD SME nor Intel TME.
+    pub memory_encryption: Optional<MemoryEncryption>,
+    /// The value of the `FS` base register when the kernel is started.
+    ///
+    /// Only set if configured through
+    /// [`BootloaderConfig::fs_base`](crate::BootloaderConfig::fs_base).
+    pub fs_base: Optional<u64>,
+    /// The value of the `GS` base register when the kernel is started.
+    ///
+    /// Only set if configured through
+    /// [`BootloaderConfig::gs_base`](crate::BootloaderConfig::gs_base).
+    pub gs_base: Optional<u64>,
+    /// The key-value config blob for the kernel, if one was loaded.
+    ///
+    /// The bootloader loads the `boot-config.bin` file from the boot partition (or the TFTP
+    /// server) like the device tree. The blob can be parsed with
+    /// [`config_blob::entries`](crate::config_blob::entries). `None` if there is no such file.
+    pub boot_config_blob: Optional<ConfigBlob>,
+    /// The UEFI handles that the kernel needs to exit the boot services itself.
+    ///
+    /// Only set if the exit was deferred through
+    /// [`BootloaderConfig::defer_exit_boot_services`](crate::BootloaderConfig::defer_exit_boot_services),
+    /// which describes the constraints that the kernel must follow.
+    pub deferred_exit: Optional<DeferredExit>,
+    /// The lowest virtual address of the loaded kernel segments.
+    ///
+    /// For position independent kernels, this is the base address that the bootloader chose,
+    /// which is randomized if [`aslr`](crate::config::Mappings::aslr) is enabled.
+    pub kernel_virtual_base: u64,
+    /// The offset between the virtual addresses in the kernel ELF file and the addresses the
+    /// kernel was loaded at.
+    ///
+    /// Subtract this value from a runtime address to get the address of the corresponding ELF
+    /// symbol. Zero for non-PIE kernels, which are loaded at their linked addresses.
+    pub kernel_load_bias: u64,
+    /// The memory regions that the bootloader used and that the kernel must not overwrite.
+    ///
+    /// Lists the [`Bootloader`](MemoryRegionKind::Bootloader) and
+    /// [`BootloaderReclaimable`](MemoryRegionKind::BootloaderReclaimable) regions of
+    /// [`memory_regions`](Self::memory_regions), which include the page tables, the boot info,
+    /// the kernel image, the kernel stack, and the ramdisk. Touching regions of the same kind
+    /// are merged, so this is a compact alternative to scanning the full memory map. The
+    /// regions are sorted by their start address.
+    pub bootloader_reserved: MemoryRegions,
+    /// The virtual address of the identity mapping of the framebuffer.
+    ///
+    /// This is the physical start address of the framebuffer, which is mapped in addition to
+    /// the [`framebuffer`](Self::framebuffer) mapping. Both aliases map the same frames with the
+    /// same cache attributes. Only set if the
+    /// [`identity_map_framebuffer`](crate::BootloaderConfig::identity_map_framebuffer) config
+    /// option is enabled.
+    pub framebuffer_identity_addr: Optional<u64>,
+    /// The kernel-chosen magic value from the
+    /// [`handoff_magic`](crate::BootloaderConfig::handoff_magic) config option.
+    ///
+    /// The kernel should compare this against the value it configured to detect a corrupted
+    /// handoff or a mismatched bootloader build. `None` if the option is not set.
+    pub handoff_magic: Optional<u64>,
+    /// Whether supervisor-mode execution prevention is enabled, i.e. whether `CR4.SMEP` is set.
+    ///
+    /// Set if the `smep` config option is enabled and the CPU supports SMEP.
+    pub smep_enabled: bool,
+    /// Whether supervisor-mode access prevention is enabled, i.e. whether `CR4.SMAP` is set.
+    ///
+    /// If `true`, the kernel must set `RFLAGS.AC` (e.g. using `stac`) before accessing
+    /// user-accessible pages. Set if the `smap` config option is enabled and the CPU supports
+    /// SMAP.
+    pub smap_enabled: bool,
 
     #[doc(hidden)]
     pub _test_sentinel: u64,
@@ -85,9 +300,117 @@ impl BootInfo {
             kernel_addr: 0,
             kernel_len: 0,
             kernel_image_offset: 0,
+            gdt: Optional::None,
+            coarse_memory_regions: Optional::None,
+            page_tables_read_only: false,
+            available_video_modes: VideoModes::new(),
+            dropped_memory_regions: 0,
+            pcid_enabled: false,
+            bsp_apic_id: Optional::None,
+            lapic_base: Optional::None,
+            x2apic_enabled: false,
+            boot_log: Optional::None,
+            boot_time: Optional::None,
+            ap_trampoline_region: Optional::None,
+            global_pages_enabled: false,
+            unmapped_usable: (&mut [][..]).into(),
+            hpet: Optional::None,
+            vbe_info: Optional::None,
+            boot_events: (&mut [][..]).into(),
+            acpi_tables: AcpiTables::new(),
+            framebuffer_phys: Optional::None,
+            iommu: Optional::None,
+            device_tree: Optional::None,
+            dma_region: Optional::None,
+            trampoline: Optional::None,
+            memory_encryption: Optional::None,
+            fs_base: Optional::None,
+            gs_base: Optional::None,
+            boot_config_blob: Optional::None,
+            deferred_exit: Optional::None,
+            kernel_virtual_base: 0,
+            kernel_load_bias: 0,
+            bootloader_reserved: (&mut [][..]).into(),
+            framebuffer_identity_addr: Optional::None,
+            handoff_magic: Optional::None,
+            smep_enabled: false,
+            smap_enabled: false,
             _test_sentinel: 0,
         }
     }
+
+    /// Returns the physical memory map, see [`memory_regions`](Self::memory_regions).
+    pub fn memory_regions(&self) -> &[MemoryRegion] {
+        &self.memory_regions
+    }
+
+    /// Returns the usable memory regions that are not covered by the physical memory mapping,
+    /// see [`unmapped_usable`](Self::unmapped_usable).
+    pub fn unmapped_usable(&self) -> &[MemoryRegion] {
+        &self.unmapped_usable
+    }
+
+    /// Returns the memory regions that the bootloader used, see
+    /// [`bootloader_reserved`](Self::bootloader_reserved).
+    pub fn bootloader_reserved(&self) -> &[MemoryRegion] {
+        &self.bootloader_reserved
+    }
+
+    /// Returns the framebuffer for screen output, if available.
+    pub fn framebuffer(&self) -> Option<&FrameBuffer> {
+        self.framebuffer.as_ref()
+    }
+
+    /// Returns a mutable reference to the framebuffer for screen output, if available.
+    pub fn framebuffer_mut(&mut self) -> Option<&mut FrameBuffer> {
+        self.framebuffer.as_mut()
+    }
+
+    /// Returns the virtual address at which the physical memory is mapped, see
+    /// [`physical_memory_offset`](Self::physical_memory_offset).
+    pub fn physical_memory_offset(&self) -> Option<u64> {
+        self.physical_memory_offset.into_option()
+    }
+
+    /// Returns the level 4 page table index of the recursive mapping, see
+    /// [`recursive_index`](Self::recursive_index).
+    pub fn recursive_index(&self) -> Option<u16> {
+        self.recursive_index.into_option()
+    }
+
+    /// Returns the physical address of the ACPI `RSDP` structure, see
+    /// [`rsdp_addr`](Self::rsdp_addr).
+    pub fn rsdp_address(&self) -> Option<u64> {
+        self.rsdp_addr.into_option()
+    }
+
+    /// Returns the thread local storage (TLS) template of the kernel executable, if present.
+    pub fn tls_template(&self) -> Option<TlsTemplate> {
+        self.tls_template.into_option()
+    }
+
+    /// Returns the virtual memory range of the ramdisk as a `(start address, length)` pair,
+    /// if a ramdisk was loaded.
+    pub fn ramdisk(&self) -> Option<(u64, u64)> {
+        self.ramdisk_addr
+            .into_option()
+            .map(|addr| (addr, self.ramdisk_len))
+    }
+
+    /// Returns the GDT and TSS set up for the bootstrap processor, see [`gdt`](Self::gdt).
+    pub fn gdt(&self) -> Option<GdtInfo> {
+        self.gdt.into_option()
+    }
+
+    /// Returns the log messages of the bootloader, if available.
+    pub fn boot_log(&self) -> Option<&BootLog> {
+        self.boot_log.as_ref()
+    }
+
+    /// Returns the wall-clock time at boot, see [`boot_time`](Self::boot_time).
+    pub fn boot_time(&self) -> Option<BootTime> {
+        self.boot_time.into_option()
+    }
 }
 
 /// FFI-safe slice of [`MemoryRegion`] structs, semantically equivalent to
@@ -144,6 +467,16 @@ pub struct MemoryRegion {
     ///
     /// Only [`Usable`][MemoryRegionKind::Usable] regions can be freely used.
     pub kind: MemoryRegionKind,
+    /// Additional properties of the memory region, e.g. its NUMA proximity domain.
+    pub attributes: MemoryRegionAttributes,
+    /// Whether the whole region is accessible through the physical memory mapping of the
+    /// kernel page table when the kernel is started.
+    ///
+    /// Always `false` if the [`physical_memory`](crate::config::Mappings::physical_memory)
+    /// mapping is disabled. If the mapping is limited through
+    /// [`identity_map_limit`](crate::config::Mappings::identity_map_limit), only the regions
+    /// below the limit are mapped.
+    pub mapped: bool,
 }
 
 impl MemoryRegion {
@@ -153,10 +486,49 @@ impl MemoryRegion {
             start: 0,
             end: 0,
             kind: MemoryRegionKind::Bootloader,
+            attributes: MemoryRegionAttributes::empty(),
+            mapped: false,
         }
     }
 }
 
+/// Additional properties of a memory region.
+///
+/// The properties are taken from the extended attributes of the E820 memory map on BIOS, the
+/// memory attributes of the UEFI memory map, and the ACPI _System Resource Affinity Table_
+/// (SRAT), if available.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct MemoryRegionAttributes {
+    /// A combination of the [`NON_VOLATILE`](Self::NON_VOLATILE) and
+    /// [`HOT_PLUGGABLE`](Self::HOT_PLUGGABLE) flags.
+    pub flags: u32,
+    /// The NUMA proximity domain of the region, as reported by the SRAT.
+    ///
+    /// Only set if the region lies completely within one memory range of the SRAT.
+    pub proximity_domain: Optional<u32>,
+}
+
+impl MemoryRegionAttributes {
+    /// The region is non-volatile memory, e.g. persistent memory.
+    pub const NON_VOLATILE: u32 = 1 << 0;
+    /// The region may be hot-removed at runtime.
+    pub const HOT_PLUGGABLE: u32 = 1 << 1;
+
+    /// Creates attributes without any flags and proximity domain.
+    pub const fn empty() -> Self {
+        Self {
+            flags: 0,
+            proximity_domain: Optional::None,
+        }
+    }
+
+    /// Returns whether all of the given flags are set.
+    pub const fn contains(&self, flags: u32) -> bool {
+        self.flags & flags == flags
+    }
+}
+
 /// Represents the different types of memory.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[non_exhaustive]
@@ -173,7 +545,33 @@ pub enum MemoryRegionKind {
     /// Contains the UEFI memory type tag.
     UnknownUefi(u32),
     /// An unknown memory region reported by the BIOS firmware.
+    ///
+    /// Contains the raw E820 memory type, e.g. `2` for reserved memory or vendor-specific
+    /// types such as `12` for persistent memory on some firmwares.
     UnknownBios(u32),
+    /// Memory used by the bootloader that the kernel may reclaim.
+    ///
+    /// Like [`Bootloader`][MemoryRegionKind::Bootloader], this includes the page tables,
+    /// the boot info, and the kernel stack, so the kernel must not use this memory before it
+    /// switched away from all bootloader-created structures. Only reported if the
+    /// [`bootloader_regions_usable`](crate::BootloaderConfig::bootloader_regions_usable)
+    /// config option is enabled.
+    BootloaderReclaimable,
+    /// Memory that is not usable by the kernel.
+    ///
+    /// Used in the [`coarse_memory_regions`](BootInfo::coarse_memory_regions), where it
+    /// combines all non-usable regions of the detailed memory map. In the detailed memory map,
+    /// it is only used for the null frame if the
+    /// [`null_frame`](crate::BootloaderConfig::null_frame) config option is set to
+    /// [`NullFrame::Reserved`](crate::config::NullFrame::Reserved).
+    Reserved,
+    /// Unused conventional memory above the
+    /// [`max_usable_physical_address`](crate::BootloaderConfig::max_usable_physical_address)
+    /// limit.
+    ///
+    /// The kernel can use this memory once it is able to address it. It is not reported as
+    /// usable in the [`coarse_memory_regions`](BootInfo::coarse_memory_regions).
+    HighMemory,
 }
 
 /// A pixel-based framebuffer that controls the screen output.
@@ -227,6 +625,16 @@ impl FrameBuffer {
     }
 }
 
+/// The physical location of a framebuffer, which is not necessarily mapped.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct PhysicalFrameBuffer {
+    /// The physical start address of the framebuffer.
+    pub addr: u64,
+    /// The layout and pixel format of the framebuffer.
+    pub info: FrameBufferInfo,
+}
+
 /// Describes the layout and pixel format of a framebuffer.
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -243,10 +651,26 @@ pub struct FrameBufferInfo {
     pub bytes_per_pixel: usize,
     /// Number of pixels between the start of a line and the start of the next.
     ///
-    /// Some framebuffers use additional padding at the end of a line, so this
-    /// value might be larger than `horizontal_resolution`. It is
-    /// therefore recommended to use this field for calculating the start address of a line.
+    /// This value is in pixels, not in bytes. Some framebuffers use additional padding at the
+    /// end of a line, so this value might be larger than [`width`](Self::width). It is
+    /// therefore recommended to use this field (or
+    /// [`bytes_per_scanline`](Self::bytes_per_scanline)) for calculating the start address of a
+    /// line.
     pub stride: usize,
+    /// The VESA BIOS Extensions (VBE) mode number of the framebuffer.
+    ///
+    /// Only set when booting through BIOS. Useful for debugging display issues.
+    pub vbe_mode: Optional<u16>,
+}
+
+impl FrameBufferInfo {
+    /// Returns the number of bytes between the start of a line and the start of the next.
+    ///
+    /// The byte offset of the pixel at `(x, y)` is
+    /// `y * bytes_per_scanline() + x * bytes_per_pixel`.
+    pub const fn bytes_per_scanline(&self) -> usize {
+        self.stride * self.bytes_per_pixel
+    }
 }
 
 /// Color format of pixels in the framebuffer.
@@ -280,6 +704,473 @@ pub enum PixelFormat {
     },
 }
 
+/// The log messages that the bootloader printed during boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootLog {
+    /// The virtual start address of the log text.
+    ///
+    /// The log is UTF-8 text with one message per line. It is stored in a memory region that
+    /// is reported as bootloader memory in the memory map.
+    pub addr: u64,
+    /// The length of the log text in bytes.
+    pub len: u64,
+    /// Whether older messages were dropped because the log buffer overflowed.
+    pub truncated: bool,
+}
+
+impl BootLog {
+    /// Returns the log text.
+    ///
+    /// ## Safety
+    ///
+    /// The memory region of the boot log must still be mapped and must not have been
+    /// reused for other data.
+    pub unsafe fn as_str(&self) -> &str {
+        let bytes = unsafe { slice::from_raw_parts(self.addr as *const u8, self.len as usize) };
+        core::str::from_utf8(bytes).unwrap_or_default()
+    }
+}
+
+/// The location of the high precision event timer (HPET) registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct HpetInfo {
+    /// The physical base address of the memory-mapped HPET registers.
+    pub base: u64,
+    /// The minimum number of clock ticks that can be set without losing interrupts in
+    /// periodic mode.
+    pub minimum_tick: u16,
+}
+
+/// The ACPI table that describes the IOMMU of the system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct IommuInfo {
+    /// The kind of the IOMMU.
+    pub kind: IommuKind,
+    /// The physical address of the DMAR or IVRS table.
+    pub table_addr: u64,
+}
+
+/// The kind of an IOMMU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[repr(C)]
+pub enum IommuKind {
+    /// An Intel VT-d IOMMU, described by the DMAR table.
+    IntelVtd,
+    /// An AMD-Vi IOMMU, described by the IVRS table.
+    AmdVi,
+}
+
+/// The location of a flattened device tree blob in memory.
+///
+/// The blob is stored in memory marked as bootloader memory in the memory map and it is
+/// mapped read-only into the kernel address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct DeviceTree {
+    /// The physical start address of the blob.
+    pub phys_addr: u64,
+    /// The virtual start address of the blob in the kernel address space.
+    pub addr: u64,
+    /// The size of the blob in bytes.
+    pub len: u64,
+}
+
+/// The location of the key-value config blob in memory.
+///
+/// The blob is stored in memory marked as bootloader memory in the memory map and it is
+/// mapped read-only into the kernel address space. See the [`config_blob`](crate::config_blob)
+/// module for the encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct ConfigBlob {
+    /// The virtual start address of the blob in the kernel address space.
+    pub addr: u64,
+    /// The size of the blob in bytes.
+    pub len: u64,
+}
+
+/// The UEFI handles needed to call `ExitBootServices` from the kernel.
+///
+/// The boot services are still active when the kernel is started. The kernel must call
+/// `GetMemoryMap` and then `ExitBootServices` with the returned map key through the boot
+/// services table of the system table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct DeferredExit {
+    /// The physical address of the UEFI system table.
+    pub system_table: u64,
+    /// The image handle of the bootloader, which has to be passed to `ExitBootServices`.
+    pub image_handle: u64,
+}
+
+/// A physically contiguous memory region that the bootloader allocated for DMA buffers.
+///
+/// The region is zeroed, marked as bootloader memory in the memory map, and mapped writable
+/// into the kernel address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct DmaRegion {
+    /// The physical start address of the region.
+    pub phys_addr: u64,
+    /// The virtual start address of the region in the kernel address space.
+    pub addr: u64,
+    /// The size of the region in bytes, a multiple of the page size.
+    pub len: u64,
+}
+
+/// A page with a stub for switching to a new address space.
+///
+/// The stub follows the System V calling convention: it loads its first argument into `CR3`,
+/// then jumps to its second argument, passing its third argument as first argument. So it can
+/// be called as `extern "sysv64" fn(level_4_table: u64, target: u64, arg: u64) -> !`. The stub
+/// doesn't touch the stack.
+///
+/// The page is mapped at its identity address and at [`addr`](Self::addr) in the kernel
+/// address space. To switch to a new address space, the new page tables must map the page at
+/// the address that the stub is called through, so that execution continues after loading
+/// `CR3`. The page is marked as bootloader memory in the memory map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Trampoline {
+    /// The physical address of the page, which is also its identity-mapped virtual address.
+    pub phys_addr: u64,
+    /// The additional virtual address of the page in the kernel address space.
+    pub addr: u64,
+}
+
+/// Information about the memory encryption support of the CPU.
+///
+/// The bootloader never sets the C-bit in the page tables that it creates, so all mappings
+/// that the kernel inherits from the bootloader access memory unencrypted, even if
+/// [`enabled`](Self::enabled) is `true`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct MemoryEncryption {
+    /// The kind of the memory encryption.
+    pub kind: MemoryEncryptionKind,
+    /// Whether memory encryption is enabled by the firmware.
+    ///
+    /// For AMD SME, this means that pages can be encrypted by setting the C-bit. For Intel
+    /// TME, all memory is encrypted transparently.
+    pub enabled: bool,
+    /// The bit position of the C-bit in page table entries.
+    ///
+    /// Only set for AMD SME.
+    pub c_bit_position: Optional<u8>,
+    /// The number of bits that the physical address space is reduced by when memory
+    /// encryption is enabled.
+    pub physical_address_reduction: u8,
+}
+
+/// The kind of memory encryption supported by the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[repr(C)]
+pub enum MemoryEncryptionKind {
+    /// AMD Secure Memory Encryption, controlled per page through the C-bit.
+    AmdSme,
+    /// Intel Total Memory Encryption, which encrypts all memory transparently.
+    IntelTme,
+}
+
+/// A calendar date and time of day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootTime {
+    /// The full year, e.g. `2024`.
+    pub year: u16,
+    /// The month, from 1 to 12.
+    pub month: u8,
+    /// The day of the month, from 1 to 31.
+    pub day: u8,
+    /// The hour, from 0 to 23.
+    pub hour: u8,
+    /// The minute, from 0 to 59.
+    pub minute: u8,
+    /// The second, from 0 to 59.
+    pub second: u8,
+}
+
+/// The raw information blocks of the VESA BIOS Extensions (VBE).
+///
+/// The blocks are copied as returned by the VBE BIOS, see the VBE 3.0 specification for
+/// their layout. Pointers contained in the blocks are real-mode `segment:offset` pointers that
+/// might point to firmware memory or into the `controller_info` block itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct VbeInfo {
+    /// The `VbeInfoBlock` returned by VBE function `0x4f00`, e.g. containing the OEM strings,
+    /// the capabilities, and the total video memory size.
+    pub controller_info: [u8; 512],
+    /// The `ModeInfoBlock` of the selected video mode, as returned by VBE function `0x4f01`.
+    pub mode_info: [u8; 256],
+}
+
+/// FFI-safe slice of [`BootEvent`] structs, semantically equivalent to
+/// `&'static mut [BootEvent]`.
+///
+/// This type implements the [`Deref`][core::ops::Deref] trait, so it can be used like a
+/// `&[BootEvent]` slice. It also implements [`From`] for easy conversions from
+/// `&'static mut [BootEvent]`.
+#[derive(Debug)]
+#[repr(C)]
+pub struct BootEvents {
+    ptr: *mut BootEvent,
+    len: usize,
+}
+
+impl BootEvents {
+    /// The maximum number of events that the bootloader records.
+    pub const CAPACITY: usize = 64;
+}
+
+impl ops::Deref for BootEvents {
+    type Target = [BootEvent];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl From<&'static mut [BootEvent]> for BootEvents {
+    fn from(events: &'static mut [BootEvent]) -> Self {
+        BootEvents {
+            ptr: events.as_mut_ptr(),
+            len: events.len(),
+        }
+    }
+}
+
+/// An event of the boot timeline.
+///
+/// All events have the same size, so that new kinds of events can be added without changing
+/// the layout of the timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootEvent {
+    /// Identifies what happened.
+    pub id: BootEventId,
+    /// The value of the time stamp counter when the event was recorded.
+    ///
+    /// Zero if the CPU has no time stamp counter.
+    pub tsc: u64,
+    /// Additional data whose meaning depends on the event, zero if unused.
+    pub payload: u64,
+}
+
+/// Identifies a [`BootEvent`].
+///
+/// Unknown identifiers should be ignored, as newer bootloader versions might add more events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct BootEventId(pub u32);
+
+impl BootEventId {
+    /// The stage of the bootloader that loads the kernel was entered.
+    pub const BOOTLOADER_START: Self = Self(1);
+    /// The kernel setup started. The payload is the size of the kernel file in bytes.
+    pub const KERNEL_SETUP_START: Self = Self(2);
+    /// The kernel ELF file was loaded and all kernel mappings were created.
+    pub const MAPPINGS_CREATED: Self = Self(3);
+    /// The boot info was created, this is the last event before the kernel is started. The
+    /// payload is the number of memory regions.
+    pub const BOOT_INFO_CREATED: Self = Self(4);
+}
+
+/// A video mode with a linear framebuffer that is supported by the graphics hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct VideoMode {
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// The number of bits per pixel.
+    pub bits_per_pixel: u8,
+    /// The firmware-specific number of the mode.
+    ///
+    /// This is the VBE mode number when booting through BIOS and the Graphics Output Protocol
+    /// mode index when booting through UEFI.
+    pub mode_number: u32,
+}
+
+/// FFI-safe list of [`VideoMode`] structs with a fixed capacity.
+///
+/// This type implements the [`Deref`][core::ops::Deref] trait, so it can be used like a
+/// `&[VideoMode]` slice.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct VideoModes {
+    modes: [VideoMode; Self::CAPACITY],
+    len: usize,
+}
+
+impl VideoModes {
+    /// The maximum number of video modes in the list.
+    pub const CAPACITY: usize = 32;
+
+    /// Creates an empty list.
+    pub const fn new() -> Self {
+        const EMPTY: VideoMode = VideoMode {
+            width: 0,
+            height: 0,
+            bits_per_pixel: 0,
+            mode_number: 0,
+        };
+        Self {
+            modes: [EMPTY; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Appends the given mode to the list.
+    ///
+    /// Returns `false` if the list is full, in which case the mode is not added.
+    pub fn push(&mut self, mode: VideoMode) -> bool {
+        match self.modes.get_mut(self.len) {
+            Some(slot) => {
+                *slot = mode;
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for VideoModes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ops::Deref for VideoModes {
+    type Target = [VideoMode];
+
+    fn deref(&self) -> &Self::Target {
+        &self.modes[..self.len]
+    }
+}
+
+/// The location of an ACPI system description table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct AcpiTable {
+    /// The signature of the table, e.g. `*b"APIC"` for the MADT.
+    pub signature: [u8; 4],
+    /// The length of the table in bytes, including the header.
+    pub len: u32,
+    /// The physical start address of the table.
+    pub addr: u64,
+}
+
+/// FFI-safe list of [`AcpiTable`] structs with a fixed capacity.
+///
+/// This type implements the [`Deref`][core::ops::Deref] trait, so it can be used like a
+/// `&[AcpiTable]` slice.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct AcpiTables {
+    tables: [AcpiTable; Self::CAPACITY],
+    len: usize,
+}
+
+impl AcpiTables {
+    /// The maximum number of tables in the list.
+    pub const CAPACITY: usize = 8;
+
+    /// Creates an empty list.
+    pub const fn new() -> Self {
+        const EMPTY: AcpiTable = AcpiTable {
+            signature: [0; 4],
+            len: 0,
+            addr: 0,
+        };
+        Self {
+            tables: [EMPTY; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Appends the given table to the list.
+    ///
+    /// Returns `false` if the list is full, in which case the table is not added.
+    pub fn push(&mut self, table: AcpiTable) -> bool {
+        match self.tables.get_mut(self.len) {
+            Some(slot) => {
+                *slot = table;
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the table with the given signature, if present.
+    pub fn find(&self, signature: [u8; 4]) -> Option<&AcpiTable> {
+        self.iter().find(|table| table.signature == signature)
+    }
+}
+
+impl Default for AcpiTables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ops::Deref for AcpiTables {
+    type Target = [AcpiTable];
+
+    fn deref(&self) -> &Self::Target {
+        &self.tables[..self.len]
+    }
+}
+
+/// Describes the GDT and TSS that the bootloader set up for the bootstrap processor.
+///
+/// The GDT and TSS are loaded when the kernel is started. All addresses are virtual addresses
+/// in the kernel address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct GdtInfo {
+    /// The start address of the memory region that contains the GDT, the TSS, and the
+    /// interrupt stacks.
+    pub region_start: u64,
+    /// The size of the memory region in bytes.
+    pub region_len: u64,
+    /// The address of the GDT.
+    pub gdt_addr: u64,
+    /// The size of the used part of the GDT in bytes, i.e. the GDT limit plus one.
+    pub gdt_len: u16,
+    /// The segment selector of the kernel code segment.
+    pub code_selector: u16,
+    /// The segment selector of the kernel data segment.
+    pub data_selector: u16,
+    /// The segment selector of the TSS.
+    pub tss_selector: u16,
+    /// The address of the TSS.
+    ///
+    /// Each entry of the interrupt stack table points to the top of a mapped stack of
+    /// [`ist_stack_size`](Self::ist_stack_size) bytes. Each stack has an unmapped guard page
+    /// below it.
+    pub tss_addr: u64,
+    /// The size of each interrupt stack in bytes.
+    pub ist_stack_size: u64,
+    /// The interrupt stack table index that is reserved for the double fault handler.
+    ///
+    /// The corresponding stack is not used for anything else, so it is safe to switch to it
+    /// even if the kernel stack overflowed. Pass this index to the `set_stack_index` method
+    /// of the double fault IDT entry.
+    pub double_fault_ist_index: u16,
+    /// The top address of the double fault stack, i.e. the value of the interrupt stack table
+    /// entry at [`double_fault_ist_index`](Self::double_fault_ist_index).
+    pub double_fault_stack_top: u64,
+}
+
 /// Information about the thread local storage (TLS) template.
 ///
 /// This template can be used to set up thread local storage for threads. For
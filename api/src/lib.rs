@@ -9,6 +9,8 @@ pub use self::{config::BootloaderConfig, info::BootInfo};
 
 /// Allows to configure the system environment set up by the bootloader.
 pub mod config;
+/// Encoding of the key-value config blob that the bootloader passes to the kernel.
+pub mod config_blob;
 /// Contains the boot information struct sent by the bootloader to the kernel on startup.
 pub mod info;
 
@@ -106,6 +108,11 @@ mod version_info {
 ///   `#[link_section = ".bootloader-config"]`, which instructs the Rust compiler to store it
 ///   in a special section of the resulting ELF executable. From there, the bootloader will
 ///   automatically read it when loading the kernel.
+/// - **Entry state:** The bootloader jumps to `_start` with interrupts disabled and with the
+///   stack set up as if `_start` was invoked by a `call` instruction, as required by the
+///   System V ABI: `RSP + 8` is 16-byte aligned and the return address at `[RSP]` is zero, so
+///   stack traces terminate there. `RBP` is zero and `RDI` contains the boot info pointer.
+///   The kernel must not return from `_start`.
 #[cfg(target_arch = "x86_64")]
 #[macro_export]
 macro_rules! entry_point {
@@ -5,6 +5,19 @@
 # This stage initializes the stack, enables the A20 line
 
 _start:
+    # When loaded as a PXE network bootstrap program, es:bx points to the `PXENV+`
+    # structure. Store its linear address in ecx (or zero if not booted through PXE).
+    xor ecx, ecx
+    cmp dword ptr es:[bx], 0x4e455850   # "PXEN"
+    jne zero_segments
+    cmp word ptr es:[bx + 4], 0x2b56    # "V+"
+    jne zero_segments
+    mov cx, es
+    shl ecx, 4
+    movzx ebx, bx
+    add ecx, ebx
+
+zero_segments:
     # zero segment registers
     xor ax, ax
     mov ds, ax
@@ -30,6 +43,10 @@ enable_a20:
     out 0x92, al
 enable_a20_after:
 
+    # the second stage was already loaded by the PXE ROM
+    test ecx, ecx
+    jnz pxe
+
 check_int13h_extensions:
     push 'y'    # error code
     mov ah, 0x41
@@ -48,3 +65,8 @@ spin:
     hlt
     jmp spin
 
+pxe:
+    push ecx    # address of PXENV+ structure
+    call pxe_stage
+    jmp spin
+
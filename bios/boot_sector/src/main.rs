@@ -60,12 +60,32 @@ pub extern "C" fn first_stage(disk_number: u16) {
     }
 
     // jump to second stage
-    let second_stage_entry_point: extern "C" fn(
-        disk_number: u16,
-        partition_table_start: *const u8,
-    ) = unsafe { core::mem::transmute(entry_point_address as *const ()) };
     let partition_table_start = unsafe { partition_table_raw() };
-    second_stage_entry_point(disk_number, partition_table_start);
+    second_stage_entry_point()(disk_number, partition_table_start);
 
     fail::fail(b'R');
 }
+
+/// Disk number that signals the second stage that we were booted through PXE.
+///
+/// Must match the `PXE_DISK_NUMBER` constant of the second stage.
+const PXE_DISK_NUMBER: u16 = 0xffff;
+
+/// Entry point when booted as a PXE network bootstrap program.
+///
+/// The PXE ROM loads the whole network bootstrap program, so the second stage is already
+/// in memory directly after the boot sector.
+#[no_mangle]
+pub extern "C" fn pxe_stage(pxenv_addr: u32) {
+    second_stage_entry_point()(PXE_DISK_NUMBER, pxenv_addr as *const u8);
+
+    fail::fail(b'P');
+}
+
+/// Returns the entry point of the second stage.
+///
+/// The second argument is the start of the partition table, or the address of the `PXENV+`
+/// structure if the disk number is [`PXE_DISK_NUMBER`].
+fn second_stage_entry_point() -> extern "C" fn(disk_number: u16, partition_table_start: *const u8) {
+    unsafe { core::mem::transmute(second_stage_start()) }
+}
@@ -0,0 +1,269 @@
+//! Minimal read-only support for the ISO9660 filesystem.
+//!
+//! Only the parts that are needed to locate files on a boot medium are implemented: the
+//! volume descriptors and the directory records. Extended attributes, interleaved files, and
+//! the Joliet and Rock Ridge extensions are not supported.
+
+/// The size of a logical sector (and logical block) of an ISO9660 filesystem.
+pub const SECTOR_SIZE: usize = 2048;
+
+/// The first logical sector of the volume descriptor set. The sectors before it form the
+/// system area, which is not used by the filesystem.
+pub const VOLUME_DESCRIPTORS_START: u32 = 16;
+
+/// Upper bound for the number of volume descriptors that are inspected, to avoid scanning
+/// the whole medium if the terminator is missing.
+const MAX_VOLUME_DESCRIPTORS: u32 = 32;
+
+const STANDARD_IDENTIFIER: &[u8] = b"CD001";
+
+/// Provides access to the logical sectors of an ISO9660 medium.
+pub trait SectorRead {
+    /// Reads the logical sector with the given number and returns its [`SECTOR_SIZE`] bytes.
+    fn read_sector(&mut self, lba: u32) -> Result<&[u8], &'static str>;
+}
+
+/// The information from the volume descriptor set that is needed to access the files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Volume {
+    /// The directory record of the root directory, from the primary volume descriptor.
+    pub root: DirectoryRecord,
+}
+
+impl Volume {
+    /// Reads the volume descriptor set, starting at [`VOLUME_DESCRIPTORS_START`].
+    ///
+    /// Returns an error if the medium does not contain an ISO9660 filesystem.
+    pub fn read(reader: &mut impl SectorRead) -> Result<Self, &'static str> {
+        let mut root = None;
+        for lba in VOLUME_DESCRIPTORS_START..VOLUME_DESCRIPTORS_START + MAX_VOLUME_DESCRIPTORS {
+            let sector = reader.read_sector(lba)?;
+            if sector.len() < SECTOR_SIZE || &sector[1..6] != STANDARD_IDENTIFIER {
+                return Err("no ISO9660 volume descriptor found");
+            }
+            match sector[0] {
+                // primary volume descriptor
+                1 if root.is_none() => {
+                    if read_u16(sector, 128) != SECTOR_SIZE as u16 {
+                        return Err("unsupported ISO9660 logical block size");
+                    }
+                    let (record, _) = DirectoryRecord::parse(&sector[156..190])
+                        .ok_or("invalid ISO9660 root directory record")?;
+                    root = Some(record);
+                }
+                // volume descriptor set terminator
+                255 => break,
+                _ => {}
+            }
+        }
+        Ok(Self {
+            root: root.ok_or("no ISO9660 primary volume descriptor found")?,
+        })
+    }
+
+    /// Looks up the file or directory with the given path, starting at the root directory.
+    ///
+    /// The path components are separated by `/`. Names are compared case-insensitively and
+    /// without the `;1` version suffix, so that `kernel-x86_64` matches `KERNEL-X86_64.;1`.
+    pub fn find(
+        &self,
+        reader: &mut impl SectorRead,
+        path: &str,
+    ) -> Result<Option<DirectoryRecord>, &'static str> {
+        let mut current = self.root;
+        for component in path.as_bytes().split(|&b| b == b'/') {
+            if component.is_empty() {
+                continue;
+            }
+            if !current.is_dir() {
+                return Ok(None);
+            }
+            match current.find_entry(reader, component)? {
+                Some(entry) => current = entry,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(current))
+    }
+}
+
+/// A directory record, which describes a file or a directory.
+///
+/// The data of a file is stored contiguously, starting at [`extent_lba`](Self::extent_lba).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirectoryRecord {
+    /// The first logical sector of the file data.
+    pub extent_lba: u32,
+    /// The length of the file data in bytes.
+    pub data_len: u32,
+    /// The file flags of the record.
+    pub flags: u8,
+}
+
+impl DirectoryRecord {
+    const FLAG_DIRECTORY: u8 = 1 << 1;
+
+    /// Parses the directory record at the start of `bytes` and returns it together with its
+    /// file identifier.
+    ///
+    /// Returns `None` if `bytes` does not start with a complete directory record. The
+    /// identifiers `\0` and `\x01` denote the current and the parent directory.
+    pub fn parse(bytes: &[u8]) -> Option<(Self, &[u8])> {
+        let len = usize::from(*bytes.first()?);
+        if len < 34 || len > bytes.len() {
+            return None;
+        }
+        let name_len = usize::from(bytes[32]);
+        let name = bytes[..len].get(33..33 + name_len)?;
+        let record = Self {
+            extent_lba: read_u32(bytes, 2),
+            data_len: read_u32(bytes, 10),
+            flags: bytes[25],
+        };
+        Some((record, name))
+    }
+
+    /// Returns whether the record describes a directory.
+    pub fn is_dir(&self) -> bool {
+        self.flags & Self::FLAG_DIRECTORY != 0
+    }
+
+    /// The byte offset of the file data on the medium.
+    pub fn start_offset(&self) -> u64 {
+        u64::from(self.extent_lba) * SECTOR_SIZE as u64
+    }
+
+    /// Looks up the entry with the given name in this directory.
+    ///
+    /// The name is compared like in [`Volume::find`].
+    pub fn find_entry(
+        &self,
+        reader: &mut impl SectorRead,
+        name: &[u8],
+    ) -> Result<Option<DirectoryRecord>, &'static str> {
+        let sectors = self.data_len.div_ceil(SECTOR_SIZE as u32);
+        let end = self
+            .extent_lba
+            .checked_add(sectors)
+            .ok_or("ISO9660 directory extent out of range")?;
+        for lba in self.extent_lba..end {
+            let mut sector = reader.read_sector(lba)?;
+            // directory records never cross sector boundaries; the rest of a sector is
+            // padded with zeros
+            while let Some((record, record_name)) = DirectoryRecord::parse(sector) {
+                if name_matches(record_name, name) {
+                    return Ok(Some(record));
+                }
+                sector = &sector[usize::from(sector[0])..];
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Compares an ISO9660 file identifier with a file name, ignoring case, the `;` version
+/// suffix, and a trailing `.` of names without extension.
+fn name_matches(identifier: &[u8], name: &[u8]) -> bool {
+    let identifier = match identifier.iter().position(|&b| b == b';') {
+        Some(idx) => &identifier[..idx],
+        None => identifier,
+    };
+    let identifier = identifier.strip_suffix(b".").unwrap_or(identifier);
+    identifier.eq_ignore_ascii_case(name)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(*bytes[offset..].first_chunk().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(*bytes[offset..].first_chunk().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Image(Vec<u8>);
+
+    impl SectorRead for Image {
+        fn read_sector(&mut self, lba: u32) -> Result<&[u8], &'static str> {
+            let start = lba as usize * SECTOR_SIZE;
+            self.0
+                .get(start..start + SECTOR_SIZE)
+                .ok_or("sector out of range")
+        }
+    }
+
+    impl Image {
+        fn sector(&mut self, lba: u32) -> &mut [u8] {
+            let start = lba as usize * SECTOR_SIZE;
+            &mut self.0[start..start + SECTOR_SIZE]
+        }
+
+        fn write_record(&mut self, lba: u32, offset: usize, record: DirectoryRecord, name: &[u8]) {
+            let len = 33 + name.len() + (name.len() + 1) % 2;
+            let bytes = &mut self.sector(lba)[offset..offset + len];
+            bytes[0] = len as u8;
+            bytes[2..6].copy_from_slice(&record.extent_lba.to_le_bytes());
+            bytes[10..14].copy_from_slice(&record.data_len.to_le_bytes());
+            bytes[25] = record.flags;
+            bytes[32] = name.len() as u8;
+            bytes[33..33 + name.len()].copy_from_slice(name);
+        }
+    }
+
+    #[test]
+    fn find_files() {
+        let dir = |extent_lba| DirectoryRecord {
+            extent_lba,
+            data_len: SECTOR_SIZE as u32,
+            flags: DirectoryRecord::FLAG_DIRECTORY,
+        };
+        let file = |extent_lba, data_len| DirectoryRecord {
+            extent_lba,
+            data_len,
+            flags: 0,
+        };
+        let mut image = Image(vec![0; 24 * SECTOR_SIZE]);
+
+        // boot record (skipped), primary volume descriptor, and terminator
+        let boot_record = image.sector(16);
+        boot_record[1..6].copy_from_slice(STANDARD_IDENTIFIER);
+        let primary = image.sector(17);
+        primary[0] = 1;
+        primary[1..6].copy_from_slice(STANDARD_IDENTIFIER);
+        primary[128..130].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        image.write_record(17, 156, dir(19), b"\0");
+        let terminator = image.sector(18);
+        terminator[0] = 255;
+        terminator[1..6].copy_from_slice(STANDARD_IDENTIFIER);
+
+        // root directory with a subdirectory and a file
+        image.write_record(19, 0, dir(19), b"\0");
+        image.write_record(19, 34, dir(19), b"\x01");
+        image.write_record(19, 68, dir(21), b"BOOT");
+        image.write_record(19, 106, file(22, 5000), b"RAMDISK.;1");
+        image.write_record(21, 0, file(23, 12), b"KERNEL-X86_64.;1");
+
+        let volume = Volume::read(&mut image).unwrap();
+        assert_eq!(volume.root, dir(19));
+
+        assert_eq!(
+            volume.find(&mut image, "boot/kernel-x86_64").unwrap(),
+            Some(file(23, 12))
+        );
+        assert_eq!(
+            volume.find(&mut image, "/ramdisk").unwrap(),
+            Some(file(22, 5000))
+        );
+        assert_eq!(volume.find(&mut image, "kernel-x86_64").unwrap(), None);
+        assert_eq!(volume.find(&mut image, "ramdisk/boot").unwrap(), None);
+        // a directory extent that exceeds the sector numbers is rejected
+        let overflowing = DirectoryRecord {
+            extent_lba: u32::MAX,
+            ..dir(0)
+        };
+        assert!(overflowing.find_entry(&mut image, b"BOOT").is_err());
+    }
+}
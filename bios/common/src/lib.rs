@@ -1,5 +1,6 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
+pub mod iso9660;
 pub mod racy_cell;
 
 #[cfg_attr(feature = "debug", derive(Debug))]
@@ -9,10 +10,17 @@ pub struct BiosInfo {
     pub kernel: Region,
     pub ramdisk: Region,
     pub config_file: Region,
+    pub device_tree: Region,
+    pub boot_config_blob: Region,
+    /// The raw VBE controller information block, followed by the raw mode information block
+    /// of the selected mode.
+    pub vbe_info: Region,
     pub last_used_addr: u64,
     pub framebuffer: BiosFramebufferInfo,
     pub memory_map_addr: u32,
     pub memory_map_len: u16,
+    pub video_modes: BiosVideoModes,
+    pub legacy_memory_sizes: LegacyMemorySizes,
 }
 
 #[cfg_attr(feature = "debug", derive(Debug))]
@@ -23,8 +31,58 @@ pub struct BiosFramebufferInfo {
     pub width: u16,
     pub height: u16,
     pub bytes_per_pixel: u8,
+    /// Number of pixels (not bytes) between the start of a line and the start of the next.
     pub stride: u16,
     pub pixel_format: PixelFormat,
+    pub vbe_mode: u16,
+}
+
+/// The linear framebuffer VBE modes supported by the graphics card.
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct BiosVideoModes {
+    pub modes: [BiosVideoMode; Self::CAPACITY],
+    pub len: u8,
+}
+
+impl BiosVideoModes {
+    pub const CAPACITY: usize = 32;
+
+    pub const fn empty() -> Self {
+        const EMPTY: BiosVideoMode = BiosVideoMode {
+            width: 0,
+            height: 0,
+            bits_per_pixel: 0,
+            mode: 0,
+        };
+        Self {
+            modes: [EMPTY; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Adds the given mode to the list. Does nothing if the list is full.
+    pub fn push(&mut self, mode: BiosVideoMode) {
+        if let Some(slot) = self.modes.get_mut(usize::from(self.len)) {
+            *slot = mode;
+            self.len += 1;
+        }
+    }
+
+    pub fn as_slice(&self) -> &[BiosVideoMode] {
+        &self.modes[..usize::from(self.len)]
+    }
+}
+
+#[cfg_attr(feature = "debug", derive(Debug))]
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct BiosVideoMode {
+    pub width: u16,
+    pub height: u16,
+    pub bits_per_pixel: u8,
+    pub mode: u16,
 }
 
 #[cfg_attr(feature = "debug", derive(Debug))]
@@ -57,6 +115,23 @@ impl PixelFormat {
     }
 }
 
+/// Translates the pixel format detected by stage 2 to the format reported to the kernel.
+pub fn translate_pixel_format(format: PixelFormat) -> bootloader_api::info::PixelFormat {
+    match format {
+        PixelFormat::Rgb => bootloader_api::info::PixelFormat::Rgb,
+        PixelFormat::Bgr => bootloader_api::info::PixelFormat::Bgr,
+        PixelFormat::Unknown {
+            red_position,
+            green_position,
+            blue_position,
+        } => bootloader_api::info::PixelFormat::Unknown {
+            red_position,
+            green_position,
+            blue_position,
+        },
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct E820MemoryRegion {
@@ -66,6 +141,274 @@ pub struct E820MemoryRegion {
     pub acpi_extended_attributes: u32,
 }
 
+/// The memory sizes reported by the legacy BIOS memory detection functions.
+///
+/// All fields are zero if the corresponding function is not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct LegacyMemorySizes {
+    /// Conventional memory starting at address zero in KiB, as reported by `INT 0x12`.
+    pub conventional_kib: u16,
+    /// Extended memory between 1 MiB and 16 MiB in KiB, as reported by `INT 0x15, AX=0xE801`.
+    pub extended_kib: u16,
+    /// Extended memory above 16 MiB in 64 KiB blocks, as reported by `INT 0x15, AX=0xE801`.
+    pub extended_64k_blocks: u16,
+}
+
+impl LegacyMemorySizes {
+    /// Returns the usable memory ranges reported by the legacy functions as
+    /// `(start, end)` pairs with an exclusive end. Empty ranges are skipped.
+    pub fn usable_ranges(&self) -> impl Iterator<Item = (u64, u64)> {
+        const KIB: u64 = 1024;
+        const MIB: u64 = 1024 * KIB;
+        [
+            (0, u64::from(self.conventional_kib) * KIB),
+            (MIB, MIB + u64::from(self.extended_kib) * KIB),
+            (
+                16 * MIB,
+                16 * MIB + u64::from(self.extended_64k_blocks) * 64 * KIB,
+            ),
+        ]
+        .into_iter()
+        .filter(|(start, end)| start < end)
+    }
+}
+
+/// Backfills the E820 memory map with the usable memory reported by the legacy BIOS functions.
+///
+/// The reconciliation follows these rules:
+///
+/// - All E820 regions are kept unchanged, so E820 takes precedence over the legacy functions.
+/// - `INT 0x12` reports usable memory starting at address zero, and `INT 0x15, AX=0xE801`
+///   reports usable memory starting at 1 MiB and at 16 MiB (see [`LegacyMemorySizes`]).
+/// - The parts of these legacy ranges that are not covered by any E820 region, regardless of
+///   its type, are added as usable regions (type 1).
+/// - The result is sorted by start address.
+///
+/// The result is written to `buffer`, which must be large enough for the E820 regions and the
+/// added regions. Panics if it is too small.
+pub fn reconcile_memory_maps<'a>(
+    e820: &[E820MemoryRegion],
+    legacy: LegacyMemorySizes,
+    buffer: &'a mut [E820MemoryRegion],
+) -> &'a mut [E820MemoryRegion] {
+    let mut len = e820.len();
+    buffer
+        .get_mut(..len)
+        .expect("too many memory regions after reconciling the memory maps")
+        .copy_from_slice(e820);
+    buffer[..len].sort_unstable_by_key(|r| r.start_addr);
+
+    // the legacy ranges are only compared against the E820 regions, not against each other
+    let e820_len = len;
+    for (start, end) in legacy.usable_ranges() {
+        let mut cursor = start;
+        for i in 0..e820_len {
+            // copied, because `push_usable` writes to the buffer
+            let region = buffer[i];
+            let region_end = region.start_addr + region.len;
+            if region_end <= cursor {
+                continue;
+            }
+            if region.start_addr >= end {
+                break;
+            }
+            if region.start_addr > cursor {
+                push_usable(buffer, &mut len, cursor, region.start_addr);
+            }
+            cursor = cursor.max(region_end);
+        }
+        if cursor < end {
+            push_usable(buffer, &mut len, cursor, end);
+        }
+    }
+
+    let regions = &mut buffer[..len];
+    regions.sort_unstable_by_key(|r| r.start_addr);
+    regions
+}
+
+fn push_usable(buffer: &mut [E820MemoryRegion], len: &mut usize, start: u64, end: u64) {
+    let slot = buffer
+        .get_mut(*len)
+        .expect("too many memory regions after reconciling the memory maps");
+    *slot = E820MemoryRegion {
+        start_addr: start,
+        len: end - start,
+        region_type: 1,
+        acpi_extended_attributes: 0,
+    };
+    *len += 1;
+}
+
+/// Resolves overlapping regions of the E820 memory map like Linux's `e820__update_table`.
+///
+/// The map is split at every region boundary. Each resulting range gets the highest E820
+/// type of all regions that cover it, so reserved memory takes precedence over overlapping
+/// usable memory. Ranges that aren't covered by any region are dropped, and touching ranges
+/// with equal type and extended attributes are merged. The result is written to `buffer`
+/// and is sorted and free of overlaps.
+pub fn sanitize_like_linux<'a>(
+    memory_map: &[E820MemoryRegion],
+    buffer: &'a mut [E820MemoryRegion],
+) -> &'a mut [E820MemoryRegion] {
+    let end = |region: &E820MemoryRegion| region.start_addr.saturating_add(region.len);
+    let regions = || memory_map.iter().filter(|r| r.len != 0);
+
+    let mut len: usize = 0;
+    let mut addr = match regions().map(|r| r.start_addr).min() {
+        Some(start) => start,
+        None => return &mut buffer[..0],
+    };
+    while let Some(next) = regions()
+        .flat_map(|r| [r.start_addr, end(r)])
+        .filter(|&boundary| boundary > addr)
+        .min()
+    {
+        let winner = regions()
+            .filter(|r| r.start_addr <= addr && end(r) >= next)
+            .max_by_key(|r| r.region_type);
+        if let Some(winner) = winner {
+            match len.checked_sub(1).map(|i| &mut buffer[i]) {
+                Some(last)
+                    if last.region_type == winner.region_type
+                        && last.acpi_extended_attributes == winner.acpi_extended_attributes
+                        && end(last) == addr =>
+                {
+                    last.len += next - addr;
+                }
+                _ => {
+                    *buffer
+                        .get_mut(len)
+                        .expect("too many memory regions after sanitizing the memory map") =
+                        E820MemoryRegion {
+                            start_addr: addr,
+                            len: next - addr,
+                            ..*winner
+                        };
+                    len += 1;
+                }
+            }
+        }
+        addr = next;
+    }
+    &mut buffer[..len]
+}
+
+/// The number of gigabytes of physical memory that stage 3 identity-maps using huge pages.
+///
+/// Stage 4 identity-maps the remaining physical memory above this boundary.
+pub const IDENTITY_MAPPED_GIGABYTES: usize = 10;
+/// The end address of the physical memory that is identity-mapped by stage 3.
+pub const IDENTITY_MAPPED_END: u64 = IDENTITY_MAPPED_GIGABYTES as u64 * 1024 * 1024 * 1024;
+
 pub fn hlt() {
     unsafe { core::arch::asm!("hlt") };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_format_translation() {
+        use bootloader_api::info::PixelFormat as ApiPixelFormat;
+
+        assert_eq!(
+            translate_pixel_format(PixelFormat::Rgb),
+            ApiPixelFormat::Rgb
+        );
+        assert_eq!(
+            translate_pixel_format(PixelFormat::Bgr),
+            ApiPixelFormat::Bgr
+        );
+        assert_eq!(
+            translate_pixel_format(PixelFormat::Unknown {
+                red_position: 16,
+                green_position: 8,
+                blue_position: 24,
+            }),
+            ApiPixelFormat::Unknown {
+                red_position: 16,
+                green_position: 8,
+                blue_position: 24,
+            }
+        );
+    }
+
+    #[test]
+    fn legacy_memory_backfills_e820() {
+        let region = |start_addr, len, region_type| E820MemoryRegion {
+            start_addr,
+            len,
+            region_type,
+            acpi_extended_attributes: 0,
+        };
+        // the E820 map misses the memory between 8 MiB and 16 MiB and above 20 MiB
+        let e820 = [
+            region(0x10_0000, 0x70_0000, 1),
+            region(0x0, 0x9_f000, 1),
+            region(0x9_f000, 0x1000, 2),
+            region(0x100_0000, 0x40_0000, 1),
+        ];
+        let legacy = LegacyMemorySizes {
+            conventional_kib: 639,
+            extended_kib: 15 * 1024,
+            extended_64k_blocks: 16 * 16,
+        };
+        let mut buffer = [region(0, 0, 0); 8];
+
+        let reconciled = reconcile_memory_maps(&e820, legacy, &mut buffer);
+        assert_eq!(
+            reconciled,
+            [
+                region(0x0, 0x9_f000, 1),
+                region(0x9_f000, 0x1000, 2),
+                region(0x10_0000, 0x70_0000, 1),
+                region(0x80_0000, 0x80_0000, 1),
+                region(0x100_0000, 0x40_0000, 1),
+                region(0x140_0000, 0xc0_0000, 1),
+            ]
+        );
+
+        // without legacy information, the E820 map is only sorted
+        let reconciled = reconcile_memory_maps(&e820, LegacyMemorySizes::default(), &mut buffer);
+        assert_eq!(reconciled.len(), e820.len());
+        assert!(reconciled
+            .windows(2)
+            .all(|w| w[0].start_addr < w[1].start_addr));
+    }
+
+    #[test]
+    fn sanitize_prefers_reserved_over_usable() {
+        let region = |start_addr, len, region_type| E820MemoryRegion {
+            start_addr,
+            len,
+            region_type,
+            acpi_extended_attributes: 0,
+        };
+        // a reserved region in the middle of a usable region, followed by touching usable
+        // regions and a reserved region that overlaps the end of the last usable region
+        let memory_map = [
+            region(0x0, 0x10_0000, 1),
+            region(0x8_0000, 0x2_0000, 2),
+            region(0x10_0000, 0x10_0000, 1),
+            region(0x1f_0000, 0x2_0000, 2),
+            region(0x30_0000, 0, 1),
+        ];
+        let mut buffer = [region(0, 0, 0); 8];
+
+        let sanitized = sanitize_like_linux(&memory_map, &mut buffer);
+        assert_eq!(
+            sanitized,
+            [
+                region(0x0, 0x8_0000, 1),
+                region(0x8_0000, 0x2_0000, 2),
+                region(0xa_0000, 0x15_0000, 1),
+                region(0x1f_0000, 0x2_0000, 2),
+            ]
+        );
+
+        assert!(sanitize_like_linux(&[], &mut buffer).is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+//! Fallback disk access using cylinder/head/sector (CHS) addressing.
+//!
+//! Only used if the BIOS does not support the INT 13h extensions for the boot disk.
+
+use core::arch::asm;
+
+/// The geometry of a disk, as reported by `INT 13h, AH=08h`.
+#[derive(Debug, Clone, Copy)]
+pub struct ChsGeometry {
+    cylinders: u32,
+    heads: u32,
+    sectors_per_track: u32,
+}
+
+impl ChsGeometry {
+    /// Queries the geometry of the given disk from the BIOS.
+    pub fn query(disk_number: u16) -> Option<Self> {
+        let carry: u8;
+        let cx: u16;
+        let dx: u16;
+        unsafe {
+            asm!(
+                "push bx",
+                "push es",
+                // di is zero
+                "mov es, di",
+                "int 0x13",
+                "setc {carry}",
+                "pop es",
+                "pop bx",
+                carry = out(reg_byte) carry,
+                inout("ax") 0x0800u16 => _,
+                inout("dx") disk_number => dx,
+                out("cx") cx,
+                // some BIOSes require es:di to be 0:0 to work around bugs
+                inout("di") 0u16 => _,
+            )
+        };
+        if carry != 0 {
+            return None;
+        }
+
+        let sectors_per_track = u32::from(cx & 0x3f);
+        let max_cylinder = u32::from(cx >> 8) | (u32::from(cx & 0xc0) << 2);
+        let max_head = u32::from(dx >> 8);
+        if sectors_per_track == 0 {
+            return None;
+        }
+
+        Some(Self {
+            cylinders: max_cylinder + 1,
+            heads: max_head + 1,
+            sectors_per_track,
+        })
+    }
+
+    /// Returns the number of sectors between `lba` and the end of its track.
+    pub fn sectors_left_in_track(&self, lba: u64) -> u64 {
+        let sectors_per_track = u64::from(self.sectors_per_track);
+        sectors_per_track - (lba % sectors_per_track)
+    }
+
+    /// Reads `number_of_sectors` sectors starting at `start_lba` into the given buffer.
+    ///
+    /// The sectors must not cross a track boundary (see [`Self::sectors_left_in_track`]).
+    /// Returns the status code of the BIOS on failure.
+    pub unsafe fn read(
+        &self,
+        disk_number: u16,
+        start_lba: u64,
+        number_of_sectors: u8,
+        target_addr: u16,
+        target_addr_segment: u16,
+    ) -> Result<(), u8> {
+        let sectors_per_track = u64::from(self.sectors_per_track);
+        let heads = u64::from(self.heads);
+
+        let sector = (start_lba % sectors_per_track) + 1;
+        let head = (start_lba / sectors_per_track) % heads;
+        let cylinder = start_lba / (sectors_per_track * heads);
+        if cylinder >= u64::from(self.cylinders) {
+            // LBA is not addressable using CHS
+            crate::fail(b'C');
+        }
+
+        let ax = 0x0200 | u16::from(number_of_sectors);
+        let cx = ((cylinder as u16 & 0xff) << 8) | ((cylinder as u16 >> 2) & 0xc0) | sector as u16;
+        let dx = ((head as u16) << 8) | (disk_number & 0xff);
+
+        let carry: u8;
+        let status: u16;
+        unsafe {
+            asm!(
+                "push bx",
+                "push es",
+                "mov es, {segment:x}",
+                "mov bx, {offset:x}",
+                "int 0x13",
+                "setc {carry}",
+                "pop es",
+                "pop bx",
+                segment = in(reg) target_addr_segment,
+                offset = in(reg) target_addr,
+                carry = out(reg_byte) carry,
+                inout("ax") ax => status,
+                in("cx") cx,
+                in("dx") dx,
+            )
+        };
+        match carry {
+            0 => Ok(()),
+            _ => Err((status >> 8) as u8),
+        }
+    }
+}
@@ -35,20 +35,66 @@ impl DiskAddressPacket {
         }
     }
 
-    pub unsafe fn perform_load(&self, disk_number: u16) {
+    /// Loads the sectors described by this packet.
+    ///
+    /// Returns the status code of the BIOS on failure.
+    pub unsafe fn perform_load(&self, disk_number: u16) -> Result<(), u8> {
         let self_addr = self as *const Self as u16;
+        let carry: u8;
+        let status: u16;
         asm!(
-            "push 0x7a", // error code `z`, passed to `fail` on error
             "mov {1:x}, si",
             "mov si, {0:x}",
             "int 0x13",
-            "jc fail",
-            "pop si", // remove error code again
+            "setc {2}",
             "mov si, {1:x}",
             in(reg) self_addr,
             out(reg) _,
-            in("ax") 0x4200u16,
+            out(reg_byte) carry,
+            inout("ax") 0x4200u16 => status,
             in("dx") disk_number,
         );
+        match carry {
+            0 => Ok(()),
+            _ => Err((status >> 8) as u8),
+        }
     }
 }
+
+/// Resets the disk controller of the given disk using `INT 13h, AH=00h`.
+pub fn reset_disk(disk_number: u16) {
+    unsafe {
+        asm!(
+            "int 0x13",
+            inout("ax") 0u16 => _,
+            in("dx") disk_number,
+        )
+    };
+}
+
+/// Checks whether the BIOS supports the INT 13h extensions for the given disk.
+///
+/// Uses `INT 13h, AH=41h`. The extensions are required for reading from the disk
+/// through a [`DiskAddressPacket`].
+pub fn extensions_supported(disk_number: u16) -> bool {
+    let carry: u8;
+    let magic: u16;
+    let support_bits: u16;
+    unsafe {
+        asm!(
+            "push bx",
+            "mov bx, 0x55aa",
+            "int 0x13",
+            "setc {carry}",
+            "mov {magic:x}, bx",
+            "pop bx",
+            carry = out(reg_byte) carry,
+            magic = out(reg) magic,
+            inout("ax") 0x4100u16 => _,
+            inout("dx") disk_number => _,
+            out("cx") support_bits,
+        )
+    };
+    // bit 0 of `cx` signals support for the disk address packet functions
+    carry == 0 && magic == 0xaa55 && support_bits & 1 != 0
+}
@@ -1,10 +1,40 @@
-use crate::dap;
+use crate::{chs::ChsGeometry, dap, screen};
+use core::fmt::Write as _;
 
 #[derive(Clone)]
 pub struct DiskAccess {
     pub disk_number: u16,
     pub base_offset: u64,
     pub current_offset: u64,
+    pub addressing: Addressing,
+    /// How often a failed read is attempted before giving up.
+    ///
+    /// The disk controller is reset between the attempts.
+    pub read_attempts: u8,
+}
+
+/// The method used to address sectors on the disk.
+#[derive(Debug, Clone, Copy)]
+pub enum Addressing {
+    /// Logical block addressing through the INT 13h extensions.
+    Lba,
+    /// Cylinder/head/sector addressing for BIOSes without INT 13h extensions.
+    Chs(ChsGeometry),
+}
+
+impl Addressing {
+    /// Uses LBA if the INT 13h extensions are supported for the given disk, and falls
+    /// back to CHS addressing otherwise.
+    pub fn detect(disk_number: u16) -> Self {
+        if dap::extensions_supported(disk_number) {
+            Addressing::Lba
+        } else {
+            match ChsGeometry::query(disk_number) {
+                Some(geometry) => Addressing::Chs(geometry),
+                None => crate::fail(b'G'),
+            }
+        }
+    }
 }
 
 impl Read for DiskAccess {
@@ -34,15 +64,36 @@ impl Read for DiskAccess {
         let mut target_addr = buf.as_ptr_range().start as u32;
 
         loop {
-            let sectors = u64::min(number_of_sectors, 32) as u16;
-            let dap = dap::DiskAddressPacket::from_lba(
-                start_lba,
-                sectors,
-                (target_addr & 0b1111) as u16,
-                (target_addr >> 4).try_into().unwrap(),
-            );
-            unsafe {
-                dap.perform_load(self.disk_number);
+            let sectors = match self.addressing {
+                Addressing::Lba => u64::min(number_of_sectors, 32) as u16,
+                Addressing::Chs(geometry) => u64::min(
+                    number_of_sectors,
+                    u64::min(geometry.sectors_left_in_track(start_lba), 32),
+                ) as u16,
+            };
+            let offset = (target_addr & 0b1111) as u16;
+            let segment = (target_addr >> 4).try_into().unwrap();
+            let mut attempt = 1;
+            while let Err(status) = match self.addressing {
+                Addressing::Lba => {
+                    let dap = dap::DiskAddressPacket::from_lba(start_lba, sectors, offset, segment);
+                    unsafe { dap.perform_load(self.disk_number) }
+                }
+                Addressing::Chs(geometry) => unsafe {
+                    geometry.read(self.disk_number, start_lba, sectors as u8, offset, segment)
+                },
+            } {
+                if attempt >= self.read_attempts {
+                    panic!("disk read failed after {attempt} attempts (status {status:#x})");
+                }
+                writeln!(
+                    screen::Writer,
+                    "disk read error {status:#x}, retrying ({attempt}/{})",
+                    self.read_attempts
+                )
+                .unwrap();
+                dap::reset_disk(self.disk_number);
+                attempt += 1;
             }
 
             start_lba += u64::from(sectors);
@@ -0,0 +1,23 @@
+use crate::disk::{AlignedArrayBuffer, DiskAccess, Read, Seek, SeekFrom};
+use bootloader_x86_64_bios_common::iso9660::{SectorRead, SECTOR_SIZE};
+
+/// Reads the logical ISO9660 sectors of a disk through the disk buffer.
+pub struct IsoReader<'a> {
+    disk: &'a mut DiskAccess,
+    buffer: &'a mut AlignedArrayBuffer<16384>,
+}
+
+impl<'a> IsoReader<'a> {
+    pub fn new(disk: &'a mut DiskAccess, buffer: &'a mut AlignedArrayBuffer<16384>) -> Self {
+        Self { disk, buffer }
+    }
+}
+
+impl SectorRead for IsoReader<'_> {
+    fn read_sector(&mut self, lba: u32) -> Result<&[u8], &'static str> {
+        self.disk
+            .seek(SeekFrom::Start(u64::from(lba) * SECTOR_SIZE as u64));
+        self.disk.read_exact_into(SECTOR_SIZE, self.buffer);
+        Ok(&self.buffer.buffer[..SECTOR_SIZE])
+    }
+}
@@ -7,23 +7,58 @@ use crate::{
         copy_to_protected_mode, enter_protected_mode_and_jump_to_stage_3, enter_unreal_mode,
     },
 };
-use bootloader_x86_64_bios_common::{hlt, BiosFramebufferInfo, BiosInfo, Region};
+use bootloader_x86_64_bios_common::{
+    hlt, iso9660, BiosFramebufferInfo, BiosInfo, BiosVideoModes, Region,
+};
 use byteorder::{ByteOrder, LittleEndian};
 use core::{fmt::Write as _, slice};
 use disk::AlignedArrayBuffer;
 use mbr_nostd::{PartitionTableEntry, PartitionType};
 
+mod chs;
 mod dap;
 mod disk;
 mod fat;
+mod iso;
 mod memory_map;
 mod protected_mode;
+mod pxe;
 mod screen;
 mod vesa;
 
+/// The disk number that the boot sector passes when started through PXE. Must match the
+/// `PXE_DISK_NUMBER` constant of the boot sector.
+const PXE_DISK_NUMBER: u16 = 0xffff;
+
 /// We use this partition type to store the second bootloader stage;
 const BOOTLOADER_SECOND_STAGE_PARTITION_TYPE: u8 = 0x20;
 
+/// How often a failed disk read is attempted before the boot is aborted.
+///
+/// Set at build time through the `BOOTLOADER_DISK_READ_ATTEMPTS` environment variable, which
+/// must be a decimal number between 1 and 255. Defaults to 5.
+const DISK_READ_ATTEMPTS: u8 = match option_env!("BOOTLOADER_DISK_READ_ATTEMPTS") {
+    Some(attempts) => parse_disk_read_attempts(attempts),
+    None => 5,
+};
+
+const fn parse_disk_read_attempts(attempts: &str) -> u8 {
+    let bytes = attempts.as_bytes();
+    let mut value: u32 = 0;
+    let mut i = 0;
+    while i < bytes.len() && value <= 255 {
+        if !bytes[i].is_ascii_digit() {
+            panic!("`BOOTLOADER_DISK_READ_ATTEMPTS` must be a number between 1 and 255");
+        }
+        value = value * 10 + (bytes[i] - b'0') as u32;
+        i += 1;
+    }
+    if value == 0 || value > 255 {
+        panic!("`BOOTLOADER_DISK_READ_ATTEMPTS` must be a number between 1 and 255");
+    }
+    value as u8
+}
+
 // 1MiB (typically 14MiB accessible here)
 const STAGE_3_DST: *mut u8 = 0x0010_0000 as *mut u8;
 // must match the start address in bios/stage-4/stage-4-link.ld
@@ -47,66 +82,34 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
 
     screen::Writer.write_str(" -> SECOND STAGE\n").unwrap();
 
-    // parse partition table
-    let partitions = {
-        const MAX_ENTRIES: usize = 4;
-        const ENTRY_SIZE: usize = 16;
-
-        let mut entries = [PartitionTableEntry::empty(); MAX_ENTRIES];
-        let raw = unsafe { slice::from_raw_parts(partition_table_start, ENTRY_SIZE * MAX_ENTRIES) };
-        for (idx, entry) in entries.iter_mut().enumerate() {
-            let offset = idx * ENTRY_SIZE;
-            let partition_type = PartitionType::from_mbr_tag_byte(raw[offset + 4]);
-            let lba = LittleEndian::read_u32(&raw[offset + 8..]);
-            let len = LittleEndian::read_u32(&raw[offset + 12..]);
-            *entry = PartitionTableEntry::new(partition_type, lba, len);
-        }
-        entries
-    };
-    // look for second stage partition
-    let second_stage_partition_idx = partitions
-        .iter()
-        .enumerate()
-        .find(|(_, e)| {
-            e.partition_type == PartitionType::Unknown(BOOTLOADER_SECOND_STAGE_PARTITION_TYPE)
-        })
-        .unwrap()
-        .0;
-    let fat_partition = partitions.get(second_stage_partition_idx + 1).unwrap();
-    assert!(matches!(
-        fat_partition.partition_type,
-        PartitionType::Fat12(_) | PartitionType::Fat16(_) | PartitionType::Fat32(_)
-    ));
+    let disk_buffer = unsafe { &mut DISK_BUFFER };
 
-    // load fat partition
-    let mut disk = disk::DiskAccess {
-        disk_number,
-        base_offset: u64::from(fat_partition.logical_block_address) * 512,
-        current_offset: 0,
+    let mut source = if disk_number == PXE_DISK_NUMBER {
+        // the boot sector passes the address of the `PXENV+` structure instead of the
+        // partition table
+        BootSource::Pxe(pxe::Pxe::new(partition_table_start))
+    } else {
+        open_boot_partition(disk_number, partition_table_start, disk_buffer)
     };
 
-    let mut fs = fat::FileSystem::parse(disk.clone());
-
-    let disk_buffer = unsafe { &mut DISK_BUFFER };
-
-    let stage_3_len = load_file("boot-stage-3", STAGE_3_DST, &mut fs, &mut disk, disk_buffer);
+    let stage_3_len = load_file("boot-stage-3", STAGE_3_DST, &mut source, disk_buffer);
     writeln!(screen::Writer, "stage 3 loaded at {STAGE_3_DST:#p}").unwrap();
     let stage_4_dst = {
         let stage_3_end = STAGE_3_DST.wrapping_add(usize::try_from(stage_3_len).unwrap());
         assert!(STAGE_4_DST > stage_3_end);
         STAGE_4_DST
     };
-    let stage_4_len = load_file("boot-stage-4", stage_4_dst, &mut fs, &mut disk, disk_buffer);
+    let stage_4_len = load_file("boot-stage-4", stage_4_dst, &mut source, disk_buffer);
     writeln!(screen::Writer, "stage 4 loaded at {stage_4_dst:#p}").unwrap();
 
     writeln!(screen::Writer, "loading kernel...").unwrap();
-    let kernel_len = load_file("kernel-x86_64", KERNEL_DST, &mut fs, &mut disk, disk_buffer);
+    let kernel_len = load_file("kernel-x86_64", KERNEL_DST, &mut source, disk_buffer);
     writeln!(screen::Writer, "kernel loaded at {KERNEL_DST:#p}").unwrap();
     let kernel_page_size = (((kernel_len - 1) / 4096) + 1) as usize;
     let ramdisk_start = KERNEL_DST.wrapping_add(kernel_page_size * 4096);
     writeln!(screen::Writer, "Loading ramdisk...").unwrap();
     let ramdisk_len =
-        try_load_file("ramdisk", ramdisk_start, &mut fs, &mut disk, disk_buffer).unwrap_or(0u64);
+        try_load_file("ramdisk", ramdisk_start, &mut source, disk_buffer).unwrap_or(0u64);
 
     if ramdisk_len == 0 {
         writeln!(screen::Writer, "No ramdisk found, skipping.").unwrap();
@@ -114,16 +117,40 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
         writeln!(screen::Writer, "Loaded ramdisk at {ramdisk_start:#p}").unwrap();
     }
     let config_file_start = ramdisk_start.wrapping_add(ramdisk_len.try_into().unwrap());
-    let config_file_len = try_load_file(
-        "boot.json",
-        config_file_start,
-        &mut fs,
-        &mut disk,
+    let config_file_len =
+        try_load_file("boot.json", config_file_start, &mut source, disk_buffer).unwrap_or(0);
+    let device_tree_start = config_file_start.wrapping_add(config_file_len.try_into().unwrap());
+    let device_tree_len = try_load_file(
+        "device-tree.dtb",
+        device_tree_start,
+        &mut source,
+        disk_buffer,
+    )
+    .unwrap_or(0);
+    let boot_config_blob_start =
+        device_tree_start.wrapping_add(device_tree_len.try_into().unwrap());
+    let boot_config_blob_len = try_load_file(
+        "boot-config.bin",
+        boot_config_blob_start,
+        &mut source,
         disk_buffer,
     )
     .unwrap_or(0);
 
-    let memory_map = unsafe { memory_map::query_memory_map() }.unwrap();
+    if let BootSource::Pxe(pxe) = &mut source {
+        // stop the network card before the memory map is queried, as it might still
+        // write received packets to memory otherwise
+        pxe.shutdown();
+    }
+
+    // stage 4 can backfill the memory map from the legacy sizes if E820 is not supported, so
+    // only fail if the legacy functions don't report any memory either
+    let legacy_memory_sizes = unsafe { memory_map::query_legacy_memory_sizes() };
+    let memory_map = match unsafe { memory_map::query_memory_map() } {
+        Ok(memory_map) => memory_map,
+        Err(()) if legacy_memory_sizes.usable_ranges().next().is_some() => Default::default(),
+        Err(()) => fail(b'M'),
+    };
     writeln!(screen::Writer, "{memory_map:x?}").unwrap();
 
     // TODO: load these from the kernel's config instead of hardcoding
@@ -131,18 +158,34 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
     let max_height = 720;
 
     let mut vesa_info = vesa::VesaInfo::query(disk_buffer).unwrap();
+    let mut video_modes = BiosVideoModes::empty();
     let vesa_mode = vesa_info
-        .get_best_mode(max_width, max_height)
+        .get_best_mode(max_width, max_height, &mut video_modes)
         .unwrap()
         .expect("no suitable VESA mode found");
     writeln!(
         screen::Writer,
-        "VESA MODE: {}x{}",
+        "VESA MODE: {}x{} (mode {:#x})",
         vesa_mode.width,
-        vesa_mode.height
+        vesa_mode.height,
+        vesa_mode.mode
     )
     .unwrap();
     vesa_mode.enable().unwrap();
+    // make sure that the monitor shows the framebuffer output; DPMS is optional, so
+    // ignore errors
+    let _ = vesa::wake_display();
+
+    // pass the raw VBE information blocks to the kernel
+    let vbe_info_start =
+        boot_config_blob_start.wrapping_add(boot_config_blob_len.try_into().unwrap());
+    let vbe_info_len = match vesa_info.raw_info_blocks(vesa_mode.mode) {
+        Ok(blocks) => {
+            unsafe { copy_to_protected_mode(vbe_info_start, blocks) };
+            blocks.len() as u64
+        }
+        Err(_) => 0,
+    };
 
     let mut info = BiosInfo {
         stage_4: Region {
@@ -161,7 +204,19 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
             start: config_file_start as u64,
             len: config_file_len,
         },
-        last_used_addr: config_file_start as u64 + config_file_len - 1,
+        device_tree: Region {
+            start: device_tree_start as u64,
+            len: device_tree_len,
+        },
+        boot_config_blob: Region {
+            start: boot_config_blob_start as u64,
+            len: boot_config_blob_len,
+        },
+        vbe_info: Region {
+            start: vbe_info_start as u64,
+            len: vbe_info_len,
+        },
+        last_used_addr: vbe_info_start as u64 + vbe_info_len - 1,
         memory_map_addr: memory_map.as_mut_ptr() as u32,
         memory_map_len: memory_map.len().try_into().unwrap(),
         framebuffer: BiosFramebufferInfo {
@@ -174,7 +229,10 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
             bytes_per_pixel: vesa_mode.bytes_per_pixel,
             stride: vesa_mode.bytes_per_scanline / u16::from(vesa_mode.bytes_per_pixel),
             pixel_format: vesa_mode.pixel_format,
+            vbe_mode: vesa_mode.mode,
         },
+        video_modes,
+        legacy_memory_sizes,
     };
 
     enter_protected_mode_and_jump_to_stage_3(STAGE_3_DST, &mut info);
@@ -184,14 +242,118 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
     }
 }
 
+/// The medium that the bootloader files are loaded from.
+enum BootSource {
+    /// The FAT partition of the boot disk.
+    Disk {
+        fs: fat::FileSystem<disk::DiskAccess>,
+        disk: disk::DiskAccess,
+    },
+    /// The ISO9660 filesystem of a hybrid ISO image, which spans the whole boot disk.
+    Iso {
+        volume: iso9660::Volume,
+        disk: disk::DiskAccess,
+    },
+    /// A TFTP server, when started as PXE network bootstrap program.
+    Pxe(pxe::Pxe),
+}
+
+/// Parses the partition table and opens the FAT partition that follows the second stage
+/// partition.
+///
+/// If there is no FAT partition after the second stage partition, the boot disk is opened as
+/// a hybrid ISO image instead.
+fn open_boot_partition(
+    disk_number: u16,
+    partition_table_start: *const u8,
+    disk_buffer: &mut AlignedArrayBuffer<16384>,
+) -> BootSource {
+    // parse partition table
+    let partitions = {
+        const MAX_ENTRIES: usize = 4;
+        const ENTRY_SIZE: usize = 16;
+
+        let mut entries = [PartitionTableEntry::empty(); MAX_ENTRIES];
+        let raw = unsafe { slice::from_raw_parts(partition_table_start, ENTRY_SIZE * MAX_ENTRIES) };
+        for (idx, entry) in entries.iter_mut().enumerate() {
+            let offset = idx * ENTRY_SIZE;
+            let partition_type = PartitionType::from_mbr_tag_byte(raw[offset + 4]);
+            let lba = LittleEndian::read_u32(&raw[offset + 8..]);
+            let len = LittleEndian::read_u32(&raw[offset + 12..]);
+            *entry = PartitionTableEntry::new(partition_type, lba, len);
+        }
+        entries
+    };
+    // look for second stage partition
+    let second_stage_partition_idx = partitions
+        .iter()
+        .enumerate()
+        .find(|(_, e)| {
+            e.partition_type == PartitionType::Unknown(BOOTLOADER_SECOND_STAGE_PARTITION_TYPE)
+        })
+        .unwrap()
+        .0;
+    let fat_partition = partitions.get(second_stage_partition_idx + 1).filter(|p| {
+        matches!(
+            p.partition_type,
+            PartitionType::Fat12(_) | PartitionType::Fat16(_) | PartitionType::Fat32(_)
+        )
+    });
+
+    let addressing = disk::Addressing::detect(disk_number);
+    if let disk::Addressing::Chs(geometry) = addressing {
+        writeln!(
+            screen::Writer,
+            "no INT 13h extensions, using CHS addressing ({geometry:?})"
+        )
+        .unwrap();
+    }
+
+    let mut disk = disk::DiskAccess {
+        disk_number,
+        base_offset: 0,
+        current_offset: 0,
+        addressing,
+        read_attempts: DISK_READ_ATTEMPTS,
+    };
+
+    let Some(fat_partition) = fat_partition else {
+        // `fail` instead of `expect`, as the `Debug` formatting of `str` is too large
+        let volume = iso9660::Volume::read(&mut iso::IsoReader::new(&mut disk, disk_buffer))
+            .unwrap_or_else(|_| fail(b'I'));
+        return BootSource::Iso { volume, disk };
+    };
+
+    // load fat partition
+    disk.base_offset = u64::from(fat_partition.logical_block_address) * 512;
+    let fs = fat::FileSystem::parse(disk.clone());
+    BootSource::Disk { fs, disk }
+}
+
+/// Loads the given file to `dst` and returns its size, or `None` if the file doesn't exist.
+///
+/// On disk, the file is read cluster by cluster following its FAT cluster chain, so it doesn't
+/// need to be stored contiguously. The clusters are assembled into a contiguous image at `dst`.
+/// On ISO9660 filesystems, the file name may be a path and the file is stored contiguously.
 fn try_load_file(
     file_name: &str,
     dst: *mut u8,
-    fs: &mut fat::FileSystem<disk::DiskAccess>,
-    disk: &mut disk::DiskAccess,
+    source: &mut BootSource,
     disk_buffer: &mut AlignedArrayBuffer<16384>,
 ) -> Option<u64> {
-    let disk_buffer_size = disk_buffer.buffer.len();
+    let (fs, disk) = match source {
+        BootSource::Disk { fs, disk } => (fs, disk),
+        BootSource::Iso { volume, disk } => {
+            let file = volume
+                .find(&mut iso::IsoReader::new(disk, disk_buffer), file_name)
+                .unwrap_or_else(|_| fail(b'I'))?;
+            let start = file.start_offset();
+            let file_size = u64::from(file.data_len);
+            load_range(disk, start, start + file_size, dst, disk_buffer);
+            return Some(file_size);
+        }
+        BootSource::Pxe(pxe) => return pxe.try_load_file(file_name, dst, disk_buffer),
+    };
     let file = fs.find_file_in_root_dir(file_name, disk_buffer)?;
 
     let file_size = file.file_size().into();
@@ -202,42 +364,57 @@ fn try_load_file(
         let cluster_start = cluster.start_offset;
         let cluster_end = cluster_start + u64::from(cluster.len_bytes);
 
-        let mut offset = 0;
-        loop {
-            let range_start = cluster_start + offset;
-            if range_start >= cluster_end {
-                break;
-            }
-            let range_end = u64::min(
-                range_start + u64::try_from(disk_buffer_size).unwrap(),
-                cluster_end,
-            );
-            let len = range_end - range_start;
-
-            disk.seek(SeekFrom::Start(range_start));
-            disk.read_exact_into(disk_buffer_size, disk_buffer);
-
-            let slice = &disk_buffer.buffer[..usize::try_from(len).unwrap()];
-            unsafe { copy_to_protected_mode(dst.wrapping_add(total_offset), slice) };
-            let written =
-                unsafe { protected_mode::read_from_protected_mode(dst.wrapping_add(total_offset)) };
-            assert_eq!(slice[0], written);
-
-            offset += len;
-            total_offset += usize::try_from(len).unwrap();
-        }
+        load_range(
+            disk,
+            cluster_start,
+            cluster_end,
+            dst.wrapping_add(total_offset),
+            disk_buffer,
+        );
+        total_offset += usize::try_from(cluster.len_bytes).unwrap();
     }
     Some(file_size)
 }
 
+/// Copies the disk bytes from `start` to `end` (exclusive) to `dst`.
+fn load_range(
+    disk: &mut disk::DiskAccess,
+    start: u64,
+    end: u64,
+    dst: *mut u8,
+    disk_buffer: &mut AlignedArrayBuffer<16384>,
+) {
+    let disk_buffer_size = disk_buffer.buffer.len();
+
+    let mut total_offset = 0;
+    loop {
+        let range_start = start + u64::try_from(total_offset).unwrap();
+        if range_start >= end {
+            break;
+        }
+        let range_end = u64::min(range_start + u64::try_from(disk_buffer_size).unwrap(), end);
+        let len = range_end - range_start;
+
+        disk.seek(SeekFrom::Start(range_start));
+        disk.read_exact_into(disk_buffer_size, disk_buffer);
+
+        let slice = &disk_buffer.buffer[..usize::try_from(len).unwrap()];
+        unsafe { copy_to_protected_mode(dst.wrapping_add(total_offset), slice) };
+        let written =
+            unsafe { protected_mode::read_from_protected_mode(dst.wrapping_add(total_offset)) };
+        assert_eq!(slice[0], written);
+
+        total_offset += usize::try_from(len).unwrap();
+    }
+}
+
 fn load_file(
     file_name: &str,
     dst: *mut u8,
-    fs: &mut fat::FileSystem<disk::DiskAccess>,
-    disk: &mut disk::DiskAccess,
+    source: &mut BootSource,
     disk_buffer: &mut AlignedArrayBuffer<16384>,
 ) -> u64 {
-    try_load_file(file_name, dst, fs, disk, disk_buffer).expect("file not found")
+    try_load_file(file_name, dst, source, disk_buffer).expect("file not found")
 }
 
 /// Taken from https://github.com/rust-lang/rust/blob/e100ec5bc7cd768ec17d75448b29c9ab4a39272b/library/core/src/slice/mod.rs#L1673-L1677
@@ -1,7 +1,7 @@
 // From http://wiki.osdev.org/Detecting_Memory_(x86)#Getting_an_E820_Memory_Map
 
 use crate::split_array_ref;
-use bootloader_x86_64_bios_common::{racy_cell::RacyCell, E820MemoryRegion};
+use bootloader_x86_64_bios_common::{racy_cell::RacyCell, E820MemoryRegion, LegacyMemorySizes};
 use core::arch::asm;
 
 static MEMORY_MAP: RacyCell<[E820MemoryRegion; 100]> = RacyCell::new(
@@ -71,3 +71,42 @@ pub unsafe fn query_memory_map() -> Result<&'static mut [E820MemoryRegion], ()>
 
     Ok(&mut memory_map[..i])
 }
+
+/// Queries the memory sizes reported by `INT 0x12` and `INT 0x15, AX=0xE801`.
+///
+/// Unsupported functions are reported as zero sizes.
+pub unsafe fn query_legacy_memory_sizes() -> LegacyMemorySizes {
+    let conventional_kib: u16;
+    unsafe { asm!("int 0x12", out("ax") conventional_kib) };
+
+    let (ax, bx, cx, dx): (u16, u16, u16, u16);
+    let failed: u8;
+    unsafe {
+        asm!(
+            "push ebx",
+            "xor ebx, ebx",
+            "clc",
+            "int 0x15",
+            "setc {failed}",
+            "mov {bx:x}, bx",
+            "pop ebx",
+            inout("ax") 0xe801u16 => ax,
+            out("cx") cx,
+            out("dx") dx,
+            bx = out(reg) bx,
+            failed = out(reg_byte) failed,
+        )
+    };
+    let (extended_kib, extended_64k_blocks) = match failed {
+        0 if cx != 0 || dx != 0 => (cx, dx),
+        // some BIOSes only report the sizes in `AX` and `BX`
+        0 => (ax, bx),
+        _ => (0, 0),
+    };
+
+    LegacyMemorySizes {
+        conventional_kib,
+        extended_kib,
+        extended_64k_blocks,
+    }
+}
@@ -0,0 +1,305 @@
+//! Loads files from a TFTP server through the PXE API of the network card's boot ROM.
+//!
+//! Used when the bootloader is started as a PXE network bootstrap program. Requires
+//! PXE version 2.1 or later. See the _Preboot Execution Environment (PXE) Specification,
+//! Version 2.1_ for details.
+
+use crate::{disk::AlignedBuffer, protected_mode::copy_to_protected_mode};
+use core::{arch::asm, fmt::Write as _, slice};
+
+const OPCODE_UNDI_SHUTDOWN: u16 = 0x0005;
+const OPCODE_TFTP_OPEN: u16 = 0x0020;
+const OPCODE_TFTP_CLOSE: u16 = 0x0021;
+const OPCODE_TFTP_READ: u16 = 0x0022;
+const OPCODE_GET_CACHED_INFO: u16 = 0x0071;
+
+/// The reply of the boot server (proxy DHCP or DHCPACK).
+const PACKET_TYPE_CACHED_REPLY: u16 = 3;
+/// The DHCPACK packet.
+const PACKET_TYPE_DHCP_ACK: u16 = 2;
+
+const PXE_STATUS_SUCCESS: u16 = 0;
+/// The TFTP port in network byte order.
+const TFTP_PORT: u16 = 69u16.to_be();
+const TFTP_PACKET_SIZE: u16 = 512;
+
+/// The maximum length of a file name, including the terminating null byte.
+const MAX_FILE_NAME_LEN: usize = 128;
+
+/// Offsets of the fields of a BOOTP/DHCP packet.
+const BOOTP_SERVER_IP: usize = 20;
+const BOOTP_GATEWAY_IP: usize = 24;
+const BOOTP_FILE: usize = 108;
+const BOOTP_OPTIONS: usize = 236;
+const DHCP_MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const DHCP_OPTION_PAD: u8 = 0;
+const DHCP_OPTION_BOOTFILE_NAME: u8 = 67;
+const DHCP_OPTION_END: u8 = 255;
+
+/// Provides access to the PXE API.
+pub struct Pxe {
+    /// Real mode entry point of the `!PXE` API as a `segment:offset` far pointer.
+    entry_point: u32,
+    server_ip: [u8; 4],
+    gateway_ip: [u8; 4],
+    /// Directory of the boot file (including the trailing slash). Files are loaded relative
+    /// to this directory.
+    directory: [u8; MAX_FILE_NAME_LEN],
+    directory_len: usize,
+}
+
+impl Pxe {
+    /// Initializes the PXE API from the `PXENV+` structure at the given address.
+    ///
+    /// Queries the TFTP server address and the boot file name from the cached DHCP packets.
+    pub fn new(pxenv: *const u8) -> Self {
+        if unsafe { slice::from_raw_parts(pxenv, 6) } != b"PXENV+" {
+            panic!("PXE boot failed: no valid PXENV+ structure found");
+        }
+        let version = unsafe { pxenv.add(6).cast::<u16>().read_unaligned() };
+        if version < 0x0201 {
+            panic!("PXE boot failed: PXE version 2.1 or later required");
+        }
+
+        let pxe = segment_offset_to_ptr(unsafe { pxenv.add(0x28).cast::<u32>().read_unaligned() });
+        if unsafe { slice::from_raw_parts(pxe, 4) } != b"!PXE" {
+            panic!("PXE boot failed: no valid !PXE structure found");
+        }
+
+        let mut this = Pxe {
+            entry_point: unsafe { pxe.add(0x10).cast::<u32>().read_unaligned() },
+            server_ip: [0; 4],
+            gateway_ip: [0; 4],
+            directory: [0; MAX_FILE_NAME_LEN],
+            directory_len: 0,
+        };
+
+        let packet = match this.cached_packet(PACKET_TYPE_CACHED_REPLY) {
+            Some(packet) if packet[BOOTP_SERVER_IP..][..4] != [0; 4] => packet,
+            _ => this
+                .cached_packet(PACKET_TYPE_DHCP_ACK)
+                .expect("PXE boot failed: no cached DHCP packet"),
+        };
+        this.server_ip
+            .copy_from_slice(&packet[BOOTP_SERVER_IP..][..4]);
+        this.gateway_ip
+            .copy_from_slice(&packet[BOOTP_GATEWAY_IP..][..4]);
+
+        let boot_file = boot_file_name(packet);
+        let directory_len = boot_file
+            .iter()
+            .rposition(|&c| c == b'/')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        if directory_len >= MAX_FILE_NAME_LEN {
+            panic!("PXE boot failed: boot file path too long");
+        }
+        this.directory[..directory_len].copy_from_slice(&boot_file[..directory_len]);
+        this.directory_len = directory_len;
+
+        crate::screen::Writer
+            .write_str("PXE boot: loading files via TFTP\n")
+            .unwrap();
+        this
+    }
+
+    /// Downloads the given file to `dst`, using `buffer` as intermediate buffer.
+    ///
+    /// Returns the file size or `None` if the file could not be opened.
+    pub fn try_load_file(
+        &mut self,
+        file_name: &str,
+        dst: *mut u8,
+        buffer: &mut dyn AlignedBuffer,
+    ) -> Option<u64> {
+        let mut open = TftpOpen {
+            status: 0,
+            server_ip: self.server_ip,
+            gateway_ip: self.gateway_ip,
+            file_name: [0; MAX_FILE_NAME_LEN],
+            tftp_port: TFTP_PORT,
+            packet_size: TFTP_PACKET_SIZE,
+        };
+        let path_len = self.directory_len + file_name.len();
+        if path_len >= MAX_FILE_NAME_LEN {
+            panic!("TFTP file name too long");
+        }
+        open.file_name[..self.directory_len].copy_from_slice(&self.directory[..self.directory_len]);
+        open.file_name[self.directory_len..path_len].copy_from_slice(file_name.as_bytes());
+
+        if unsafe { self.call(OPCODE_TFTP_OPEN, &mut open) } != PXE_STATUS_SUCCESS {
+            return None;
+        }
+        let packet_size = open.packet_size;
+
+        let buffer = buffer.slice_mut();
+        assert!(buffer.len() >= usize::from(packet_size));
+        let mut total_len = 0;
+        loop {
+            let mut read = TftpRead {
+                status: 0,
+                packet_number: 0,
+                buffer_size: 0,
+                buffer: ptr_to_segment_offset(buffer.as_mut_ptr()),
+            };
+            let status = unsafe { self.call(OPCODE_TFTP_READ, &mut read) };
+            if status != PXE_STATUS_SUCCESS {
+                panic!("TFTP read failed");
+            }
+
+            let len = usize::from(read.buffer_size);
+            unsafe { copy_to_protected_mode(dst.wrapping_add(total_len), &buffer[..len]) };
+            total_len += len;
+
+            // a short packet marks the end of the file
+            if read.buffer_size < packet_size {
+                break;
+            }
+        }
+
+        let mut close = Status { status: 0 };
+        unsafe { self.call(OPCODE_TFTP_CLOSE, &mut close) };
+
+        Some(total_len.try_into().unwrap())
+    }
+
+    /// Shuts down the network interface, so that it doesn't write to memory anymore.
+    pub fn shutdown(&mut self) {
+        let mut shutdown = Status { status: 0 };
+        let status = unsafe { self.call(OPCODE_UNDI_SHUTDOWN, &mut shutdown) };
+        if status != PXE_STATUS_SUCCESS {
+            crate::screen::Writer
+                .write_str("Failed to shut down PXE network interface\n")
+                .unwrap();
+        }
+    }
+
+    /// Queries the cached DHCP packet of the given type.
+    fn cached_packet(&mut self, packet_type: u16) -> Option<&'static [u8]> {
+        let mut info = GetCachedInfo {
+            status: 0,
+            packet_type,
+            buffer_size: 0,
+            buffer: 0,
+            buffer_limit: 0,
+        };
+        let status = unsafe { self.call(OPCODE_GET_CACHED_INFO, &mut info) };
+        if status != PXE_STATUS_SUCCESS || info.buffer_size == 0 {
+            return None;
+        }
+        let packet = segment_offset_to_ptr(info.buffer);
+        Some(unsafe { slice::from_raw_parts(packet, info.buffer_size.into()) })
+    }
+
+    /// Calls the given PXE API function.
+    ///
+    /// Returns the value of `AX`, which is zero on success. The parameter structure must be
+    /// located in the first MiB of memory.
+    unsafe fn call<T>(&mut self, opcode: u16, parameters: &mut T) -> u16 {
+        let parameters = ptr_to_segment_offset((parameters as *mut T).cast());
+        let entry_point: *const u32 = &self.entry_point;
+        let status: u16;
+        unsafe {
+            // the API expects the opcode, the offset, and the segment of the parameter
+            // structure on the stack (in this order, from low to high addresses)
+            asm!(
+                "push ebx",
+                "push esi",
+                "push edi",
+                "push ebp",
+                "push ds",
+                "push es",
+                "push {parameters:e}",
+                "push ax",
+                "lcall dword ptr [{entry_point:e}]",
+                "add sp, 6",
+                "pop es",
+                "pop ds",
+                "pop ebp",
+                "pop edi",
+                "pop esi",
+                "pop ebx",
+                parameters = in(reg) parameters,
+                entry_point = in(reg) entry_point,
+                inout("ax") opcode => status,
+                lateout("cx") _,
+                lateout("dx") _,
+            )
+        };
+        status
+    }
+}
+
+#[repr(C, packed)]
+struct Status {
+    status: u16,
+}
+
+#[repr(C, packed)]
+struct GetCachedInfo {
+    status: u16,
+    packet_type: u16,
+    buffer_size: u16,
+    buffer: u32,
+    buffer_limit: u16,
+}
+
+#[repr(C, packed)]
+struct TftpOpen {
+    status: u16,
+    server_ip: [u8; 4],
+    gateway_ip: [u8; 4],
+    file_name: [u8; MAX_FILE_NAME_LEN],
+    tftp_port: u16,
+    packet_size: u16,
+}
+
+#[repr(C, packed)]
+struct TftpRead {
+    status: u16,
+    packet_number: u16,
+    buffer_size: u16,
+    buffer: u32,
+}
+
+/// Returns the boot file name from DHCP option 67 or from the `file` field of the packet.
+///
+/// The name does not include the terminating null byte.
+fn boot_file_name(packet: &[u8]) -> &[u8] {
+    let mut name = &packet[BOOTP_FILE..][..128];
+    if packet.get(BOOTP_OPTIONS..BOOTP_OPTIONS + 4) == Some(&DHCP_MAGIC_COOKIE) {
+        let mut i = BOOTP_OPTIONS + 4;
+        while let Some(&tag) = packet.get(i) {
+            match tag {
+                DHCP_OPTION_PAD => i += 1,
+                DHCP_OPTION_END => break,
+                _ => {
+                    let Some(&len) = packet.get(i + 1) else { break };
+                    let value = packet.get(i + 2..i + 2 + usize::from(len));
+                    if let (DHCP_OPTION_BOOTFILE_NAME, Some(value)) = (tag, value) {
+                        name = value;
+                        break;
+                    }
+                    i += 2 + usize::from(len);
+                }
+            }
+        }
+    }
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    &name[..len]
+}
+
+/// Converts a `segment:offset` far pointer to a linear address.
+fn segment_offset_to_ptr(segment_offset: u32) -> *const u8 {
+    let segment = segment_offset >> 16;
+    let offset = segment_offset & 0xffff;
+    ((segment << 4) + offset) as *const u8
+}
+
+/// Converts a linear address in the first MiB to a `segment:offset` far pointer.
+fn ptr_to_segment_offset(ptr: *mut u8) -> u32 {
+    let addr = ptr as u32;
+    let segment = addr >> 4;
+    let offset = addr & 0b1111;
+    (segment << 16) | offset
+}
@@ -1,6 +1,6 @@
 // info taken from https://wiki.osdev.org/VESA_Video_Modes
 
-use bootloader_x86_64_bios_common::PixelFormat;
+use bootloader_x86_64_bios_common::{BiosVideoMode, BiosVideoModes, PixelFormat};
 
 use crate::{disk::AlignedBuffer, AlignedArrayBuffer};
 use core::arch::asm;
@@ -37,8 +37,11 @@ impl<'a> VesaInfo<'a> {
         slice.fill(0);
         let block_ptr = slice.as_mut_ptr();
         let ret;
+        // the buffer might not be located in the first segment
+        let segment = block_ptr as u32 >> 4;
+        let offset = block_ptr as u32 & 0b1111;
         unsafe {
-            asm!("push es", "mov es, {:x}", "int 0x10", "pop es", in(reg)0, inout("ax") 0x4f00u16 => ret, in("di") block_ptr)
+            asm!("push es", "mov es, {:x}", "int 0x10", "pop es", in(reg) segment as u16, inout("ax") 0x4f00u16 => ret, in("di") offset as u16)
         };
         match ret {
             0x4f => {
@@ -52,10 +55,14 @@ impl<'a> VesaInfo<'a> {
         }
     }
 
+    /// Returns the largest supported mode that fits into the given size.
+    ///
+    /// All supported modes are added to `available_modes`.
     pub fn get_best_mode(
         &mut self,
         max_width: u16,
         max_height: u16,
+        available_modes: &mut BiosVideoModes,
     ) -> Result<Option<VesaModeInfo>, u16> {
         let mut best: Option<VesaModeInfo> = None;
         for i in 0.. {
@@ -65,8 +72,8 @@ impl<'a> VesaInfo<'a> {
             };
             let mode_info = VesaModeInfo::query(mode, self.rest_of_buffer).unwrap();
 
-            if mode_info.attributes & 0x90 != 0x90 {
-                // not a graphics mode with linear frame buffer support
+            if !mode_info.is_linear_graphics_mode() {
+                // banked frame buffers are not directly usable
                 continue;
             }
 
@@ -79,6 +86,13 @@ impl<'a> VesaInfo<'a> {
                 continue;
             }
 
+            available_modes.push(BiosVideoMode {
+                width: mode_info.width,
+                height: mode_info.height,
+                bits_per_pixel: mode_info.bits_per_pixel,
+                mode: mode_info.mode,
+            });
+
             if mode_info.width > max_width || mode_info.height > max_height {
                 continue;
             }
@@ -99,6 +113,15 @@ impl<'a> VesaInfo<'a> {
         Ok(best)
     }
 
+    /// Returns the raw VBE controller information block, directly followed by the raw mode
+    /// information block of the given mode.
+    pub fn raw_info_blocks(self, mode: u16) -> Result<&'a [u8], u16> {
+        VesaModeInfo::query(mode, self.rest_of_buffer)?;
+        // the mode information is placed at the start of the rest of the buffer
+        let block_ptr: *const u8 = (self.info_block as *const VbeInfoBlock).cast();
+        Ok(unsafe { core::slice::from_raw_parts(block_ptr, 512 + 256) })
+    }
+
     fn get_mode(&self, index: usize) -> Option<u16> {
         let (segment, offset) = {
             let raw = self.info_block.video_mode_ptr;
@@ -117,14 +140,54 @@ impl<'a> VesaInfo<'a> {
     }
 }
 
+/// The mode is supported by the present hardware configuration.
+const MODE_ATTRIBUTE_SUPPORTED: u16 = 1 << 0;
+/// The mode is a graphics mode (as opposed to a text mode).
+const MODE_ATTRIBUTE_GRAPHICS: u16 = 1 << 4;
+/// A linear frame buffer is available for the mode.
+const MODE_ATTRIBUTE_LINEAR_FRAMEBUFFER: u16 = 1 << 7;
+
+/// Requests the linear frame buffer instead of the banked window when setting a mode.
+const SET_MODE_LINEAR_FRAMEBUFFER: u16 = 1 << 14;
+
+/// The `BX` value of the VBE/PM `0x4f10` function to set the display power state to "on"
+/// (`BL = 0x01` for "set state", `BH = 0x00` for "on").
+const DPMS_SET_STATE_ON: u16 = 0x0001;
+
+/// Turns the display on through the VBE display power management signaling (DPMS)
+/// extension.
+///
+/// Some monitors are still in a power saving state at boot, so that the first output is not
+/// shown. Returns the VBE status code as error if the BIOS doesn't support DPMS.
+///
+/// See https://www.ctyme.com/intr/rb-0282.htm for details.
+pub fn wake_display() -> Result<(), u16> {
+    let mut ret: u16;
+    unsafe {
+        asm!(
+            "push bx",
+            "mov bx, {:x}",
+            "int 0x10",
+            "pop bx",
+            in(reg) DPMS_SET_STATE_ON,
+            inout("ax") 0x4f10u16 => ret,
+        )
+    };
+    match ret {
+        0x4f => Ok(()),
+        other => Err(other),
+    }
+}
+
 #[derive(Debug)]
 pub struct VesaModeInfo {
-    mode: u16,
+    pub mode: u16,
     pub width: u16,
     pub height: u16,
     pub framebuffer_start: u32,
     pub bytes_per_scanline: u16,
     pub bytes_per_pixel: u8,
+    pub bits_per_pixel: u8,
     pub pixel_format: PixelFormat,
 
     memory_model: u8,
@@ -199,6 +262,7 @@ impl VesaModeInfo {
                     framebuffer_start: block.framebuffer,
                     bytes_per_scanline: block.bytes_per_scanline,
                     bytes_per_pixel: block.bits_per_pixel / 8,
+                    bits_per_pixel: block.bits_per_pixel,
                     pixel_format: match (
                         block.red_position,
                         block.green_position,
@@ -220,6 +284,14 @@ impl VesaModeInfo {
         }
     }
 
+    /// Returns whether the mode is a graphics mode that is supported by the hardware and
+    /// provides a linear frame buffer.
+    fn is_linear_graphics_mode(&self) -> bool {
+        let required =
+            MODE_ATTRIBUTE_SUPPORTED | MODE_ATTRIBUTE_GRAPHICS | MODE_ATTRIBUTE_LINEAR_FRAMEBUFFER;
+        self.attributes & required == required
+    }
+
     pub fn enable(&self) -> Result<(), u16> {
         let mut ret: u16;
         unsafe {
@@ -228,7 +300,7 @@ impl VesaModeInfo {
                 "mov bx, {:x}",
                 "int 0x10",
                 "pop bx",
-                in(reg) self.mode,
+                in(reg) self.mode | SET_MODE_LINEAR_FRAMEBUFFER,
                 inout("ax") 0x4f02u16 => ret,
             )
         };
@@ -1,9 +1,10 @@
-use bootloader_x86_64_bios_common::racy_cell::RacyCell;
+use bootloader_x86_64_bios_common::{racy_cell::RacyCell, IDENTITY_MAPPED_GIGABYTES};
 use core::arch::asm;
 
 static LEVEL_4: RacyCell<PageTable> = RacyCell::new(PageTable::empty());
 static LEVEL_3: RacyCell<PageTable> = RacyCell::new(PageTable::empty());
-static LEVEL_2: RacyCell<[PageTable; 10]> = RacyCell::new([PageTable::empty(); 10]);
+static LEVEL_2: RacyCell<[PageTable; IDENTITY_MAPPED_GIGABYTES]> =
+    RacyCell::new([PageTable::empty(); IDENTITY_MAPPED_GIGABYTES]);
 
 pub fn init() {
     create_mappings();
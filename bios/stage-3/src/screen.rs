@@ -96,7 +96,6 @@ impl ScreenWriter {
     }
 
     fn write_pixel(&mut self, x: usize, y: usize, intensity: u8) {
-        let pixel_offset = y * usize::from(self.info.stride) + x;
         let color = match self.info.pixel_format {
             PixelFormat::Rgb => [intensity, intensity, intensity / 2, 0],
             PixelFormat::Bgr => [intensity / 2, intensity, intensity, 0],
@@ -108,7 +107,8 @@ impl ScreenWriter {
             }
         };
         let bytes_per_pixel = self.info.bytes_per_pixel;
-        let byte_offset = pixel_offset * usize::from(bytes_per_pixel);
+        let bytes_per_scanline = usize::from(self.info.stride) * usize::from(bytes_per_pixel);
+        let byte_offset = y * bytes_per_scanline + x * usize::from(bytes_per_pixel);
         self.framebuffer[byte_offset..(byte_offset + usize::from(bytes_per_pixel))]
             .copy_from_slice(&color[..usize::from(bytes_per_pixel)]);
         let _ = unsafe { ptr::read_volatile(&self.framebuffer[byte_offset]) };
@@ -2,15 +2,20 @@
 #![no_main]
 
 use crate::memory_descriptor::MemoryRegion;
-use bootloader_api::info::{FrameBufferInfo, PixelFormat};
-use bootloader_boot_config::{BootConfig, LevelFilter};
-use bootloader_x86_64_bios_common::{BiosFramebufferInfo, BiosInfo, E820MemoryRegion};
+use bootloader_api::info::{
+    BootEventId, FrameBufferInfo, Optional, VbeInfo, VideoMode, VideoModes,
+};
+use bootloader_boot_config::{BootConfig, LevelFilter, LogColors, Splash};
+use bootloader_x86_64_bios_common::{
+    BiosFramebufferInfo, BiosInfo, BiosVideoModes, E820MemoryRegion, Region, IDENTITY_MAPPED_END,
+};
 use bootloader_x86_64_common::RawFrameBufferInfo;
 use bootloader_x86_64_common::{
-    legacy_memory_region::LegacyFrameAllocator, load_and_switch_to_kernel, Kernel, PageTables,
-    SystemInfo,
+    boot_events, identity_map_protection, init_level_4_table,
+    legacy_memory_region::LegacyFrameAllocator, load_and_switch_to_kernel, reset, Kernel,
+    PageTables, SystemInfo,
 };
-use core::{cmp, slice};
+use core::{arch::global_asm, cmp, fmt, mem, slice};
 use usize_conversions::usize_from;
 use x86_64::structures::paging::{FrameAllocator, OffsetPageTable};
 use x86_64::structures::paging::{
@@ -19,12 +24,54 @@ use x86_64::structures::paging::{
 use x86_64::{PhysAddr, VirtAddr};
 
 const GIGABYTE: u64 = 4096 * 512 * 512;
+/// The end of the conventional memory that is addressable in real mode (1 MiB).
+const LOW_MEMORY_END: u64 = 0x10_0000;
+/// Stage 2 and its data must fit below this address.
+const STAGE_2_END: u64 = 0x8_0000;
+/// The E820 type used for usable memory.
+const E820_USABLE: u32 = 1;
+/// The maximum number of memory regions after reserving the BIOS areas.
+///
+/// Stage 2 queries at most 100 E820 regions.
+const MAX_MEMORY_REGIONS: usize = 128;
+/// The size of an ELF64 file header, no valid kernel can be smaller.
+const MIN_KERNEL_SIZE: u64 = 64;
+
+/// Written to the lowest address of the stage 4 stack to detect stack overflows.
+const STACK_CANARY: u64 = 0x5354_4143_4b5f_454e;
 
 mod memory_descriptor;
+mod rtc;
+
+extern "C" {
+    /// The lowest address of the stage 4 stack, defined in `stage-4-link.ld`.
+    static _stack_start: u8;
+    /// The end address of the stage 4 stack, which is also the end of stage 4 in memory.
+    static _stack_end: u8;
+    /// The end of the code and read-only data of stage 4, defined in `stage-4-link.ld`.
+    static _code_end: u8;
+}
+
+// Stage 3 calls `_start` on the small stack below the boot sector. Switch to the larger
+// stage 4 stack, which is reserved in `stage-4-link.ld`, before running any Rust code.
+global_asm!(
+    ".section .start, \"ax\"",
+    ".global _start",
+    "_start:",
+    "lea rsp, [rip + _stack_end]",
+    // the `BiosInfo` pointer is still in `rdi`
+    "call {start}",
+    "2:",
+    "cli",
+    "hlt",
+    "jmp 2b",
+    start = sym start,
+);
+
+extern "C" fn start(info: &mut BiosInfo) -> ! {
+    unsafe { stack_canary().write_volatile(STACK_CANARY) };
+    boot_events::record(BootEventId::BOOTLOADER_START, 0);
 
-#[no_mangle]
-#[link_section = ".start"]
-pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
     let memory_map: &mut [E820MemoryRegion] = unsafe {
         core::slice::from_raw_parts_mut(
             info.memory_map_addr as *mut _,
@@ -32,7 +79,83 @@ pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
         )
     };
 
-    memory_map.sort_unstable_by_key(|e| e.start_addr);
+    let (memory_map, memory_map_duplicates) = memory_descriptor::sort_and_dedup(memory_map);
+
+    let mut config_file_slice: Option<&[u8]> = None;
+    if info.config_file.len != 0 {
+        config_file_slice = {
+            let ptr = info.config_file.start as *mut u8;
+            unsafe {
+                Some(slice::from_raw_parts_mut(
+                    ptr,
+                    usize_from(info.config_file.len),
+                ))
+            }
+        };
+    }
+    let mut error_loading_config: Option<serde_json_core::de::Error> = None;
+    let mut config: BootConfig = match config_file_slice
+        .map(serde_json_core::from_slice)
+        .transpose()
+    {
+        Ok(data) => data.unwrap_or_default().0,
+        Err(err) => {
+            error_loading_config = Some(err);
+            Default::default()
+        }
+    };
+
+    // the second stage only passes an empty memory map if the legacy functions reported memory
+    if memory_map.is_empty() && !config.reconcile_legacy_memory_map {
+        early_panic(
+            info,
+            memory_map,
+            &config,
+            format_args!(
+                "the BIOS does not support E820, enable `reconcile_legacy_memory_map` to use \
+                the legacy memory sizes instead"
+            ),
+        );
+    }
+
+    // backfill memory that the E820 map misses
+    let mut reconcile_buffer = [E820MemoryRegion {
+        start_addr: 0,
+        len: 0,
+        region_type: 0,
+        acpi_extended_attributes: 0,
+    }; MAX_MEMORY_REGIONS];
+    let memory_map: &[E820MemoryRegion] = if config.reconcile_legacy_memory_map {
+        bootloader_x86_64_bios_common::reconcile_memory_maps(
+            memory_map,
+            info.legacy_memory_sizes,
+            &mut reconcile_buffer,
+        )
+    } else {
+        memory_map
+    };
+
+    // resolve overlapping regions before the BIOS areas are reserved
+    let mut sanitize_buffer = [E820MemoryRegion {
+        start_addr: 0,
+        len: 0,
+        region_type: 0,
+        acpi_extended_attributes: 0,
+    }; MAX_MEMORY_REGIONS];
+    let memory_map: &[E820MemoryRegion] = if config.sanitize_e820_like_linux {
+        bootloader_x86_64_bios_common::sanitize_like_linux(memory_map, &mut sanitize_buffer)
+    } else {
+        memory_map
+    };
+
+    // make room for splitting a region and for the additional reserved region
+    let mut memory_map_buffer = [E820MemoryRegion {
+        start_addr: 0,
+        len: 0,
+        region_type: 0,
+        acpi_extended_attributes: 0,
+    }; MAX_MEMORY_REGIONS];
+    let memory_map = memory_descriptor::reserve_bios_areas(memory_map, &mut memory_map_buffer);
 
     let max_phys_addr = {
         let max = memory_map
@@ -54,6 +177,24 @@ pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
         PhysAddr::new(info.kernel.start)
     };
     let kernel_size = info.kernel.len;
+    if kernel_size < MIN_KERNEL_SIZE {
+        early_panic(
+            info,
+            memory_map,
+            &config,
+            format_args!(
+                "no kernel loaded: the kernel is {} bytes long, but a kernel must be at least {} \
+                bytes (the size of an ELF header)",
+                info.kernel.len, MIN_KERNEL_SIZE
+            ),
+        );
+    }
+    // The kernel and the ramdisk are read through the identity mapping, so it needs to cover
+    // them even if they were loaded above the 4 GiB limit of `max_phys_addr`.
+    let identity_map_end = [info.kernel, info.ramdisk]
+        .iter()
+        .map(|region| region.start + region.len)
+        .fold(max_phys_addr, u64::max);
     let next_free_frame = PhysFrame::containing_address(PhysAddr::new(info.last_used_addr)) + 1;
     let mut frame_allocator = LegacyFrameAllocator::new_starting_at(
         next_free_frame,
@@ -68,11 +209,12 @@ pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
         let table: *mut PageTable = (phys_offset + frame.start_address().as_u64()).as_mut_ptr();
         unsafe { OffsetPageTable::new(&mut *table, phys_offset) }
     };
-    // identity-map remaining physical memory (first 10 gigabytes are already identity-mapped)
+    // identity-map remaining physical memory (stage 3 already identity-mapped the
+    // memory below `IDENTITY_MAPPED_END`)
     {
         let start_frame: PhysFrame<Size2MiB> =
-            PhysFrame::containing_address(PhysAddr::new(GIGABYTE * 10));
-        let end_frame = PhysFrame::containing_address(PhysAddr::new(max_phys_addr - 1));
+            PhysFrame::containing_address(PhysAddr::new(IDENTITY_MAPPED_END));
+        let end_frame = PhysFrame::containing_address(PhysAddr::new(identity_map_end - 1));
         for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
             let flusher = unsafe {
                 bootloader_page_table
@@ -98,38 +240,25 @@ pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
     // it's mapped using `invlpg`, for efficiency.
     x86_64::instructions::tlb::flush_all();
 
+    if config.protect_bootloader_code {
+        let code_end = unsafe { core::ptr::addr_of!(_code_end) } as u64;
+        identity_map_protection::protect_bootloader_code(
+            &mut bootloader_page_table,
+            &[PhysAddr::new(info.stage_4.start)..PhysAddr::new(code_end)],
+            &mut frame_allocator,
+        );
+    }
+
     let page_tables = create_page_tables(&mut frame_allocator);
 
     let kernel_slice = {
         let ptr = kernel_start.as_u64() as *const u8;
         unsafe { slice::from_raw_parts(ptr, usize_from(kernel_size)) }
     };
+    #[cfg(feature = "signed-kernel")]
+    let kernel_slice = bootloader_x86_64_common::kernel_signature::verify(kernel_slice);
     let kernel = Kernel::parse(kernel_slice);
 
-    let mut config_file_slice: Option<&[u8]> = None;
-    if info.config_file.len != 0 {
-        config_file_slice = {
-            let ptr = info.config_file.start as *mut u8;
-            unsafe {
-                Some(slice::from_raw_parts_mut(
-                    ptr,
-                    usize_from(info.config_file.len),
-                ))
-            }
-        };
-    }
-    let mut error_loading_config: Option<serde_json_core::de::Error> = None;
-    let mut config: BootConfig = match config_file_slice
-        .map(serde_json_core::from_slice)
-        .transpose()
-    {
-        Ok(data) => data.unwrap_or_default().0,
-        Err(err) => {
-            error_loading_config = Some(err);
-            Default::default()
-        }
-    };
-
     #[allow(deprecated)]
     if config.frame_buffer.minimum_framebuffer_height.is_none() {
         config.frame_buffer.minimum_framebuffer_height =
@@ -140,64 +269,302 @@ pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
         config.frame_buffer.minimum_framebuffer_width =
             kernel.config.frame_buffer.minimum_framebuffer_width;
     }
+    let stage_4_end = unsafe { core::ptr::addr_of!(_stack_end) } as u64;
+    let loaded_regions = [
+        (
+            "stage 4",
+            Region {
+                start: info.stage_4.start,
+                len: stage_4_end - info.stage_4.start,
+            },
+        ),
+        ("kernel", info.kernel),
+        ("ramdisk", info.ramdisk),
+        ("config file", info.config_file),
+        ("device tree", info.device_tree),
+        ("boot config blob", info.boot_config_blob),
+        ("VBE info", info.vbe_info),
+    ];
+    let framebuffer_error =
+        check_framebuffer(info.framebuffer.region, memory_map, &loaded_regions).err();
     let framebuffer_info = init_logger(
         info.framebuffer,
+        framebuffer_error.is_none(),
         config.log_level,
+        config.log_colors,
         config.frame_buffer_logging,
         config.serial_logging,
+        config.frame_buffer.self_test,
+        config.splash,
+        boot_config_blob(info),
     );
 
+    if let Some(err) = framebuffer_error {
+        log::warn!(
+            "Ignoring invalid framebuffer at {:#x} (size {:#x}): {}",
+            info.framebuffer.region.start,
+            info.framebuffer.region.len,
+            err
+        );
+    }
+    reset::set_panic_behavior(config.panic_behavior);
     if let Some(err) = error_loading_config {
         log::warn!("Failed to deserialize the config file {:?}", err);
     }
+    if kernel.config.defer_exit_boot_services.is_some() {
+        log::warn!("Deferring the firmware exit is only supported on UEFI, ignoring it");
+    }
+    if memory_map_duplicates.removed > 0 {
+        log::warn!(
+            "Removed {} duplicate regions from the E820 memory map",
+            memory_map_duplicates.removed
+        );
+    }
+    if memory_map_duplicates.conflicting > 0 {
+        log::warn!(
+            "The E820 memory map contains {} regions that start at the same address as \
+            another region",
+            memory_map_duplicates.conflicting
+        );
+    }
 
     log::info!("4th Stage");
     log::info!("{info:x?}");
     log::info!("BIOS boot");
 
     let system_info = SystemInfo {
-        framebuffer: Some(RawFrameBufferInfo {
+        framebuffer: framebuffer_info.map(|framebuffer_info| RawFrameBufferInfo {
             addr: PhysAddr::new(info.framebuffer.region.start),
             info: framebuffer_info,
         }),
-        rsdp_addr: detect_rsdp(),
+        rsdp_addr: if kernel.config.detect_acpi {
+            detect_rsdp()
+        } else {
+            None
+        },
         ramdisk_addr: match info.ramdisk.len {
             0 => None,
             _ => Some(info.ramdisk.start),
         },
         ramdisk_len: info.ramdisk.len,
+        device_tree: (info.device_tree.len != 0).then(|| unsafe {
+            slice::from_raw_parts(
+                info.device_tree.start as *const u8,
+                usize_from(info.device_tree.len),
+            )
+        }),
+        boot_config_blob: boot_config_blob(info),
+        deferred_exit: None,
+        available_video_modes: convert_video_modes(&info.video_modes),
+        boot_time: rtc::read_time(),
+        ap_trampoline_addr: kernel
+            .config
+            .ap_trampoline_size
+            .and_then(|size| reserve_ap_trampoline(memory_map, size)),
+        vbe_info: read_vbe_info(info.vbe_info),
     };
 
-    load_and_switch_to_kernel(kernel, config, frame_allocator, page_tables, system_info);
+    check_stack_canary();
+    load_and_switch_to_kernel(
+        kernel,
+        config,
+        frame_allocator,
+        page_tables,
+        system_info,
+        None,
+    );
+}
+
+/// Returns a pointer to the canary at the lowest address of the stage 4 stack.
+fn stack_canary() -> *mut u64 {
+    unsafe { core::ptr::addr_of!(_stack_start) as *mut u64 }
+}
+
+/// Panics if the stage 4 stack overflowed, i.e. if the stack canary was overwritten.
+fn check_stack_canary() {
+    if unsafe { stack_canary().read_volatile() } != STACK_CANARY {
+        panic!("bootloader stack overflow");
+    }
+}
+
+/// Reserves a page-aligned region of `size` bytes at the end of the conventional memory
+/// below 1 MiB.
+///
+/// Stage 2 and its data are located below `0x80000` (see the stage 2 linker script), so the
+/// memory above is unused. As the frame allocator starts after the loaded stages, the region
+/// is reported as bootloader memory without further bookkeeping.
+fn reserve_ap_trampoline(memory_map: &[E820MemoryRegion], size: u64) -> Option<PhysAddr> {
+    let size = size.div_ceil(4096) * 4096;
+    let region = memory_map
+        .iter()
+        .filter(|r| r.region_type == E820_USABLE && r.start_addr < LOW_MEMORY_END)
+        .max_by_key(|r| r.start_addr + r.len);
+    let start = region.and_then(|region| {
+        let end = cmp::min(region.start_addr + region.len, LOW_MEMORY_END) & !0xfff;
+        let start = end.checked_sub(size)?;
+        (size != 0 && start >= cmp::max(region.start_addr, STAGE_2_END)).then_some(start)
+    });
+    if start.is_none() {
+        log::warn!("Failed to reserve {size:#x} bytes of low memory for the AP trampoline");
+    }
+    start.map(PhysAddr::new)
+}
+
+/// Reads the raw VBE information blocks that stage 2 copied to the given region.
+fn read_vbe_info(region: Region) -> Option<VbeInfo> {
+    if region.len != mem::size_of::<VbeInfo>() as u64 {
+        return None;
+    }
+    Some(unsafe { (region.start as *const VbeInfo).read_unaligned() })
+}
+
+fn convert_video_modes(modes: &BiosVideoModes) -> VideoModes {
+    let mut video_modes = VideoModes::new();
+    for mode in modes.as_slice() {
+        video_modes.push(VideoMode {
+            width: mode.width.into(),
+            height: mode.height.into(),
+            bits_per_pixel: mode.bits_per_pixel,
+            mode_number: mode.mode.into(),
+        });
+    }
+    video_modes
+}
+
+/// The reason why the framebuffer reported by stage 2 can't be used.
+enum FramebufferError {
+    /// The framebuffer region itself is invalid.
+    Invalid(&'static str),
+    /// The framebuffer overlaps a region that the bootloader loaded into memory.
+    Overlap { name: &'static str, region: Region },
+}
+
+impl fmt::Display for FramebufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FramebufferError::Invalid(reason) => f.write_str(reason),
+            FramebufferError::Overlap { name, region } => write!(
+                f,
+                "framebuffer overlaps the {name} at {:#x}..{:#x}",
+                region.start,
+                region.start + region.len
+            ),
+        }
+    }
+}
+
+impl From<&'static str> for FramebufferError {
+    fn from(reason: &'static str) -> Self {
+        FramebufferError::Invalid(reason)
+    }
+}
+
+/// Checks that the framebuffer region reported by stage 2 can be safely written to.
+///
+/// The region must be non-empty, located in the memory identity-mapped by stage 3, and must
+/// overlap neither usable memory nor any of the given `loaded_regions`, which are named in
+/// the error.
+fn check_framebuffer(
+    region: Region,
+    memory_map: &[E820MemoryRegion],
+    loaded_regions: &[(&'static str, Region)],
+) -> Result<(), FramebufferError> {
+    if region.start == 0 || region.len == 0 {
+        return Err("framebuffer address or size is zero".into());
+    }
+    let end = region
+        .start
+        .checked_add(region.len)
+        .ok_or("framebuffer region overflows")?;
+    if end > IDENTITY_MAPPED_END {
+        return Err("framebuffer is not identity-mapped".into());
+    }
+    let overlap = loaded_regions.iter().find(|(_, loaded)| {
+        loaded.len != 0 && loaded.start < end && region.start < loaded.start + loaded.len
+    });
+    if let Some(&(name, region)) = overlap {
+        return Err(FramebufferError::Overlap { name, region });
+    }
+    let overlaps_usable_memory = memory_map
+        .iter()
+        .filter(|r| r.region_type == E820_USABLE)
+        .any(|r| r.start_addr < end && region.start < r.start_addr + r.len);
+    if overlaps_usable_memory {
+        return Err("framebuffer overlaps usable memory".into());
+    }
+    Ok(())
+}
+
+/// Panics with the given message before the logger is initialized.
+///
+/// Initializes the logger first, as the panic message would not be visible otherwise.
+fn early_panic(
+    info: &BiosInfo,
+    memory_map: &[E820MemoryRegion],
+    config: &BootConfig,
+    message: fmt::Arguments,
+) -> ! {
+    let framebuffer_valid = check_framebuffer(
+        info.framebuffer.region,
+        memory_map,
+        &[("stage 4", info.stage_4)],
+    )
+    .is_ok();
+    init_logger(
+        info.framebuffer,
+        framebuffer_valid,
+        config.log_level,
+        config.log_colors,
+        config.frame_buffer_logging,
+        config.serial_logging,
+        false,
+        config.splash,
+        None,
+    );
+    panic!("{message}");
 }
 
+/// Initializes the logger.
+///
+/// If `framebuffer_valid` is `false`, the framebuffer is not accessed and the logger always
+/// writes to the serial port instead. Returns the framebuffer info if the framebuffer is used.
+#[allow(clippy::too_many_arguments)]
 fn init_logger(
     info: BiosFramebufferInfo,
+    framebuffer_valid: bool,
     log_level: LevelFilter,
+    log_colors: LogColors,
     frame_buffer_logger_status: bool,
     serial_logger_status: bool,
-) -> FrameBufferInfo {
+    frame_buffer_self_test: bool,
+    splash: Splash,
+    boot_config_blob: Option<&[u8]>,
+) -> Option<FrameBufferInfo> {
     let framebuffer_info = FrameBufferInfo {
         byte_len: info.region.len.try_into().unwrap(),
         width: info.width.into(),
         height: info.height.into(),
-        pixel_format: match info.pixel_format {
-            bootloader_x86_64_bios_common::PixelFormat::Rgb => PixelFormat::Rgb,
-            bootloader_x86_64_bios_common::PixelFormat::Bgr => PixelFormat::Bgr,
-            bootloader_x86_64_bios_common::PixelFormat::Unknown {
-                red_position,
-                green_position,
-                blue_position,
-            } => PixelFormat::Unknown {
-                red_position,
-                green_position,
-                blue_position,
-            },
-        },
+        pixel_format: bootloader_x86_64_bios_common::translate_pixel_format(info.pixel_format),
         bytes_per_pixel: info.bytes_per_pixel.into(),
         stride: info.stride.into(),
+        vbe_mode: Optional::Some(info.vbe_mode),
     };
 
+    if !framebuffer_valid {
+        bootloader_x86_64_common::init_logger(
+            &mut [],
+            framebuffer_info,
+            log_level,
+            log_colors,
+            false,
+            true,
+            false,
+            Splash::default(),
+            None,
+        );
+        return None;
+    }
+
     let framebuffer = unsafe {
         core::slice::from_raw_parts_mut(
             info.region.start as *mut u8,
@@ -205,15 +572,29 @@ fn init_logger(
         )
     };
 
-    bootloader_x86_64_common::init_logger(
+    let framebuffer_info = bootloader_x86_64_common::init_logger(
         framebuffer,
         framebuffer_info,
         log_level,
+        log_colors,
         frame_buffer_logger_status,
         serial_logger_status,
+        frame_buffer_self_test,
+        splash,
+        boot_config_blob,
     );
 
-    framebuffer_info
+    Some(framebuffer_info)
+}
+
+/// Returns the boot config blob that was loaded by stage 2, if any.
+fn boot_config_blob(info: &BiosInfo) -> Option<&'static [u8]> {
+    (info.boot_config_blob.len != 0).then(|| unsafe {
+        slice::from_raw_parts(
+            info.boot_config_blob.start as *const u8,
+            usize_from(info.boot_config_blob.len),
+        )
+    })
 }
 
 /// Creates page table abstraction types for both the bootloader and kernel page tables.
@@ -221,7 +602,8 @@ fn create_page_tables(frame_allocator: &mut impl FrameAllocator<Size4KiB>) -> Pa
     // We identity-mapped all memory, so the offset between physical and virtual addresses is 0
     let phys_offset = VirtAddr::new(0);
 
-    // copy the currently active level 4 page table, because it might be read-only
+    // the active level 4 page table is a static of stage 3, so it is writable and can be
+    // modified in place
     let bootloader_page_table = {
         let frame = x86_64::registers::control::Cr3::read().0;
         let table: *mut PageTable = (phys_offset + frame.start_address().as_u64()).as_mut_ptr();
@@ -233,12 +615,8 @@ fn create_page_tables(frame_allocator: &mut impl FrameAllocator<Size4KiB>) -> Pa
         // get an unused frame for new level 4 page table
         let frame: PhysFrame = frame_allocator.allocate_frame().expect("no unused frames");
         log::info!("New page table at: {frame:#?}");
-        // get the corresponding virtual address
-        let addr = phys_offset + frame.start_address().as_u64();
         // initialize a new page table
-        let ptr: *mut PageTable = addr.as_mut_ptr();
-        unsafe { ptr.write(PageTable::new()) };
-        let level_4_table = unsafe { &mut *ptr };
+        let level_4_table = unsafe { init_level_4_table(frame, phys_offset) };
         (
             unsafe { OffsetPageTable::new(level_4_table, phys_offset) },
             frame,
@@ -298,8 +676,11 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
             .get()
             .map(|l| l.force_unlock())
     };
-    log::error!("{info}");
-    loop {
-        unsafe { core::arch::asm!("cli; hlt") };
+    // if the framebuffer was already handed to the kernel, this only logs to the serial port
+    if unsafe { stack_canary().read_volatile() } != STACK_CANARY {
+        log::error!("bootloader stack overflow");
     }
+    log::error!("{info}");
+    bootloader_x86_64_common::panic_sinks::run(info);
+    bootloader_x86_64_common::reset::handle_panic()
 }
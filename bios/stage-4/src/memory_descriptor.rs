@@ -1,4 +1,4 @@
-use bootloader_api::info::MemoryRegionKind;
+use bootloader_api::info::{MemoryRegionAttributes, MemoryRegionKind};
 use bootloader_x86_64_bios_common::E820MemoryRegion;
 use bootloader_x86_64_common::legacy_memory_region::LegacyMemoryRegion;
 use x86_64::PhysAddr;
@@ -19,6 +19,22 @@ impl LegacyMemoryRegion for MemoryRegion {
         }
     }
 
+    fn attributes(&self) -> MemoryRegionAttributes {
+        let extended = self.0.acpi_extended_attributes;
+        // the extended attributes are only valid if their "enabled" bit is set
+        let non_volatile = self.0.region_type == E820_PERSISTENT_MEMORY
+            || (extended & E820_ATTRIBUTE_ENABLED != 0
+                && extended & E820_ATTRIBUTE_NON_VOLATILE != 0);
+        MemoryRegionAttributes {
+            flags: if non_volatile {
+                MemoryRegionAttributes::NON_VOLATILE
+            } else {
+                0
+            },
+            ..MemoryRegionAttributes::empty()
+        }
+    }
+
     fn usable_after_bootloader_exit(&self) -> bool {
         matches!(self.kind(), MemoryRegionKind::Usable)
     }
@@ -31,3 +47,147 @@ impl LegacyMemoryRegion for MemoryRegion {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct MemoryRegion(pub E820MemoryRegion);
+
+/// Physical address of the BIOS data area field that stores the segment of the EBDA.
+const EBDA_POINTER_ADDR: u64 = 0x40e;
+/// Start of the VGA memory, which is followed by the BIOS ROM area.
+const VGA_MEMORY_START: u64 = 0xa0000;
+/// End of the BIOS ROM area (1 MiB).
+const BIOS_AREA_END: u64 = 0x10_0000;
+/// The E820 type used for reserved memory.
+const E820_RESERVED: u32 = 2;
+/// The E820 type used for persistent memory.
+const E820_PERSISTENT_MEMORY: u32 = 7;
+/// The bit of the ACPI 3.0 extended attributes that marks the entry as valid.
+const E820_ATTRIBUTE_ENABLED: u32 = 1 << 0;
+/// The bit of the ACPI 3.0 extended attributes that marks the memory as non-volatile.
+const E820_ATTRIBUTE_NON_VOLATILE: u32 = 1 << 1;
+
+/// The number of duplicate regions that [`sort_and_dedup`] found in the E820 memory map.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Duplicates {
+    /// The number of regions that were removed because they were identical to another region.
+    pub removed: usize,
+    /// The number of regions that start at the same address as another, different region.
+    pub conflicting: usize,
+}
+
+/// The order of the E820 memory map: by start address, then by length, type, and extended
+/// attributes.
+///
+/// The key includes all fields, so regions with equal keys are identical and even an unstable
+/// sort results in a fully deterministic order, independent of the order in which the BIOS
+/// reported the regions.
+fn sort_key(region: &E820MemoryRegion) -> (u64, u64, u32, u32) {
+    (
+        region.start_addr,
+        region.len,
+        region.region_type,
+        region.acpi_extended_attributes,
+    )
+}
+
+/// Sorts the E820 memory map deterministically (see [`sort_key`]) and removes identical
+/// duplicate regions.
+///
+/// Some BIOSes report the same region twice. Regions that start at the same address but
+/// differ otherwise are kept, because there is no way to tell which one is correct. They are
+/// counted as conflicting in the returned [`Duplicates`], so that they can be reported.
+pub fn sort_and_dedup(
+    memory_map: &mut [E820MemoryRegion],
+) -> (&mut [E820MemoryRegion], Duplicates) {
+    memory_map.sort_unstable_by_key(sort_key);
+
+    let mut duplicates = Duplicates::default();
+    let mut len: usize = 0;
+    for i in 0..memory_map.len() {
+        let region = memory_map[i];
+        if let Some(previous) = len.checked_sub(1).map(|j| memory_map[j]) {
+            if region == previous {
+                duplicates.removed += 1;
+                continue;
+            }
+            if region.start_addr == previous.start_addr {
+                duplicates.conflicting += 1;
+            }
+        }
+        memory_map[len] = region;
+        len += 1;
+    }
+    (&mut memory_map[..len], duplicates)
+}
+
+/// Returns the physical start address of the extended BIOS data area (EBDA).
+///
+/// Falls back to the start of the VGA memory if the pointer in the BIOS data area
+/// looks invalid.
+fn ebda_start() -> u64 {
+    // the BIOS data area is identity-mapped
+    let segment = unsafe { core::ptr::read_volatile(EBDA_POINTER_ADDR as *const u16) };
+    let start = u64::from(segment) << 4;
+    // the EBDA is at most 128KiB large and directly below the VGA memory
+    if (0x8_0000..VGA_MEMORY_START).contains(&start) {
+        start
+    } else {
+        VGA_MEMORY_START
+    }
+}
+
+/// Marks the EBDA and the `0xA0000..0x100000` area as reserved, independent of the
+/// E820 memory map.
+///
+/// Some BIOSes don't report these areas as reserved, so the kernel would otherwise see
+/// them as usable. The regions of `memory_map` are clipped to exclude the reserved range
+/// and written to `buffer`, together with a single reserved region covering the range.
+/// The returned subslice of `buffer` is sorted in the same order as [`sort_and_dedup`].
+pub fn reserve_bios_areas<'a>(
+    memory_map: &[E820MemoryRegion],
+    buffer: &'a mut [E820MemoryRegion],
+) -> &'a mut [E820MemoryRegion] {
+    let reserved_start = ebda_start();
+    let reserved_end = BIOS_AREA_END;
+
+    let mut len = 0;
+    let mut push = |region: E820MemoryRegion| {
+        if region.len == 0 {
+            return;
+        }
+        *buffer
+            .get_mut(len)
+            .expect("too many memory regions after reserving BIOS areas") = region;
+        len += 1;
+    };
+
+    for region in memory_map {
+        let start = region.start_addr;
+        let end = region.start_addr + region.len;
+        if start >= reserved_end || end <= reserved_start {
+            push(*region);
+            continue;
+        }
+        // region overlaps with the reserved range -> keep only the parts outside of it
+        if start < reserved_start {
+            push(E820MemoryRegion {
+                len: reserved_start - start,
+                ..*region
+            });
+        }
+        if end > reserved_end {
+            push(E820MemoryRegion {
+                start_addr: reserved_end,
+                len: end - reserved_end,
+                ..*region
+            });
+        }
+    }
+    push(E820MemoryRegion {
+        start_addr: reserved_start,
+        len: reserved_end - reserved_start,
+        region_type: E820_RESERVED,
+        acpi_extended_attributes: 0,
+    });
+
+    let regions = &mut buffer[..len];
+    regions.sort_unstable_by_key(sort_key);
+    regions
+}
@@ -0,0 +1,114 @@
+//! Reads the current date and time from the CMOS real-time clock.
+
+use bootloader_api::info::BootTime;
+use x86_64::instructions::port::Port;
+
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0a;
+const REGISTER_STATUS_B: u8 = 0x0b;
+
+/// Set in status register A while the clock updates its registers.
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Set in status register B if the hours are stored in 24-hour format.
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// Set in status register B if the values are stored in binary instead of BCD.
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Set in the hours register in 12-hour format for PM times.
+const HOURS_PM: u8 = 1 << 7;
+
+/// The maximum number of reads until two consecutive reads return the same time.
+const MAX_ATTEMPTS: usize = 16;
+
+/// Reads the current date and time from the CMOS real-time clock.
+///
+/// The clock registers are read repeatedly until two consecutive reads match, so that an
+/// update during the read doesn't result in an inconsistent time. The clock only stores a
+/// two-digit year, which is interpreted as a year of the 21st century.
+///
+/// Returns `None` if no consistent or valid time could be read.
+pub fn read_time() -> Option<BootTime> {
+    let mut previous = read_registers()?;
+    for _ in 0..MAX_ATTEMPTS {
+        let current = read_registers()?;
+        if current == previous {
+            return convert(current, read_register(REGISTER_STATUS_B));
+        }
+        previous = current;
+    }
+    None
+}
+
+/// The raw values of the seconds, minutes, hours, day, month, and year registers.
+type RawTime = [u8; 6];
+
+/// Waits until no update is in progress and reads the clock registers.
+fn read_registers() -> Option<RawTime> {
+    let mut waited = 0;
+    while read_register(REGISTER_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {
+        // an update takes less than 2ms, so this only fails if there is no working clock
+        waited += 1;
+        if waited > 1_000_000 {
+            return None;
+        }
+    }
+    Some(
+        [
+            REGISTER_SECONDS,
+            REGISTER_MINUTES,
+            REGISTER_HOURS,
+            REGISTER_DAY,
+            REGISTER_MONTH,
+            REGISTER_YEAR,
+        ]
+        .map(read_register),
+    )
+}
+
+/// Converts the raw register values to a [`BootTime`] based on the format in status
+/// register B.
+fn convert(raw: RawTime, status_b: u8) -> Option<BootTime> {
+    let [second, minute, hours, day, month, year] = raw;
+    let from_clock = |value: u8| {
+        if status_b & STATUS_B_BINARY != 0 {
+            value
+        } else {
+            (value >> 4) * 10 + (value & 0xf)
+        }
+    };
+    let mut hour = from_clock(hours & !HOURS_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12 AM is midnight and 12 PM is noon
+        hour %= 12;
+        if hours & HOURS_PM != 0 {
+            hour += 12;
+        }
+    }
+    let time = BootTime {
+        year: 2000 + u16::from(from_clock(year)),
+        month: from_clock(month),
+        day: from_clock(day),
+        hour,
+        minute: from_clock(minute),
+        second: from_clock(second),
+    };
+    let valid = (1..=12).contains(&time.month)
+        && (1..=31).contains(&time.day)
+        && time.hour < 24
+        && time.minute < 60
+        && time.second < 60;
+    valid.then_some(time)
+}
+
+fn read_register(register: u8) -> u8 {
+    let mut address = Port::<u8>::new(0x70);
+    let mut data = Port::<u8>::new(0x71);
+    unsafe {
+        address.write(register);
+        data.read()
+    }
+}
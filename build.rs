@@ -10,6 +10,14 @@ fn main() {
     #[cfg(not(feature = "bios"))]
     async fn bios_main() {}
 
+    // the public key is compiled into the UEFI bootloader and BIOS stage 4
+    #[cfg(feature = "signed-kernel")]
+    println!("cargo:rerun-if-env-changed=BOOTLOADER_KERNEL_PUBLIC_KEY");
+
+    // the number of disk read attempts is compiled into BIOS stage 2
+    #[cfg(feature = "bios")]
+    println!("cargo:rerun-if-env-changed=BOOTLOADER_DISK_READ_ATTEMPTS");
+
     block_on((uefi_main(), bios_main()).join());
 }
 
@@ -74,6 +82,14 @@ async fn build_uefi_bootloader(out_dir: &Path) -> PathBuf {
         cmd.arg("--version").arg(BOOTLOADER_VERSION);
     }
     cmd.arg("--locked");
+    #[cfg(feature = "multiboot2")]
+    cmd.arg("--features").arg("multiboot2");
+    #[cfg(feature = "debug-shell")]
+    cmd.arg("--features").arg("debug-shell");
+    #[cfg(feature = "signed-kernel")]
+    cmd.arg("--features").arg("signed-kernel");
+    #[cfg(feature = "measured-boot")]
+    cmd.arg("--features").arg("measured-boot");
     cmd.arg("--target").arg("x86_64-unknown-uefi");
     cmd.arg("-Zbuild-std=core")
         .arg("-Zbuild-std-features=compiler-builtins-mem");
@@ -325,6 +341,14 @@ async fn build_bios_stage_4(out_dir: &Path) -> PathBuf {
         cmd.arg("--version").arg(BOOTLOADER_VERSION);
     }
     cmd.arg("--locked");
+    #[cfg(feature = "multiboot2")]
+    cmd.arg("--features").arg("multiboot2");
+    #[cfg(feature = "debug-shell")]
+    cmd.arg("--features").arg("debug-shell");
+    #[cfg(feature = "signed-kernel")]
+    cmd.arg("--features").arg("signed-kernel");
+    #[cfg(feature = "measured-boot")]
+    cmd.arg("--features").arg("measured-boot");
     cmd.arg("--target").arg("x86_64-stage-4.json");
     cmd.arg("--profile").arg("stage-4");
     cmd.arg("-Zbuild-std=core")
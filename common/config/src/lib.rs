@@ -25,6 +25,103 @@ pub struct BootConfig {
     /// Enabled by default.
     pub serial_logging: bool,
 
+    /// The colors used for printing log messages to the framebuffer.
+    ///
+    /// Defaults to light text on a black background.
+    pub log_colors: LogColors,
+
+    /// Configuration for a splash screen that is drawn to the framebuffer before the first log
+    /// message.
+    ///
+    /// Disabled by default.
+    pub splash: Splash,
+
+    /// Configuration for measuring the boot components into the PCRs of a TPM 2.0.
+    ///
+    /// Disabled by default.
+    pub measured_boot: MeasuredBoot,
+
+    /// The time in milliseconds that the bootloader waits before jumping to the kernel.
+    ///
+    /// Gives slowly syncing displays time to show the boot messages. The default is `0`,
+    /// i.e. no delay.
+    pub post_message_delay_ms: u64,
+
+    /// The time in milliseconds that the bootloader waits for a key press on the serial port
+    /// to enter the interactive debug shell.
+    ///
+    /// The shell is started right before the kernel is started and allows inspecting the
+    /// memory map, memory contents, and page table mappings. Only has an effect if the
+    /// bootloader was built with the `debug-shell` feature. The default is `0`, i.e. the
+    /// shell can't be entered.
+    pub debug_shell_timeout_ms: u64,
+
+    /// Whether the bootloader should halt instead of starting the kernel.
+    ///
+    /// In a dry run, the bootloader prepares everything for the kernel as usual, but instead
+    /// of jumping to the kernel entry point, it prints the system information, the kernel
+    /// mappings, the level 4 page table entries, and the memory map and then halts the CPU.
+    /// This is useful for debugging on hardware that reboots immediately on a fault.
+    ///
+    /// Disabled by default.
+    pub dry_run: bool,
+
+    /// Whether the bootloader should write back and invalidate all caches (`wbinvd`) and flush
+    /// the TLB before switching to the kernel address space.
+    ///
+    /// Only needed on hardware where the kernel otherwise sees stale page table entries.
+    /// Flushing the caches is slow, so this is disabled by default.
+    pub flush_caches: bool,
+
+    /// Whether the bootloader should touch every page of the kernel image after mapping it.
+    ///
+    /// Each page of the loaded kernel segments is looked up in the kernel page table and then
+    /// read, and written back if it is writable. A missing or broken mapping then panics in the
+    /// bootloader with the faulting address instead of crashing the kernel later. This is slow
+    /// for large kernels, so it is disabled by default.
+    pub prefault_kernel_pages: bool,
+
+    /// Whether the BIOS bootloader should sanitize the E820 memory map the way Linux does.
+    ///
+    /// Overlapping regions are resolved by giving the higher E820 type precedence, so that
+    /// e.g. reserved memory wins over overlapping usable memory. Adjacent regions of the same
+    /// type are merged afterwards. Has no effect on UEFI. Disabled by default.
+    pub sanitize_e820_like_linux: bool,
+
+    /// Whether the BIOS bootloader should backfill the E820 memory map with the memory sizes
+    /// reported by the legacy `INT 0x12` and `INT 0x15, AX=0xE801` functions.
+    ///
+    /// E820 takes precedence: only the parts of the legacy ranges that no E820 region covers
+    /// are added as usable memory. This recovers memory on legacy hardware with an incomplete
+    /// E820 map. Applied before
+    /// [`sanitize_e820_like_linux`](Self::sanitize_e820_like_linux). Has no effect on UEFI.
+    /// Disabled by default.
+    pub reconcile_legacy_memory_map: bool,
+
+    /// Whether the bootloader should protect its own code in the identity mapping it runs on.
+    ///
+    /// The code and read-only data of the bootloader are mapped read-only and all other memory
+    /// is mapped non-executable, so that wild writes of the bootloader fault instead of
+    /// silently corrupting it. This only affects the bootloader address space, not the kernel.
+    /// Disabled by default.
+    pub protect_bootloader_code: bool,
+
+    /// Whether the bootloader should check that the kernel fits into the available memory
+    /// before mapping it.
+    ///
+    /// The number of frames needed for the kernel segments, the kernel stack, and the page
+    /// tables is estimated up front and compared to the number of free frames. If there are
+    /// not enough, the bootloader panics with a clear error instead of failing an allocation
+    /// in the middle of the mapping code. The estimate is an upper bound, so a kernel that
+    /// barely fits might be rejected. Disabled by default.
+    pub check_kernel_memory_requirements: bool,
+
+    /// What the bootloader does after a panic.
+    ///
+    /// Rebooting allows automated test setups to detect and recover from bootloader errors.
+    /// The default is [`PanicBehavior::Halt`].
+    pub panic_behavior: PanicBehavior,
+
     #[doc(hidden)]
     pub _test_sentinel: u64,
 }
@@ -36,6 +133,19 @@ impl Default for BootConfig {
             log_level: Default::default(),
             frame_buffer_logging: true,
             serial_logging: true,
+            log_colors: Default::default(),
+            splash: Default::default(),
+            measured_boot: Default::default(),
+            post_message_delay_ms: 0,
+            debug_shell_timeout_ms: 0,
+            dry_run: false,
+            flush_caches: false,
+            prefault_kernel_pages: false,
+            sanitize_e820_like_linux: false,
+            reconcile_legacy_memory_map: false,
+            protect_bootloader_code: false,
+            check_kernel_memory_requirements: false,
+            panic_behavior: Default::default(),
             _test_sentinel: 0,
         }
     }
@@ -53,6 +163,147 @@ pub struct FrameBuffer {
     ///
     /// If this is not possible, the bootloader will fall back to a smaller format.
     pub minimum_framebuffer_width: Option<u64>,
+    /// Whether the bootloader should check that the framebuffer works by writing a test
+    /// pattern to a few pixels and reading it back.
+    ///
+    /// A warning is logged if the check fails. Disabled by default.
+    pub self_test: bool,
+}
+
+/// The colors used by the framebuffer logger.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct LogColors {
+    /// The color of the text.
+    ///
+    /// Defaults to a light, slightly warm white (`#ffff7f`).
+    pub foreground: Color,
+    /// The color of the background.
+    ///
+    /// Defaults to black.
+    pub background: Color,
+}
+
+impl Default for LogColors {
+    fn default() -> Self {
+        Self {
+            foreground: Color {
+                red: 0xff,
+                green: 0xff,
+                blue: 0x7f,
+            },
+            background: Color {
+                red: 0,
+                green: 0,
+                blue: 0,
+            },
+        }
+    }
+}
+
+/// A color with 8 bits per channel.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Color {
+    /// The red channel.
+    pub red: u8,
+    /// The green channel.
+    pub green: u8,
+    /// The blue channel.
+    pub blue: u8,
+}
+
+/// Configuration for the splash screen.
+///
+/// The splash screen is drawn when the framebuffer is set up, before any log messages. If it
+/// is enabled, the framebuffer logger doesn't clear the screen, so log messages are drawn on
+/// top of the splash screen. Disable
+/// [`frame_buffer_logging`](BootConfig::frame_buffer_logging) to only show the splash screen.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct Splash {
+    /// The color that the screen is cleared to before the logo is drawn.
+    ///
+    /// If `None`, the screen is cleared to the background color of the
+    /// [`log_colors`](BootConfig::log_colors).
+    pub clear_color: Option<Color>,
+    /// Whether the bootloader should draw the logo from the boot config blob centered on the
+    /// screen.
+    ///
+    /// The logo is taken from the first entry of the boot config blob with the key
+    /// [`SPLASH_LOGO_KEY`](Self::SPLASH_LOGO_KEY). Its value has the following format:
+    ///
+    /// | Offset | Size        | Content                                         |
+    /// |--------|-------------|-------------------------------------------------|
+    /// | 0      | 4           | width `w` as little-endian `u32`                |
+    /// | 4      | 4           | height `h` as little-endian `u32`               |
+    /// | 8      | `w * h * 3` | pixels, row by row, as `red, green, blue` bytes |
+    ///
+    /// Parts of a logo that is larger than the screen are clipped, keeping the center of the
+    /// logo visible.
+    pub logo: bool,
+}
+
+impl Splash {
+    /// The key of the boot config blob entry that contains the logo.
+    pub const SPLASH_LOGO_KEY: &'static str = "bootloader.splash-logo";
+
+    /// Returns whether a splash screen should be drawn.
+    pub fn enabled(&self) -> bool {
+        self.clear_color.is_some() || self.logo
+    }
+}
+
+/// Configuration for measuring the kernel and ramdisk into the PCRs of a TPM 2.0.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct MeasuredBoot {
+    /// Whether the bootloader should extend the PCRs of the TPM with the SHA-256 digests of
+    /// the kernel and the ramdisk before jumping to the kernel.
+    ///
+    /// If no TPM 2.0 is found, no measurements are taken. Only has an effect if the
+    /// bootloader was built with the `measured-boot` feature.
+    pub enabled: bool,
+    /// The PCR index that is extended with the digest of the kernel executable.
+    ///
+    /// Defaults to `8`.
+    pub kernel_pcr: u32,
+    /// The PCR index that is extended with the digest of the ramdisk, if there is one.
+    ///
+    /// Defaults to `9`.
+    pub ramdisk_pcr: u32,
+}
+
+impl Default for MeasuredBoot {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            kernel_pcr: 8,
+            ramdisk_pcr: 9,
+        }
+    }
+}
+
+/// The action that the bootloader takes after a panic.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PanicBehavior {
+    /// Halt the CPU forever, so that the panic message stays visible.
+    Halt,
+    /// Reset the machine through the ACPI reset register, falling back to the keyboard
+    /// controller and finally to a triple fault if the reset doesn't happen.
+    Reboot,
+    /// Deliberately cause a triple fault, which resets the machine or lets a virtual machine
+    /// report the failure.
+    TripleFault,
+}
+
+impl Default for PanicBehavior {
+    fn default() -> Self {
+        Self::Halt
+    }
 }
 
 /// An enum representing the available verbosity level filters of the logger.
@@ -0,0 +1,411 @@
+use bootloader_api::info::{
+    AcpiTable, AcpiTables, HpetInfo, IommuInfo, IommuKind, MemoryRegion, MemoryRegionAttributes,
+    Optional,
+};
+use x86_64::PhysAddr;
+
+/// The size of the header that all system description tables start with.
+const SDT_HEADER_LEN: u32 = 36;
+/// The address space ID of the system memory space in a generic address structure.
+const ADDRESS_SPACE_SYSTEM_MEMORY: u8 = 0;
+/// The offset of the first affinity structure in the SRAT.
+const SRAT_ENTRIES_OFFSET: u32 = SDT_HEADER_LEN + 12;
+/// The type of the SRAT memory affinity structure.
+const SRAT_MEMORY_AFFINITY: u8 = 1;
+/// The length of the SRAT memory affinity structure.
+const SRAT_MEMORY_AFFINITY_LEN: u32 = 40;
+/// Flags of the SRAT memory affinity structure.
+const SRAT_MEMORY_ENABLED: u32 = 1 << 0;
+const SRAT_MEMORY_HOT_PLUGGABLE: u32 = 1 << 1;
+const SRAT_MEMORY_NON_VOLATILE: u32 = 1 << 2;
+
+/// Looks up the HPET description table and returns the location of the HPET registers.
+///
+/// Returns `None` if there is no valid HPET table or if the registers are not memory-mapped.
+///
+/// ## Safety
+///
+/// The given RSDP address must point to a valid RSDP structure and all ACPI tables must be
+/// identity-mapped in the current address space.
+pub unsafe fn find_hpet(rsdp_addr: PhysAddr) -> Option<HpetInfo> {
+    let table = unsafe { find_table(rsdp_addr, *b"HPET") }?;
+    if unsafe { read::<u32>(table, 4) } < SDT_HEADER_LEN + 20 {
+        return None;
+    }
+
+    // the base address is a generic address structure at offset 40
+    let address_space = unsafe { read::<u8>(table, 40) };
+    if address_space != ADDRESS_SPACE_SYSTEM_MEMORY {
+        log::warn!("HPET registers are not memory-mapped");
+        return None;
+    }
+    Some(HpetInfo {
+        base: unsafe { read(table, 44) },
+        minimum_tick: unsafe { read(table, 53) },
+    })
+}
+
+/// Looks up the DMAR or IVRS table, which describe an Intel or AMD IOMMU.
+///
+/// Returns `None` if neither table is present with a valid checksum.
+///
+/// ## Safety
+///
+/// See [`find_hpet`].
+pub unsafe fn find_iommu(rsdp_addr: PhysAddr) -> Option<IommuInfo> {
+    [
+        (*b"DMAR", IommuKind::IntelVtd),
+        (*b"IVRS", IommuKind::AmdVi),
+    ]
+    .into_iter()
+    .find_map(|(signature, kind)| {
+        let table = unsafe { find_table(rsdp_addr, signature) }?;
+        Some(IommuInfo {
+            kind,
+            table_addr: table.as_u64(),
+        })
+    })
+}
+
+/// The offset of the `RESET_REG` generic address structure in the FADT.
+const FADT_RESET_REG_OFFSET: u64 = 116;
+/// The offset of the value that is written to the reset register in the FADT.
+const FADT_RESET_VALUE_OFFSET: u64 = 128;
+/// The FADT flag that indicates that the reset register is supported.
+const FADT_RESET_REG_SUP: u32 = 1 << 10;
+/// The address space ID of the system I/O space in a generic address structure.
+const ADDRESS_SPACE_SYSTEM_IO: u8 = 1;
+
+/// The reset register of the FADT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetRegister {
+    /// The register is an I/O port.
+    Io { port: u16, value: u8 },
+    /// The register is memory-mapped at the given physical address.
+    Memory { addr: u64, value: u8 },
+}
+
+/// Looks up the reset register in the FADT.
+///
+/// Returns `None` if there is no valid FADT, if the FADT is too old to contain a reset
+/// register, or if the reset register is not supported or not in the I/O or memory space.
+///
+/// ## Safety
+///
+/// See [`find_hpet`].
+pub unsafe fn find_reset_register(rsdp_addr: PhysAddr) -> Option<ResetRegister> {
+    let table = unsafe { find_table(rsdp_addr, *b"FACP") }?;
+    if u64::from(unsafe { read::<u32>(table, 4) }) <= FADT_RESET_VALUE_OFFSET {
+        return None;
+    }
+    let flags = unsafe { read::<u32>(table, 112) };
+    if flags & FADT_RESET_REG_SUP == 0 {
+        return None;
+    }
+
+    let address_space = unsafe { read::<u8>(table, FADT_RESET_REG_OFFSET) };
+    let addr = unsafe { read::<u64>(table, FADT_RESET_REG_OFFSET + 4) };
+    let value = unsafe { read::<u8>(table, FADT_RESET_VALUE_OFFSET) };
+    match address_space {
+        ADDRESS_SPACE_SYSTEM_IO => Some(ResetRegister::Io {
+            port: u16::try_from(addr).ok()?,
+            value,
+        }),
+        ADDRESS_SPACE_SYSTEM_MEMORY => Some(ResetRegister::Memory { addr, value }),
+        _ => None,
+    }
+}
+
+/// The signatures of the tables that are reported in [`BootInfo::acpi_tables`], in order.
+///
+/// [`BootInfo::acpi_tables`]: bootloader_api::BootInfo::acpi_tables
+const CACHED_TABLES: [[u8; 4]; 4] = [*b"FACP", *b"APIC", *b"MCFG", *b"HPET"];
+
+/// Looks up the FADT, MADT, MCFG, and HPET tables and returns the location of the ones that
+/// are present and have a valid checksum.
+///
+/// ## Safety
+///
+/// See [`find_hpet`].
+pub unsafe fn find_cached_tables(rsdp_addr: PhysAddr) -> AcpiTables {
+    let mut tables = AcpiTables::new();
+    for signature in CACHED_TABLES {
+        if let Some(addr) = unsafe { find_table(rsdp_addr, signature) } {
+            tables.push(AcpiTable {
+                signature,
+                len: unsafe { read(addr, 4) },
+                addr: addr.as_u64(),
+            });
+        }
+    }
+    tables
+}
+
+/// Sets the NUMA proximity domain and the hot-plug and non-volatile flags of the given memory
+/// regions from the memory affinity structures of the SRAT.
+///
+/// A region is only updated if it lies completely within an enabled memory range of the SRAT.
+/// Does nothing if there is no valid SRAT.
+///
+/// ## Safety
+///
+/// See [`find_hpet`].
+pub unsafe fn apply_memory_affinity(rsdp_addr: PhysAddr, regions: &mut [MemoryRegion]) {
+    let Some(table) = (unsafe { find_table(rsdp_addr, *b"SRAT") }) else {
+        return;
+    };
+    let len = unsafe { read::<u32>(table, 4) };
+    let mut offset = SRAT_ENTRIES_OFFSET;
+    while offset + 2 <= len {
+        let entry_type = unsafe { read::<u8>(table, offset.into()) };
+        let entry_len = u32::from(unsafe { read::<u8>(table, u64::from(offset) + 1) });
+        if entry_len < 2 || offset + entry_len > len {
+            log::warn!("SRAT contains an invalid affinity structure");
+            return;
+        }
+        if entry_type == SRAT_MEMORY_AFFINITY && entry_len >= SRAT_MEMORY_AFFINITY_LEN {
+            let entry = |field_offset: u32| u64::from(offset + field_offset);
+            let flags = unsafe { read::<u32>(table, entry(28)) };
+            if flags & SRAT_MEMORY_ENABLED != 0 {
+                let domain = unsafe { read::<u32>(table, entry(2)) };
+                let start = unsafe { read::<u64>(table, entry(8)) };
+                let end = start.saturating_add(unsafe { read::<u64>(table, entry(16)) });
+                for region in regions
+                    .iter_mut()
+                    .filter(|r| start <= r.start && r.end <= end)
+                {
+                    let attributes = &mut region.attributes;
+                    attributes.proximity_domain = Optional::Some(domain);
+                    if flags & SRAT_MEMORY_HOT_PLUGGABLE != 0 {
+                        attributes.flags |= MemoryRegionAttributes::HOT_PLUGGABLE;
+                    }
+                    if flags & SRAT_MEMORY_NON_VOLATILE != 0 {
+                        attributes.flags |= MemoryRegionAttributes::NON_VOLATILE;
+                    }
+                }
+            }
+        }
+        offset += entry_len;
+    }
+}
+
+/// Looks up the system description table with the given signature.
+///
+/// Uses the XSDT if the RSDP is from ACPI 2.0 or later and the RSDT otherwise. Tables with an
+/// invalid checksum are ignored.
+///
+/// ## Safety
+///
+/// See [`find_hpet`].
+unsafe fn find_table(rsdp_addr: PhysAddr, signature: [u8; 4]) -> Option<PhysAddr> {
+    let revision = unsafe { read::<u8>(rsdp_addr, 15) };
+    let xsdt_addr = if revision >= 2 {
+        unsafe { read::<u64>(rsdp_addr, 24) }
+    } else {
+        0
+    };
+    let (root, entry_size) = if xsdt_addr != 0 {
+        (PhysAddr::new(xsdt_addr), 8)
+    } else {
+        (
+            PhysAddr::new(unsafe { read::<u32>(rsdp_addr, 16) }.into()),
+            4,
+        )
+    };
+    if !unsafe { checksum_valid(root) } {
+        log::warn!("ACPI root table at {:#x} is invalid", root.as_u64());
+        return None;
+    }
+
+    let len = unsafe { read::<u32>(root, 4) };
+    (SDT_HEADER_LEN..len)
+        .step_by(entry_size)
+        .map(|offset| {
+            let addr = match entry_size {
+                8 => unsafe { read::<u64>(root, offset.into()) },
+                _ => unsafe { read::<u32>(root, offset.into()) }.into(),
+            };
+            PhysAddr::new(addr)
+        })
+        .find(|&table| {
+            let table_signature: [u8; 4] = unsafe { read(table, 0) };
+            table_signature == signature && unsafe { checksum_valid(table) }
+        })
+}
+
+/// Checks that all bytes of the system description table at the given address sum to zero.
+unsafe fn checksum_valid(table: PhysAddr) -> bool {
+    let len = unsafe { read::<u32>(table, 4) };
+    let sum = (0..len).fold(0u8, |sum, offset| {
+        sum.wrapping_add(unsafe { read::<u8>(table, offset.into()) })
+    });
+    len >= SDT_HEADER_LEN && sum == 0
+}
+
+/// Reads a value of type `T` at the given offset from an identity-mapped physical address.
+unsafe fn read<T: Copy>(addr: PhysAddr, offset: u64) -> T {
+    let ptr = (addr.as_u64() + offset) as *const T;
+    unsafe { ptr.read_unaligned() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a system description table with the given signature and body and a valid
+    /// checksum.
+    fn table(signature: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut table = signature.to_vec();
+        let len = SDT_HEADER_LEN as usize + body.len();
+        table.extend_from_slice(&u32::try_from(len).unwrap().to_le_bytes());
+        table.resize(SDT_HEADER_LEN as usize, 0);
+        table.extend_from_slice(body);
+        let sum = table.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        table[9] = 0u8.wrapping_sub(sum);
+        table
+    }
+
+    fn addr(table: &[u8]) -> u64 {
+        table.as_ptr() as u64
+    }
+
+    /// Creates an XSDT that points to the given tables and an ACPI 2.0 RSDP that points to the
+    /// XSDT. Returns both, as the tables are only referenced by their addresses.
+    fn rsdp_with_xsdt(tables: &[&[u8]]) -> (Vec<u8>, Vec<u8>) {
+        let xsdt_body: Vec<u8> = tables
+            .iter()
+            .flat_map(|table| addr(table).to_le_bytes())
+            .collect();
+        let xsdt = table(b"XSDT", &xsdt_body);
+        let mut rsdp = vec![0; 36];
+        rsdp[15] = 2;
+        rsdp[24..32].copy_from_slice(&addr(&xsdt).to_le_bytes());
+        (rsdp, xsdt)
+    }
+
+    #[test]
+    fn hpet_found_through_xsdt() {
+        let mut hpet_body = vec![0; 20];
+        hpet_body[8..16].copy_from_slice(&0xfed0_0000u64.to_le_bytes());
+        hpet_body[17..19].copy_from_slice(&0x80u16.to_le_bytes());
+        let hpet = table(b"HPET", &hpet_body);
+        let apic = table(b"APIC", &[0; 8]);
+        let (rsdp, _xsdt) = rsdp_with_xsdt(&[&apic, &hpet]);
+
+        let info = unsafe { find_hpet(PhysAddr::new(addr(&rsdp))) };
+        assert_eq!(
+            info,
+            Some(HpetInfo {
+                base: 0xfed0_0000,
+                minimum_tick: 0x80,
+            })
+        );
+    }
+
+    #[test]
+    fn cached_tables_found() {
+        let mcfg = table(b"MCFG", &[0; 12]);
+        let ssdt = table(b"SSDT", &[0; 4]);
+        let apic = table(b"APIC", &[0; 8]);
+        let mut facp = table(b"FACP", &[0; 16]);
+        facp[9] = facp[9].wrapping_add(1);
+        let (rsdp, _xsdt) = rsdp_with_xsdt(&[&mcfg, &ssdt, &apic, &facp]);
+
+        // the FADT is skipped because of its invalid checksum
+        let tables = unsafe { find_cached_tables(PhysAddr::new(addr(&rsdp))) };
+        assert_eq!(
+            *tables,
+            [
+                AcpiTable {
+                    signature: *b"APIC",
+                    len: 44,
+                    addr: addr(&apic),
+                },
+                AcpiTable {
+                    signature: *b"MCFG",
+                    len: 48,
+                    addr: addr(&mcfg),
+                },
+            ]
+        );
+        assert_eq!(tables.find(*b"MCFG").map(|t| t.len), Some(48));
+    }
+
+    #[test]
+    fn reset_register_from_fadt() {
+        let mut fadt_body = vec![0; 129 - SDT_HEADER_LEN as usize];
+        let body = |offset: u64| offset as usize - SDT_HEADER_LEN as usize;
+        fadt_body[body(112)..body(116)].copy_from_slice(&FADT_RESET_REG_SUP.to_le_bytes());
+        fadt_body[body(FADT_RESET_REG_OFFSET)] = ADDRESS_SPACE_SYSTEM_IO;
+        fadt_body[body(FADT_RESET_REG_OFFSET + 4)..body(FADT_RESET_REG_OFFSET + 12)]
+            .copy_from_slice(&0xcf9u64.to_le_bytes());
+        fadt_body[body(FADT_RESET_VALUE_OFFSET)] = 0x6;
+        let fadt = table(b"FACP", &fadt_body);
+        let (rsdp, _xsdt) = rsdp_with_xsdt(&[&fadt]);
+
+        let register = unsafe { find_reset_register(PhysAddr::new(addr(&rsdp))) };
+        assert_eq!(
+            register,
+            Some(ResetRegister::Io {
+                port: 0xcf9,
+                value: 0x6
+            })
+        );
+    }
+
+    #[test]
+    fn iommu_found_through_xsdt() {
+        let apic = table(b"APIC", &[0; 8]);
+        let ivrs = table(b"IVRS", &[0; 12]);
+        let (rsdp, _xsdt) = rsdp_with_xsdt(&[&apic, &ivrs]);
+
+        let info = unsafe { find_iommu(PhysAddr::new(addr(&rsdp))) };
+        assert_eq!(
+            info,
+            Some(IommuInfo {
+                kind: IommuKind::AmdVi,
+                table_addr: addr(&ivrs),
+            })
+        );
+    }
+
+    #[test]
+    fn memory_affinity_from_srat() {
+        // SRAT with one enabled, hot-pluggable memory range in domain 3
+        let mut affinity = vec![0; 40];
+        affinity[0] = SRAT_MEMORY_AFFINITY;
+        affinity[1] = 40;
+        affinity[2..6].copy_from_slice(&3u32.to_le_bytes());
+        affinity[8..16].copy_from_slice(&0x1_0000_0000u64.to_le_bytes());
+        affinity[16..24].copy_from_slice(&0x1_0000_0000u64.to_le_bytes());
+        affinity[28..32]
+            .copy_from_slice(&(SRAT_MEMORY_ENABLED | SRAT_MEMORY_HOT_PLUGGABLE).to_le_bytes());
+        let mut srat_body = vec![0; 12];
+        srat_body.extend_from_slice(&affinity);
+        let srat = table(b"SRAT", &srat_body);
+        let (rsdp, _xsdt) = rsdp_with_xsdt(&[&srat]);
+
+        let region = |start, end| MemoryRegion {
+            start,
+            end,
+            ..MemoryRegion::empty()
+        };
+        let mut regions = [
+            region(0x1000, 0x9f000),
+            region(0x1_0000_0000, 0x1_8000_0000),
+            region(0x1_8000_0000, 0x2_8000_0000),
+        ];
+        unsafe { apply_memory_affinity(PhysAddr::new(addr(&rsdp)), &mut regions) };
+
+        assert_eq!(regions[0].attributes, MemoryRegionAttributes::empty());
+        assert_eq!(
+            regions[1].attributes,
+            MemoryRegionAttributes {
+                flags: MemoryRegionAttributes::HOT_PLUGGABLE,
+                proximity_domain: Optional::Some(3),
+            }
+        );
+        // only partially covered by the SRAT range
+        assert_eq!(regions[2].attributes, MemoryRegionAttributes::empty());
+    }
+}
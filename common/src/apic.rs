@@ -0,0 +1,47 @@
+use raw_cpuid::CpuId;
+use x86_64::registers::model_specific::Msr;
+
+/// The `IA32_APIC_BASE` model-specific register.
+const IA32_APIC_BASE: u32 = 0x1b;
+/// Set in `IA32_APIC_BASE` if the local APIC is in x2APIC mode.
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+/// Mask for the physical base address in `IA32_APIC_BASE`.
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Information about the local APIC of the bootstrap processor.
+pub struct ApicInfo {
+    /// The local APIC ID of the current processor.
+    pub apic_id: u32,
+    /// The physical base address of the local APIC registers.
+    pub lapic_base: u64,
+    /// Whether the local APIC is in x2APIC mode.
+    pub x2apic_enabled: bool,
+}
+
+/// Reads the APIC ID and the local APIC base address of the current processor.
+///
+/// Returns `None` if the CPU has no local APIC.
+pub fn detect() -> Option<ApicInfo> {
+    let cpuid = CpuId::new();
+    let feature_info = cpuid.get_feature_info()?;
+    if !feature_info.has_apic() {
+        return None;
+    }
+
+    // reading the MSR is safe because its existence is indicated by the APIC feature flag
+    let apic_base = unsafe { Msr::new(IA32_APIC_BASE).read() };
+    let x2apic_enabled = apic_base & APIC_BASE_X2APIC_ENABLE != 0;
+
+    // the initial APIC ID in leaf 1 is only 8 bits wide, so use the full x2APIC ID if available
+    let apic_id = cpuid
+        .get_extended_topology_info()
+        .and_then(|mut levels| levels.next())
+        .map(|level| level.x2apic_id())
+        .unwrap_or_else(|| feature_info.initial_local_apic_id().into());
+
+    Some(ApicInfo {
+        apic_id,
+        lapic_base: apic_base & APIC_BASE_ADDRESS_MASK,
+        x2apic_enabled,
+    })
+}
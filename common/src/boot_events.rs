@@ -0,0 +1,104 @@
+use bootloader_api::info::{BootEvent, BootEventId, BootEvents};
+use conquer_once::spin::OnceCell;
+use core::mem::MaybeUninit;
+use raw_cpuid::CpuId;
+use spinning_top::{const_spinlock, Spinlock};
+
+/// The events that were recorded so far.
+static BOOT_EVENTS: Spinlock<BootEventLog> = const_spinlock(BootEventLog::new());
+
+/// Whether the CPU supports the `RDTSC` instruction.
+static TSC_SUPPORTED: OnceCell<bool> = OnceCell::uninit();
+
+/// Records a boot event with the current value of the time stamp counter.
+///
+/// Events are dropped once [`BootEvents::CAPACITY`] events were recorded.
+pub fn record(id: BootEventId, payload: u64) {
+    let event = BootEvent {
+        id,
+        tsc: read_tsc(),
+        payload,
+    };
+    BOOT_EVENTS.lock().push(event);
+}
+
+/// Copies the recorded events to `dst` and returns the initialized part of it.
+pub(crate) fn copy_to(dst: &mut [MaybeUninit<BootEvent>]) -> &mut [BootEvent] {
+    BOOT_EVENTS.lock().copy_to(dst)
+}
+
+fn read_tsc() -> u64 {
+    let supported = *TSC_SUPPORTED.get_or_init(|| {
+        CpuId::new()
+            .get_feature_info()
+            .map_or(false, |info| info.has_tsc())
+    });
+    if supported {
+        // SAFETY: We checked that the cpu supports `RDTSC` and we run in ring 0.
+        unsafe { core::arch::x86_64::_rdtsc() }
+    } else {
+        0
+    }
+}
+
+/// A list of boot events with a fixed capacity.
+struct BootEventLog {
+    events: [BootEvent; BootEvents::CAPACITY],
+    len: usize,
+}
+
+impl BootEventLog {
+    const fn new() -> Self {
+        const EMPTY: BootEvent = BootEvent {
+            id: BootEventId(0),
+            tsc: 0,
+            payload: 0,
+        };
+        Self {
+            events: [EMPTY; BootEvents::CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, event: BootEvent) {
+        if let Some(slot) = self.events.get_mut(self.len) {
+            *slot = event;
+            self.len += 1;
+        }
+    }
+
+    fn copy_to<'a>(&self, dst: &'a mut [MaybeUninit<BootEvent>]) -> &'a mut [BootEvent] {
+        let len = usize::min(self.len, dst.len());
+        for (dst, event) in dst.iter_mut().zip(&self.events[..len]) {
+            dst.write(*event);
+        }
+        let initialized = &mut dst[..len];
+        unsafe {
+            // inlined variant of: `MaybeUninit::slice_assume_init_mut(initialized)`
+            // TODO: undo inlining when `slice_assume_init_mut` becomes stable
+            &mut *(initialized as *mut [_] as *mut [BootEvent])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_beyond_capacity_are_dropped() {
+        let mut log = BootEventLog::new();
+        for i in 0..BootEvents::CAPACITY + 1 {
+            log.push(BootEvent {
+                id: BootEventId::BOOTLOADER_START,
+                tsc: i as u64,
+                payload: 0,
+            });
+        }
+
+        let mut dst = vec![MaybeUninit::uninit(); BootEvents::CAPACITY + 1];
+        let events = log.copy_to(&mut dst);
+        assert_eq!(events.len(), BootEvents::CAPACITY);
+        assert!(events.iter().enumerate().all(|(i, e)| e.tsc == i as u64));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{delay, serial::SerialPort, PageTables};
+use bootloader_api::info::MemoryRegion;
+use core::fmt::Write;
+use x86_64::{
+    structures::paging::{
+        mapper::{MappedFrame, TranslateResult},
+        PageTableFlags, Translate,
+    },
+    VirtAddr,
+};
+
+/// The maximum length of a command line.
+const MAX_LINE_LEN: usize = 80;
+/// The number of bytes that `read` dumps if no length is given.
+const DEFAULT_READ_LEN: u64 = 0x40;
+/// The maximum number of bytes that `read` dumps at once.
+const MAX_READ_LEN: u64 = 0x1000;
+
+const HELP: &str = "\
+Commands (all numbers are hexadecimal):
+  help                 show this help
+  memmap               print the memory map that is passed to the kernel
+  read <addr> [len]    dump memory of the bootloader address space
+  write <addr> <byte>  write a byte to the bootloader address space
+  translate <addr>     translate a virtual address of the kernel address space
+  boot                 leave the shell and start the kernel
+";
+
+/// Waits up to `timeout_ms` milliseconds for a key press on the serial port and runs the
+/// debug shell if a key was pressed.
+///
+/// Returns when the shell is left through the `boot` command. The serial port is
+/// reinitialized for this, so the serial logger must not be used while the shell runs.
+pub fn run_if_requested(
+    timeout_ms: u64,
+    page_tables: &PageTables,
+    memory_regions: &[MemoryRegion],
+) {
+    let mut serial = unsafe { SerialPort::init() };
+    writeln!(
+        serial,
+        "Press any key within {timeout_ms} ms to enter the debug shell"
+    )
+    .unwrap();
+    let pressed = (0..timeout_ms).any(|_| {
+        let pressed = serial.try_receive().is_some();
+        if !pressed {
+            delay::busy_wait_ms(1);
+        }
+        pressed
+    });
+    if pressed {
+        let mut shell = Shell {
+            serial,
+            page_tables,
+            memory_regions,
+        };
+        shell.run();
+    }
+}
+
+struct Shell<'a> {
+    serial: SerialPort,
+    page_tables: &'a PageTables,
+    memory_regions: &'a [MemoryRegion],
+}
+
+impl Shell<'_> {
+    fn run(&mut self) {
+        writeln!(
+            self.serial,
+            "\nDebug shell, type `help` for a list of commands"
+        )
+        .unwrap();
+        let mut buffer = [0; MAX_LINE_LEN];
+        loop {
+            write!(self.serial, "> ").unwrap();
+            let len = self.read_line(&mut buffer);
+            // only printable ASCII characters are stored in the buffer
+            let line = core::str::from_utf8(&buffer[..len]).unwrap();
+            let mut args = line.split_whitespace();
+            let result = match args.next() {
+                None => Ok(()),
+                Some("help") => self.serial.write_str(HELP).map_err(|_| "write failed"),
+                Some("memmap") => self.memory_map(),
+                Some("read") => self.read(args.next(), args.next()),
+                Some("write") => self.write(args.next(), args.next()),
+                Some("translate") => self.translate(args.next()),
+                Some("boot") => return,
+                Some(_) => Err("unknown command, type `help` for a list of commands"),
+            };
+            if let Err(err) = result {
+                writeln!(self.serial, "error: {err}").unwrap();
+            }
+        }
+    }
+
+    /// Reads a line into the given buffer and returns its length.
+    ///
+    /// Echoes the typed characters and supports backspace. Non-printable characters and
+    /// characters exceeding the buffer size are ignored.
+    fn read_line(&mut self, buffer: &mut [u8]) -> usize {
+        let mut len = 0;
+        loop {
+            match self.serial.receive() {
+                b'\r' | b'\n' => {
+                    writeln!(self.serial).unwrap();
+                    return len;
+                }
+                0x08 | 0x7f if len > 0 => {
+                    len -= 1;
+                    write!(self.serial, "\x08 \x08").unwrap();
+                }
+                c @ b' '..=b'~' if len < buffer.len() => {
+                    buffer[len] = c;
+                    len += 1;
+                    write!(self.serial, "{}", char::from(c)).unwrap();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn memory_map(&mut self) -> Result<(), &'static str> {
+        for region in self.memory_regions {
+            writeln!(
+                self.serial,
+                "{:#018x} - {:#018x} {:?}",
+                region.start, region.end, region.kind
+            )
+            .unwrap();
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, addr: Option<&str>, len: Option<&str>) -> Result<(), &'static str> {
+        let addr = parse_addr(addr)?;
+        let len = len.map_or(Ok(DEFAULT_READ_LEN), parse_number)?;
+        if len > MAX_READ_LEN {
+            return Err("length too large");
+        }
+        self.check_mapped(addr, len, PageTableFlags::PRESENT)?;
+
+        for line_start in (0..len).step_by(16) {
+            let line_addr = addr + line_start;
+            write!(self.serial, "{:#018x}:", line_addr.as_u64()).unwrap();
+            for offset in line_start..len.min(line_start + 16) {
+                let byte = unsafe { (addr + offset).as_ptr::<u8>().read_volatile() };
+                write!(self.serial, " {byte:02x}").unwrap();
+            }
+            writeln!(self.serial).unwrap();
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, addr: Option<&str>, value: Option<&str>) -> Result<(), &'static str> {
+        let addr = parse_addr(addr)?;
+        let value = u8::try_from(parse_number(value.ok_or("missing value")?)?)
+            .map_err(|_| "value must be a single byte")?;
+        self.check_mapped(addr, 1, PageTableFlags::PRESENT | PageTableFlags::WRITABLE)?;
+
+        unsafe { addr.as_mut_ptr::<u8>().write_volatile(value) };
+        Ok(())
+    }
+
+    fn translate(&mut self, addr: Option<&str>) -> Result<(), &'static str> {
+        let addr = parse_addr(addr)?;
+        match self.page_tables.kernel.translate(addr) {
+            TranslateResult::Mapped {
+                frame,
+                offset,
+                flags,
+            } => {
+                let size = match frame {
+                    MappedFrame::Size4KiB(_) => "4KiB",
+                    MappedFrame::Size2MiB(_) => "2MiB",
+                    MappedFrame::Size1GiB(_) => "1GiB",
+                };
+                writeln!(
+                    self.serial,
+                    "{:#x} -> {:#x} ({size} page, {flags:?})",
+                    addr.as_u64(),
+                    (frame.start_address() + offset).as_u64()
+                )
+                .unwrap();
+            }
+            TranslateResult::NotMapped => writeln!(self.serial, "not mapped").unwrap(),
+            TranslateResult::InvalidFrameAddress(frame) => {
+                writeln!(self.serial, "invalid frame address {frame:?}").unwrap()
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that all pages of the given range are mapped with the given flags in the
+    /// bootloader address space, so that accessing them doesn't fault.
+    fn check_mapped(
+        &self,
+        addr: VirtAddr,
+        len: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), &'static str> {
+        let end = VirtAddr::try_new(addr.as_u64().checked_add(len).ok_or("range overflows")?)
+            .map_err(|_| "range is not canonical")?;
+        let mut page_addr = addr.align_down(0x1000u64);
+        while page_addr < end {
+            match self.page_tables.bootloader.translate(page_addr) {
+                TranslateResult::Mapped {
+                    flags: page_flags, ..
+                } if page_flags.contains(flags) => {}
+                TranslateResult::Mapped { .. } => return Err("memory is not writable"),
+                _ => return Err("memory is not mapped"),
+            }
+            match page_addr.as_u64().checked_add(0x1000) {
+                Some(next) => page_addr = VirtAddr::new_truncate(next),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_addr(arg: Option<&str>) -> Result<VirtAddr, &'static str> {
+    VirtAddr::try_new(parse_number(arg.ok_or("missing address")?)?)
+        .map_err(|_| "address is not canonical")
+}
+
+fn parse_number(arg: &str) -> Result<u64, &'static str> {
+    let digits = arg.strip_prefix("0x").unwrap_or(arg);
+    u64::from_str_radix(digits, 16).map_err(|_| "invalid hexadecimal number")
+}
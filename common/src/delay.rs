@@ -0,0 +1,36 @@
+use core::hint::spin_loop;
+use x86_64::instructions::port::Port;
+
+/// The frequency of the programmable interval timer (PIT) in Hz.
+const PIT_FREQUENCY: u64 = 1_193_182;
+/// The number of PIT ticks per millisecond.
+const TICKS_PER_MS: u16 = (PIT_FREQUENCY / 1000) as u16;
+
+/// Busy-waits for the given number of milliseconds.
+///
+/// Uses channel 2 of the PIT, which runs at a fixed frequency, so no calibration is needed.
+/// The speaker output of the channel is disabled while waiting.
+pub fn busy_wait_ms(ms: u64) {
+    let mut port_b = Port::<u8>::new(0x61);
+    let mut command = Port::<u8>::new(0x43);
+    let mut channel_2 = Port::<u8>::new(0x42);
+
+    unsafe {
+        let original_port_b = port_b.read();
+        // enable the gate of channel 2, but don't connect it to the speaker
+        port_b.write((original_port_b & !0b10) | 0b1);
+
+        for _ in 0..ms {
+            // channel 2, access mode lobyte/hibyte, mode 0 (interrupt on terminal count)
+            command.write(0b1011_0000);
+            channel_2.write(TICKS_PER_MS as u8);
+            channel_2.write((TICKS_PER_MS >> 8) as u8);
+            // the output of channel 2 goes high when the counter reaches zero
+            while port_b.read() & 0b10_0000 == 0 {
+                spin_loop();
+            }
+        }
+
+        port_b.write(original_port_b);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{Mappings, PageTables};
+use bootloader_api::BootInfo;
+use core::fmt;
+
+/// Logs everything that the bootloader prepared for the kernel and halts the CPU forever
+/// instead of starting the kernel.
+///
+/// The output goes through the normal loggers, so it appears on the framebuffer and the
+/// serial port, depending on the boot config.
+pub fn print_summary_and_halt(
+    boot_info: &BootInfo,
+    mappings: &Mappings,
+    page_tables: &PageTables,
+) -> ! {
+    log::info!("Dry run: not starting the kernel");
+    print_system_info(boot_info);
+    print_mappings(mappings);
+    print_page_tables(page_tables);
+    print_memory_map(boot_info);
+    log::info!("Dry run finished, halting");
+    loop {
+        x86_64::instructions::interrupts::disable();
+        x86_64::instructions::hlt();
+    }
+}
+
+fn print_system_info(boot_info: &BootInfo) {
+    match boot_info.framebuffer() {
+        Some(framebuffer) => {
+            let info = framebuffer.info();
+            log::info!(
+                "Framebuffer: {}x{} ({:?}, {} bytes per pixel, stride {}) at {:#x}",
+                info.width,
+                info.height,
+                info.pixel_format,
+                info.bytes_per_pixel,
+                info.stride,
+                framebuffer.buffer().as_ptr() as u64
+            );
+        }
+        None => log::info!("Framebuffer: none"),
+    }
+    match boot_info.rsdp_address() {
+        Some(addr) => log::info!("RSDP: {addr:#x}"),
+        None => log::info!("RSDP: none"),
+    }
+    match boot_info.ramdisk() {
+        Some((addr, len)) => log::info!("Ramdisk: {addr:#x}, {len:#x} bytes"),
+        None => log::info!("Ramdisk: none"),
+    }
+    log::info!(
+        "Kernel: {:#x}, {:#x} bytes",
+        boot_info.kernel_addr,
+        boot_info.kernel_len
+    );
+}
+
+fn print_mappings(mappings: &Mappings) {
+    log::info!("Entry point: {:#x}", mappings.entry_point.as_u64());
+    log::info!("Stack top: {:#x}", mappings.stack_top.as_u64());
+    log::info!(
+        "Kernel image offset: {:#x}",
+        mappings.kernel_image_offset.as_u64()
+    );
+    if let Some(offset) = mappings.physical_memory_offset {
+        log::info!("Physical memory offset: {:#x}", offset.as_u64());
+    }
+    if let Some(index) = mappings.recursive_index {
+        log::info!("Recursive index: {}", u16::from(index));
+    }
+    if let Some(gdt) = &mappings.gdt {
+        log::info!("GDT: {:#x}", gdt.gdt_addr);
+    }
+}
+
+fn print_page_tables(page_tables: &PageTables) {
+    log::info!("Kernel page tables:");
+    let mut writer = LineLogger {
+        line: [0; MAX_LINE_LEN],
+        len: 0,
+    };
+    page_tables.dump(&mut writer).unwrap();
+}
+
+/// The maximum length of a line that [`LineLogger`] logs, longer lines are split.
+const MAX_LINE_LEN: usize = 160;
+
+/// Logs each line that is written to it as a separate log message.
+struct LineLogger {
+    line: [u8; MAX_LINE_LEN],
+    len: usize,
+}
+
+impl LineLogger {
+    fn flush(&mut self) {
+        // the page table dump is ASCII, so splitting long lines never splits a character
+        let line = core::str::from_utf8(&self.line[..self.len]).unwrap_or("<invalid>");
+        log::info!("{line}");
+        self.len = 0;
+    }
+}
+
+impl fmt::Write for LineLogger {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if byte == b'\n' {
+                self.flush();
+                continue;
+            }
+            if self.len == MAX_LINE_LEN {
+                self.flush();
+            }
+            self.line[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+fn print_memory_map(boot_info: &BootInfo) {
+    log::info!("Memory map:");
+    for region in boot_info.memory_regions() {
+        log::info!(
+            "  {:#018x} - {:#018x} {:?}",
+            region.start,
+            region.end,
+            region.kind
+        );
+    }
+}
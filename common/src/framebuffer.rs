@@ -1,4 +1,5 @@
 use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+use bootloader_boot_config::{Color, LogColors, Splash};
 use core::{fmt, ptr};
 use font_constants::BACKUP_CHAR;
 use noto_sans_mono_bitmap::{
@@ -43,24 +44,190 @@ fn get_char_raster(c: char) -> RasterizedChar {
     get(c).unwrap_or_else(|| get(BACKUP_CHAR).expect("Should get raster of backup char."))
 }
 
+/// Clamps the `byte_len` of the given framebuffer info to the size covered by its dimensions.
+///
+/// Some firmwares report a padded framebuffer region, so a kernel that clears the whole
+/// reported buffer would write to adjacent memory. The length is never increased, as the
+/// memory after the reported region might not belong to the framebuffer.
+pub fn clamp_byte_len(mut info: FrameBufferInfo) -> FrameBufferInfo {
+    info.byte_len = info.byte_len.min(info.height * info.bytes_per_scanline());
+    info
+}
+
+/// Writes a test pattern to a few pixels of the framebuffer and checks that it can be read back.
+///
+/// The original pixel values are restored afterwards. Returns `false` if a written value could
+/// not be read back, which indicates a broken framebuffer mapping.
+pub fn self_test(framebuffer: &mut [u8], info: FrameBufferInfo) -> bool {
+    const PATTERN: [u8; 4] = [0x55, 0xaa, 0x0f, 0xf0];
+
+    if info.width == 0 || info.height == 0 {
+        return false;
+    }
+    let last_line = info.height - 1;
+    let pixels = [
+        (0, 0),
+        (info.width / 2, last_line / 2),
+        (info.width - 1, last_line),
+    ];
+    pixels.into_iter().all(|(x, y)| {
+        let offset = y * info.bytes_per_scanline() + x * info.bytes_per_pixel;
+        let Some(bytes) = framebuffer.get_mut(offset..offset + info.bytes_per_pixel) else {
+            return false;
+        };
+        let mut matches = true;
+        for (byte, pattern) in bytes.iter_mut().zip(PATTERN.into_iter().cycle()) {
+            let ptr: *mut u8 = byte;
+            unsafe {
+                let original = ptr::read_volatile(ptr);
+                ptr::write_volatile(ptr, pattern);
+                matches &= ptr::read_volatile(ptr) == pattern;
+                ptr::write_volatile(ptr, original);
+            }
+        }
+        matches
+    })
+}
+
+/// A logo for the splash screen in the format described in [`Splash::logo`].
+#[derive(Debug, Clone, Copy)]
+pub struct Logo<'a> {
+    width: usize,
+    height: usize,
+    pixels: &'a [u8],
+}
+
+impl<'a> Logo<'a> {
+    /// Parses a logo from the raw bytes of the boot config blob entry.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, &'static str> {
+        let (header, pixels) = bytes
+            .split_first_chunk::<8>()
+            .ok_or("splash logo too short")?;
+        let width = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let height = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+        let len = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or("splash logo too large")?;
+        let pixels = pixels
+            .get(..len)
+            .ok_or("splash logo pixel data too short")?;
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Looks up the logo in the given boot config blob.
+    ///
+    /// Returns `None` if the blob has no [`Splash::SPLASH_LOGO_KEY`] entry.
+    pub fn from_boot_config_blob(blob: &'a [u8]) -> Option<Result<Self, &'static str>> {
+        bootloader_api::config_blob::entries(blob)
+            .map_while(Result::ok)
+            .find(|(key, _)| *key == Splash::SPLASH_LOGO_KEY.as_bytes())
+            .map(|(_, value)| Self::parse(value))
+    }
+}
+
+/// Draws the splash screen: clears the screen and draws the given logo centered on it.
+///
+/// The screen is cleared to the `clear_color` of the splash config, or to `fallback_color` if
+/// none is set. Parts of a logo that is larger than the screen are clipped evenly on both
+/// sides. Returns an error if the pixel format of the framebuffer is not supported.
+pub fn draw_splash(
+    framebuffer: &mut [u8],
+    info: FrameBufferInfo,
+    splash: Splash,
+    fallback_color: Color,
+    logo: Option<Logo>,
+) -> Result<(), &'static str> {
+    let bytes_per_pixel = info.bytes_per_pixel;
+    let mut write_pixel = |x: usize, y: usize, color: Color| {
+        let encoded = encode_color(color, info.pixel_format)?;
+        let byte_offset = y * info.bytes_per_scanline() + x * bytes_per_pixel;
+        if let Some(pixel) = framebuffer.get_mut(byte_offset..byte_offset + bytes_per_pixel) {
+            pixel.copy_from_slice(&encoded[..bytes_per_pixel]);
+        }
+        Ok(())
+    };
+
+    let clear_color = splash.clear_color.unwrap_or(fallback_color);
+    for y in 0..info.height {
+        for x in 0..info.width {
+            write_pixel(x, y, clear_color)?;
+        }
+    }
+
+    if let Some(logo) = logo {
+        let visible_width = logo.width.min(info.width);
+        let visible_height = logo.height.min(info.height);
+        let (screen_x, logo_x) = (
+            (info.width - visible_width) / 2,
+            (logo.width - visible_width) / 2,
+        );
+        let (screen_y, logo_y) = (
+            (info.height - visible_height) / 2,
+            (logo.height - visible_height) / 2,
+        );
+        for y in 0..visible_height {
+            for x in 0..visible_width {
+                let offset = ((logo_y + y) * logo.width + logo_x + x) * 3;
+                let color = Color {
+                    red: logo.pixels[offset],
+                    green: logo.pixels[offset + 1],
+                    blue: logo.pixels[offset + 2],
+                };
+                write_pixel(screen_x + x, screen_y + y, color)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Encodes the given color in the given pixel format.
+///
+/// Returns an error for unsupported pixel formats. For [`PixelFormat::U8`], only the brightest
+/// channel is used.
+fn encode_color(color: Color, pixel_format: PixelFormat) -> Result<[u8; 4], &'static str> {
+    let Color { red, green, blue } = color;
+    match pixel_format {
+        PixelFormat::Rgb => Ok([red, green, blue, 0]),
+        PixelFormat::Bgr => Ok([blue, green, red, 0]),
+        PixelFormat::U8 => Ok([red.max(green).max(blue) >> 4, 0, 0, 0]),
+        _ => Err("unsupported pixel format"),
+    }
+}
+
 /// Allows logging text to a pixel-based framebuffer.
 pub struct FrameBufferWriter {
     framebuffer: &'static mut [u8],
     info: FrameBufferInfo,
+    colors: LogColors,
     x_pos: usize,
     y_pos: usize,
 }
 
 impl FrameBufferWriter {
-    /// Creates a new logger that uses the given framebuffer.
-    pub fn new(framebuffer: &'static mut [u8], info: FrameBufferInfo) -> Self {
+    /// Creates a new logger that uses the given framebuffer and colors.
+    ///
+    /// The screen is cleared unless `keep_contents` is set, e.g. to keep a splash screen.
+    pub fn new(
+        framebuffer: &'static mut [u8],
+        info: FrameBufferInfo,
+        colors: LogColors,
+        keep_contents: bool,
+    ) -> Self {
         let mut logger = Self {
             framebuffer,
             info,
-            x_pos: 0,
-            y_pos: 0,
+            colors,
+            x_pos: BORDER_PADDING,
+            y_pos: BORDER_PADDING,
         };
-        logger.clear();
+        if !keep_contents {
+            logger.clear();
+        }
         logger
     }
 
@@ -77,7 +244,17 @@ impl FrameBufferWriter {
     pub fn clear(&mut self) {
         self.x_pos = BORDER_PADDING;
         self.y_pos = BORDER_PADDING;
-        self.framebuffer.fill(0);
+        let background = self.colors.background;
+        if background == Color::default() {
+            // black is all zeros in every supported pixel format
+            self.framebuffer.fill(0);
+        } else {
+            for y in 0..self.height() {
+                for x in 0..self.width() {
+                    self.write_pixel(x, y, 0);
+                }
+            }
+        }
     }
 
     fn width(&self) -> usize {
@@ -120,27 +297,45 @@ impl FrameBufferWriter {
         self.x_pos += rendered_char.width() + LETTER_SPACING;
     }
 
+    /// Writes a pixel that blends the foreground and background color according to the
+    /// given glyph `intensity`.
     fn write_pixel(&mut self, x: usize, y: usize, intensity: u8) {
-        let pixel_offset = y * self.info.stride + x;
         let color = match self.info.pixel_format {
-            PixelFormat::Rgb => [intensity, intensity, intensity / 2, 0],
-            PixelFormat::Bgr => [intensity / 2, intensity, intensity, 0],
-            PixelFormat::U8 => [if intensity > 200 { 0xf } else { 0 }, 0, 0, 0],
-            other => {
-                // set a supported (but invalid) pixel format before panicking to avoid a double
-                // panic; it might not be readable though
-                self.info.pixel_format = PixelFormat::Rgb;
-                panic!("pixel format {:?} not supported in logger", other)
-            }
+            // only supports two brightness levels
+            PixelFormat::U8 if intensity > 200 => self.colors.foreground,
+            PixelFormat::U8 => self.colors.background,
+            _ => blend(self.colors, intensity),
+        };
+        let Ok(color) = encode_color(color, self.info.pixel_format) else {
+            // set a supported (but invalid) pixel format before panicking to avoid a double
+            // panic; it might not be readable though
+            let other = self.info.pixel_format;
+            self.info.pixel_format = PixelFormat::Rgb;
+            panic!("pixel format {:?} not supported in logger", other)
         };
         let bytes_per_pixel = self.info.bytes_per_pixel;
-        let byte_offset = pixel_offset * bytes_per_pixel;
+        let byte_offset = y * self.info.bytes_per_scanline() + x * bytes_per_pixel;
         self.framebuffer[byte_offset..(byte_offset + bytes_per_pixel)]
             .copy_from_slice(&color[..bytes_per_pixel]);
         let _ = unsafe { ptr::read_volatile(&self.framebuffer[byte_offset]) };
     }
 }
 
+/// Linearly interpolates between the background and the foreground color.
+fn blend(colors: LogColors, intensity: u8) -> Color {
+    let channel = |background: u8, foreground: u8| {
+        let background = u16::from(background);
+        let foreground = u16::from(foreground);
+        let intensity = u16::from(intensity);
+        ((background * (255 - intensity) + foreground * intensity) / 255) as u8
+    };
+    Color {
+        red: channel(colors.background.red, colors.foreground.red),
+        green: channel(colors.background.green, colors.foreground.green),
+        blue: channel(colors.background.blue, colors.foreground.blue),
+    }
+}
+
 unsafe impl Send for FrameBufferWriter {}
 unsafe impl Sync for FrameBufferWriter {}
 
@@ -152,3 +347,57 @@ impl fmt::Write for FrameBufferWriter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bootloader_api::info::Optional;
+
+    #[test]
+    fn splash_logo_is_centered_and_clipped() {
+        let info = FrameBufferInfo {
+            byte_len: 4 * 3 * 4,
+            width: 3,
+            height: 3,
+            pixel_format: PixelFormat::Bgr,
+            bytes_per_pixel: 4,
+            stride: 4,
+            vbe_mode: Optional::None,
+        };
+        let color = |value| Color {
+            red: value,
+            green: 0,
+            blue: 0,
+        };
+        let clear_color = color(0xff);
+        let mut splash = Splash::default();
+        splash.clear_color = Some(clear_color);
+        splash.logo = true;
+        let pixel = |framebuffer: &[u8], x: usize, y: usize| {
+            let offset = y * info.bytes_per_scanline() + x * info.bytes_per_pixel;
+            framebuffer[offset + 2]
+        };
+
+        // a 1x1 logo is drawn in the center
+        let mut framebuffer = [0; 4 * 3 * 4];
+        let logo = Logo::parse(&[1, 0, 0, 0, 1, 0, 0, 0, 0x42, 0, 0]).unwrap();
+        draw_splash(&mut framebuffer, info, splash, color(0), Some(logo)).unwrap();
+        assert_eq!(pixel(&framebuffer, 1, 1), 0x42);
+        assert_eq!(pixel(&framebuffer, 0, 0), 0xff);
+        assert_eq!(pixel(&framebuffer, 2, 2), 0xff);
+        // the padding after each line is not touched
+        assert_eq!(framebuffer[3 * 4..4 * 4], [0; 4]);
+
+        // a 5x1 logo is clipped to its center three pixels
+        let mut bytes = vec![5, 0, 0, 0, 1, 0, 0, 0];
+        for value in 1..=5 {
+            bytes.extend_from_slice(&[value, 0, 0]);
+        }
+        let logo = Logo::parse(&bytes).unwrap();
+        draw_splash(&mut framebuffer, info, splash, color(0), Some(logo)).unwrap();
+        assert_eq!([0, 1, 2].map(|x| pixel(&framebuffer, x, 1)), [2, 3, 4]);
+        assert_eq!(pixel(&framebuffer, 1, 0), 0xff);
+
+        assert!(Logo::parse(&[2, 0, 0, 0, 2, 0, 0, 0, 0]).is_err());
+    }
+}
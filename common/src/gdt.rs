@@ -1,10 +1,17 @@
+use crate::PageTables;
+use bootloader_api::{config::GdtLayout, info::GdtInfo};
 use x86_64::{
-    instructions::segmentation::{self, Segment},
+    instructions::{
+        segmentation::{self, Segment},
+        tables,
+    },
     structures::{
-        gdt::{Descriptor, GlobalDescriptorTable},
-        paging::PhysFrame,
+        gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector},
+        paging::{FrameAllocator, Mapper, Page, PageSize, PageTableFlags, PhysFrame, Size4KiB},
+        tss::TaskStateSegment,
+        DescriptorTablePointer,
     },
-    VirtAddr,
+    PrivilegeLevel, VirtAddr,
 };
 
 pub fn create_and_load(frame: PhysFrame) {
@@ -30,3 +37,141 @@ pub fn create_and_load(frame: PhysFrame) {
         segmentation::SS::set_reg(data_selector);
     }
 }
+
+/// The number of interrupt stack table entries in the TSS.
+const IST_STACK_COUNT: u64 = 7;
+/// The size of each interrupt stack, excluding its guard page.
+const IST_STACK_SIZE: u64 = 4 * Size4KiB::SIZE;
+/// The interrupt stack table index that is reserved for double faults.
+const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+/// The offset of the TSS within the first page of the kernel GDT region.
+const TSS_OFFSET: u64 = 0x100;
+
+/// The size of the kernel GDT region in bytes.
+///
+/// The first page contains the GDT and the TSS. It is followed by the interrupt stacks, each
+/// with an unmapped guard page below it.
+pub const KERNEL_GDT_REGION_SIZE: u64 =
+    Size4KiB::SIZE + IST_STACK_COUNT * (Size4KiB::SIZE + IST_STACK_SIZE);
+
+/// Creates a GDT and a TSS for the kernel at the given virtual address and loads them.
+///
+/// The GDT entries are taken from the given layout. The region is mapped in both the kernel
+/// and the bootloader address space, so that the GDT can be loaded before switching to the
+/// kernel.
+pub fn create_and_load_for_kernel<A>(
+    start: Page,
+    layout: &GdtLayout,
+    page_tables: &mut PageTables,
+    frame_allocator: &mut A,
+) -> GdtInfo
+where
+    A: FrameAllocator<Size4KiB>,
+{
+    if let Err(err) = layout.validate() {
+        panic!("invalid GDT layout: {}", err);
+    }
+    let region_start = start.start_address();
+    log::info!("Creating kernel GDT and TSS at {:?}", region_start);
+
+    let ist_stack_top = |index: u64| {
+        region_start + Size4KiB::SIZE + (index + 1) * (Size4KiB::SIZE + IST_STACK_SIZE)
+    };
+
+    // map the GDT page and the interrupt stacks, but not the guard pages
+    let stack_pages = (0..IST_STACK_COUNT).flat_map(|index| {
+        let stack_top = ist_stack_top(index);
+        Page::range(
+            Page::containing_address(stack_top - IST_STACK_SIZE),
+            Page::containing_address(stack_top),
+        )
+    });
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    for page in core::iter::once(start).chain(stack_pages) {
+        let frame = frame_allocator
+            .allocate_frame()
+            .expect("frame allocation for kernel GDT failed");
+        match unsafe {
+            page_tables
+                .kernel
+                .map_to(page, frame, flags, frame_allocator)
+        } {
+            Ok(tlb) => tlb.flush(),
+            Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+        }
+        // we need to be able to access it too
+        match unsafe {
+            page_tables
+                .bootloader
+                .map_to(page, frame, flags, frame_allocator)
+        } {
+            Ok(tlb) => tlb.flush(),
+            Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+        }
+    }
+
+    let tss_addr = region_start + TSS_OFFSET;
+    let tss: &'static TaskStateSegment = {
+        let mut tss = TaskStateSegment::new();
+        // the TSS is packed, so we can't take references to its fields
+        let mut interrupt_stack_table = [VirtAddr::zero(); IST_STACK_COUNT as usize];
+        for (index, entry) in (0..).zip(interrupt_stack_table.iter_mut()) {
+            *entry = ist_stack_top(index);
+        }
+        tss.interrupt_stack_table = interrupt_stack_table;
+        let ptr: *mut TaskStateSegment = tss_addr.as_mut_ptr();
+        unsafe {
+            ptr.write(tss);
+            &*ptr
+        }
+    };
+
+    let mut entries = layout.entries;
+    let tss_index = usize::from(layout.tss_index);
+    match Descriptor::tss_segment(tss) {
+        Descriptor::SystemSegment(low, high) => {
+            entries[tss_index] = low;
+            entries[tss_index + 1] = high;
+        }
+        Descriptor::UserSegment(_) => unreachable!("TSS descriptor must be a system segment"),
+    }
+    let gdt_len = u16::from(layout.len) * 8;
+    let gdt: &'static [u64] = {
+        let ptr: *mut u64 = region_start.as_mut_ptr();
+        let len = usize::from(layout.len);
+        unsafe {
+            ptr.copy_from_nonoverlapping(entries.as_ptr(), len);
+            core::slice::from_raw_parts(ptr, len)
+        }
+    };
+    let selector = |index: u8| SegmentSelector::new(index.into(), PrivilegeLevel::Ring0);
+    let code_selector = selector(layout.code_index);
+    let data_selector = selector(layout.data_index);
+    let tss_selector = selector(layout.tss_index);
+
+    unsafe {
+        tables::lgdt(&DescriptorTablePointer {
+            limit: gdt_len - 1,
+            base: VirtAddr::from_ptr(gdt.as_ptr()),
+        });
+        segmentation::CS::set_reg(code_selector);
+        segmentation::DS::set_reg(data_selector);
+        segmentation::ES::set_reg(data_selector);
+        segmentation::SS::set_reg(data_selector);
+        tables::load_tss(tss_selector);
+    }
+
+    GdtInfo {
+        region_start: region_start.as_u64(),
+        region_len: KERNEL_GDT_REGION_SIZE,
+        gdt_addr: region_start.as_u64(),
+        gdt_len,
+        code_selector: code_selector.0,
+        data_selector: data_selector.0,
+        tss_selector: tss_selector.0,
+        tss_addr: tss_addr.as_u64(),
+        ist_stack_size: IST_STACK_SIZE,
+        double_fault_ist_index: DOUBLE_FAULT_IST_INDEX,
+        double_fault_stack_top: ist_stack_top(DOUBLE_FAULT_IST_INDEX.into()).as_u64(),
+    }
+}
@@ -0,0 +1,144 @@
+use core::ops::Range;
+use x86_64::{
+    structures::paging::{
+        page_table::PageTableEntry, FrameAllocator, OffsetPageTable, PageSize, PageTable,
+        PageTableFlags, Size1GiB, Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+/// Maps the code of the bootloader read-only and all other memory non-executable in the
+/// identity mapping that the bootloader runs on.
+///
+/// `code` are the physical address ranges of the executable code and read-only data of the
+/// bootloader. Huge pages that contain code are split into 4KiB pages, so that the code ranges
+/// are protected exactly. The no-execute and write protection bits are enabled first, so that
+/// the new flags are enforced immediately.
+pub fn protect_bootloader_code(
+    page_table: &mut OffsetPageTable<'static>,
+    code: &[Range<PhysAddr>],
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) {
+    crate::enable_nxe_bit();
+    crate::enable_write_protect_bit();
+
+    let phys_offset = page_table.phys_offset();
+    let level_4_table: *mut PageTable = page_table.level_4_table();
+
+    for range in code {
+        let mut addr = range.start.align_down(Size4KiB::SIZE);
+        while addr < range.end {
+            let virt = phys_offset + addr.as_u64();
+            unsafe { protect_code_page(level_4_table, virt, phys_offset, frame_allocator) };
+            addr += Size4KiB::SIZE;
+        }
+    }
+
+    let is_code = |addr: PhysAddr| {
+        code.iter()
+            .any(|range| (range.start.align_down(Size4KiB::SIZE)..range.end).contains(&addr))
+    };
+    unsafe { mark_data_no_execute(&mut *level_4_table, 4, phys_offset, &is_code) };
+
+    x86_64::instructions::tlb::flush_all();
+}
+
+/// Maps the 4KiB page at `virt` read-only and executable, splitting the huge page that contains
+/// it if necessary.
+///
+/// The page tables are modified in place instead of unmapping the huge page first, because the
+/// huge page might contain the running code or the stack.
+unsafe fn protect_code_page(
+    level_4_table: *mut PageTable,
+    virt: VirtAddr,
+    phys_offset: VirtAddr,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) {
+    let indices = [
+        virt.p4_index(),
+        virt.p3_index(),
+        virt.p2_index(),
+        virt.p1_index(),
+    ];
+    let mut table = level_4_table;
+    for (level, index) in (1..=4u8).rev().zip(indices) {
+        let entry = unsafe { &mut (*table)[index] };
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            panic!("bootloader code at {:?} is not mapped", virt);
+        }
+        if level == 1 {
+            entry.set_flags(flags - PageTableFlags::WRITABLE - PageTableFlags::NO_EXECUTE);
+            return;
+        }
+        if flags.contains(PageTableFlags::HUGE_PAGE) {
+            split_huge_page(entry, level, phys_offset, frame_allocator);
+        }
+        table = (phys_offset + entry.addr().as_u64()).as_mut_ptr();
+    }
+}
+
+/// Replaces the huge page mapped by the given entry of a level 3 or level 2 table by a table of
+/// pages of the next smaller size with the same flags.
+///
+/// The new table is filled completely before the entry is updated, so the mapped memory stays
+/// accessible at all times.
+fn split_huge_page(
+    entry: &mut PageTableEntry,
+    level: u8,
+    phys_offset: VirtAddr,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) {
+    let (huge_size, small_size) = match level {
+        3 => (Size1GiB::SIZE, Size2MiB::SIZE),
+        2 => (Size2MiB::SIZE, Size4KiB::SIZE),
+        _ => unreachable!("only level 3 and level 2 entries can map huge pages"),
+    };
+    let flags = entry.flags();
+    let start = entry.addr().align_down(huge_size);
+    let small_flags = if level == 2 {
+        flags - PageTableFlags::HUGE_PAGE
+    } else {
+        flags
+    };
+
+    let frame = frame_allocator
+        .allocate_frame()
+        .expect("failed to allocate a page table for splitting a huge page");
+    let table: *mut PageTable = (phys_offset + frame.start_address().as_u64()).as_mut_ptr();
+    let table = unsafe {
+        table.write(PageTable::new());
+        &mut *table
+    };
+    for (i, small_entry) in table.iter_mut().enumerate() {
+        small_entry.set_addr(start + i as u64 * small_size, small_flags);
+    }
+
+    let table_flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | (flags & PageTableFlags::USER_ACCESSIBLE);
+    entry.set_addr(frame.start_address(), table_flags);
+}
+
+/// Sets the no-execute bit on all mappings of the given table and its child tables, except
+/// for the 4KiB pages that contain code.
+unsafe fn mark_data_no_execute(
+    table: &mut PageTable,
+    level: u8,
+    phys_offset: VirtAddr,
+    is_code: &impl Fn(PhysAddr) -> bool,
+) {
+    for entry in table.iter_mut() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let is_leaf = level == 1 || flags.contains(PageTableFlags::HUGE_PAGE);
+        if !is_leaf {
+            let child: *mut PageTable = (phys_offset + entry.addr().as_u64()).as_mut_ptr();
+            unsafe { mark_data_no_execute(&mut *child, level - 1, phys_offset, is_code) };
+        } else if level > 1 || !is_code(entry.addr()) {
+            entry.set_flags(flags | PageTableFlags::NO_EXECUTE);
+        }
+    }
+}
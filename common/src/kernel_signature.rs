@@ -0,0 +1,62 @@
+use ed25519_compact::{PublicKey, Signature};
+
+/// Marks the end of a signed kernel file.
+pub const SIGNATURE_MAGIC: [u8; 8] = *b"KERNSIG1";
+
+/// The Ed25519 public key that the kernel signature is verified against.
+///
+/// Set at build time through the `BOOTLOADER_KERNEL_PUBLIC_KEY` environment variable, which
+/// must contain the 32-byte key as 64 hexadecimal digits.
+const PUBLIC_KEY: [u8; 32] = parse_public_key(env!(
+    "BOOTLOADER_KERNEL_PUBLIC_KEY",
+    "the `signed-kernel` feature requires the `BOOTLOADER_KERNEL_PUBLIC_KEY` environment \
+    variable to be set to the hex-encoded Ed25519 public key"
+));
+
+/// Verifies the signature of a signed kernel file and returns the contained kernel ELF file.
+///
+/// A signed kernel file consists of the kernel ELF file, followed by the 64-byte Ed25519
+/// signature over the ELF file and the 8-byte [`SIGNATURE_MAGIC`]. The signature follows
+/// RFC 8032, non-canonical signatures and small-order public keys are rejected.
+///
+/// Panics if the kernel is not signed or if the signature is invalid.
+pub fn verify(kernel_file: &[u8]) -> &[u8] {
+    let Some(signed) = kernel_file.strip_suffix(&SIGNATURE_MAGIC) else {
+        panic!("kernel is not signed, but the bootloader requires a kernel signature");
+    };
+    let Some(kernel_len) = signed.len().checked_sub(Signature::BYTES) else {
+        panic!("kernel signature is truncated");
+    };
+    let (kernel, signature) = signed.split_at(kernel_len);
+
+    log::info!("Verifying kernel signature");
+    let public_key = PublicKey::new(PUBLIC_KEY);
+    let signature = Signature::from_slice(signature).unwrap();
+    if public_key.verify(kernel, &signature).is_err() {
+        panic!("kernel signature is invalid, refusing to boot");
+    }
+    kernel
+}
+
+const fn parse_public_key(hex: &str) -> [u8; 32] {
+    let hex = hex.as_bytes();
+    if hex.len() != 64 {
+        panic!("the public key must consist of 64 hexadecimal digits");
+    }
+    let mut key = [0; 32];
+    let mut i = 0;
+    while i < key.len() {
+        key[i] = hex_digit(hex[2 * i]) << 4 | hex_digit(hex[2 * i + 1]);
+        i += 1;
+    }
+    key
+}
+
+const fn hex_digit(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        b'A'..=b'F' => digit - b'A' + 10,
+        _ => panic!("the public key contains an invalid hexadecimal digit"),
+    }
+}
@@ -1,7 +1,10 @@
-use bootloader_api::info::{MemoryRegion, MemoryRegionKind};
+use bootloader_api::{
+    config::NullFrame,
+    info::{MemoryRegion, MemoryRegionAttributes, MemoryRegionKind, Optional},
+};
 use core::mem::MaybeUninit;
 use x86_64::{
-    structures::paging::{FrameAllocator, PhysFrame, Size4KiB},
+    structures::paging::{frame::PhysFrameRange, FrameAllocator, PhysFrame, Size4KiB},
     PhysAddr,
 };
 
@@ -17,12 +20,23 @@ pub trait LegacyMemoryRegion: Copy + core::fmt::Debug {
     }
     /// Returns the type of the region, e.g. whether it is usable or reserved.
     fn kind(&self) -> MemoryRegionKind;
+    /// Returns additional properties of the region, e.g. whether it is non-volatile.
+    ///
+    /// The default implementation reports no attributes.
+    fn attributes(&self) -> MemoryRegionAttributes {
+        MemoryRegionAttributes::empty()
+    }
 
     /// Some regions become usable when the bootloader jumps to the kernel.
     fn usable_after_bootloader_exit(&self) -> bool;
 }
 
 /// A physical frame allocator based on a BIOS or UEFI provided memory map.
+///
+/// Frames are allocated in a deterministic order: the usable regions are visited in the
+/// order of the memory map and each region is allocated from low to high addresses, never
+/// going below a previously allocated frame. So identical memory maps always result in the
+/// same sequence of allocated frames, which makes boots reproducible.
 pub struct LegacyFrameAllocator<I, D> {
     original: I,
     memory_map: I,
@@ -30,6 +44,9 @@ pub struct LegacyFrameAllocator<I, D> {
     next_frame: PhysFrame,
 }
 
+/// The end address of the frame at physical address zero.
+const NULL_FRAME_END: u64 = 0x1000;
+
 impl<I, D> LegacyFrameAllocator<I, D>
 where
     I: ExactSizeIterator<Item = D> + Clone,
@@ -77,6 +94,100 @@ where
         }
     }
 
+    /// Allocates `count` physically contiguous frames.
+    ///
+    /// Like [`allocate_contiguous_below`](Self::allocate_contiguous_below), allocated frames
+    /// that are not part of a large enough contiguous range are skipped and stay unused.
+    pub fn allocate_contiguous(&mut self, count: u64) -> Option<PhysFrameRange> {
+        self.allocate_contiguous_below(count, PhysAddr::new_truncate(u64::MAX))
+    }
+
+    /// Allocates `count` physically contiguous frames that end below the given `limit`.
+    ///
+    /// Allocated frames that are not part of a large enough contiguous range are skipped
+    /// and stay unused. As frames are allocated in ascending order, this only succeeds if
+    /// the allocator didn't allocate any frames above `limit` yet, so it should be called
+    /// before any other allocations. Returns `None` without allocating anything if no
+    /// suitable range was found.
+    pub fn allocate_contiguous_below(
+        &mut self,
+        count: u64,
+        limit: PhysAddr,
+    ) -> Option<PhysFrameRange> {
+        let memory_map = self.memory_map.clone();
+        let current_descriptor = self.current_descriptor;
+        let next_frame = self.next_frame;
+
+        let range = self.search_contiguous_below(count, limit);
+        if range.is_none() {
+            // release the skipped frames so that they can still be used by other allocations
+            self.memory_map = memory_map;
+            self.current_descriptor = current_descriptor;
+            self.next_frame = next_frame;
+        }
+        range
+    }
+
+    fn search_contiguous_below(&mut self, count: u64, limit: PhysAddr) -> Option<PhysFrameRange> {
+        let mut start: Option<PhysFrame> = None;
+        let mut len = 0;
+        while len < count {
+            let frame = self.allocate_frame()?;
+            if frame.start_address() + frame.size() > limit {
+                return None;
+            }
+            match start {
+                Some(start_frame) if frame == start_frame + len => len += 1,
+                _ => {
+                    start = Some(frame);
+                    len = 1;
+                }
+            }
+        }
+        start.map(|start| PhysFrame::range(start, start + count))
+    }
+
+    /// Allocates the lowest free frame that ends below the given `limit`.
+    ///
+    /// This is useful for DMA buffers of devices that can only address low memory, e.g.
+    /// below 16 MiB for ISA DMA. As frames are allocated in ascending order, the next frame
+    /// is the lowest free frame. Returns `None` without allocating anything if this frame is
+    /// not below `limit`, i.e. if all usable memory below `limit` is exhausted.
+    pub fn allocate_frame_below(&mut self, limit: PhysAddr) -> Option<PhysFrame> {
+        let memory_map = self.memory_map.clone();
+        let current_descriptor = self.current_descriptor;
+        let next_frame = self.next_frame;
+
+        let frame = self.allocate_frame()?;
+        if frame.start_address() + frame.size() > limit {
+            // undo the allocation so that the frame can still be used by other allocations
+            self.memory_map = memory_map;
+            self.current_descriptor = current_descriptor;
+            self.next_frame = next_frame;
+            return None;
+        }
+        Some(frame)
+    }
+
+    /// Allocates the next free frame whose start address is a multiple of `align`.
+    ///
+    /// The free frames between the current allocation position and the aligned frame are
+    /// skipped. They stay unused, but are reported like allocated frames in the memory map
+    /// created by [`construct_memory_map`](Self::construct_memory_map).
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn allocate_aligned(&mut self, align: u64) -> Option<PhysFrame> {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        loop {
+            let frame = self.allocate_frame()?;
+            if frame.start_address().is_aligned(align) {
+                return Some(frame);
+            }
+            // continue at the next aligned address, possibly in a later region
+            self.next_frame = PhysFrame::containing_address(frame.start_address().align_up(align));
+        }
+    }
+
     /// Returns the number of memory regions in the underlying memory map.
     ///
     /// The function always returns the same value, i.e. the length doesn't
@@ -101,12 +212,60 @@ where
             .unwrap()
     }
 
+    /// Returns the ranges of usable frames that were not allocated yet.
+    ///
+    /// Only regions that are [`MemoryRegionKind::Usable`] while the bootloader is running are
+    /// considered, so the returned ranges never contain the kernel or the ramdisk. Call this
+    /// right before [`construct_memory_map`](Self::construct_memory_map) to get the frames
+    /// that stay free after all bootloader allocations.
+    pub fn free_ranges(&self) -> impl Iterator<Item = PhysFrameRange> + '_ {
+        let next_free = self.next_frame;
+        self.original
+            .clone()
+            .filter(|descriptor| descriptor.kind() == MemoryRegionKind::Usable)
+            .filter_map(move |descriptor| {
+                let start_frame = PhysFrame::containing_address(descriptor.start());
+                let end_addr = descriptor.start() + descriptor.len();
+                let end_frame = PhysFrame::containing_address(end_addr - 1u64) + 1;
+                let start_frame = start_frame.max(next_free);
+                (start_frame < end_frame).then(|| PhysFrame::range(start_frame, end_frame))
+            })
+    }
+
+    /// Returns the number of usable frames that were not allocated yet.
+    ///
+    /// This is the total size of the [`free_ranges`](Self::free_ranges).
+    pub fn free_frames(&self) -> u64 {
+        self.free_ranges().map(|range| range.count() as u64).sum()
+    }
+
     /// Converts this type to a boot info memory map.
     ///
-    /// The memory map is placed in the given `regions` slice. The length of the given slice
-    /// must be at least the value returned by [`len`] plus 1.
+    /// The memory map is placed in the given `regions` slice. To fit all regions, the length of
+    /// the given slice should be at least the value returned by [`len`] plus 6, as the
+    /// regions containing allocated frames, the kernel, the ramdisk, and `max_usable_addr`
+    /// might be split.
     ///
     /// The return slice is a subslice of `regions`, shortened to the actual number of regions.
+    /// The length of `regions` is a strict bound: if it is too small, the remaining regions
+    /// are dropped instead of being written past the end of the slice. The number of dropped
+    /// regions is returned as second tuple element, so the memory map was truncated if it is
+    /// non-zero.
+    ///
+    /// The frames allocated by this allocator are reported as
+    /// [`MemoryRegionKind::BootloaderReclaimable`] if `bootloader_regions_usable` is set and as
+    /// [`MemoryRegionKind::Bootloader`] otherwise. The kernel and ramdisk slices are always
+    /// reported as [`MemoryRegionKind::Bootloader`], even if they share a region. Usable memory
+    /// at or above `max_usable_addr` is reported as [`MemoryRegionKind::HighMemory`].
+    ///
+    /// The frame at address zero is never allocated. If it is part of a usable region, it is
+    /// reported according to `null_frame`: like the allocated frames, as
+    /// [`MemoryRegionKind::Reserved`], or not at all.
+    ///
+    /// If the memory map doesn't fit into `regions`, touching regions of the same kind are
+    /// merged to make room. Regions that still don't fit are dropped and counted in the
+    /// returned number.
+    #[allow(clippy::too_many_arguments)]
     pub fn construct_memory_map(
         self,
         regions: &mut [MaybeUninit<MemoryRegion>],
@@ -114,29 +273,68 @@ where
         kernel_slice_len: u64,
         ramdisk_slice_start: Option<PhysAddr>,
         ramdisk_slice_len: u64,
-    ) -> &mut [MemoryRegion] {
+        bootloader_regions_usable: bool,
+        max_usable_addr: Option<PhysAddr>,
+        null_frame: NullFrame,
+    ) -> (&mut [MemoryRegion], usize) {
         let mut next_index = 0;
+        let max_usable_addr = max_usable_addr.map(|a| a.as_u64());
         let kernel_slice_start = kernel_slice_start.as_u64();
         let ramdisk_slice_start = ramdisk_slice_start.map(|a| a.as_u64());
+        let bootloader_kind = if bootloader_regions_usable {
+            MemoryRegionKind::BootloaderReclaimable
+        } else {
+            MemoryRegionKind::Bootloader
+        };
+        // the kernel and ramdisk slices, which are always reported as `Bootloader`
+        let excluded = [
+            (kernel_slice_start, kernel_slice_start + kernel_slice_len),
+            ramdisk_slice_start.map_or((0, 0), |s| (s, s + ramdisk_slice_len)),
+        ];
 
         for descriptor in self.original {
             let mut start = descriptor.start();
             let end = start + descriptor.len();
             let next_free = self.next_frame.start_address();
+            if descriptor.kind() == MemoryRegionKind::Usable
+                && start.as_u64() < NULL_FRAME_END
+                && null_frame != NullFrame::Bootloader
+            {
+                let null_frame_end = PhysAddr::new(u64::min(end.as_u64(), NULL_FRAME_END));
+                if null_frame == NullFrame::Reserved {
+                    let null_region = MemoryRegion {
+                        start: start.as_u64(),
+                        end: null_frame_end.as_u64(),
+                        kind: MemoryRegionKind::Reserved,
+                        attributes: descriptor.attributes(),
+                        mapped: false,
+                    };
+                    Self::add_region(null_region, None, regions, &mut next_index);
+                }
+                start = null_frame_end;
+            }
             let kind = match descriptor.kind() {
                 MemoryRegionKind::Usable => {
                     if end <= next_free {
-                        MemoryRegionKind::Bootloader
-                    } else if descriptor.start() >= next_free {
+                        bootloader_kind
+                    } else if start >= next_free {
                         MemoryRegionKind::Usable
                     } else {
                         // part of the region is used -> add it separately
                         let used_region = MemoryRegion {
-                            start: descriptor.start().as_u64(),
+                            start: start.as_u64(),
                             end: next_free.as_u64(),
-                            kind: MemoryRegionKind::Bootloader,
+                            kind: bootloader_kind,
+                            attributes: descriptor.attributes(),
+                            mapped: false,
                         };
-                        Self::add_region(used_region, regions, &mut next_index);
+                        Self::add_region_excluding(
+                            used_region,
+                            excluded,
+                            max_usable_addr,
+                            regions,
+                            &mut next_index,
+                        );
 
                         // add unused part normally
                         start = next_free;
@@ -158,107 +356,77 @@ where
                 start: start.as_u64(),
                 end: end.as_u64(),
                 kind,
+                attributes: descriptor.attributes(),
+                mapped: false,
             };
-
-            // check if region overlaps with kernel or ramdisk
-            let kernel_slice_end = kernel_slice_start + kernel_slice_len;
-            let ramdisk_slice_end = ramdisk_slice_start.map(|s| s + ramdisk_slice_len);
-            if region.kind == MemoryRegionKind::Usable
-                && kernel_slice_start < region.end
-                && kernel_slice_end > region.start
-            {
-                // region overlaps with kernel -> we might need to split it
-
-                // ensure that the kernel allocation does not span multiple regions
-                assert!(
-                    kernel_slice_start >= region.start,
-                    "region overlaps with kernel, but kernel begins before region \
-                    (kernel_slice_start: {kernel_slice_start:#x}, region_start: {:#x})",
-                    region.start
-                );
-                assert!(
-                    kernel_slice_end <= region.end,
-                    "region overlaps with kernel, but region ends before kernel \
-                    (kernel_slice_end: {kernel_slice_end:#x}, region_end: {:#x})",
-                    region.end,
-                );
-
-                // split the region into three parts
-                let before_kernel = MemoryRegion {
-                    end: kernel_slice_start,
-                    ..region
-                };
-                let kernel = MemoryRegion {
-                    start: kernel_slice_start,
-                    end: kernel_slice_end,
-                    kind: MemoryRegionKind::Bootloader,
-                };
-                let after_kernel = MemoryRegion {
-                    start: kernel_slice_end,
-                    ..region
-                };
-
-                // add the three regions (empty regions are ignored in `add_region`)
-                Self::add_region(before_kernel, regions, &mut next_index);
-                Self::add_region(kernel, regions, &mut next_index);
-                Self::add_region(after_kernel, regions, &mut next_index);
-            } else if region.kind == MemoryRegionKind::Usable
-                && ramdisk_slice_start.map(|s| s < region.end).unwrap_or(false)
-                && ramdisk_slice_end.map(|e| e > region.start).unwrap_or(false)
-            {
-                // region overlaps with ramdisk -> we might need to split it
-                let ramdisk_slice_start = ramdisk_slice_start.unwrap();
-                let ramdisk_slice_end = ramdisk_slice_end.unwrap();
-
-                // ensure that the ramdisk allocation does not span multiple regions
-                assert!(
-                    ramdisk_slice_start >= region.start,
-                    "region overlaps with ramdisk, but ramdisk begins before region \
-                (ramdisk_start: {ramdisk_slice_start:#x}, region_start: {:#x})",
-                    region.start
-                );
-                assert!(
-                    ramdisk_slice_end <= region.end,
-                    "region overlaps with ramdisk, but region ends before ramdisk \
-                (ramdisk_end: {ramdisk_slice_end:#x}, region_end: {:#x})",
-                    region.end,
-                );
-
-                // split the region into three parts
-                let before_ramdisk = MemoryRegion {
-                    end: ramdisk_slice_start,
-                    ..region
-                };
-                let ramdisk = MemoryRegion {
-                    start: ramdisk_slice_start,
-                    end: ramdisk_slice_end,
-                    kind: MemoryRegionKind::Bootloader,
-                };
-                let after_ramdisk = MemoryRegion {
-                    start: ramdisk_slice_end,
-                    ..region
-                };
-
-                // add the three regions (empty regions are ignored in `add_region`)
-                Self::add_region(before_ramdisk, regions, &mut next_index);
-                Self::add_region(ramdisk, regions, &mut next_index);
-                Self::add_region(after_ramdisk, regions, &mut next_index);
-            } else {
-                // add the region normally
-                Self::add_region(region, regions, &mut next_index);
-            }
+            Self::add_region_excluding(region, excluded, max_usable_addr, regions, &mut next_index);
         }
 
-        let initialized = &mut regions[..next_index];
-        unsafe {
+        // `next_index` also counts the regions that didn't fit
+        let dropped = next_index.saturating_sub(regions.len());
+        let initialized = &mut regions[..next_index - dropped];
+        let initialized = unsafe {
             // inlined variant of: `MaybeUninit::slice_assume_init_mut(initialized)`
             // TODO: undo inlining when `slice_assume_init_mut` becomes stable
             &mut *(initialized as *mut [_] as *mut [_])
+        };
+        (initialized, dropped)
+    }
+
+    /// Adds the given region, splitting out the parts that overlap one of the `excluded`
+    /// ranges as [`MemoryRegionKind::Bootloader`] regions.
+    ///
+    /// Only usable and reclaimable regions are split, the ranges are not allowed to overlap
+    /// other regions anyway.
+    fn add_region_excluding(
+        region: MemoryRegion,
+        excluded: [(u64, u64); 2],
+        max_usable_addr: Option<u64>,
+        regions: &mut [MaybeUninit<MemoryRegion>],
+        next_index: &mut usize,
+    ) {
+        if !matches!(
+            region.kind,
+            MemoryRegionKind::Usable | MemoryRegionKind::BootloaderReclaimable
+        ) {
+            Self::add_region(region, max_usable_addr, regions, next_index);
+            return;
         }
+
+        let mut start = region.start;
+        loop {
+            // find the first excluded range that overlaps the remaining part of the region
+            let overlap = excluded
+                .iter()
+                .map(|&(s, e)| (u64::max(s, start), u64::min(e, region.end)))
+                .filter(|(s, e)| s < e)
+                .min();
+            let Some((overlap_start, overlap_end)) = overlap else {
+                break;
+            };
+            let before = MemoryRegion {
+                start,
+                end: overlap_start,
+                ..region
+            };
+            let overlapping = MemoryRegion {
+                start: overlap_start,
+                end: overlap_end,
+                kind: MemoryRegionKind::Bootloader,
+                ..region
+            };
+            // empty regions are ignored in `add_region`
+            Self::add_region(before, max_usable_addr, regions, next_index);
+            Self::add_region(overlapping, max_usable_addr, regions, next_index);
+            start = overlap_end;
+        }
+        let rest = MemoryRegion { start, ..region };
+        Self::add_region(rest, max_usable_addr, regions, next_index);
     }
 
     fn add_region(
         region: MemoryRegion,
+        max_usable_addr: Option<u64>,
         regions: &mut [MaybeUninit<MemoryRegion>],
         next_index: &mut usize,
     ) {
@@ -266,15 +434,289 @@ where
             // skip zero sized regions
             return;
         }
-        unsafe {
-            regions
-                .get_mut(*next_index)
-                .expect("cannot add region: no more free entries in memory map")
-                .as_mut_ptr()
-                .write(region)
-        };
+        match max_usable_addr {
+            Some(limit) if region.kind == MemoryRegionKind::Usable && region.end > limit => {
+                // split the region at the limit (empty parts are skipped)
+                let usable = MemoryRegion {
+                    end: u64::max(region.start, limit),
+                    ..region
+                };
+                let high = MemoryRegion {
+                    start: u64::max(region.start, limit),
+                    kind: MemoryRegionKind::HighMemory,
+                    ..region
+                };
+                Self::add_region(usable, None, regions, next_index);
+                Self::add_region(high, None, regions, next_index);
+                return;
+            }
+            _ => {}
+        }
+        if let Some(entry) = regions.get_mut(*next_index) {
+            entry.write(region);
+        } else if !regions.is_empty() && Self::merge_to_make_room(regions, region) {
+            return;
+        }
+        // if the region was dropped because there are no more free entries, still count it
         *next_index += 1;
     }
+
+    /// Tries to add `region` to the full `regions` slice by merging two touching regions of
+    /// the same kind.
+    ///
+    /// The region is merged into the last entry if possible. Otherwise, the pair with the
+    /// smallest combined length is merged, preferring pairs with equal attributes, and the
+    /// region is appended. Returns `false` if there are no touching regions of the same kind.
+    fn merge_to_make_room(regions: &mut [MaybeUninit<MemoryRegion>], region: MemoryRegion) -> bool {
+        let regions = unsafe {
+            // all entries are initialized because the slice is full
+            &mut *(regions as *mut [_] as *mut [MemoryRegion])
+        };
+        let last = regions.len() - 1;
+        if let Some(merged) = merge_touching(regions[last], region) {
+            regions[last] = merged;
+            return true;
+        }
+        let smallest_pair = (0..last)
+            .filter(|&i| merge_touching(regions[i], regions[i + 1]).is_some())
+            .min_by_key(|&i| {
+                let (a, b) = (regions[i], regions[i + 1]);
+                (a.attributes != b.attributes, b.end - a.start)
+            });
+        let Some(i) = smallest_pair else {
+            return false;
+        };
+        regions[i] = merge_touching(regions[i], regions[i + 1]).unwrap();
+        regions.copy_within(i + 2.., i + 1);
+        regions[last] = region;
+        true
+    }
+}
+
+/// Merges the two given regions if `b` directly follows `a` and both have the same kind.
+///
+/// Attributes that differ between the regions are dropped.
+fn merge_touching(a: MemoryRegion, b: MemoryRegion) -> Option<MemoryRegion> {
+    if a.end != b.start || a.kind != b.kind {
+        return None;
+    }
+    let attributes = MemoryRegionAttributes {
+        flags: a.attributes.flags & b.attributes.flags,
+        proximity_domain: if a.attributes.proximity_domain == b.attributes.proximity_domain {
+            a.attributes.proximity_domain
+        } else {
+            Optional::None
+        },
+    };
+    Some(MemoryRegion {
+        end: b.end,
+        attributes,
+        ..a
+    })
+}
+
+/// Creates a coarse view of the given memory map with a minimal number of regions.
+///
+/// Overlapping and adjacent [`MemoryRegionKind::Usable`] regions are merged. All other regions
+/// and the holes between the regions are combined into [`MemoryRegionKind::Reserved`] regions.
+/// The resulting regions are sorted by their start address and cover the range from the lowest
+/// start address to the highest end address of the given memory map.
+///
+/// The coarse memory map is placed in the given `regions` slice, which must have at least
+/// [`coarse_memory_map_len`] entries. The returned slice is a subslice of `regions`, shortened
+/// to the actual number of regions.
+pub fn construct_coarse_memory_map<'a>(
+    memory_map: &[MemoryRegion],
+    regions: &'a mut [MaybeUninit<MemoryRegion>],
+) -> &'a mut [MemoryRegion] {
+    let capacity = regions.len();
+    assert!(
+        capacity >= coarse_memory_map_len(memory_map.len()),
+        "not enough entries for the coarse memory map"
+    );
+    let non_empty = memory_map.iter().filter(|r| r.start < r.end);
+    let (Some(span_start), Some(span_end)) = (
+        non_empty.clone().map(|r| r.start).min(),
+        non_empty.clone().map(|r| r.end).max(),
+    ) else {
+        return &mut [];
+    };
+
+    regions.fill(MaybeUninit::new(MemoryRegion::empty()));
+    let regions = unsafe {
+        // inlined variant of: `MaybeUninit::slice_assume_init_mut(regions)`
+        // TODO: undo inlining when `slice_assume_init_mut` becomes stable
+        &mut *(regions as *mut [_] as *mut [MemoryRegion])
+    };
+
+    // collect the usable regions at the end of the slice and merge them
+    let mut usable_start = capacity;
+    for region in non_empty.filter(|r| r.kind == MemoryRegionKind::Usable) {
+        usable_start -= 1;
+        regions[usable_start] = *region;
+    }
+    let usable = &mut regions[usable_start..];
+    usable.sort_unstable_by_key(|region| region.start);
+    let mut merged = 0;
+    for i in 0..usable.len() {
+        if merged > 0 && usable[i].start <= usable[merged - 1].end {
+            usable[merged - 1].end = u64::max(usable[merged - 1].end, usable[i].end);
+            if usable[merged - 1].attributes != usable[i].attributes {
+                // the merged region has no common attributes
+                usable[merged - 1].attributes = MemoryRegionAttributes::empty();
+            }
+        } else {
+            usable[merged] = usable[i];
+            merged += 1;
+        }
+    }
+
+    // fill the gaps with reserved regions; the output never overtakes the usable regions
+    // that are still to be read because `regions` has enough capacity
+    let mut len = 0;
+    let mut cursor = span_start;
+    for i in usable_start..usable_start + merged {
+        let region = regions[i];
+        if cursor < region.start {
+            regions[len] = MemoryRegion {
+                start: cursor,
+                end: region.start,
+                kind: MemoryRegionKind::Reserved,
+                attributes: MemoryRegionAttributes::empty(),
+                mapped: false,
+            };
+            len += 1;
+        }
+        regions[len] = region;
+        len += 1;
+        cursor = region.end;
+    }
+    if cursor < span_end {
+        regions[len] = MemoryRegion {
+            start: cursor,
+            end: span_end,
+            kind: MemoryRegionKind::Reserved,
+            attributes: MemoryRegionAttributes::empty(),
+            mapped: false,
+        };
+        len += 1;
+    }
+
+    &mut regions[..len]
+}
+
+/// Returns the number of entries that [`construct_coarse_memory_map`] requires for a memory
+/// map with `len` regions.
+pub fn coarse_memory_map_len(len: usize) -> usize {
+    // each usable region might be surrounded by reserved regions
+    2 * len + 1
+}
+
+/// Collects the usable memory that is not covered by a physical memory mapping of the
+/// addresses below `mapped_end`.
+///
+/// The resulting regions are the parts of the [`MemoryRegionKind::Usable`] regions of
+/// `memory_map` that start at or above `mapped_end`, sorted by their start address. They are
+/// placed in the given `regions` slice, which must be at least as long as `memory_map`. The
+/// returned slice is a subslice of `regions`, shortened to the actual number of regions.
+pub fn construct_unmapped_usable_memory_map<'a>(
+    memory_map: &[MemoryRegion],
+    mapped_end: PhysAddr,
+    regions: &'a mut [MaybeUninit<MemoryRegion>],
+) -> &'a mut [MemoryRegion] {
+    assert!(
+        regions.len() >= memory_map.len(),
+        "not enough entries for the unmapped memory map"
+    );
+    let mut len = 0;
+    for region in memory_map
+        .iter()
+        .filter(|r| r.kind == MemoryRegionKind::Usable)
+    {
+        let start = u64::max(region.start, mapped_end.as_u64());
+        if start < region.end {
+            regions[len].write(MemoryRegion {
+                start,
+                end: region.end,
+                ..*region
+            });
+            len += 1;
+        }
+    }
+
+    let regions = &mut regions[..len];
+    let regions = unsafe {
+        // inlined variant of: `MaybeUninit::slice_assume_init_mut(regions)`
+        // TODO: undo inlining when `slice_assume_init_mut` becomes stable
+        &mut *(regions as *mut [_] as *mut [MemoryRegion])
+    };
+    regions.sort_unstable_by_key(|region| region.start);
+    regions
+}
+
+/// Sets the [`mapped`](MemoryRegion::mapped) flag of all regions that lie completely below
+/// `mapped_end`, i.e. that are covered by a physical memory mapping of the addresses below
+/// `mapped_end`. The flag of all other regions is cleared.
+pub fn mark_mapped_regions(memory_map: &mut [MemoryRegion], mapped_end: PhysAddr) {
+    for region in memory_map {
+        region.mapped = region.end <= mapped_end.as_u64();
+    }
+}
+
+/// Collects the memory that the bootloader used and that the kernel must not touch.
+///
+/// The resulting regions are the [`MemoryRegionKind::Bootloader`] and
+/// [`MemoryRegionKind::BootloaderReclaimable`] regions of `memory_map`, i.e. the page tables,
+/// the boot info, the kernel image, the kernel stack, and the ramdisk. Touching regions of the
+/// same kind are merged and the result is sorted by start address. The regions are placed in
+/// the given `regions` slice, which must be at least as long as `memory_map`. The returned
+/// slice is a subslice of `regions`, shortened to the actual number of regions.
+pub fn construct_bootloader_reserved_memory_map<'a>(
+    memory_map: &[MemoryRegion],
+    regions: &'a mut [MaybeUninit<MemoryRegion>],
+) -> &'a mut [MemoryRegion] {
+    assert!(
+        regions.len() >= memory_map.len(),
+        "not enough entries for the bootloader reserved memory map"
+    );
+    let mut len = 0;
+    for region in memory_map.iter().filter(|r| {
+        matches!(
+            r.kind,
+            MemoryRegionKind::Bootloader | MemoryRegionKind::BootloaderReclaimable
+        ) && r.start < r.end
+    }) {
+        regions[len].write(MemoryRegion {
+            attributes: MemoryRegionAttributes::empty(),
+            ..*region
+        });
+        len += 1;
+    }
+
+    let regions = &mut regions[..len];
+    let regions = unsafe {
+        // inlined variant of: `MaybeUninit::slice_assume_init_mut(regions)`
+        // TODO: undo inlining when `slice_assume_init_mut` becomes stable
+        &mut *(regions as *mut [_] as *mut [MemoryRegion])
+    };
+    regions.sort_unstable_by_key(|region| region.start);
+
+    // merge touching regions of the same kind
+    let mut merged = 0;
+    for i in 0..regions.len() {
+        let region = regions[i];
+        if merged > 0 {
+            let last = &mut regions[merged - 1];
+            if last.kind == region.kind && last.end == region.start {
+                last.end = region.end;
+                last.mapped &= region.mapped;
+                continue;
+            }
+        }
+        regions[merged] = region;
+        merged += 1;
+    }
+    &mut regions[..merged]
 }
 
 unsafe impl<I, D> FrameAllocator<Size4KiB> for LegacyFrameAllocator<I, D>
@@ -306,3 +748,871 @@ where
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bootloader_api::info::Optional;
+    use rand::{Rng, SeedableRng};
+    use rand_hc::Hc128Rng;
+
+    #[derive(Copy, Clone, Debug)]
+    struct TestMemoryRegion {
+        start: PhysAddr,
+        len: u64,
+        kind: MemoryRegionKind,
+        attributes: MemoryRegionAttributes,
+    }
+
+    impl LegacyMemoryRegion for TestMemoryRegion {
+        fn start(&self) -> PhysAddr {
+            self.start
+        }
+
+        fn len(&self) -> u64 {
+            assert!(self.len % 4096 == 0);
+            self.len
+        }
+
+        fn kind(&self) -> MemoryRegionKind {
+            self.kind
+        }
+
+        fn attributes(&self) -> MemoryRegionAttributes {
+            self.attributes
+        }
+
+        fn usable_after_bootloader_exit(&self) -> bool {
+            matches!(self.kind, MemoryRegionKind::Usable)
+        }
+    }
+
+    // we need some kind of max phys memory, 4GB seems reasonable
+    const MAX_PHYS_ADDR: u64 = 0x4_0000_0000;
+
+    fn create_single_test_region() -> Vec<TestMemoryRegion> {
+        vec![TestMemoryRegion {
+            start: PhysAddr::new(0),
+            len: MAX_PHYS_ADDR,
+            kind: MemoryRegionKind::Usable,
+            attributes: MemoryRegionAttributes::empty(),
+        }]
+    }
+
+    fn region(start: u64, end: u64, kind: MemoryRegionKind) -> MemoryRegion {
+        MemoryRegion {
+            start,
+            end,
+            kind,
+            ..MemoryRegion::empty()
+        }
+    }
+
+    fn bootloader_region_kind(bootloader_regions_usable: bool) -> MemoryRegionKind {
+        let regions = create_single_test_region();
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        for _ in 0..4 {
+            allocator.allocate_frame().unwrap();
+        }
+
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_slice_start = PhysAddr::new(0x50000);
+        let kernel_slice_len = 0x1000;
+
+        let (kernel_regions, dropped) = allocator.construct_memory_map(
+            &mut regions,
+            kernel_slice_start,
+            kernel_slice_len,
+            None,
+            0,
+            bootloader_regions_usable,
+            None,
+            NullFrame::Bootloader,
+        );
+        assert_eq!(dropped, 0);
+
+        // the allocator skips frame 0, so the allocated frames are 0x1000..0x5000
+        let region = kernel_regions
+            .iter()
+            .find(|r| r.start == 0)
+            .expect("no region for the allocated frames");
+        assert_eq!(region.end, 0x5000);
+
+        // the kernel is always reported as `Bootloader`
+        let kernel_region = kernel_regions
+            .iter()
+            .find(|r| r.start == kernel_slice_start.as_u64())
+            .expect("no region for the kernel slice");
+        assert_eq!(kernel_region.kind, MemoryRegionKind::Bootloader);
+
+        region.kind
+    }
+
+    #[test]
+    fn free_ranges_skip_allocated_frames() {
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x4000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x4000),
+                len: 0x4000,
+                kind: MemoryRegionKind::UnknownBios(2),
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x8000),
+                len: 0x8000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+        ];
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        // allocates 0x1000..0x4000 and 0x8000..0xa000
+        let allocated: Vec<_> = (0..5)
+            .map(|_| allocator.allocate_frame().unwrap())
+            .collect();
+
+        let free: Vec<_> = allocator.free_ranges().collect();
+        assert_eq!(free.len(), 1);
+        assert_eq!(free[0].start.start_address(), PhysAddr::new(0xa000));
+        assert_eq!(free[0].end.start_address(), PhysAddr::new(0x10000));
+        for frame in allocated {
+            assert!(free
+                .iter()
+                .all(|range| frame < range.start || frame >= range.end));
+        }
+    }
+
+    #[test]
+    fn allocation_order_is_deterministic() {
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x3000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x3000),
+                len: 0x2000,
+                kind: MemoryRegionKind::UnknownBios(2),
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x10000),
+                len: 0x2000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x8000),
+                len: 0x2000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x20000),
+                len: 0x3000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+        ];
+        let allocate = |regions: Vec<TestMemoryRegion>| {
+            let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+            core::iter::from_fn(|| allocator.allocate_frame())
+                .map(|frame| frame.start_address().as_u64())
+                .collect::<Vec<_>>()
+        };
+
+        let frames = allocate(regions.clone());
+        // frame 0 is skipped and the region at 0x8000 is skipped because it lies below
+        // the already allocated frames of the preceding region
+        assert_eq!(
+            frames,
+            [0x1000, 0x2000, 0x10000, 0x11000, 0x20000, 0x21000, 0x22000]
+        );
+        assert_eq!(allocate(regions), frames);
+    }
+
+    #[test]
+    fn contiguous_allocation_below_limit() {
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x3000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x8000),
+                len: 0x4000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+        ];
+        let limit = PhysAddr::new(0xc000);
+
+        // the first region only has two free frames, so it is skipped
+        let mut allocator = LegacyFrameAllocator::new(regions.clone().into_iter());
+        let range = allocator.allocate_contiguous_below(3, limit).unwrap();
+        assert_eq!(range.start.start_address(), PhysAddr::new(0x8000));
+        assert_eq!(range.end.start_address(), PhysAddr::new(0xb000));
+
+        // the requested frames don't fit below the limit, so no frames are consumed
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        assert_eq!(allocator.allocate_contiguous_below(5, limit), None);
+        assert_eq!(
+            allocator
+                .allocate_frame()
+                .map(|frame| frame.start_address()),
+            Some(PhysAddr::new(0x1000))
+        );
+    }
+
+    #[test]
+    fn frame_allocation_below_16_mib() {
+        let limit = PhysAddr::new(16 * 1024 * 1024);
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0xfff000),
+                len: 0x2000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x2000000),
+                len: 0x10000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+        ];
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+
+        let frame = allocator.allocate_frame_below(limit).unwrap();
+        assert_eq!(frame.start_address(), PhysAddr::new(0xfff000));
+        assert!(frame.start_address() + frame.size() <= limit);
+
+        // the low memory is exhausted, which must not consume any high frames
+        assert_eq!(allocator.allocate_frame_below(limit), None);
+        assert_eq!(
+            allocator.allocate_frame().unwrap().start_address(),
+            PhysAddr::new(0x1000000)
+        );
+    }
+
+    #[test]
+    fn aligned_frame_allocation() {
+        let align = 64 * 1024;
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x18000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x23000),
+                len: 0x20000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+        ];
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+
+        let first = allocator.allocate_aligned(align).unwrap();
+        assert_eq!(first.start_address(), PhysAddr::new(0x10000));
+        // the first region has no further aligned frame, so the second one is used
+        let second = allocator.allocate_aligned(align).unwrap();
+        assert_eq!(second.start_address(), PhysAddr::new(0x30000));
+        assert!([first, second]
+            .iter()
+            .all(|frame| frame.start_address().is_aligned(align)));
+        // normal allocations continue after the aligned frame
+        assert_eq!(
+            allocator.allocate_frame().unwrap().start_address(),
+            PhysAddr::new(0x31000)
+        );
+
+        // the skipped frames are not reported as usable
+        let mut regions = [MaybeUninit::uninit(); 6];
+        let (memory_map, _) = allocator.construct_memory_map(
+            &mut regions,
+            PhysAddr::new(0x40000),
+            0x1000,
+            None,
+            0,
+            false,
+            None,
+            NullFrame::Bootloader,
+        );
+        assert!(memory_map
+            .iter()
+            .filter(|r| r.kind == MemoryRegionKind::Usable)
+            .all(|r| r.start >= 0x32000));
+    }
+
+    #[test]
+    fn memory_map_overflow_is_reported() {
+        let regions: Vec<_> = (0..8)
+            .map(|i| TestMemoryRegion {
+                start: PhysAddr::new(0x10000 * (i + 1)),
+                len: 0x1000,
+                kind: if i % 2 == 0 {
+                    MemoryRegionKind::Usable
+                } else {
+                    MemoryRegionKind::UnknownBios(2)
+                },
+                attributes: MemoryRegionAttributes::empty(),
+            })
+            .collect();
+        let allocator = LegacyFrameAllocator::new(regions.into_iter());
+
+        // only pass the first three entries, the last one must not be touched
+        let sentinel = MemoryRegion {
+            start: 0xdead_0000,
+            end: 0xdead_1000,
+            kind: MemoryRegionKind::UnknownBios(0xdead),
+            attributes: MemoryRegionAttributes::empty(),
+            mapped: false,
+        };
+        let mut regions = [MaybeUninit::uninit(); 4];
+        regions[3].write(sentinel);
+        let (kernel_regions, dropped) = allocator.construct_memory_map(
+            &mut regions[..3],
+            PhysAddr::new(0x10000),
+            0x1000,
+            None,
+            0,
+            false,
+            None,
+            NullFrame::Bootloader,
+        );
+        assert_eq!(kernel_regions.len(), 3);
+        assert_eq!(dropped, 5);
+        assert_eq!(kernel_regions[2].start, 0x30000);
+        assert_eq!(unsafe { regions[3].assume_init() }, sentinel);
+    }
+
+    #[test]
+    fn memory_map_overflow_merges_touching_regions() {
+        // fragmented usable memory with different NUMA domains, interrupted by reserved regions
+        let layout = [
+            (0x0, 0x10000, MemoryRegionKind::Usable, 0),
+            (0x10000, 0x20000, MemoryRegionKind::Usable, 1),
+            (0x20000, 0x30000, MemoryRegionKind::Usable, 0),
+            (0x30000, 0x31000, MemoryRegionKind::UnknownBios(2), 0),
+            (0x31000, 0x40000, MemoryRegionKind::Usable, 0),
+            (0x40000, 0x48000, MemoryRegionKind::Usable, 1),
+            (0x48000, 0x50000, MemoryRegionKind::Usable, 1),
+            (0x50000, 0x60000, MemoryRegionKind::UnknownBios(2), 0),
+            (0x60000, 0x61000, MemoryRegionKind::UnknownBios(2), 0),
+            (0x61000, 0x80000, MemoryRegionKind::Usable, 0),
+            (0x80000, 0x90000, MemoryRegionKind::Usable, 1),
+        ];
+        let regions: Vec<_> = layout
+            .iter()
+            .map(|&(start, end, kind, domain)| TestMemoryRegion {
+                start: PhysAddr::new(start),
+                len: end - start,
+                kind,
+                attributes: MemoryRegionAttributes {
+                    proximity_domain: Optional::Some(domain),
+                    ..MemoryRegionAttributes::empty()
+                },
+            })
+            .collect();
+        let allocator = LegacyFrameAllocator::new(regions.into_iter());
+
+        let mut regions = [MaybeUninit::uninit(); 6];
+        let (kernel_regions, dropped) = allocator.construct_memory_map(
+            &mut regions,
+            PhysAddr::new(0x1000),
+            0x1000,
+            None,
+            0,
+            false,
+            None,
+            NullFrame::Bootloader,
+        );
+        assert_eq!(dropped, 0);
+        assert_eq!(kernel_regions.len(), 6);
+
+        // all memory is still described with its original kind
+        for &(start, end, kind, _) in &layout {
+            let same_kind: Vec<_> = kernel_regions
+                .iter()
+                .copied()
+                .filter(|r| match kind {
+                    MemoryRegionKind::Usable => {
+                        matches!(
+                            r.kind,
+                            MemoryRegionKind::Usable | MemoryRegionKind::Bootloader
+                        )
+                    }
+                    _ => r.kind == kind,
+                })
+                .collect();
+            assert_eq!(covered_len(&same_kind, (start, end)), end - start);
+        }
+        assert!(kernel_regions.windows(2).all(|w| w[0].end <= w[1].start));
+    }
+
+    #[test]
+    fn raw_bios_memory_type_is_preserved() {
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x10000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x100000),
+                len: 0x100000,
+                kind: MemoryRegionKind::UnknownBios(12),
+                attributes: MemoryRegionAttributes::empty(),
+            },
+        ];
+        let allocator = LegacyFrameAllocator::new(regions.into_iter());
+
+        let mut regions = [MaybeUninit::uninit(); 6];
+        let (kernel_regions, dropped) = allocator.construct_memory_map(
+            &mut regions,
+            PhysAddr::new(0x1000),
+            0x1000,
+            None,
+            0,
+            false,
+            None,
+            NullFrame::Bootloader,
+        );
+        assert_eq!(dropped, 0);
+        assert!(kernel_regions.iter().any(|r| r.start == 0x100000
+            && r.end == 0x200000
+            && r.kind == MemoryRegionKind::UnknownBios(12)));
+    }
+
+    #[test]
+    fn region_attributes_are_propagated() {
+        let attributes = MemoryRegionAttributes {
+            flags: MemoryRegionAttributes::NON_VOLATILE | MemoryRegionAttributes::HOT_PLUGGABLE,
+            proximity_domain: Optional::Some(1),
+        };
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x10000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x100000),
+                len: 0x100000,
+                kind: MemoryRegionKind::Usable,
+                attributes,
+            },
+        ];
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        allocator.allocate_frame().unwrap();
+
+        let mut regions = [MaybeUninit::uninit(); 6];
+        let (kernel_regions, dropped) = allocator.construct_memory_map(
+            &mut regions,
+            PhysAddr::new(0x180000),
+            0x1000,
+            None,
+            0,
+            false,
+            None,
+            NullFrame::Bootloader,
+        );
+        assert_eq!(dropped, 0);
+
+        // the region is split around the kernel, all parts must keep the attributes
+        let split: Vec<_> = kernel_regions
+            .iter()
+            .filter(|r| r.start >= 0x100000)
+            .collect();
+        assert_eq!(split.len(), 3);
+        assert!(split.iter().all(|r| r.attributes == attributes));
+        assert!(kernel_regions
+            .iter()
+            .filter(|r| r.start < 0x100000)
+            .all(|r| r.attributes == MemoryRegionAttributes::empty()));
+    }
+
+    #[test]
+    fn usable_memory_above_limit_is_high_memory() {
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0x1000),
+                len: 0xf_f000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x10_0000),
+                len: 0x1_0000_0000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x2_0000_0000),
+                len: 0x1000,
+                kind: MemoryRegionKind::Usable,
+                attributes: MemoryRegionAttributes::empty(),
+            },
+        ];
+        let allocator = LegacyFrameAllocator::new(regions.into_iter());
+
+        let mut regions = [MaybeUninit::uninit(); 8];
+        let (memory_map, dropped) = allocator.construct_memory_map(
+            &mut regions,
+            PhysAddr::new(0x20_0000),
+            0x1000,
+            None,
+            0,
+            false,
+            Some(PhysAddr::new(0x1_0000_0000)),
+            NullFrame::Bootloader,
+        );
+        assert_eq!(dropped, 0);
+
+        let kinds: Vec<_> = memory_map
+            .iter()
+            .map(|r| (r.start, r.end, r.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (0x1000, 0x10_0000, MemoryRegionKind::Usable),
+                (0x10_0000, 0x20_0000, MemoryRegionKind::Usable),
+                (0x20_0000, 0x20_1000, MemoryRegionKind::Bootloader),
+                (0x20_1000, 0x1_0000_0000, MemoryRegionKind::Usable),
+                (0x1_0000_0000, 0x1_0010_0000, MemoryRegionKind::HighMemory),
+                (0x2_0000_0000, 0x2_0000_1000, MemoryRegionKind::HighMemory),
+            ]
+        );
+    }
+
+    #[test]
+    fn coarse_memory_map_has_fewer_regions() {
+        let detailed = [
+            region(0x0, 0x1000, MemoryRegionKind::Bootloader),
+            region(0x1000, 0x9f000, MemoryRegionKind::Usable),
+            region(0x9f000, 0xa0000, MemoryRegionKind::UnknownBios(2)),
+            region(0xf0000, 0x100000, MemoryRegionKind::UnknownBios(2)),
+            region(0x100000, 0x200000, MemoryRegionKind::Bootloader),
+            region(0x200000, 0x300000, MemoryRegionKind::Usable),
+            region(0x300000, 0x400000, MemoryRegionKind::Usable),
+            region(0x400000, 0x500000, MemoryRegionKind::UnknownBios(3)),
+            region(0x500000, 0x600000, MemoryRegionKind::BootloaderReclaimable),
+            region(0x600000, 0x800000, MemoryRegionKind::Usable),
+        ];
+        let mut regions = [MaybeUninit::uninit(); 21];
+        assert_eq!(regions.len(), coarse_memory_map_len(detailed.len()));
+
+        let coarse = construct_coarse_memory_map(&detailed, &mut regions);
+
+        assert!(coarse.len() < detailed.len());
+        assert_eq!(
+            coarse,
+            [
+                region(0x0, 0x1000, MemoryRegionKind::Reserved),
+                region(0x1000, 0x9f000, MemoryRegionKind::Usable),
+                region(0x9f000, 0x200000, MemoryRegionKind::Reserved),
+                region(0x200000, 0x400000, MemoryRegionKind::Usable),
+                region(0x400000, 0x600000, MemoryRegionKind::Reserved),
+                region(0x600000, 0x800000, MemoryRegionKind::Usable),
+            ]
+        );
+    }
+
+    #[test]
+    fn unmapped_usable_memory_above_mapping() {
+        let memory_map = [
+            region(0x600000, 0x800000, MemoryRegionKind::Usable),
+            region(0x0, 0x1000, MemoryRegionKind::Bootloader),
+            region(0x1000, 0x9f000, MemoryRegionKind::Usable),
+            region(0x100000, 0x200000, MemoryRegionKind::Bootloader),
+            region(0x200000, 0x400000, MemoryRegionKind::Usable),
+            region(0x400000, 0x500000, MemoryRegionKind::UnknownBios(3)),
+        ];
+        let mut regions = [MaybeUninit::uninit(); 6];
+
+        let unmapped = construct_unmapped_usable_memory_map(
+            &memory_map,
+            PhysAddr::new(0x300000),
+            &mut regions,
+        );
+        assert_eq!(
+            unmapped,
+            [
+                region(0x300000, 0x400000, MemoryRegionKind::Usable),
+                region(0x600000, 0x800000, MemoryRegionKind::Usable),
+            ]
+        );
+
+        let unmapped =
+            construct_unmapped_usable_memory_map(&memory_map, PhysAddr::new(0), &mut regions);
+        assert_eq!(unmapped.len(), 3);
+        assert_eq!(
+            unmapped[0],
+            region(0x1000, 0x9f000, MemoryRegionKind::Usable)
+        );
+    }
+
+    #[test]
+    fn mapped_flag_follows_physical_memory_mapping() {
+        let mut memory_map = [
+            region(0x0, 0x1000, MemoryRegionKind::Usable),
+            region(0x1000, 0x200000, MemoryRegionKind::Usable),
+            region(0x200000, 0x400000, MemoryRegionKind::Usable),
+            region(0x400000, 0x800000, MemoryRegionKind::Usable),
+        ];
+        let mapped = |memory_map: &[MemoryRegion]| -> Vec<bool> {
+            memory_map.iter().map(|r| r.mapped).collect()
+        };
+
+        // full physical memory mapping
+        mark_mapped_regions(&mut memory_map, PhysAddr::new(0x800000));
+        assert_eq!(mapped(&memory_map), [true, true, true, true]);
+
+        // mapping limited by `identity_map_limit`, the region crossing the limit is not mapped
+        mark_mapped_regions(&mut memory_map, PhysAddr::new(0x300000));
+        assert_eq!(mapped(&memory_map), [true, true, false, false]);
+
+        // no physical memory mapping
+        mark_mapped_regions(&mut memory_map, PhysAddr::zero());
+        assert_eq!(mapped(&memory_map), [false, false, false, false]);
+    }
+
+    #[test]
+    fn bootloader_reserved_regions_are_merged() {
+        let memory_map = [
+            region(0x200000, 0x300000, MemoryRegionKind::Bootloader),
+            region(0x0, 0x1000, MemoryRegionKind::UnknownBios(2)),
+            region(0x1000, 0x9f000, MemoryRegionKind::Usable),
+            region(0x100000, 0x200000, MemoryRegionKind::Bootloader),
+            region(0x300000, 0x380000, MemoryRegionKind::BootloaderReclaimable),
+            region(0x400000, 0x500000, MemoryRegionKind::Bootloader),
+        ];
+        let mut regions = [MaybeUninit::uninit(); 6];
+
+        let reserved = construct_bootloader_reserved_memory_map(&memory_map, &mut regions);
+        assert_eq!(
+            reserved,
+            [
+                region(0x100000, 0x300000, MemoryRegionKind::Bootloader),
+                region(0x300000, 0x380000, MemoryRegionKind::BootloaderReclaimable),
+                region(0x400000, 0x500000, MemoryRegionKind::Bootloader),
+            ]
+        );
+    }
+
+    #[test]
+    fn bootloader_regions_reserved() {
+        assert_eq!(bootloader_region_kind(false), MemoryRegionKind::Bootloader);
+    }
+
+    #[test]
+    fn null_frame_kind_is_configurable() {
+        let first_region = |null_frame| {
+            let mut allocator = LegacyFrameAllocator::new(create_single_test_region().into_iter());
+            allocator.allocate_frame().unwrap();
+            let mut regions = [MaybeUninit::uninit(); 10];
+            let (memory_map, _) = allocator.construct_memory_map(
+                &mut regions,
+                PhysAddr::new(0x50000),
+                0x1000,
+                None,
+                0,
+                false,
+                None,
+                null_frame,
+            );
+            let mut memory_map = memory_map.to_vec();
+            memory_map.sort_unstable_by_key(|r| r.start);
+            (memory_map[0].start, memory_map[0].end, memory_map[0].kind)
+        };
+
+        assert_eq!(
+            first_region(NullFrame::Bootloader),
+            (0, 0x2000, MemoryRegionKind::Bootloader)
+        );
+        assert_eq!(
+            first_region(NullFrame::Reserved),
+            (0, 0x1000, MemoryRegionKind::Reserved)
+        );
+        assert_eq!(
+            first_region(NullFrame::Omitted),
+            (0x1000, 0x2000, MemoryRegionKind::Bootloader)
+        );
+    }
+
+    #[test]
+    fn bootloader_regions_reclaimable() {
+        assert_eq!(
+            bootloader_region_kind(true),
+            MemoryRegionKind::BootloaderReclaimable
+        );
+    }
+
+    /// A random but valid input for `construct_memory_map`.
+    struct RandomLayout {
+        regions: Vec<TestMemoryRegion>,
+        allocated_frames: usize,
+        kernel: (u64, u64),
+        ramdisk: Option<(u64, u64)>,
+        bootloader_regions_usable: bool,
+        max_usable_addr: Option<u64>,
+    }
+
+    fn random_layout(rng: &mut impl Rng) -> RandomLayout {
+        let kinds = [
+            MemoryRegionKind::Usable,
+            MemoryRegionKind::UnknownBios(2),
+            MemoryRegionKind::UnknownUefi(0),
+        ];
+        let mut regions = Vec::new();
+        let mut next_start = rng.gen_range(0..4) * 0x1000;
+        for i in 0..rng.gen_range(1..10) {
+            let len = rng.gen_range(1..64) * 0x1000;
+            // the first region is always usable to have a place for the kernel
+            let kind = match i {
+                0 => MemoryRegionKind::Usable,
+                _ => kinds[rng.gen_range(0..kinds.len())],
+            };
+            regions.push(TestMemoryRegion {
+                start: PhysAddr::new(next_start),
+                len,
+                kind,
+                attributes: MemoryRegionAttributes::empty(),
+            });
+            next_start += len + rng.gen_range(0..4) * 0x1000;
+        }
+
+        // places a slice of random length at a random position in a random usable region
+        let random_slice = |rng: &mut dyn rand::RngCore| {
+            let usable: Vec<_> = regions
+                .iter()
+                .filter(|r| r.kind == MemoryRegionKind::Usable)
+                .collect();
+            let region = usable[rng.gen_range(0..usable.len())];
+            let pages = region.len / 0x1000;
+            let first = rng.gen_range(0..pages);
+            let len = rng.gen_range(1..=pages - first);
+            let start = region.start.as_u64() + first * 0x1000;
+            (start, start + len * 0x1000)
+        };
+        let kernel = random_slice(rng);
+        let ramdisk = rng.gen_bool(0.5).then(|| random_slice(rng));
+
+        RandomLayout {
+            allocated_frames: rng.gen_range(0..32),
+            kernel,
+            ramdisk,
+            bootloader_regions_usable: rng.gen(),
+            max_usable_addr: rng
+                .gen_bool(0.5)
+                .then(|| rng.gen_range(0..next_start / 0x1000) * 0x1000),
+            regions,
+        }
+    }
+
+    /// Merges adjacent ranges of the given sorted ranges.
+    fn merge_adjacent(ranges: impl Iterator<Item = (u64, u64)>) -> Vec<(u64, u64)> {
+        let mut merged: Vec<(u64, u64)> = Vec::new();
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if last.1 == start => last.1 = end,
+                _ => merged.push((start, end)),
+            }
+        }
+        merged
+    }
+
+    /// Returns the number of bytes of `range` that are covered by the given regions.
+    fn covered_len(memory_map: &[MemoryRegion], (start, end): (u64, u64)) -> u64 {
+        memory_map
+            .iter()
+            .map(|r| u64::min(r.end, end).saturating_sub(u64::max(r.start, start)))
+            .sum()
+    }
+
+    #[test]
+    fn memory_map_invariants_hold_for_random_layouts() {
+        for seed in 0..2000 {
+            let mut rng = Hc128Rng::seed_from_u64(seed);
+            let layout = random_layout(&mut rng);
+            let input_len = layout.regions.len();
+            let input_ranges = merge_adjacent(
+                layout
+                    .regions
+                    .iter()
+                    .map(|r| (r.start.as_u64(), r.start.as_u64() + r.len)),
+            );
+
+            let mut allocator = LegacyFrameAllocator::new(layout.regions.into_iter());
+            for _ in 0..layout.allocated_frames {
+                allocator.allocate_frame();
+            }
+            let mut regions = vec![MaybeUninit::uninit(); input_len + 6];
+            let (memory_map, dropped) = allocator.construct_memory_map(
+                &mut regions,
+                PhysAddr::new(layout.kernel.0),
+                layout.kernel.1 - layout.kernel.0,
+                layout.ramdisk.map(|(start, _)| PhysAddr::new(start)),
+                layout.ramdisk.map_or(0, |(start, end)| end - start),
+                layout.bootloader_regions_usable,
+                layout.max_usable_addr.map(PhysAddr::new),
+                NullFrame::Bootloader,
+            );
+            assert_eq!(dropped, 0, "seed {seed}: memory map was truncated");
+
+            // regions are non-empty, sorted, and don't overlap
+            for region in memory_map.iter() {
+                assert!(region.start < region.end, "seed {seed}: empty {region:?}");
+            }
+            for pair in memory_map.windows(2) {
+                assert!(
+                    pair[0].end <= pair[1].start,
+                    "seed {seed}: unsorted or overlapping {pair:?}"
+                );
+            }
+
+            // the regions cover exactly the input regions
+            let output_ranges = merge_adjacent(memory_map.iter().map(|r| (r.start, r.end)));
+            assert_eq!(output_ranges, input_ranges, "seed {seed}: coverage differs");
+
+            // the kernel and ramdisk are always reported as `Bootloader`
+            let bootloader: Vec<_> = memory_map
+                .iter()
+                .copied()
+                .filter(|r| r.kind == MemoryRegionKind::Bootloader)
+                .collect();
+            for slice in [Some(layout.kernel), layout.ramdisk].into_iter().flatten() {
+                assert_eq!(
+                    covered_len(&bootloader, slice),
+                    slice.1 - slice.0,
+                    "seed {seed}: slice {slice:x?} is not reported as bootloader memory"
+                );
+            }
+
+            // no usable memory is reported above the limit
+            if let Some(limit) = layout.max_usable_addr {
+                assert!(
+                    memory_map
+                        .iter()
+                        .filter(|r| r.kind == MemoryRegionKind::Usable)
+                        .all(|r| r.end <= limit),
+                    "seed {seed}: usable memory above {limit:#x}"
+                );
+            }
+        }
+    }
+}
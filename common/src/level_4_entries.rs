@@ -29,7 +29,7 @@ impl UsedLevel4Entries {
     ///
     /// Marks the statically configured virtual address ranges from the config as used.
     pub fn new(
-        max_phys_addr: PhysAddr,
+        physical_memory_end: PhysAddr,
         regions_len: usize,
         framebuffer: Option<&RawFrameBufferInfo>,
         config: &BootloaderConfig,
@@ -46,7 +46,10 @@ impl UsedLevel4Entries {
         if let Some(config::Mapping::FixedAddress(physical_memory_offset)) =
             config.mappings.physical_memory
         {
-            used.mark_range_as_used(physical_memory_offset, max_phys_addr.as_u64().into_usize());
+            used.mark_range_as_used(
+                physical_memory_offset,
+                physical_memory_end.as_u64().into_usize(),
+            );
         }
 
         if let Some(config::Mapping::FixedAddress(recursive_address)) =
@@ -69,12 +72,22 @@ impl UsedLevel4Entries {
             used.mark_range_as_used(boot_info_address, combined.size());
         }
 
+        if let Some(config::Mapping::FixedAddress(gdt_address)) = config.mappings.gdt {
+            used.mark_range_as_used(gdt_address, crate::gdt::KERNEL_GDT_REGION_SIZE);
+        }
+
         if let config::Mapping::FixedAddress(framebuffer_address) = config.mappings.framebuffer {
-            if let Some(framebuffer) = framebuffer {
+            if let Some(framebuffer) = framebuffer.filter(|_| config.map_framebuffer_for_kernel) {
                 used.mark_range_as_used(framebuffer_address, framebuffer.info.byte_len);
             }
         }
 
+        if let Some(framebuffer) = framebuffer
+            .filter(|_| config.map_framebuffer_for_kernel && config.identity_map_framebuffer)
+        {
+            used.mark_range_as_used(framebuffer.addr.as_u64(), framebuffer.info.byte_len);
+        }
+
         // Mark everything before the dynamic range as unusable.
         if let Some(dynamic_range_start) = config.mappings.dynamic_range_start {
             let dynamic_range_start = VirtAddr::new(dynamic_range_start);
@@ -136,6 +149,22 @@ impl UsedLevel4Entries {
         }
     }
 
+    /// Marks all p4 entries between the lowest start and the highest end address of the
+    /// given segments as used, including the gaps between the segments.
+    pub fn mark_segment_span<'a>(
+        &mut self,
+        segments: impl Iterator<Item = ProgramHeader<'a>>,
+        virtual_address_offset: VirtualAddressOffset,
+    ) {
+        let span = segments
+            .filter(|s| s.mem_size() > 0)
+            .map(|s| (s.virtual_addr(), s.virtual_addr() + s.mem_size()))
+            .reduce(|(start, end), (s, e)| (start.min(s), end.max(e)));
+        if let Some((start, end)) = span {
+            self.mark_range_as_used(virtual_address_offset + start, end - start);
+        }
+    }
+
     /// Returns the first index of a `num` contiguous unused level 4 entries and marks them as
     /// used. If `CONFIG.aslr` is enabled, this will return random contiguous available entries.
     ///
@@ -1,31 +1,68 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(step_trait)]
 #![deny(unsafe_op_in_unsafe_fn)]
 
 use crate::legacy_memory_region::{LegacyFrameAllocator, LegacyMemoryRegion};
 use bootloader_api::{
-    config::Mapping,
-    info::{FrameBuffer, FrameBufferInfo, MemoryRegion, TlsTemplate},
+    config::{EntryConvention, GdtLayout, Mapping, PageTableCachePolicy, StackFill},
+    info::{
+        BootEvent, BootEventId, BootEvents, BootLog, BootTime, ConfigBlob, DeferredExit,
+        DeviceTree, DmaRegion, FrameBuffer, FrameBufferInfo, GdtInfo, MemoryRegion, Optional,
+        PhysicalFrameBuffer, TlsTemplate, Trampoline, VbeInfo, VideoModes,
+    },
     BootInfo, BootloaderConfig,
 };
-use bootloader_boot_config::{BootConfig, LevelFilter};
-use core::{alloc::Layout, arch::asm, mem::MaybeUninit, slice};
+use bootloader_boot_config::{BootConfig, LevelFilter, LogColors, Splash};
+use core::{
+    alloc::Layout,
+    arch::asm,
+    cmp, fmt,
+    mem::{self, MaybeUninit},
+    slice,
+};
 use level_4_entries::UsedLevel4Entries;
+use raw_cpuid::CpuId;
 use usize_conversions::FromUsize;
 use x86_64::{
+    registers::{
+        control::{Cr3, Cr3Flags, Cr4, Cr4Flags},
+        model_specific::{FsBase, GsBase},
+    },
     structures::paging::{
-        page_table::PageTableLevel, FrameAllocator, Mapper, OffsetPageTable, Page, PageSize,
-        PageTableFlags, PageTableIndex, PhysFrame, Size2MiB, Size4KiB,
+        mapper::MapToError, page_table::PageTableLevel, FrameAllocator, Mapper, OffsetPageTable,
+        Page, PageSize, PageTable, PageTableFlags, PageTableIndex, PhysFrame, Size2MiB, Size4KiB,
+        Translate,
     },
     PhysAddr, VirtAddr,
 };
-use xmas_elf::ElfFile;
+use xmas_elf::{
+    header::{Class, Machine},
+    ElfFile,
+};
 
+/// Provides functions to look up ACPI tables.
+mod acpi;
+/// Provides a function to read the local APIC information of the current processor.
+mod apic;
+/// Records the timeline of the boot process.
+pub mod boot_events;
+/// Provides an interactive shell over the serial port for debugging the boot process.
+#[cfg(feature = "debug-shell")]
+mod debug_shell;
+/// Provides a busy-wait delay based on the PIT.
+mod delay;
+/// Prints the prepared boot information instead of starting the kernel.
+mod dry_run;
 /// Provides a function to gather entropy and build a RNG.
 mod entropy;
 /// Provides a type that logs output as text to pixel-based framebuffers.
 pub mod framebuffer;
 mod gdt;
+/// Protects the code of the bootloader in its own identity mapping.
+pub mod identity_map_protection;
+/// Verifies the signature of a signed kernel file.
+#[cfg(feature = "signed-kernel")]
+pub mod kernel_signature;
 /// Provides a frame allocator based on a BIOS or UEFI memory map.
 pub mod legacy_memory_region;
 /// Provides a type to keep track of used entries in a level 4 page table.
@@ -34,30 +71,86 @@ pub mod level_4_entries;
 pub mod load_kernel;
 /// Provides a logger that logs output as text in various formats.
 pub mod logger;
+/// Detects whether memory encryption (AMD SME or Intel TME) is active.
+mod memory_encryption;
+/// Creates the multiboot2 boot information structure.
+#[cfg(feature = "multiboot2")]
+pub mod multiboot2;
+/// Writes the kernel page table hierarchy in a human-readable form.
+mod page_table_dump;
+/// Maps the kernel page tables read-only.
+mod page_table_protection;
+/// Allows registering additional output channels for panics.
+pub mod panic_sinks;
+/// Touches all pages of the mapped kernel image to surface mapping errors early.
+mod prefault;
+/// Implements the configurable behavior after a panic, e.g. rebooting the machine.
+pub mod reset;
 /// Provides a type that logs output as text to a Serial Being port.
 pub mod serial;
+/// Measures the boot components into the PCRs of a TPM 2.0.
+#[cfg(feature = "measured-boot")]
+mod tpm;
 
 const PAGE_SIZE: u64 = 4096;
 
 /// Initialize a text-based logger using the given pixel-based framebuffer as output.
+///
+/// Returns the framebuffer info with the `byte_len` clamped to the size that is covered by
+/// the framebuffer dimensions, see [`framebuffer::clamp_byte_len`]. This info should be
+/// passed to the kernel.
+#[allow(clippy::too_many_arguments)]
 pub fn init_logger(
     framebuffer: &'static mut [u8],
     info: FrameBufferInfo,
     log_level: LevelFilter,
+    log_colors: LogColors,
     frame_buffer_logger_status: bool,
     serial_logger_status: bool,
-) {
+    frame_buffer_self_test: bool,
+    splash: Splash,
+    boot_config_blob: Option<&[u8]>,
+) -> FrameBufferInfo {
+    let reported_len = info.byte_len;
+    let info = framebuffer::clamp_byte_len(info);
+    let framebuffer_len = cmp::min(info.byte_len, framebuffer.len());
+    let (framebuffer, _) = framebuffer.split_at_mut(framebuffer_len);
+    let self_test_failed = frame_buffer_self_test && !framebuffer::self_test(framebuffer, info);
+    let splash_result = splash.enabled().then(|| {
+        let logo = match boot_config_blob.and_then(framebuffer::Logo::from_boot_config_blob) {
+            Some(logo) if splash.logo => Some(logo?),
+            None if splash.logo => return Err("no splash logo in the boot config blob"),
+            _ => None,
+        };
+        framebuffer::draw_splash(framebuffer, info, splash, log_colors.background, logo)
+    });
     let logger = logger::LOGGER.get_or_init(move || {
         logger::LockedLogger::new(
             framebuffer,
             info,
+            log_colors,
             frame_buffer_logger_status,
             serial_logger_status,
+            splash_result.is_some(),
         )
     });
     log::set_logger(logger).expect("logger already set");
     log::set_max_level(convert_level(log_level));
     log::info!("Framebuffer info: {:?}", info);
+    if self_test_failed {
+        log::warn!("Framebuffer self-test failed: written pixels could not be read back");
+    }
+    if let Some(Err(err)) = splash_result {
+        log::warn!("Failed to draw the splash screen: {}", err);
+    }
+    let expected_len = info.height * info.bytes_per_scanline();
+    if reported_len != expected_len {
+        log::warn!(
+            "Framebuffer size {reported_len:#x} doesn't match its dimensions, which cover \
+            {expected_len:#x} bytes"
+        );
+    }
+    info
 }
 
 fn convert_level(level: LevelFilter) -> log::LevelFilter {
@@ -80,6 +173,72 @@ pub struct SystemInfo {
     pub rsdp_addr: Option<PhysAddr>,
     pub ramdisk_addr: Option<u64>,
     pub ramdisk_len: u64,
+    /// The device tree blob loaded by the firmware stage, if any.
+    ///
+    /// The blob is copied to bootloader memory before it is mapped for the kernel, so it might
+    /// be stored in memory that is reported as usable.
+    pub device_tree: Option<&'static [u8]>,
+    /// The key-value config blob for the kernel loaded by the firmware stage, if any.
+    ///
+    /// Copied to bootloader memory before it is mapped, like the device tree.
+    pub boot_config_blob: Option<&'static [u8]>,
+    /// The UEFI handles for exiting the boot services from the kernel, if the exit was
+    /// deferred.
+    pub deferred_exit: Option<DeferredExit>,
+    /// The linear framebuffer video modes supported by the graphics hardware.
+    pub available_video_modes: VideoModes,
+    /// The wall-clock time at boot, as reported by the firmware.
+    pub boot_time: Option<BootTime>,
+    /// The start address of the reserved low memory region for a real-mode trampoline.
+    pub ap_trampoline_addr: Option<PhysAddr>,
+    /// The raw VBE information blocks, only available on BIOS.
+    pub vbe_info: Option<VbeInfo>,
+}
+
+impl SystemInfo {
+    /// Logs all fields at the debug level.
+    ///
+    /// Blobs are summarized by their address and length and the video modes by their number,
+    /// so that the output stays readable on the framebuffer.
+    pub fn log_summary(&self) {
+        log::debug!("System info:");
+        log::debug!("  framebuffer: {:?}", self.framebuffer);
+        log::debug!("  rsdp_addr: {:?}", self.rsdp_addr);
+        log::debug!(
+            "  ramdisk: {:?} ({:#x} bytes)",
+            self.ramdisk_addr.map(|addr| addr as *const u8),
+            self.ramdisk_len
+        );
+        log::debug!("  device_tree: {:?}", self.device_tree.map(BlobSummary));
+        log::debug!(
+            "  boot_config_blob: {:?}",
+            self.boot_config_blob.map(BlobSummary)
+        );
+        log::debug!("  deferred_exit: {:?}", self.deferred_exit);
+        log::debug!(
+            "  available_video_modes: {} modes",
+            self.available_video_modes.len()
+        );
+        log::debug!("  boot_time: {:?}", self.boot_time);
+        log::debug!("  ap_trampoline_addr: {:?}", self.ap_trampoline_addr);
+        log::debug!(
+            "  vbe_info: {}",
+            if self.vbe_info.is_some() {
+                "available"
+            } else {
+                "not available"
+            }
+        );
+    }
+}
+
+/// Formats a blob as its address and length instead of its contents.
+struct BlobSummary<'a>(&'a [u8]);
+
+impl fmt::Debug for BlobSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:p} ({:#x} bytes)", self.0.as_ptr(), self.0.len())
+    }
 }
 
 /// The physical address of the framebuffer and information about the framebuffer.
@@ -99,8 +258,21 @@ pub struct Kernel<'a> {
 }
 
 impl<'a> Kernel<'a> {
+    /// Parses the given kernel ELF file and its bootloader config.
+    ///
+    /// Panics if the file is not a 64-bit x86_64 ELF file or if the config is missing or
+    /// invalid.
     pub fn parse(kernel_slice: &'a [u8]) -> Self {
         let kernel_elf = ElfFile::new(kernel_slice).unwrap();
+        // reject kernels for other architectures before interpreting the rest of the file
+        let class = kernel_elf.header.pt1.class();
+        let machine = kernel_elf.header.pt2.machine().as_machine();
+        if class != Class::SixtyFour || machine != Machine::X86_64 {
+            panic!(
+                "wrong kernel architecture: expected a 64-bit x86_64 ELF file, found \
+                class {class:?} and machine {machine:?}"
+            );
+        }
         let config = {
             let section = kernel_elf
                 .find_section_by_name(".bootloader-config")
@@ -123,18 +295,109 @@ impl<'a> Kernel<'a> {
 /// This function is a convenience function that first calls [`set_up_mappings`], then
 /// [`create_boot_info`], and finally [`switch_to_kernel`]. The given arguments are passed
 /// directly to these functions, so see their docs for more info.
+///
+/// The optional `pre_handoff` callback is invoked after all kernel mappings are set up, right
+/// before the boot info and the memory map are created. It can be used to add custom mappings
+/// or to perform additional checks. Frames allocated through the given frame allocator are
+/// reported as used in the memory map. The callback must not invalidate the existing mappings,
+/// e.g. by unmapping the kernel, its stack, or the boot info region, and it must not modify
+/// the active page tables of the bootloader in a way that breaks the final context switch.
 pub fn load_and_switch_to_kernel<I, D>(
     kernel: Kernel,
     boot_config: BootConfig,
     mut frame_allocator: LegacyFrameAllocator<I, D>,
     mut page_tables: PageTables,
     system_info: SystemInfo,
+    pre_handoff: Option<&mut PreHandoffCallback<I, D>>,
 ) -> !
 where
     I: ExactSizeIterator<Item = D> + Clone,
     D: LegacyMemoryRegion,
 {
+    system_info.log_summary();
+
     let config = kernel.config;
+    reset::set_rsdp_addr(system_info.rsdp_addr);
+    boot_events::record(BootEventId::KERNEL_SETUP_START, kernel.len as u64);
+
+    // the kernel might be loaded anywhere in physical memory, e.g. above 4 GiB, but it is
+    // read through the bootloader page table, so it must be mapped there
+    let kernel_last_byte = VirtAddr::from_ptr(kernel.start_address) + (kernel.len as u64 - 1);
+    assert!(
+        page_tables
+            .bootloader
+            .translate_addr(kernel_last_byte)
+            .is_some(),
+        "kernel end {kernel_last_byte:?} is not mapped in the bootloader address space"
+    );
+    let kernel_slice = unsafe { slice::from_raw_parts(kernel.start_address, kernel.len) };
+    #[cfg(feature = "measured-boot")]
+    {
+        let ramdisk_slice = system_info.ramdisk_addr.map(|addr| unsafe {
+            slice::from_raw_parts(addr as *const u8, system_info.ramdisk_len as usize)
+        });
+        tpm::measure_boot_components(&boot_config.measured_boot, kernel_slice, ramdisk_slice);
+    }
+    #[cfg(not(feature = "measured-boot"))]
+    if boot_config.measured_boot.enabled {
+        log::warn!(
+            "Measured boot requested, but the bootloader was built without the `measured-boot` \
+            feature"
+        );
+    }
+
+    if boot_config.check_kernel_memory_requirements {
+        let segments = kernel
+            .elf
+            .program_iter()
+            .filter(|header| header.get_type() == Ok(xmas_elf::program::Type::Load))
+            .map(|header| (header.virtual_addr(), header.mem_size()));
+        let boot_info_layout = BootInfoLayout::new(&config, frame_allocator.len());
+        let boot_info_size = u64::from_usize(boot_info_layout.combined.size());
+        #[cfg(feature = "multiboot2")]
+        let multiboot2_size = u64::from_usize(multiboot2::max_len(boot_info_layout.regions));
+        #[cfg(not(feature = "multiboot2"))]
+        let multiboot2_size = 0;
+        let blob_size = |blob: Option<&[u8]>| blob.map_or(0, |blob| u64::from_usize(blob.len()));
+        let allocated = [
+            config.kernel_stack_size,
+            boot_info_size,
+            multiboot2_size,
+            blob_size(system_info.device_tree),
+            blob_size(system_info.boot_config_blob),
+            config.dma_region_size.unwrap_or(0),
+        ];
+        let framebuffer_size = system_info
+            .framebuffer
+            .filter(|_| config.map_framebuffer_for_kernel)
+            .map_or(0, |framebuffer| u64::from_usize(framebuffer.info.byte_len));
+        let existing = [
+            framebuffer_size,
+            if config.identity_map_framebuffer {
+                framebuffer_size
+            } else {
+                0
+            },
+            system_info.ramdisk_len,
+            config.ap_trampoline_size.unwrap_or(0),
+            // the boot info is mapped in the bootloader address space too
+            boot_info_size,
+        ];
+        let physical_memory_end = config.mappings.physical_memory.map(|_| {
+            physical_memory_mapping_end(frame_allocator.max_phys_addr(), &config).as_u64()
+        });
+        let required = kernel_frames_estimate(segments, &allocated, &existing, physical_memory_end);
+        let available = frame_allocator.free_frames();
+        log::info!("Kernel needs up to {required} frames, {available} frames are free");
+        if required > available {
+            panic!(
+                "kernel too large for available memory: needs up to {} frames, but only {} \
+                frames are free",
+                required, available
+            );
+        }
+    }
+
     let mut mappings = set_up_mappings(
         kernel,
         &mut frame_allocator,
@@ -143,6 +406,15 @@ where
         &config,
         &system_info,
     );
+    boot_events::record(BootEventId::MAPPINGS_CREATED, 0);
+    if boot_config.prefault_kernel_pages {
+        log::info!("Touch all kernel pages");
+        prefault::touch_kernel_pages(kernel_slice, mappings.kernel_image_offset, &page_tables);
+    }
+    if let Some(pre_handoff) = pre_handoff {
+        log::info!("Run pre-handoff callback");
+        pre_handoff(&mut page_tables, &mut frame_allocator, &mut mappings);
+    }
     let boot_info = create_boot_info(
         &config,
         &boot_config,
@@ -151,9 +423,54 @@ where
         &mut mappings,
         system_info,
     );
+    #[cfg(feature = "debug-shell")]
+    if boot_config.debug_shell_timeout_ms > 0 {
+        debug_shell::run_if_requested(
+            boot_config.debug_shell_timeout_ms,
+            &page_tables,
+            &boot_info.memory_regions,
+        );
+    }
+    check_segment_bases(boot_info, &page_tables);
+    if boot_config.post_message_delay_ms > 0 {
+        log::info!(
+            "Waiting {} ms before jumping to kernel",
+            boot_config.post_message_delay_ms
+        );
+        delay::busy_wait_ms(boot_config.post_message_delay_ms);
+    }
+    if boot_config.dry_run {
+        dry_run::print_summary_and_halt(boot_info, &mappings, &page_tables);
+    }
+    if boot_config.flush_caches {
+        log::info!("Flush caches and TLB");
+        flush_caches_and_tlb();
+    }
     switch_to_kernel(page_tables, mappings, boot_info);
 }
 
+/// Checks that the configured `FS` and `GS` base addresses are valid kernel addresses.
+///
+/// Panics if an address is not canonical, as writing it to the register would fault. Only
+/// warns if an address is not mapped, as the kernel might not access it right away.
+fn check_segment_bases(boot_info: &BootInfo, page_tables: &PageTables) {
+    for (name, base) in [("FS", boot_info.fs_base), ("GS", boot_info.gs_base)] {
+        let Optional::Some(base) = base else {
+            continue;
+        };
+        let Ok(addr) = VirtAddr::try_new(base) else {
+            panic!("{name} base {base:#x} is not a canonical address");
+        };
+        if page_tables.translate(addr).is_none() {
+            log::warn!("{name} base {base:#x} is not mapped in the kernel address space");
+        }
+    }
+}
+
+/// A callback that is invoked by [`load_and_switch_to_kernel`] before the kernel is started.
+pub type PreHandoffCallback<I, D> =
+    dyn FnMut(&mut PageTables, &mut LegacyFrameAllocator<I, D>, &mut Mappings);
+
 /// Sets up mappings for a kernel stack and the framebuffer.
 ///
 /// The `kernel_bytes` slice should contain the raw bytes of the kernel ELF executable. The
@@ -163,8 +480,8 @@ where
 ///
 /// The `framebuffer_addr` and `framebuffer_size` fields should be set to the start address and
 /// byte length the pixel-based framebuffer. These arguments are required because the functions
-/// maps this framebuffer in the kernel-level page table, unless the `map_framebuffer` config
-/// option is disabled.
+/// maps this framebuffer in the kernel-level page table, unless the
+/// `map_framebuffer_for_kernel` config option is disabled.
 ///
 /// This function reacts to unexpected situations (e.g. invalid kernel ELF file) with a panic, so
 /// errors are not recoverable.
@@ -182,8 +499,9 @@ where
 {
     let kernel_page_table = &mut page_tables.kernel;
 
+    let physical_memory_end = physical_memory_mapping_end(frame_allocator.max_phys_addr(), config);
     let mut used_entries = UsedLevel4Entries::new(
-        frame_allocator.max_phys_addr(),
+        physical_memory_end,
         frame_allocator.len(),
         framebuffer,
         config,
@@ -197,6 +515,14 @@ where
     let config = kernel.config;
     let kernel_slice_start = PhysAddr::new(kernel.start_address as _);
     let kernel_slice_len = u64::try_from(kernel.len).unwrap();
+    // the lowest virtual address of the loadable segments, before relocation
+    let kernel_min_vaddr = kernel
+        .elf
+        .program_iter()
+        .filter(|header| header.get_type() == Ok(xmas_elf::program::Type::Load))
+        .map(|header| header.virtual_addr())
+        .min()
+        .unwrap_or(0);
 
     let (kernel_image_offset, entry_point, tls_template) = load_kernel::load_kernel(
         kernel,
@@ -225,6 +551,13 @@ where
         let frame = frame_allocator
             .allocate_frame()
             .expect("frame allocation failed when mapping a kernel stack");
+        unsafe {
+            fill_stack_frame(
+                frame,
+                config.kernel_stack_fill,
+                kernel_page_table.phys_offset(),
+            )
+        };
         let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
         match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
             Ok(tlb) => tlb.flush(),
@@ -280,8 +613,35 @@ where
         Err(err) => panic!("failed to identity map frame {:?}: {:?}", gdt_frame, err),
     }
 
+    // identity-map the AP trampoline, as the startup code of the application processors
+    // switches from real mode to the kernel page table while running from this region
+    if let (Some(start), Some(size)) = (system_info.ap_trampoline_addr, config.ap_trampoline_size) {
+        log::info!("Identity-map AP trampoline at {:#x}", start.as_u64());
+        let start_frame = PhysFrame::<Size4KiB>::containing_address(start);
+        let end_frame = PhysFrame::containing_address(start + (size.max(1) - 1));
+        for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
+            let page = Page::containing_address(VirtAddr::new(frame.start_address().as_u64()));
+            let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+            match unsafe {
+                kernel_page_table.map_to_with_table_flags(
+                    page,
+                    frame,
+                    flags,
+                    PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+                    frame_allocator,
+                )
+            } {
+                Ok(tlb) => tlb.flush(),
+                Err(err) => panic!("failed to identity map frame {:?}: {:?}", frame, err),
+            }
+        }
+    }
+
     // map framebuffer
-    let framebuffer_virt_addr = if let Some(framebuffer) = framebuffer {
+    let mut framebuffer_identity_addr = None;
+    let framebuffer_virt_addr = if let Some(framebuffer) =
+        framebuffer.filter(|_| config.map_framebuffer_for_kernel)
+    {
         log::info!("Map framebuffer");
 
         let framebuffer_start_frame: PhysFrame = PhysFrame::containing_address(framebuffer.addr);
@@ -307,6 +667,25 @@ where
                 ),
             }
         }
+        if config.identity_map_framebuffer {
+            // the physical memory mapping at offset zero covers the framebuffer already
+            if config.mappings.physical_memory != Some(Mapping::FixedAddress(0)) {
+                log::info!("Identity-map framebuffer");
+                let flags =
+                    PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+                for frame in
+                    PhysFrame::range_inclusive(framebuffer_start_frame, framebuffer_end_frame)
+                {
+                    let page =
+                        Page::containing_address(VirtAddr::new(frame.start_address().as_u64()));
+                    match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+                        Ok(tlb) => tlb.flush(),
+                        Err(err) => panic!("failed to identity map frame {:?}: {:?}", frame, err),
+                    }
+                }
+            }
+            framebuffer_identity_addr = Some(VirtAddr::new(framebuffer.addr.as_u64()));
+        }
         let framebuffer_virt_addr = start_page.start_address();
         Some(framebuffer_virt_addr)
     } else {
@@ -345,24 +724,101 @@ where
         None
     };
 
+    let device_tree = system_info
+        .device_tree
+        .filter(|blob| !blob.is_empty())
+        .map(|blob| {
+            log::info!("Map device tree");
+            map_device_tree(blob, kernel_page_table, frame_allocator, &mut used_entries)
+        });
+
+    let boot_config_blob = system_info
+        .boot_config_blob
+        .filter(|blob| !blob.is_empty())
+        .map(|blob| {
+            log::info!("Map boot config blob");
+            let (_, addr) = copy_and_map_blob(
+                blob,
+                "boot config blob",
+                kernel_page_table,
+                frame_allocator,
+                &mut used_entries,
+            );
+            ConfigBlob {
+                addr: addr.as_u64(),
+                len: blob.len() as u64,
+            }
+        });
+
+    let trampoline = config.address_switch_trampoline.then(|| {
+        log::info!("Map address switch trampoline");
+        map_trampoline(kernel_page_table, frame_allocator, &mut used_entries)
+    });
+    let dma_region = config.dma_region_size.and_then(|size| {
+        log::info!("Allocate DMA region");
+        map_dma_region(
+            size,
+            config.dma_region_max_addr,
+            kernel_page_table,
+            frame_allocator,
+            &mut used_entries,
+        )
+    });
+
     let physical_memory_offset = if let Some(mapping) = config.mappings.physical_memory {
         log::info!("Map physical memory");
 
+        if let Some(limit) = config.mappings.identity_map_limit {
+            let limit_end = PhysAddr::new(x86_64::align_up(limit, Size2MiB::SIZE));
+            let kernel_end = kernel_slice_start + kernel_slice_len;
+            if kernel_end > limit_end {
+                panic!(
+                    "identity map limit {:#x} does not cover the kernel, which ends at {:#x}",
+                    limit,
+                    kernel_end.as_u64()
+                );
+            }
+            if let Some(framebuffer) = framebuffer {
+                let framebuffer_end = framebuffer.addr + u64::from_usize(framebuffer.info.byte_len);
+                if framebuffer_end > limit_end {
+                    panic!(
+                        "identity map limit {:#x} does not cover the framebuffer, which ends \
+                        at {:#x}",
+                        limit,
+                        framebuffer_end.as_u64()
+                    );
+                }
+            }
+        }
+
         let start_frame = PhysFrame::containing_address(PhysAddr::new(0));
-        let max_phys = frame_allocator.max_phys_addr();
-        let end_frame: PhysFrame<Size2MiB> = PhysFrame::containing_address(max_phys - 1u64);
+        let end_frame: PhysFrame<Size2MiB> =
+            PhysFrame::containing_address(physical_memory_end - 1u64);
 
-        let size = max_phys.as_u64();
+        let size = physical_memory_end.as_u64();
         let alignment = Size2MiB::SIZE;
         let offset = mapping_addr(mapping, size, alignment, &mut used_entries)
             .expect("start address for physical memory mapping must be 2MiB-page-aligned");
 
+        let mut flags =
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        if config.global_pages {
+            flags |= PageTableFlags::GLOBAL;
+        }
+        // the kernel calls the boot services through this identity mapping
+        if system_info.deferred_exit.is_some() {
+            flags.remove(PageTableFlags::NO_EXECUTE);
+        }
         for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
             let page = Page::containing_address(offset + frame.start_address().as_u64());
-            let flags =
-                PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
             match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
                 Ok(tlb) => tlb.ignore(),
+                // The context switch, the GDT, and the trampolines are identity-mapped with
+                // 4KiB pages above, so with an offset of zero their 2MiB page already has a
+                // level 1 table. Map the rest of that 2MiB page with 4KiB pages instead.
+                Err(MapToError::PageAlreadyMapped(_)) => {
+                    map_physical_memory_4kib(kernel_page_table, page, frame, flags, frame_allocator)
+                }
                 Err(err) => panic!(
                     "failed to map page {:?} to frame {:?}: {:?}",
                     page, frame, err
@@ -372,6 +828,9 @@ where
 
         Some(offset)
     } else {
+        if config.mappings.identity_map_limit.is_some() {
+            log::warn!("identity map limit is set, but physical memory is not mapped");
+        }
         None
     };
 
@@ -409,13 +868,30 @@ where
         None
     };
 
+    let gdt = config.mappings.gdt.map(|mapping| {
+        let start_page = mapping_addr_page_aligned(
+            mapping,
+            gdt::KERNEL_GDT_REGION_SIZE,
+            &mut used_entries,
+            "GDT",
+        );
+        let layout = config.gdt_layout.unwrap_or(GdtLayout::new_default());
+        gdt::create_and_load_for_kernel(start_page, &layout, page_tables, frame_allocator)
+    });
+    if gdt.is_none() && config.gdt_layout.is_some() {
+        log::warn!("GDT layout is set, but no GDT is created for the kernel");
+    }
+
     Mappings {
         framebuffer: framebuffer_virt_addr,
+        framebuffer_identity_addr,
         entry_point,
         // Use the configured stack size, even if it's not page-aligned. However, we
         // need to align it down to the next 16-byte boundary because the System V
         // ABI requires a 16-byte stack alignment.
         stack_top: stack_end_addr.align_down(16u8),
+        entry_convention: config.entry_convention,
+        boot_info_on_stack: config.boot_info_on_stack,
         used_entries,
         physical_memory_offset,
         recursive_index,
@@ -424,10 +900,229 @@ where
         kernel_slice_start,
         kernel_slice_len,
         kernel_image_offset,
+        kernel_virtual_base: kernel_image_offset + kernel_min_vaddr,
 
         ramdisk_slice_phys_start,
         ramdisk_slice_start,
         ramdisk_slice_len,
+        device_tree,
+        dma_region,
+        trampoline,
+        boot_config_blob,
+        gdt,
+        #[cfg(feature = "multiboot2")]
+        multiboot2_info: None,
+    }
+}
+
+/// Copies the given device tree blob to newly allocated frames and maps them read-only into
+/// the kernel address space.
+///
+/// The firmware stage might have loaded the blob into memory that is reported as usable, so
+/// the copy makes sure that the kernel doesn't overwrite it accidentally.
+fn map_device_tree<I, D>(
+    blob: &[u8],
+    kernel_page_table: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+    used_entries: &mut UsedLevel4Entries,
+) -> DeviceTree
+where
+    I: ExactSizeIterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    let (phys_addr, addr) = copy_and_map_blob(
+        blob,
+        "device tree",
+        kernel_page_table,
+        frame_allocator,
+        used_entries,
+    );
+    DeviceTree {
+        phys_addr: phys_addr.as_u64(),
+        addr: addr.as_u64(),
+        len: blob.len() as u64,
+    }
+}
+
+/// Copies the given blob to newly allocated frames and maps them read-only at a dynamic
+/// address of the kernel address space.
+///
+/// Returns the physical and virtual start address of the copy.
+fn copy_and_map_blob<I, D>(
+    blob: &[u8],
+    name: &'static str,
+    kernel_page_table: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+    used_entries: &mut UsedLevel4Entries,
+) -> (PhysAddr, VirtAddr)
+where
+    I: ExactSizeIterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    let len = blob.len() as u64;
+    let page_count = len.div_ceil(Size4KiB::SIZE);
+    let frames = frame_allocator
+        .allocate_contiguous(page_count)
+        .unwrap_or_else(|| panic!("failed to allocate frames for the {}", name));
+    let start_page = mapping_addr_page_aligned(Mapping::Dynamic, len, used_entries, name);
+    let phys_offset = kernel_page_table.phys_offset();
+    let phys_addr = frames.start.start_address();
+    let dst: *mut u8 = (phys_offset + phys_addr.as_u64()).as_mut_ptr();
+    unsafe { core::ptr::copy_nonoverlapping(blob.as_ptr(), dst, blob.len()) };
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE;
+    for (i, frame) in frames.enumerate() {
+        let page = start_page + i as u64;
+        match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+            Ok(tlb) => tlb.ignore(),
+            Err(err) => panic!(
+                "Failed to map page {:?} to frame {:?}: {:?}",
+                page, frame, err
+            ),
+        };
+    }
+    (phys_addr, start_page.start_address())
+}
+
+/// Loads `rdi` into `CR3`, moves `rdx` to `rdi`, and jumps to `rsi`.
+const TRAMPOLINE_STUB: [u8; 8] = [
+    0x0f, 0x22, 0xdf, // mov cr3, rdi
+    0x48, 0x89, 0xd7, // mov rdi, rdx
+    0xff, 0xe6, // jmp rsi
+];
+
+/// Maps the given 2MiB page of the physical memory mapping with 4KiB pages, skipping the pages
+/// that are already mapped to the same frame.
+fn map_physical_memory_4kib(
+    kernel_page_table: &mut OffsetPageTable<'static>,
+    page: Page<Size2MiB>,
+    frame: PhysFrame<Size2MiB>,
+    flags: PageTableFlags,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) {
+    let start_page = Page::<Size4KiB>::containing_address(page.start_address());
+    let start_frame = PhysFrame::<Size4KiB>::containing_address(frame.start_address());
+    for i in 0..(Size2MiB::SIZE / Size4KiB::SIZE) {
+        let (page, frame) = (start_page + i, start_frame + i);
+        match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+            Ok(tlb) => tlb.ignore(),
+            Err(MapToError::PageAlreadyMapped(mapped)) if mapped == frame => {}
+            Err(err) => panic!(
+                "failed to map page {:?} to frame {:?}: {:?}",
+                page, frame, err
+            ),
+        }
+    }
+}
+
+/// Creates the address switch trampoline page and maps it at its identity address and at a
+/// dynamic address into the kernel address space.
+fn map_trampoline<I, D>(
+    kernel_page_table: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+    used_entries: &mut UsedLevel4Entries,
+) -> Trampoline
+where
+    I: ExactSizeIterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    let frame = frame_allocator
+        .allocate_frame()
+        .expect("failed to allocate trampoline frame");
+    let phys_addr = frame.start_address();
+    let page_ptr: *mut u8 = (kernel_page_table.phys_offset() + phys_addr.as_u64()).as_mut_ptr();
+    // fill the rest of the page with `int3` instructions
+    unsafe {
+        core::ptr::write_bytes(page_ptr, 0xcc, Size4KiB::SIZE as usize);
+        core::ptr::copy_nonoverlapping(TRAMPOLINE_STUB.as_ptr(), page_ptr, TRAMPOLINE_STUB.len());
+    }
+
+    let identity_page = Page::containing_address(VirtAddr::new(phys_addr.as_u64()));
+    let page =
+        mapping_addr_page_aligned(Mapping::Dynamic, Size4KiB::SIZE, used_entries, "trampoline");
+    for page in [identity_page, page] {
+        match unsafe {
+            kernel_page_table.map_to_with_table_flags(
+                page,
+                frame,
+                PageTableFlags::PRESENT,
+                PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+                frame_allocator,
+            )
+        } {
+            Ok(tlb) => tlb.ignore(),
+            Err(err) => panic!("failed to map trampoline page {:?}: {:?}", page, err),
+        }
+    }
+    Trampoline {
+        phys_addr: phys_addr.as_u64(),
+        addr: page.start_address().as_u64(),
+    }
+}
+
+/// Allocates a zeroed, physically contiguous region of at least `size` bytes that ends below
+/// `max_addr` and maps it writable into the kernel address space.
+///
+/// Returns `None` if no suitable memory was found.
+fn map_dma_region<I, D>(
+    size: u64,
+    max_addr: Option<u64>,
+    kernel_page_table: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+    used_entries: &mut UsedLevel4Entries,
+) -> Option<DmaRegion>
+where
+    I: ExactSizeIterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    let page_count = size.div_ceil(Size4KiB::SIZE);
+    // limits beyond the physical address width don't restrict the allocation
+    let limit = max_addr.and_then(|addr| PhysAddr::try_new(addr).ok());
+    let frames = match limit {
+        _ if page_count == 0 => None,
+        Some(limit) => frame_allocator.allocate_contiguous_below(page_count, limit),
+        None => frame_allocator.allocate_contiguous(page_count),
+    };
+    let Some(frames) = frames else {
+        log::warn!("Failed to allocate a DMA region of {size:#x} bytes");
+        return None;
+    };
+    let len = page_count * Size4KiB::SIZE;
+    let start_page = mapping_addr_page_aligned(Mapping::Dynamic, len, used_entries, "DMA region");
+    let phys_addr = frames.start.start_address();
+    let phys_ptr: *mut u8 = (kernel_page_table.phys_offset() + phys_addr.as_u64()).as_mut_ptr();
+    unsafe { core::ptr::write_bytes(phys_ptr, 0, len as usize) };
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    for (i, frame) in frames.enumerate() {
+        let page = start_page + i as u64;
+        match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+            Ok(tlb) => tlb.ignore(),
+            Err(err) => panic!(
+                "Failed to map page {:?} to frame {:?}: {:?}",
+                page, frame, err
+            ),
+        };
+    }
+    Some(DmaRegion {
+        phys_addr: phys_addr.as_u64(),
+        addr: start_page.start_address().as_u64(),
+        len,
+    })
+}
+
+/// Returns the end of the physical address range that is covered by the physical memory
+/// mapping.
+///
+/// This is the largest physical address, unless the `identity_map_limit` config option
+/// restricts the mapping to a lower address.
+fn physical_memory_mapping_end(max_phys_addr: PhysAddr, config: &BootloaderConfig) -> PhysAddr {
+    match config.mappings.identity_map_limit {
+        Some(limit) => {
+            let limit = x86_64::align_up(u64::min(limit, max_phys_addr.as_u64()), Size2MiB::SIZE);
+            PhysAddr::new(u64::min(limit, max_phys_addr.as_u64()))
+        }
+        None => max_phys_addr,
     }
 }
 
@@ -437,11 +1132,17 @@ pub struct Mappings {
     pub entry_point: VirtAddr,
     /// The (exclusive) end address of the kernel stack.
     pub stack_top: VirtAddr,
+    /// How the boot info is passed to the kernel entry point.
+    pub entry_convention: EntryConvention,
+    /// Whether a copy of the boot info is passed on the kernel stack.
+    pub boot_info_on_stack: bool,
     /// Keeps track of used entries in the level 4 page table, useful for finding a free
     /// virtual memory when needed.
     pub used_entries: UsedLevel4Entries,
     /// The start address of the framebuffer, if any.
     pub framebuffer: Option<VirtAddr>,
+    /// The start address of the identity mapping of the framebuffer, if enabled.
+    pub framebuffer_identity_addr: Option<VirtAddr>,
     /// The start address of the physical memory mapping, if enabled.
     pub physical_memory_offset: Option<VirtAddr>,
     /// The level 4 page table index of the recursive mapping, if enabled.
@@ -455,9 +1156,81 @@ pub struct Mappings {
     pub kernel_slice_len: u64,
     /// Relocation offset of the kernel image in virtual memory.
     pub kernel_image_offset: VirtAddr,
+    /// The lowest virtual address of the loaded kernel segments.
+    pub kernel_virtual_base: VirtAddr,
     pub ramdisk_slice_phys_start: Option<PhysAddr>,
     pub ramdisk_slice_start: Option<VirtAddr>,
     pub ramdisk_slice_len: u64,
+    /// The location of the copied device tree blob, if any.
+    pub device_tree: Option<DeviceTree>,
+    /// The location of the allocated DMA region, if any.
+    pub dma_region: Option<DmaRegion>,
+    /// The location of the address switch trampoline page, if enabled.
+    pub trampoline: Option<Trampoline>,
+    /// The location of the copied boot config blob, if any.
+    pub boot_config_blob: Option<ConfigBlob>,
+    /// The GDT and TSS created for the kernel, if enabled.
+    pub gdt: Option<GdtInfo>,
+    /// The physical address of the multiboot2 information structure, if it was created.
+    #[cfg(feature = "multiboot2")]
+    pub multiboot2_info: Option<PhysAddr>,
+}
+
+/// The layout of the boot info allocation, which also holds the memory maps, the boot events,
+/// and the boot log.
+struct BootInfoLayout {
+    combined: Layout,
+    /// The maximum number of regions in the memory map.
+    regions: usize,
+    /// The maximum number of regions in the coarse memory map, zero if it is disabled.
+    coarse_regions: usize,
+    memory_regions_offset: usize,
+    coarse_memory_regions_offset: usize,
+    unmapped_usable_offset: usize,
+    bootloader_reserved_offset: usize,
+    boot_events_offset: usize,
+    boot_log_offset: usize,
+}
+
+impl BootInfoLayout {
+    /// Computes the layout for a memory map with `memory_map_len` regions.
+    fn new(config: &BootloaderConfig, memory_map_len: usize) -> Self {
+        let boot_info_layout = Layout::new::<BootInfo>();
+        let regions = memory_map_len + 6; // up to 6 additional regions might be created by splits
+        let regions = match config.max_memory_regions {
+            Some(max) => regions.min(usize::try_from(max).unwrap_or(usize::MAX).max(1)),
+            None => regions,
+        };
+        let memory_regions_layout = Layout::array::<MemoryRegion>(regions).unwrap();
+        let (combined, memory_regions_offset) =
+            boot_info_layout.extend(memory_regions_layout).unwrap();
+        let coarse_regions = if config.coarse_memory_map {
+            legacy_memory_region::coarse_memory_map_len(regions)
+        } else {
+            0
+        };
+        let coarse_memory_regions_layout = Layout::array::<MemoryRegion>(coarse_regions).unwrap();
+        let (combined, coarse_memory_regions_offset) =
+            combined.extend(coarse_memory_regions_layout).unwrap();
+        let (combined, unmapped_usable_offset) = combined.extend(memory_regions_layout).unwrap();
+        let (combined, bootloader_reserved_offset) =
+            combined.extend(memory_regions_layout).unwrap();
+        let boot_events_layout = Layout::array::<BootEvent>(BootEvents::CAPACITY).unwrap();
+        let (combined, boot_events_offset) = combined.extend(boot_events_layout).unwrap();
+        let boot_log_layout = Layout::array::<u8>(logger::BOOT_LOG_SIZE).unwrap();
+        let (combined, boot_log_offset) = combined.extend(boot_log_layout).unwrap();
+        Self {
+            combined,
+            regions,
+            coarse_regions,
+            memory_regions_offset,
+            coarse_memory_regions_offset,
+            unmapped_usable_offset,
+            bootloader_reserved_offset,
+            boot_events_offset,
+            boot_log_offset,
+        }
+    }
 }
 
 /// Allocates and initializes the boot info struct and the memory map.
@@ -481,12 +1254,26 @@ where
     log::info!("Allocate bootinfo");
 
     // allocate and map space for the boot info
-    let (boot_info, memory_regions) = {
-        let boot_info_layout = Layout::new::<BootInfo>();
-        let regions = frame_allocator.len() + 4; // up to 4 regions might be split into used/unused
-        let memory_regions_layout = Layout::array::<MemoryRegion>(regions).unwrap();
-        let (combined, memory_regions_offset) =
-            boot_info_layout.extend(memory_regions_layout).unwrap();
+    let (
+        boot_info,
+        memory_regions,
+        coarse_memory_regions,
+        unmapped_usable,
+        bootloader_reserved,
+        boot_events,
+        boot_log,
+    ) = {
+        let BootInfoLayout {
+            combined,
+            regions,
+            coarse_regions,
+            memory_regions_offset,
+            coarse_memory_regions_offset,
+            unmapped_usable_offset,
+            bootloader_reserved_offset,
+            boot_events_offset,
+            boot_log_offset,
+        } = BootInfoLayout::new(config, frame_allocator.len());
 
         let boot_info_addr = mapping_addr(
             config.mappings.boot_info,
@@ -497,6 +1284,11 @@ where
         .expect("boot info addr is not properly aligned");
 
         let memory_map_regions_addr = boot_info_addr + memory_regions_offset;
+        let coarse_memory_map_regions_addr = boot_info_addr + coarse_memory_regions_offset;
+        let unmapped_usable_addr = boot_info_addr + unmapped_usable_offset;
+        let bootloader_reserved_addr = boot_info_addr + bootloader_reserved_offset;
+        let boot_events_addr = boot_info_addr + boot_events_offset;
+        let boot_log_addr = boot_info_addr + boot_log_offset;
         let memory_map_regions_end = boot_info_addr + combined.size();
 
         let start_page = Page::containing_address(boot_info_addr);
@@ -530,25 +1322,155 @@ where
             unsafe { &mut *boot_info_addr.as_mut_ptr() };
         let memory_regions: &'static mut [MaybeUninit<MemoryRegion>] =
             unsafe { slice::from_raw_parts_mut(memory_map_regions_addr.as_mut_ptr(), regions) };
-        (boot_info, memory_regions)
+        let coarse_memory_regions: Option<&'static mut [MaybeUninit<MemoryRegion>]> =
+            config.coarse_memory_map.then(|| unsafe {
+                slice::from_raw_parts_mut(
+                    coarse_memory_map_regions_addr.as_mut_ptr(),
+                    coarse_regions,
+                )
+            });
+        let unmapped_usable: &'static mut [MaybeUninit<MemoryRegion>] =
+            unsafe { slice::from_raw_parts_mut(unmapped_usable_addr.as_mut_ptr(), regions) };
+        let bootloader_reserved: &'static mut [MaybeUninit<MemoryRegion>] =
+            unsafe { slice::from_raw_parts_mut(bootloader_reserved_addr.as_mut_ptr(), regions) };
+        let boot_events: &'static mut [MaybeUninit<BootEvent>] = unsafe {
+            slice::from_raw_parts_mut(boot_events_addr.as_mut_ptr(), BootEvents::CAPACITY)
+        };
+        let boot_log: &'static mut [u8] =
+            unsafe { slice::from_raw_parts_mut(boot_log_addr.as_mut_ptr(), logger::BOOT_LOG_SIZE) };
+        (
+            boot_info,
+            memory_regions,
+            coarse_memory_regions,
+            unmapped_usable,
+            bootloader_reserved,
+            boot_events,
+            boot_log,
+        )
+    };
+
+    #[cfg(feature = "multiboot2")]
+    let multiboot2_info = {
+        log::info!("Allocate multiboot2 information");
+        multiboot2::Multiboot2Info::allocate(
+            memory_regions.len(),
+            &mut frame_allocator,
+            page_tables,
+        )
+    };
+
+    let page_tables_read_only = config.read_only_page_tables;
+    let page_table_cache_flags = match config.page_table_cache_policy {
+        PageTableCachePolicy::WriteThrough => PageTableFlags::WRITE_THROUGH,
+        PageTableCachePolicy::Uncached => PageTableFlags::WRITE_THROUGH | PageTableFlags::NO_CACHE,
+        _ => PageTableFlags::empty(),
+    };
+    if page_tables_read_only || !page_table_cache_flags.is_empty() {
+        // must happen after all other changes to the kernel page tables
+        if let Some(offset) = mappings.physical_memory_offset {
+            log::info!(
+                "Update kernel page table mappings (read-only: {}, cache flags: {:?})",
+                page_tables_read_only,
+                page_table_cache_flags
+            );
+            page_table_protection::update_kernel_page_table_mappings(
+                page_tables,
+                offset,
+                &mut frame_allocator,
+                page_tables_read_only,
+                page_table_cache_flags,
+            );
+        } else if !page_table_cache_flags.is_empty() {
+            log::warn!("page table cache policy is ignored without a physical memory mapping");
+        }
+    }
+    if page_tables_read_only && mappings.recursive_index.is_none() {
+        log::warn!("kernel page tables are read-only, but no recursive mapping is set up");
+    }
+
+    let pcid_enabled = config.pcid && pcid_supported();
+    if config.pcid && !pcid_enabled {
+        log::warn!("PCIDs are not supported by the CPU, leaving them disabled");
+    }
+    let global_pages_enabled = config.global_pages && global_pages_supported();
+    if config.global_pages && !global_pages_enabled {
+        log::warn!("Global pages are not supported by the CPU, leaving them disabled");
+    }
+    let smep_enabled = config.smep && smep_supported();
+    if config.smep && !smep_enabled {
+        log::warn!("SMEP is not supported by the CPU, leaving it disabled");
+    }
+    let smap_enabled = config.smap && smap_supported();
+    if config.smap && !smap_enabled {
+        log::warn!("SMAP is not supported by the CPU, leaving it disabled");
+    }
+
+    let mapped_end = match mappings.physical_memory_offset {
+        Some(_) => physical_memory_mapping_end(frame_allocator.max_phys_addr(), config),
+        None => PhysAddr::zero(),
     };
 
     log::info!("Create Memory Map");
 
     // build memory map
-    let memory_regions = frame_allocator.construct_memory_map(
+    let (memory_regions, dropped_memory_regions) = frame_allocator.construct_memory_map(
         memory_regions,
         mappings.kernel_slice_start,
         mappings.kernel_slice_len,
         mappings.ramdisk_slice_phys_start,
         mappings.ramdisk_slice_len,
+        config.bootloader_regions_usable,
+        config.max_usable_physical_address.map(PhysAddr::new),
+        config.null_frame,
+    );
+    if dropped_memory_regions > 0 {
+        log::warn!(
+            "{} memory regions did not fit into the memory map",
+            dropped_memory_regions
+        );
+    }
+    if let Some(rsdp_addr) = system_info.rsdp_addr {
+        // the firmware tables are identity-mapped in the bootloader address space
+        unsafe { acpi::apply_memory_affinity(rsdp_addr, memory_regions) };
+    }
+    legacy_memory_region::mark_mapped_regions(memory_regions, mapped_end);
+    let coarse_memory_regions = coarse_memory_regions.map(|regions| {
+        let regions = legacy_memory_region::construct_coarse_memory_map(memory_regions, regions);
+        legacy_memory_region::mark_mapped_regions(regions, mapped_end);
+        regions
+    });
+    let unmapped_usable = legacy_memory_region::construct_unmapped_usable_memory_map(
+        memory_regions,
+        mapped_end,
+        unmapped_usable,
+    );
+    let bootloader_reserved = legacy_memory_region::construct_bootloader_reserved_memory_map(
+        memory_regions,
+        bootloader_reserved,
     );
 
+    #[cfg(feature = "multiboot2")]
+    if let Some(multiboot2_info) = multiboot2_info {
+        log::info!("Create multiboot2 information");
+        multiboot2_info.write(
+            memory_regions,
+            system_info.framebuffer.as_ref(),
+            system_info.rsdp_addr,
+            mappings
+                .ramdisk_slice_phys_start
+                .map(|addr| (addr, mappings.ramdisk_slice_len)),
+        );
+        mappings.multiboot2_info = Some(multiboot2_info.start_address());
+    }
+
     log::info!("Create bootinfo");
 
     // create boot info
     let boot_info = boot_info.write({
         let mut info = BootInfo::new(memory_regions.into());
+        info.coarse_memory_regions = coarse_memory_regions.map(Into::into).into();
+        info.unmapped_usable = unmapped_usable.into();
+        info.bootloader_reserved = bootloader_reserved.into();
         info.framebuffer = mappings
             .framebuffer
             .map(|addr| unsafe {
@@ -564,6 +1486,18 @@ where
                 )
             })
             .into();
+        info.handoff_magic = config.handoff_magic.into();
+        info.framebuffer_identity_addr = mappings
+            .framebuffer_identity_addr
+            .map(VirtAddr::as_u64)
+            .into();
+        info.framebuffer_phys = system_info
+            .framebuffer
+            .map(|framebuffer| PhysicalFrameBuffer {
+                addr: framebuffer.addr.as_u64(),
+                info: framebuffer.info,
+            })
+            .into();
         info.physical_memory_offset = mappings.physical_memory_offset.map(VirtAddr::as_u64).into();
         info.recursive_index = mappings.recursive_index.map(Into::into).into();
         info.rsdp_addr = system_info.rsdp_addr.map(|addr| addr.as_u64()).into();
@@ -573,10 +1507,62 @@ where
             .map(|addr| addr.as_u64())
             .into();
         info.ramdisk_len = mappings.ramdisk_slice_len;
+        info.device_tree = mappings.device_tree.into();
+        info.dma_region = mappings.dma_region.into();
+        info.trampoline = mappings.trampoline.into();
+        info.boot_config_blob = mappings.boot_config_blob.into();
         info.kernel_addr = mappings.kernel_slice_start.as_u64();
         info.kernel_len = mappings.kernel_slice_len as _;
         info.kernel_image_offset = mappings.kernel_image_offset.as_u64();
+        info.kernel_virtual_base = mappings.kernel_virtual_base.as_u64();
+        info.kernel_load_bias = mappings.kernel_image_offset.as_u64();
+        info.gdt = mappings.gdt.into();
+        info.page_tables_read_only = page_tables_read_only;
+        info.pcid_enabled = pcid_enabled;
+        info.global_pages_enabled = global_pages_enabled;
+        info.smep_enabled = smep_enabled;
+        info.smap_enabled = smap_enabled;
+        let apic = apic::detect();
+        info.bsp_apic_id = apic.as_ref().map(|apic| apic.apic_id).into();
+        info.lapic_base = apic.as_ref().map(|apic| apic.lapic_base).into();
+        info.x2apic_enabled = apic.map_or(false, |apic| apic.x2apic_enabled);
+        info.memory_encryption = memory_encryption::detect().into();
+        info.fs_base = config.fs_base.into();
+        info.gs_base = config.gs_base.into();
+        // the firmware tables are identity-mapped in the bootloader address space
+        info.hpet = system_info
+            .rsdp_addr
+            .and_then(|rsdp_addr| unsafe { acpi::find_hpet(rsdp_addr) })
+            .into();
+        info.iommu = system_info
+            .rsdp_addr
+            .and_then(|rsdp_addr| unsafe { acpi::find_iommu(rsdp_addr) })
+            .into();
+        if config.acpi_table_cache {
+            info.acpi_tables = system_info
+                .rsdp_addr
+                .map(|rsdp_addr| unsafe { acpi::find_cached_tables(rsdp_addr) })
+                .unwrap_or_default();
+        }
+        info.available_video_modes = system_info.available_video_modes;
+        info.vbe_info = system_info.vbe_info.into();
+        info.boot_time = system_info.boot_time.into();
+        info.deferred_exit = system_info.deferred_exit.into();
+        info.ap_trampoline_region = system_info.ap_trampoline_addr.map(PhysAddr::as_u64).into();
+        info.dropped_memory_regions = dropped_memory_regions as u64;
         info._test_sentinel = boot_config._test_sentinel;
+        boot_events::record(
+            BootEventId::BOOT_INFO_CREATED,
+            info.memory_regions.len() as u64,
+        );
+        info.boot_events = boot_events::copy_to(boot_events).into();
+        // copy the log last to include as many messages as possible
+        let (boot_log_len, boot_log_truncated) = logger::BOOT_LOG.lock().copy_to(boot_log);
+        info.boot_log = Optional::Some(BootLog {
+            addr: boot_log.as_ptr() as u64,
+            len: boot_log_len as u64,
+            truncated: boot_log_truncated,
+        });
         info
     });
 
@@ -597,19 +1583,120 @@ pub fn switch_to_kernel(
         page_table: kernel_level_4_frame,
         stack_top: mappings.stack_top,
         entry_point: mappings.entry_point,
+        entry_convention: mappings.entry_convention,
+        boot_info_on_stack: mappings.boot_info_on_stack,
         boot_info,
+        #[cfg(feature = "multiboot2")]
+        multiboot2_info: mappings.multiboot2_info,
     };
+    let entry_stack_pointer = addresses.entry_stack_pointer();
+    if let Err(err) = check_entry_stack_pointer(entry_stack_pointer, addresses.stack_top) {
+        panic!(
+            "invalid kernel stack pointer {entry_stack_pointer:#x} at handoff (stack top {:?}): \
+            {err}",
+            addresses.stack_top
+        );
+    }
+
+    if addresses.boot_info.pcid_enabled {
+        log::info!("Enable PCIDs");
+        enable_pcid();
+    }
+    if addresses.boot_info.global_pages_enabled {
+        log::info!("Enable global pages");
+        unsafe { Cr4::update(|flags| flags.insert(Cr4Flags::PAGE_GLOBAL)) };
+    }
+    // the bootloader only uses supervisor mappings, so it is not affected by SMEP and SMAP
+    if addresses.boot_info.smep_enabled {
+        log::info!("Enable SMEP");
+        unsafe {
+            Cr4::update(|flags| flags.insert(Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION))
+        };
+    }
+    if addresses.boot_info.smap_enabled {
+        log::info!("Enable SMAP");
+        unsafe { Cr4::update(|flags| flags.insert(Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION)) };
+    }
+    // the bootloader doesn't use the FS and GS segments, so the bases can be set early
+    if let Optional::Some(base) = addresses.boot_info.fs_base {
+        FsBase::write(VirtAddr::new(base));
+    }
+    if let Optional::Some(base) = addresses.boot_info.gs_base {
+        GsBase::write(VirtAddr::new(base));
+    }
 
     log::info!(
         "Jumping to kernel entry point at {:?}",
         addresses.entry_point
     );
+    // the kernel owns the framebuffer from now on
+    if let Some(logger) = logger::LOGGER.get() {
+        logger.disable_framebuffer();
+    }
 
     unsafe {
         context_switch(addresses);
     }
 }
 
+/// Returns whether the CPU supports process-context identifiers.
+fn pcid_supported() -> bool {
+    CpuId::new()
+        .get_feature_info()
+        .map_or(false, |info| info.has_pcid())
+}
+
+/// Returns whether the CPU supports global pages.
+fn global_pages_supported() -> bool {
+    CpuId::new()
+        .get_feature_info()
+        .map_or(false, |info| info.has_pge())
+}
+
+/// Returns whether the CPU supports supervisor-mode execution prevention.
+fn smep_supported() -> bool {
+    CpuId::new()
+        .get_extended_feature_info()
+        .map_or(false, |info| info.has_smep())
+}
+
+/// Returns whether the CPU supports supervisor-mode access prevention.
+fn smap_supported() -> bool {
+    CpuId::new()
+        .get_extended_feature_info()
+        .map_or(false, |info| info.has_smap())
+}
+
+/// Sets `CR4.PCIDE`.
+///
+/// Setting the flag causes a general protection fault if the lower 12 bits of `CR3` are not
+/// zero, so `CR3` is reloaded without any flags first.
+fn enable_pcid() {
+    let (frame, _) = Cr3::read();
+    unsafe {
+        Cr3::write(frame, Cr3Flags::empty());
+        Cr4::update(|flags| flags.insert(Cr4Flags::PCID));
+    }
+}
+
+/// Writes back and invalidates all caches and flushes the complete TLB, including global pages.
+///
+/// This makes sure that the page tables written by the bootloader are in memory before the
+/// kernel address space is loaded, even on hardware with incoherent caches.
+fn flush_caches_and_tlb() {
+    unsafe { asm!("wbinvd", options(nostack, preserves_flags)) };
+    let cr4 = Cr4::read();
+    if cr4.contains(Cr4Flags::PAGE_GLOBAL) {
+        // toggling `CR4.PGE` invalidates all TLB entries, including global ones
+        unsafe {
+            Cr4::write(cr4 - Cr4Flags::PAGE_GLOBAL);
+            Cr4::write(cr4);
+        }
+    } else {
+        x86_64::instructions::tlb::flush_all();
+    }
+}
+
 /// Provides access to the page tables of the bootloader and kernel address space.
 pub struct PageTables {
     /// Provides access to the page tables of the bootloader address space.
@@ -624,21 +1711,186 @@ pub struct PageTables {
     pub kernel_level_4_frame: PhysFrame,
 }
 
+impl PageTables {
+    /// Translates a virtual address of the kernel address space to the mapped physical address.
+    ///
+    /// Walks the kernel page tables without modifying them, so it can be used to verify the
+    /// kernel mappings before the kernel is started, e.g. in a [`PreHandoffCallback`]. Returns
+    /// `None` if the address is not mapped.
+    pub fn translate(&self, virt: VirtAddr) -> Option<PhysAddr> {
+        self.kernel.translate_addr(virt)
+    }
+}
+
+/// Writes an empty level 4 page table to the given frame and returns a reference to it.
+///
+/// Some firmware reports ROM or reserved memory as usable, where writes are silently ignored.
+/// To avoid a subtle fault later, a test pattern is written to the frame and read back first.
+/// Panics if the frame is not writable.
+///
+/// # Safety
+///
+/// The frame must be unused and mapped at `phys_offset + frame.start_address()`.
+pub unsafe fn init_level_4_table(
+    frame: PhysFrame,
+    phys_offset: VirtAddr,
+) -> &'static mut PageTable {
+    const TEST_PATTERN: u64 = 0x5a5a_a5a5_0f0f_f0f0;
+
+    let ptr: *mut PageTable = (phys_offset + frame.start_address().as_u64()).as_mut_ptr();
+    let first_entry: *mut u64 = ptr.cast();
+    let writable = unsafe {
+        first_entry.write_volatile(TEST_PATTERN);
+        first_entry.read_volatile() == TEST_PATTERN
+    };
+    if !writable {
+        panic!(
+            "level 4 page table frame {:?} is not writable (ROM or reserved memory?)",
+            frame
+        );
+    }
+    unsafe {
+        ptr.write(PageTable::new());
+        &mut *ptr
+    }
+}
+
+/// Returns an upper bound for the number of frames needed to map the kernel and to create
+/// the boot info.
+///
+/// `segments` are the virtual start addresses and memory sizes of the loadable segments. Each
+/// segment page might need its own frame, e.g. for `.bss` or copy-on-write pages, and each
+/// segment might need its own level 3, level 2, and level 1 tables. `allocated` are the sizes
+/// of the other mappings that are backed by newly allocated frames, e.g. the kernel stack and
+/// the boot info. `existing` are the sizes of the mappings of existing physical memory, e.g.
+/// the framebuffer and the ramdisk, which only need page tables. If the physical memory is
+/// mapped, `physical_memory_end` is the end of this mapping, which uses 2MiB pages. A few more
+/// frames are added for the small fixed-size mappings.
+fn kernel_frames_estimate(
+    segments: impl Iterator<Item = (u64, u64)>,
+    allocated: &[u64],
+    existing: &[u64],
+    physical_memory_end: Option<u64>,
+) -> u64 {
+    /// The frames for the GDT, the context switch identity mapping, and the trampoline,
+    /// including their page tables.
+    const SMALL_MAPPINGS: u64 = 16;
+
+    /// Returns the number of page tables below the level 4 table that are needed to map
+    /// `pages` consecutive pages, where `levels` is the number of these table levels.
+    fn tables(pages: u64, levels: u32) -> u64 {
+        if pages == 0 {
+            return 0;
+        }
+        // a range spanning N entries of a table level may touch one additional table
+        (1..=levels)
+            .map(|level| pages / 512u64.pow(level) + 2)
+            .sum()
+    }
+    fn pages(start: u64, size: u64) -> u64 {
+        if size == 0 {
+            return 0;
+        }
+        let first_page = start / Size4KiB::SIZE;
+        let last_page = (start + size - 1) / Size4KiB::SIZE;
+        last_page - first_page + 1
+    }
+
+    let segment_frames: u64 = segments
+        .map(|(start, size)| pages(start, size))
+        .map(|pages| pages + tables(pages, 3))
+        .sum();
+    let allocated_frames: u64 = allocated
+        .iter()
+        .map(|&size| pages(0, size))
+        .map(|pages| pages + tables(pages, 3))
+        .sum();
+    // the start of existing memory might not be page-aligned
+    let existing_tables: u64 = existing
+        .iter()
+        .map(|&size| tables(pages(Size4KiB::SIZE - 1, size), 3))
+        .sum();
+    let physical_memory_tables =
+        physical_memory_end.map_or(0, |end| tables(end.div_ceil(Size2MiB::SIZE), 2));
+    segment_frames + allocated_frames + existing_tables + physical_memory_tables + SMALL_MAPPINGS
+}
+
+/// Fills the given kernel stack frame according to the `kernel_stack_fill` config option.
+///
+/// # Safety
+///
+/// The frame must be unused and mapped at `phys_offset + frame.start_address()`.
+unsafe fn fill_stack_frame(frame: PhysFrame, fill: StackFill, phys_offset: VirtAddr) {
+    let value = match fill {
+        StackFill::Zero => 0,
+        StackFill::Poison => StackFill::POISON,
+        _ => return,
+    };
+    let ptr: *mut u8 = (phys_offset + frame.start_address().as_u64()).as_mut_ptr();
+    unsafe { core::ptr::write_bytes(ptr, value, Size4KiB::SIZE as usize) };
+}
+
 /// Performs the actual context switch.
+///
+/// Interrupts are disabled before the switch. The stack top must be 16-byte aligned. After
+/// pushing a zero return address, `RSP + 8` is 16-byte aligned on entry, which is what the
+/// System V ABI expects right after a `call` instruction. With [`EntryConvention::Stack`],
+/// the boot info pointer and a padding word are pushed before the return address, so the
+/// alignment on entry is the same.
+///
+/// If the boot info should be passed on the stack, it is copied to the kernel stack after
+/// loading the kernel page tables, which map the original boot info at the same address.
+/// The stack pointer is then aligned down to 16 bytes again.
+///
+/// With the `multiboot2` feature, the multiboot2 magic value is passed in `EAX` and the
+/// address of the multiboot2 information structure in `EBX`. Otherwise, both registers are
+/// zero. The `rbx` register can't be used as an operand, so it is set through a scratch
+/// register. This is fine because we never return.
 unsafe fn context_switch(addresses: Addresses) -> ! {
+    #[cfg(feature = "multiboot2")]
+    let (magic, multiboot2_info) = match addresses.multiboot2_info {
+        Some(addr) => (multiboot2::BOOTLOADER_MAGIC, addr.as_u64()),
+        None => (0, 0),
+    };
+    #[cfg(not(feature = "multiboot2"))]
+    let (magic, multiboot2_info) = (0u32, 0u64);
+    let stack_argument = u64::from(addresses.stack_argument());
+    let boot_info_copy_len = addresses.boot_info_copy_len();
     unsafe {
         asm!(
             r#"
+            cli
             xor rbp, rbp
+            mov rbx, {}
             mov cr3, {}
             mov rsp, {}
+            test rcx, rcx
+            jz 3f
+            sub rsp, rcx
+            and rsp, -16
+            mov rsi, rdi
+            mov rdi, rsp
+            cld
+            rep movsb
+            mov rdi, rsp
+            3:
+            test {stack_argument}, {stack_argument}
+            jz 2f
+            push 0
+            push rdi
+            2:
             push 0
             jmp {}
             "#,
+            in(reg) multiboot2_info,
             in(reg) addresses.page_table.start_address().as_u64(),
             in(reg) addresses.stack_top.as_u64(),
             in(reg) addresses.entry_point.as_u64(),
             in("rdi") addresses.boot_info as *const _ as usize,
+            in("eax") magic,
+            in("rcx") boot_info_copy_len,
+            out("rsi") _,
+            stack_argument = in(reg) stack_argument,
         );
     }
     unreachable!();
@@ -649,7 +1901,73 @@ struct Addresses {
     page_table: PhysFrame,
     stack_top: VirtAddr,
     entry_point: VirtAddr,
+    entry_convention: EntryConvention,
+    boot_info_on_stack: bool,
     boot_info: &'static mut BootInfo,
+    #[cfg(feature = "multiboot2")]
+    multiboot2_info: Option<PhysAddr>,
+}
+
+impl Addresses {
+    /// Whether the boot info pointer is pushed to the kernel stack.
+    fn stack_argument(&self) -> bool {
+        self.entry_convention == EntryConvention::Stack
+    }
+
+    /// The number of bytes of the boot info copy on the kernel stack.
+    fn boot_info_copy_len(&self) -> usize {
+        match self.boot_info_on_stack {
+            true => mem::size_of::<BootInfo>(),
+            false => 0,
+        }
+    }
+
+    /// Returns the stack pointer at the kernel entry point.
+    ///
+    /// Mirrors the stack setup of [`context_switch`].
+    fn entry_stack_pointer(&self) -> u64 {
+        entry_stack_pointer(
+            self.stack_top,
+            self.boot_info_copy_len() as u64,
+            self.stack_argument(),
+        )
+    }
+}
+
+/// Returns the stack pointer at the kernel entry point for the given stack setup.
+fn entry_stack_pointer(stack_top: VirtAddr, boot_info_copy_len: u64, stack_argument: bool) -> u64 {
+    let mut rsp = stack_top.as_u64();
+    if boot_info_copy_len > 0 {
+        rsp = (rsp - boot_info_copy_len) & !0xf;
+    }
+    if stack_argument {
+        // boot info pointer and padding word
+        rsp -= 16;
+    }
+    // zero return address
+    rsp - 8
+}
+
+/// Checks the stack pointer at the kernel entry point.
+///
+/// The stack pointer must be 16-byte aligned before the zero return address is pushed, as
+/// the kernel is entered like a called function. The stack between the stack pointer and
+/// the stack top must be canonical, i.e. it must not cross the non-canonical hole.
+fn check_entry_stack_pointer(rsp: u64, stack_top: VirtAddr) -> Result<(), &'static str> {
+    if rsp.wrapping_add(8) % 16 != 0 {
+        return Err("not 16-byte aligned before the return address");
+    }
+    if VirtAddr::try_new(rsp).is_err() {
+        return Err("not canonical");
+    }
+    if rsp >= stack_top.as_u64() {
+        return Err("not below the stack top");
+    }
+    // both addresses are canonical, so they are in the same half if bit 47 matches
+    if rsp >> 47 != (stack_top.as_u64() - 1) >> 47 {
+        return Err("the stack crosses the non-canonical address range");
+    }
+    Ok(())
 }
 
 fn mapping_addr_page_aligned(
@@ -690,3 +2008,273 @@ fn enable_write_protect_bit() {
     use x86_64::registers::control::{Cr0, Cr0Flags};
     unsafe { Cr0::update(|cr0| *cr0 |= Cr0Flags::WRITE_PROTECT) };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Allocates page tables on the heap; the physical memory offset of the tests is zero.
+    struct HeapFrameAllocator;
+
+    unsafe impl FrameAllocator<Size4KiB> for HeapFrameAllocator {
+        fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+            let table: &'static mut PageTable = Box::leak(Box::new(PageTable::new()));
+            let addr = PhysAddr::new(table as *mut PageTable as u64);
+            Some(PhysFrame::from_start_address(addr).unwrap())
+        }
+    }
+
+    fn empty_page_table() -> (OffsetPageTable<'static>, PhysFrame) {
+        let frame = HeapFrameAllocator.allocate_frame().unwrap();
+        let table = unsafe { &mut *(frame.start_address().as_u64() as *mut PageTable) };
+        (
+            unsafe { OffsetPageTable::new(table, VirtAddr::new(0)) },
+            frame,
+        )
+    }
+
+    #[test]
+    fn translate_kernel_address() {
+        let (mut kernel, kernel_level_4_frame) = empty_page_table();
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(0xffff_8000_0020_0000));
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x1234_5000));
+        unsafe {
+            kernel
+                .map_to(
+                    page,
+                    frame,
+                    PageTableFlags::PRESENT,
+                    &mut HeapFrameAllocator,
+                )
+                .unwrap()
+                .ignore();
+        }
+        let page_tables = PageTables {
+            bootloader: empty_page_table().0,
+            kernel,
+            kernel_level_4_frame,
+        };
+
+        assert_eq!(
+            page_tables.translate(page.start_address() + 0x678u64),
+            Some(PhysAddr::new(0x1234_5678))
+        );
+        assert_eq!(page_tables.translate((page + 1).start_address()), None);
+        assert_eq!(page_tables.translate(VirtAddr::new(0x1000)), None);
+    }
+
+    #[test]
+    fn dump_kernel_page_tables() {
+        let (mut kernel, kernel_level_4_frame) = empty_page_table();
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        // three contiguous pages are summarized, the fourth one maps a different frame
+        for (page, frame) in [
+            (0, 0x10_0000),
+            (1, 0x10_1000),
+            (2, 0x10_2000),
+            (3, 0x50_0000),
+        ] {
+            let page =
+                Page::<Size4KiB>::containing_address(VirtAddr::new(0x40_0000 + page * 0x1000));
+            let frame = PhysFrame::containing_address(PhysAddr::new(frame));
+            unsafe {
+                kernel
+                    .map_to(page, frame, flags, &mut HeapFrameAllocator)
+                    .unwrap()
+                    .ignore();
+            }
+        }
+        let page = Page::<Size2MiB>::containing_address(VirtAddr::new(0xffff_8000_0000_0000));
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x4000_0000));
+        unsafe {
+            kernel
+                .map_to(page, frame, flags, &mut HeapFrameAllocator)
+                .unwrap()
+                .ignore();
+        }
+        let page_tables = PageTables {
+            bootloader: empty_page_table().0,
+            kernel,
+            kernel_level_4_frame,
+        };
+
+        let mut dump = String::new();
+        page_tables.dump(&mut dump).unwrap();
+
+        // the page tables are allocated on the heap, so hide their addresses
+        let lines: Vec<_> = dump
+            .lines()
+            .map(|line| match line.split_once(" at ") {
+                Some((head, rest)) => {
+                    let tail = rest.split_once(' ').map_or("", |(_, tail)| tail);
+                    format!("{head} at <addr> {tail}").trim_end().to_owned()
+                }
+                None => line.to_owned(),
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "level 4 table at <addr>",
+                "[  0] 0x0000000000000000: level 3 table at <addr> (PRESENT | WRITABLE)",
+                "  [  0] 0x0000000000000000: level 2 table at <addr> (PRESENT | WRITABLE)",
+                "    [  2] 0x0000000000400000: level 1 table at <addr> (PRESENT | WRITABLE)",
+                "      [  0-  2] 0x0000000000400000 -> 0x100000: 3 x 4KiB (PRESENT | WRITABLE)",
+                "      [  3] 0x0000000000403000 -> 0x500000: 1 x 4KiB (PRESENT | WRITABLE)",
+                "[256] 0xffff800000000000: level 3 table at <addr> (PRESENT | WRITABLE)",
+                "  [  0] 0xffff800000000000: level 2 table at <addr> (PRESENT | WRITABLE)",
+                "    [  0] 0xffff800000000000 -> 0x40000000: 1 x 2MiB (PRESENT | WRITABLE | HUGE_PAGE)",
+            ]
+        );
+    }
+
+    #[test]
+    fn page_table_cache_flags() {
+        use x86_64::structures::paging::mapper::TranslateResult;
+
+        let (mut kernel, kernel_level_4_frame) = empty_page_table();
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(0xffff_8000_0000_0000));
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x1234_5000));
+        unsafe {
+            kernel
+                .map_to(page, frame, flags, &mut HeapFrameAllocator)
+                .unwrap()
+                .ignore();
+        }
+        // identity-map the level 4 table, as the physical memory mapping at offset zero would
+        unsafe {
+            kernel
+                .identity_map(kernel_level_4_frame, flags, &mut HeapFrameAllocator)
+                .unwrap()
+                .ignore();
+        }
+        let mut page_tables = PageTables {
+            bootloader: empty_page_table().0,
+            kernel,
+            kernel_level_4_frame,
+        };
+
+        page_table_protection::update_kernel_page_table_mappings(
+            &mut page_tables,
+            VirtAddr::new(0),
+            &mut HeapFrameAllocator,
+            true,
+            PageTableFlags::WRITE_THROUGH,
+        );
+
+        let level_4_addr = VirtAddr::new(kernel_level_4_frame.start_address().as_u64());
+        let TranslateResult::Mapped { flags, .. } = page_tables.kernel.translate(level_4_addr)
+        else {
+            panic!("level 4 table is not mapped");
+        };
+        assert!(flags.contains(PageTableFlags::WRITE_THROUGH));
+        assert!(!flags.contains(PageTableFlags::WRITABLE));
+        // the entry pointing to the level 3 table of the kernel mapping uses the cache policy too
+        let level_4_entry = &page_tables.kernel.level_4_table()[page.p4_index()];
+        assert!(level_4_entry
+            .flags()
+            .contains(PageTableFlags::WRITE_THROUGH));
+        // other mappings are not changed
+        assert_eq!(
+            page_tables.translate(page.start_address()),
+            Some(frame.start_address())
+        );
+    }
+
+    #[test]
+    fn kernel_frames_estimate_is_upper_bound() {
+        let estimate = |segments: &[(u64, u64)], allocated: &[u64], existing: &[u64], physical| {
+            kernel_frames_estimate(segments.iter().copied(), allocated, existing, physical)
+        };
+        // a single page needs at most one table per level
+        assert_eq!(estimate(&[(0x1000, 1)], &[], &[], None), 1 + 6 + 16);
+        // a segment crossing a level 1 table boundary
+        assert_eq!(estimate(&[(0x1ff000, 0x2000)], &[], &[], None), 2 + 6 + 16);
+        // 80 KiB stack
+        assert_eq!(estimate(&[], &[80 * 1024], &[], None), 20 + 6 + 16);
+        // an unaligned 8 KiB framebuffer spans three pages, but only needs page tables
+        assert_eq!(estimate(&[], &[], &[0x2000], None), 6 + 16);
+        // 4 GiB of physical memory are mapped with 2048 2MiB pages in four level 2 tables
+        assert_eq!(estimate(&[], &[], &[], Some(4 << 30)), (4 + 2) + 2 + 16);
+        // empty mappings don't need any frames
+        assert_eq!(estimate(&[(0x1000, 0)], &[0], &[0], None), 16);
+    }
+
+    #[test]
+    fn physical_memory_mapping_keeps_identity_mapped_pages() {
+        use x86_64::structures::paging::mapper::TranslateResult;
+
+        let (mut kernel, _) = empty_page_table();
+        // an executable identity mapping like the one of the context switch function
+        let context_switch = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(0x20_1000));
+        unsafe {
+            kernel
+                .identity_map(
+                    context_switch,
+                    PageTableFlags::PRESENT,
+                    &mut HeapFrameAllocator,
+                )
+                .unwrap()
+                .ignore();
+        }
+
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        let page = Page::<Size2MiB>::containing_address(VirtAddr::new(0x20_0000));
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x20_0000));
+        let result = unsafe { kernel.map_to(page, frame, flags, &mut HeapFrameAllocator) };
+        assert!(matches!(result, Err(MapToError::PageAlreadyMapped(_))));
+        map_physical_memory_4kib(&mut kernel, page, frame, flags, &mut HeapFrameAllocator);
+
+        let flags_at = |addr: u64| match kernel.translate(VirtAddr::new(addr)) {
+            TranslateResult::Mapped { frame, flags, .. } => {
+                assert_eq!(frame.start_address().as_u64(), addr & !0xfff);
+                flags
+            }
+            _ => panic!("{addr:#x} is not mapped"),
+        };
+        assert_eq!(flags_at(0x20_1000), PageTableFlags::PRESENT);
+        assert_eq!(flags_at(0x20_0000), flags);
+        assert_eq!(flags_at(0x3f_f000), flags);
+    }
+
+    #[test]
+    fn fill_kernel_stack_frame() {
+        let frame = HeapFrameAllocator.allocate_frame().unwrap();
+        let bytes = unsafe {
+            slice::from_raw_parts_mut(
+                frame.start_address().as_u64() as *mut u8,
+                Size4KiB::SIZE as usize,
+            )
+        };
+        bytes.fill(0x55);
+
+        unsafe { fill_stack_frame(frame, StackFill::Unchanged, VirtAddr::new(0)) };
+        assert!(bytes.iter().all(|&b| b == 0x55));
+        unsafe { fill_stack_frame(frame, StackFill::Zero, VirtAddr::new(0)) };
+        assert!(bytes.iter().all(|&b| b == 0));
+        unsafe { fill_stack_frame(frame, StackFill::Poison, VirtAddr::new(0)) };
+        assert!(bytes.iter().all(|&b| b == StackFill::POISON));
+    }
+
+    #[test]
+    fn entry_stack_pointer_is_checked() {
+        let stack_top = VirtAddr::new(0x1_0000_0000);
+        for (copy_len, stack_argument) in [(0, false), (0, true), (0x123, false), (0x123, true)] {
+            let rsp = entry_stack_pointer(stack_top, copy_len, stack_argument);
+            assert_eq!(check_entry_stack_pointer(rsp, stack_top), Ok(()));
+        }
+
+        // off by 8
+        let misaligned = entry_stack_pointer(stack_top, 0, false) - 8;
+        assert!(check_entry_stack_pointer(misaligned, stack_top).is_err());
+        let misaligned_top = stack_top + 8u64;
+        let rsp = entry_stack_pointer(misaligned_top, 0, false);
+        assert!(check_entry_stack_pointer(rsp, misaligned_top).is_err());
+
+        // a stack at the start of the higher half must not reach into the non-canonical hole
+        let stack_top = VirtAddr::new(0xffff_8000_0000_0000);
+        assert!(check_entry_stack_pointer(0x0000_7fff_ffff_fff8, stack_top).is_err());
+        assert!(check_entry_stack_pointer(0xffff_7fff_ffff_fff8, stack_top).is_err());
+    }
+}
@@ -25,6 +25,7 @@ const COPIED: Flags = Flags::BIT_9;
 struct Loader<'a, M, F> {
     elf_file: ElfFile<'a>,
     inner: Inner<'a, M, F>,
+    segment_guard_pages: bool,
 }
 
 struct Inner<'a, M, F> {
@@ -32,6 +33,7 @@ struct Inner<'a, M, F> {
     virtual_address_offset: VirtualAddressOffset,
     page_table: &'a mut M,
     frame_allocator: &'a mut F,
+    global_pages: bool,
 }
 
 impl<'a, M, F> Loader<'a, M, F>
@@ -90,6 +92,11 @@ where
         );
 
         used_entries.mark_segments(elf_file.program_iter(), virtual_address_offset);
+        let segment_guard_pages = kernel.config.kernel_segment_guard_pages;
+        if segment_guard_pages {
+            // keep other mappings out of the gaps between the segments
+            used_entries.mark_segment_span(elf_file.program_iter(), virtual_address_offset);
+        }
 
         header::sanity_check(&elf_file)?;
         let loader = Loader {
@@ -99,7 +106,9 @@ where
                 virtual_address_offset,
                 page_table,
                 frame_allocator,
+                global_pages: kernel.config.global_pages,
             },
+            segment_guard_pages,
         };
 
         Ok(loader)
@@ -130,6 +139,10 @@ where
             }
         }
 
+        if self.segment_guard_pages {
+            self.check_segment_guard_pages()?;
+        }
+
         // Apply relocations in virtual memory.
         for program_header in self.elf_file.program_iter() {
             if let Type::Dynamic = program_header.get_type()? {
@@ -151,6 +164,37 @@ where
         Ok(tls_template)
     }
 
+    /// Checks that the pages between the loadable segments are unmapped, so that they act as
+    /// guard pages.
+    ///
+    /// Segments that are directly adjacent in virtual memory have no room for a guard page.
+    /// They are skipped with a log message.
+    fn check_segment_guard_pages(&self) -> Result<(), &'static str> {
+        // the ELF specification requires loadable segments to be sorted by virtual address
+        let mut previous_end: Option<VirtAddr> = None;
+        for segment in self.elf_file.program_iter() {
+            if !matches!(segment.get_type(), Ok(Type::Load)) || segment.mem_size() == 0 {
+                continue;
+            }
+            let start = VirtAddr::new(self.inner.virtual_address_offset + segment.virtual_addr());
+            if let Some(previous_end) = previous_end {
+                let guard_page = Page::<Size4KiB>::containing_address(previous_end - 1u64) + 1;
+                if Page::containing_address(start) <= guard_page {
+                    log::warn!(
+                        "No guard page possible between the adjacent kernel segments ending \
+                        at {previous_end:#x} and starting at {start:#x}"
+                    );
+                } else if self.inner.page_table.translate_page(guard_page).is_ok() {
+                    return Err("guard page between kernel segments is mapped");
+                } else {
+                    log::info!("Guard page between kernel segments at {guard_page:?}");
+                }
+            }
+            previous_end = Some(start + segment.mem_size());
+        }
+        Ok(())
+    }
+
     fn entry_point(&self) -> VirtAddr {
         VirtAddr::new(self.inner.virtual_address_offset + self.elf_file.header.pt2.entry_point())
     }
@@ -164,14 +208,6 @@ where
     fn handle_load_segment(&mut self, segment: ProgramHeader) -> Result<(), &'static str> {
         log::info!("Handling Segment: {:x?}", segment);
 
-        let phys_start_addr = self.kernel_offset + segment.offset();
-        let start_frame: PhysFrame = PhysFrame::containing_address(phys_start_addr);
-        let end_frame: PhysFrame =
-            PhysFrame::containing_address(phys_start_addr + segment.file_size() - 1u64);
-
-        let virt_start_addr = VirtAddr::new(self.virtual_address_offset + segment.virtual_addr());
-        let start_page: Page = Page::containing_address(virt_start_addr);
-
         let mut segment_flags = Flags::PRESENT;
         if !segment.flags().is_execute() {
             segment_flags |= Flags::NO_EXECUTE;
@@ -179,6 +215,30 @@ where
         if segment.flags().is_write() {
             segment_flags |= Flags::WRITABLE;
         }
+        // kernel segments are never user-accessible, so they can be global
+        if self.global_pages {
+            segment_flags |= Flags::GLOBAL;
+        }
+
+        let phys_start_addr = self.kernel_offset + segment.offset();
+        let virt_start_addr = VirtAddr::new(self.virtual_address_offset + segment.virtual_addr());
+
+        let align = segment.align();
+        if align > Size4KiB::SIZE {
+            // The loaded kernel file is only page-aligned, so the physical placement of the
+            // segment might not satisfy its alignment. Also, the frames of a `.bss` part would
+            // not be contiguous with the frames of the file.
+            let misaligned =
+                (phys_start_addr.as_u64()).wrapping_sub(virt_start_addr.as_u64()) % align != 0;
+            if misaligned || segment.mem_size() > segment.file_size() {
+                return self.handle_aligned_load_segment(&segment, segment_flags);
+            }
+        }
+
+        let start_frame: PhysFrame = PhysFrame::containing_address(phys_start_addr);
+        let end_frame: PhysFrame =
+            PhysFrame::containing_address(phys_start_addr + segment.file_size() - 1u64);
+        let start_page: Page = Page::containing_address(virt_start_addr);
 
         // map all frames of the segment at the desired virtual address
         for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
@@ -211,6 +271,110 @@ where
         Ok(())
     }
 
+    /// Copies the given segment to newly allocated, physically contiguous frames that satisfy
+    /// the alignment of the segment and maps them.
+    ///
+    /// Any `.bss` part of the segment is zeroed.
+    fn handle_aligned_load_segment(
+        &mut self,
+        segment: &ProgramHeader,
+        segment_flags: Flags,
+    ) -> Result<(), &'static str> {
+        if segment.mem_size() == 0 {
+            return Ok(());
+        }
+
+        let virt_start_addr = VirtAddr::new(self.virtual_address_offset + segment.virtual_addr());
+        let start_page: Page = Page::containing_address(virt_start_addr);
+        let end_page: Page = Page::containing_address(virt_start_addr + segment.mem_size() - 1u64);
+        let page_count = end_page - start_page + 1;
+        log::info!(
+            "Copying segment to {} frames aligned to {:#x}",
+            page_count,
+            segment.align()
+        );
+
+        let align = segment.align();
+        let start_frame = self.allocate_contiguous_frames(
+            page_count,
+            align,
+            start_page.start_address().as_u64() % align,
+        )?;
+
+        // zero the frames and copy the file data, utilizing identity-mapping
+        let start_ptr = start_frame.start_address().as_u64() as *mut u8;
+        let page_offset = virt_start_addr - start_page.start_address();
+        let src_ptr = (self.kernel_offset + segment.offset()).as_u64() as *const u8;
+        unsafe {
+            core::ptr::write_bytes(start_ptr, 0, (page_count * Size4KiB::SIZE) as usize);
+            core::ptr::copy_nonoverlapping(
+                src_ptr,
+                start_ptr.add(page_offset as usize),
+                segment.file_size() as usize,
+            );
+        }
+
+        // the frames are already copies, so `make_mut` doesn't need to copy them again
+        let flags = segment_flags | COPIED;
+        for (page, frame) in Page::range_inclusive(start_page, end_page)
+            .zip(PhysFrame::range(start_frame, start_frame + page_count))
+        {
+            let flusher = unsafe {
+                // The parent table flags need to be both readable and writable to
+                // support recursive page tables.
+                // See https://github.com/rust-osdev/bootloader/issues/443#issuecomment-2130010621
+                self.page_table
+                    .map_to_with_table_flags(
+                        page,
+                        frame,
+                        flags,
+                        Flags::PRESENT | Flags::WRITABLE,
+                        self.frame_allocator,
+                    )
+                    .map_err(|_err| "Failed to map aligned frame for segment")?
+            };
+            // we operate on an inactive page table, so we don't need to flush our changes
+            flusher.ignore();
+        }
+
+        Ok(())
+    }
+
+    /// Allocates `count` physically contiguous frames. The start address of the first frame
+    /// is equal to `align_offset` modulo `align`.
+    ///
+    /// Returns the first frame. Allocated frames that don't satisfy these requirements are
+    /// skipped and stay unused. This wastes less than `align` bytes of memory with the
+    /// [`LegacyFrameAllocator`](crate::legacy_memory_region::LegacyFrameAllocator), which
+    /// returns frames in ascending order.
+    fn allocate_contiguous_frames(
+        &mut self,
+        count: u64,
+        align: u64,
+        align_offset: u64,
+    ) -> Result<PhysFrame, &'static str> {
+        let mut start: Option<PhysFrame> = None;
+        let mut len = 0;
+        while len < count {
+            let frame = self
+                .frame_allocator
+                .allocate_frame()
+                .ok_or("Failed to allocate aligned frames for segment")?;
+            match start {
+                Some(start_frame) if frame == start_frame + len => len += 1,
+                _ if frame.start_address().as_u64() % align == align_offset => {
+                    start = Some(frame);
+                    len = 1;
+                }
+                _ => {
+                    start = None;
+                    len = 0;
+                }
+            }
+        }
+        Ok(start.unwrap())
+    }
+
     fn handle_bss_section(
         &mut self,
         segment: &ProgramHeader,
@@ -1,28 +1,49 @@
 use crate::{framebuffer::FrameBufferWriter, serial::SerialPort};
 use bootloader_api::info::FrameBufferInfo;
+use bootloader_boot_config::LogColors;
 use conquer_once::spin::OnceCell;
-use core::fmt::Write;
-use spinning_top::Spinlock;
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use spinning_top::{const_spinlock, Spinlock};
 
 /// The global logger instance used for the `log` crate.
 pub static LOGGER: OnceCell<LockedLogger> = OnceCell::uninit();
 
+/// The size of the in-memory boot log in bytes.
+pub const BOOT_LOG_SIZE: usize = 64 * 1024;
+
+/// Keeps the most recent log messages in memory, so that they can be passed to the kernel.
+pub static BOOT_LOG: Spinlock<BootLogBuffer> = const_spinlock(BootLogBuffer::new());
+
 /// A logger instance protected by a spinlock.
 pub struct LockedLogger {
     framebuffer: Option<Spinlock<FrameBufferWriter>>,
+    /// Whether messages are still drawn to the framebuffer.
+    framebuffer_active: AtomicBool,
     serial: Option<Spinlock<SerialPort>>,
 }
 
 impl LockedLogger {
     /// Create a new instance that logs to the given framebuffer.
+    ///
+    /// The framebuffer is cleared unless `keep_framebuffer_contents` is set.
     pub fn new(
         framebuffer: &'static mut [u8],
         info: FrameBufferInfo,
+        colors: LogColors,
         frame_buffer_logger_status: bool,
         serial_logger_status: bool,
+        keep_framebuffer_contents: bool,
     ) -> Self {
         let framebuffer = match frame_buffer_logger_status {
-            true => Some(Spinlock::new(FrameBufferWriter::new(framebuffer, info))),
+            true => Some(Spinlock::new(FrameBufferWriter::new(
+                framebuffer,
+                info,
+                colors,
+                keep_framebuffer_contents,
+            ))),
             false => None,
         };
 
@@ -33,10 +54,34 @@ impl LockedLogger {
 
         LockedLogger {
             framebuffer,
+            framebuffer_active: AtomicBool::new(true),
             serial,
         }
     }
 
+    /// Stops drawing log messages to the framebuffer.
+    ///
+    /// Called when the framebuffer is handed over to the kernel, so that a late bootloader
+    /// message or panic doesn't draw over the kernel's output. Messages are still written to
+    /// the serial port and the boot log.
+    pub fn disable_framebuffer(&self) {
+        self.framebuffer_active.store(false, Ordering::SeqCst);
+    }
+
+    /// Resumes drawing log messages to the framebuffer after [`disable_framebuffer`].
+    ///
+    /// Should only be used for fatal errors before the kernel is started.
+    ///
+    /// [`disable_framebuffer`]: Self::disable_framebuffer
+    pub fn enable_framebuffer(&self) {
+        self.framebuffer_active.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether log messages are drawn to the framebuffer.
+    pub fn framebuffer_active(&self) -> bool {
+        self.framebuffer.is_some() && self.framebuffer_active.load(Ordering::SeqCst)
+    }
+
     /// Force-unlocks the logger to prevent a deadlock.
     ///
     /// ## Safety
@@ -48,6 +93,7 @@ impl LockedLogger {
         if let Some(serial) = &self.serial {
             unsafe { serial.force_unlock() };
         }
+        unsafe { BOOT_LOG.force_unlock() };
     }
 }
 
@@ -57,7 +103,11 @@ impl log::Log for LockedLogger {
     }
 
     fn log(&self, record: &log::Record) {
-        if let Some(framebuffer) = &self.framebuffer {
+        if let Some(framebuffer) = self
+            .framebuffer
+            .as_ref()
+            .filter(|_| self.framebuffer_active())
+        {
             let mut framebuffer = framebuffer.lock();
             writeln!(framebuffer, "{:5}: {}", record.level(), record.args()).unwrap();
         }
@@ -65,7 +115,94 @@ impl log::Log for LockedLogger {
             let mut serial = serial.lock();
             writeln!(serial, "{:5}: {}", record.level(), record.args()).unwrap();
         }
+        let mut boot_log = BOOT_LOG.lock();
+        writeln!(boot_log, "{:5}: {}", record.level(), record.args()).unwrap();
     }
 
     fn flush(&self) {}
 }
+
+/// A ring buffer for log messages.
+///
+/// Once the buffer is full, the oldest messages are overwritten.
+pub struct BootLogBuffer {
+    buffer: [u8; BOOT_LOG_SIZE],
+    /// The index at which the next byte is written.
+    next: usize,
+    /// Whether older messages were overwritten.
+    wrapped: bool,
+}
+
+impl BootLogBuffer {
+    const fn new() -> Self {
+        Self {
+            buffer: [0; BOOT_LOG_SIZE],
+            next: 0,
+            wrapped: false,
+        }
+    }
+
+    /// Copies the log messages in chronological order to `dst`.
+    ///
+    /// If older messages were overwritten, the oldest remaining line is skipped because it
+    /// might be incomplete, so the copied log always starts at the beginning of a line.
+    /// Returns the number of copied bytes and whether messages were dropped.
+    pub fn copy_to(&self, dst: &mut [u8]) -> (usize, bool) {
+        let (older, newer) = if self.wrapped {
+            (&self.buffer[self.next..], &self.buffer[..self.next])
+        } else {
+            (&self.buffer[..0], &self.buffer[..self.next])
+        };
+        let mut bytes = older.iter().chain(newer).copied();
+        if self.wrapped {
+            bytes.by_ref().find(|&byte| byte == b'\n');
+        }
+
+        let mut len = 0;
+        for (dst, byte) in dst.iter_mut().zip(bytes) {
+            *dst = byte;
+            len += 1;
+        }
+        (len, self.wrapped)
+    }
+}
+
+impl fmt::Write for BootLogBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.buffer[self.next] = byte;
+            self.next += 1;
+            if self.next == BOOT_LOG_SIZE {
+                self.next = 0;
+                self.wrapped = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boot_log_skips_overwritten_line() {
+        let mut log = BootLogBuffer::new();
+        let mut dst = vec![0; BOOT_LOG_SIZE];
+
+        writeln!(log, "first").unwrap();
+        writeln!(log, "second").unwrap();
+        assert_eq!(log.copy_to(&mut dst), (13, false));
+        assert_eq!(&dst[..13], b"first\nsecond\n");
+
+        // overwrite "first", which makes "second" the oldest (skipped) line
+        let filler = "x".repeat(BOOT_LOG_SIZE - 13 - 1);
+        writeln!(log, "{filler}").unwrap();
+        writeln!(log, "third").unwrap();
+        let (len, truncated) = log.copy_to(&mut dst);
+        assert!(truncated);
+        assert_eq!(len, filler.len() + 1 + 6);
+        assert!(dst[..len].starts_with(filler.as_bytes()));
+        assert!(dst[..len].ends_with(b"\nthird\n"));
+    }
+}
@@ -0,0 +1,50 @@
+use bootloader_api::info::{MemoryEncryption, MemoryEncryptionKind, Optional};
+use raw_cpuid::CpuId;
+use x86_64::registers::model_specific::Msr;
+
+/// The AMD `SYSCFG` model-specific register.
+const AMD_SYSCFG: u32 = 0xc001_0010;
+/// Set in `SYSCFG` if memory encryption through the C-bit is enabled.
+const SYSCFG_MEM_ENCRYPT_ENABLE: u64 = 1 << 23;
+/// The Intel `IA32_TME_ACTIVATE` model-specific register.
+const IA32_TME_ACTIVATE: u32 = 0x982;
+/// The lock and enable bits of `IA32_TME_ACTIVATE`, TME is only active if both are set.
+const TME_ACTIVATE_LOCKED_AND_ENABLED: u64 = 0b11;
+
+/// Detects whether the CPU supports AMD SME or Intel TME and whether it is enabled.
+///
+/// Returns `None` if the CPU supports neither.
+pub fn detect() -> Option<MemoryEncryption> {
+    let cpuid = CpuId::new();
+
+    // CPUID leaf 0x8000_001f is reserved on Intel CPUs, so the SME bit is never set there
+    if let Some(info) = cpuid
+        .get_memory_encryption_info()
+        .filter(|info| info.has_sme())
+    {
+        // reading the MSR is safe because it exists on all CPUs that support SME
+        let syscfg = unsafe { Msr::new(AMD_SYSCFG).read() };
+        return Some(MemoryEncryption {
+            kind: MemoryEncryptionKind::AmdSme,
+            enabled: syscfg & SYSCFG_MEM_ENCRYPT_ENABLE != 0,
+            c_bit_position: Optional::Some(info.c_bit_position()),
+            physical_address_reduction: info.physical_address_reduction(),
+        });
+    }
+
+    if cpuid
+        .get_extended_feature_info()
+        .is_some_and(|features| features.has_tme_en())
+    {
+        // reading the MSR is safe because its existence is indicated by the TME_EN flag
+        let activate = unsafe { Msr::new(IA32_TME_ACTIVATE).read() };
+        return Some(MemoryEncryption {
+            kind: MemoryEncryptionKind::IntelTme,
+            enabled: activate & TME_ACTIVATE_LOCKED_AND_ENABLED == TME_ACTIVATE_LOCKED_AND_ENABLED,
+            c_bit_position: Optional::None,
+            physical_address_reduction: 0,
+        });
+    }
+
+    None
+}
@@ -0,0 +1,255 @@
+//! Creates a multiboot2 boot information structure from the data collected for the
+//! [`BootInfo`](bootloader_api::BootInfo).
+//!
+//! The structure is passed to the kernel in addition to the `BootInfo`, following the
+//! multiboot2 convention: `EAX` contains [`BOOTLOADER_MAGIC`] and `EBX` contains the physical
+//! address of the structure. The structure is identity-mapped in the kernel address space.
+//!
+//! See <https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html#Boot-information-format>
+//! for the format specification.
+
+use crate::{
+    legacy_memory_region::{LegacyFrameAllocator, LegacyMemoryRegion},
+    PageTables, RawFrameBufferInfo,
+};
+use bootloader_api::info::{MemoryRegion, MemoryRegionKind, PixelFormat};
+use core::slice;
+use usize_conversions::FromUsize;
+use x86_64::{
+    structures::paging::{Mapper, PageSize, PageTableFlags, Size4KiB},
+    PhysAddr,
+};
+
+/// The magic value that is passed to the kernel in `EAX`.
+pub const BOOTLOADER_MAGIC: u32 = 0x36d7_6289;
+
+const TAG_END: u32 = 0;
+const TAG_MODULE: u32 = 3;
+const TAG_MEMORY_MAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+const TAG_ACPI_OLD: u32 = 14;
+const TAG_ACPI_NEW: u32 = 15;
+
+const MEMORY_AVAILABLE: u32 = 1;
+const MEMORY_RESERVED: u32 = 2;
+const MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+const MEMORY_NVS: u32 = 4;
+
+/// UEFI memory types that have an equivalent multiboot2 memory type.
+const UEFI_ACPI_RECLAIM_MEMORY: u32 = 9;
+const UEFI_ACPI_MEMORY_NVS: u32 = 10;
+
+const MEMORY_MAP_ENTRY_SIZE: u32 = 24;
+const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+/// The command line of the module tag for the ramdisk.
+const RAMDISK_MODULE_STRING: &[u8] = b"ramdisk\0";
+/// The maximum size of the RSDP structure (ACPI 2.0 and later).
+const RSDP_MAX_LEN: usize = 36;
+/// The size of the RSDP structure of ACPI 1.0.
+const RSDP_V1_LEN: usize = 20;
+
+/// The physical memory that is reserved for the multiboot2 information structure.
+pub struct Multiboot2Info {
+    start: PhysAddr,
+    len: usize,
+}
+
+impl Multiboot2Info {
+    /// Allocates and identity-maps physically contiguous memory for a multiboot2 information
+    /// structure with up to `memory_regions` memory map entries.
+    ///
+    /// Returns `None` if no suitable memory below 4GiB was found, because the address needs to
+    /// fit into `EBX`.
+    pub fn allocate<I, D>(
+        memory_regions: usize,
+        frame_allocator: &mut LegacyFrameAllocator<I, D>,
+        page_tables: &mut PageTables,
+    ) -> Option<Self>
+    where
+        I: ExactSizeIterator<Item = D> + Clone,
+        D: LegacyMemoryRegion,
+    {
+        let len = max_len(memory_regions);
+        let frames = u64::from_usize(len).div_ceil(Size4KiB::SIZE);
+
+        let Some(range) = frame_allocator.allocate_contiguous_below(frames, PhysAddr::new(1 << 32))
+        else {
+            log::warn!("no memory below 4GiB available for multiboot2 information");
+            return None;
+        };
+
+        let flags = PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE;
+        for frame in range {
+            match unsafe {
+                page_tables
+                    .kernel
+                    .identity_map(frame, flags, frame_allocator)
+            } {
+                Ok(tlb) => tlb.flush(),
+                Err(err) => panic!("failed to identity-map frame {:?}: {:?}", frame, err),
+            }
+        }
+
+        Some(Self {
+            start: range.start.start_address(),
+            len,
+        })
+    }
+
+    /// Returns the physical start address of the information structure.
+    pub fn start_address(&self) -> PhysAddr {
+        self.start
+    }
+
+    /// Writes the multiboot2 information structure to the allocated memory.
+    ///
+    /// The memory is accessed through the identity mapping of the bootloader address space.
+    pub fn write(
+        &self,
+        memory_regions: &[MemoryRegion],
+        framebuffer: Option<&RawFrameBufferInfo>,
+        rsdp_addr: Option<PhysAddr>,
+        ramdisk: Option<(PhysAddr, u64)>,
+    ) {
+        let buffer = unsafe { slice::from_raw_parts_mut(self.start.as_u64() as *mut u8, self.len) };
+        let mut writer = Writer {
+            buffer,
+            offset: 8,
+            tag_start: 8,
+        };
+
+        writer.begin_tag(TAG_MEMORY_MAP);
+        writer.write_u32(MEMORY_MAP_ENTRY_SIZE);
+        // entry version
+        writer.write_u32(0);
+        for region in memory_regions {
+            writer.write_u64(region.start);
+            writer.write_u64(region.end - region.start);
+            writer.write_u32(memory_type(region.kind));
+            writer.write_u32(0);
+        }
+        writer.end_tag();
+
+        if let Some(framebuffer) = framebuffer {
+            let info = framebuffer.info;
+            let (red, green, blue) = match info.pixel_format {
+                PixelFormat::Rgb => (0, 8, 16),
+                PixelFormat::Bgr => (16, 8, 0),
+                PixelFormat::Unknown {
+                    red_position,
+                    green_position,
+                    blue_position,
+                } => (red_position, green_position, blue_position),
+                _ => (0, 0, 0),
+            };
+            writer.begin_tag(TAG_FRAMEBUFFER);
+            writer.write_u64(framebuffer.addr.as_u64());
+            writer.write_u32(info.bytes_per_scanline() as u32);
+            writer.write_u32(info.width as u32);
+            writer.write_u32(info.height as u32);
+            writer.write_bytes(&[(info.bytes_per_pixel * 8) as u8, FRAMEBUFFER_TYPE_RGB]);
+            // reserved
+            writer.write_bytes(&[0; 2]);
+            writer.write_bytes(&[red, 8, green, 8, blue, 8]);
+            writer.end_tag();
+        }
+
+        if let Some(rsdp_addr) = rsdp_addr {
+            let rsdp = rsdp_addr.as_u64() as *const u8;
+            // the revision field is at offset 15, the length field (ACPI 2.0+) at offset 20
+            let revision = unsafe { rsdp.add(15).read() };
+            if revision >= 2 {
+                let len = unsafe { rsdp.add(20).cast::<u32>().read_unaligned() } as usize;
+                let len = usize::min(len, RSDP_MAX_LEN);
+                writer.begin_tag(TAG_ACPI_NEW);
+                writer.write_bytes(unsafe { slice::from_raw_parts(rsdp, len) });
+            } else {
+                writer.begin_tag(TAG_ACPI_OLD);
+                writer.write_bytes(unsafe { slice::from_raw_parts(rsdp, RSDP_V1_LEN) });
+            }
+            writer.end_tag();
+        }
+
+        if let Some((ramdisk_start, ramdisk_len)) = ramdisk {
+            let ramdisk_end = ramdisk_start + ramdisk_len;
+            if ramdisk_end.as_u64() <= u64::from(u32::MAX) {
+                writer.begin_tag(TAG_MODULE);
+                writer.write_u32(ramdisk_start.as_u64() as u32);
+                writer.write_u32(ramdisk_end.as_u64() as u32);
+                writer.write_bytes(RAMDISK_MODULE_STRING);
+                writer.end_tag();
+            } else {
+                log::warn!("ramdisk is not below 4GiB, omitting it from multiboot2 information");
+            }
+        }
+
+        writer.begin_tag(TAG_END);
+        writer.end_tag();
+
+        // fixed part: total size and a reserved field
+        let total_size = writer.offset as u32;
+        writer.buffer[0..4].copy_from_slice(&total_size.to_ne_bytes());
+        writer.buffer[4..8].copy_from_slice(&0u32.to_ne_bytes());
+    }
+}
+
+/// Returns the maximum size of the information structure in bytes.
+pub(crate) fn max_len(memory_regions: usize) -> usize {
+    // fixed part and end tag
+    let fixed = 8 + 8;
+    let memory_map = 16 + memory_regions * MEMORY_MAP_ENTRY_SIZE as usize;
+    let framebuffer = 40;
+    let rsdp = 8 + RSDP_MAX_LEN + 4;
+    let module = 16 + RAMDISK_MODULE_STRING.len();
+    fixed + memory_map + framebuffer + rsdp + module
+}
+
+/// Converts the memory region kind to the corresponding multiboot2 memory type.
+fn memory_type(kind: MemoryRegionKind) -> u32 {
+    match kind {
+        MemoryRegionKind::Usable => MEMORY_AVAILABLE,
+        MemoryRegionKind::UnknownUefi(UEFI_ACPI_RECLAIM_MEMORY) => MEMORY_ACPI_RECLAIMABLE,
+        MemoryRegionKind::UnknownUefi(UEFI_ACPI_MEMORY_NVS) => MEMORY_NVS,
+        // the E820 memory types are the same as the multiboot2 memory types
+        MemoryRegionKind::UnknownBios(ty) => ty,
+        _ => MEMORY_RESERVED,
+    }
+}
+
+/// Writes tags with 8-byte alignment into a byte buffer.
+struct Writer<'a> {
+    buffer: &'a mut [u8],
+    offset: usize,
+    /// Offset of the tag that is currently written.
+    tag_start: usize,
+}
+
+impl Writer<'_> {
+    fn begin_tag(&mut self, ty: u32) {
+        self.tag_start = self.offset;
+        self.write_u32(ty);
+        // size, filled in by `end_tag`
+        self.write_u32(0);
+    }
+
+    fn end_tag(&mut self) {
+        let start = self.tag_start;
+        let size = (self.offset - start) as u32;
+        self.buffer[start + 4..start + 8].copy_from_slice(&size.to_ne_bytes());
+        let padding = self.offset.next_multiple_of(8) - self.offset;
+        self.write_bytes(&[0; 8][..padding]);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_ne_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_ne_bytes());
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer[self.offset..][..bytes.len()].copy_from_slice(bytes);
+        self.offset += bytes.len();
+    }
+}
@@ -0,0 +1,123 @@
+use crate::PageTables;
+use core::fmt;
+use x86_64::{
+    structures::paging::{page_table::PageTableLevel, PageTable, PageTableFlags},
+    PhysAddr, VirtAddr,
+};
+
+impl PageTables {
+    /// Writes the present entries of all levels of the kernel page tables to `writer`.
+    ///
+    /// Each entry that points to a lower level table is written on its own line, followed by
+    /// the entries of that table with a deeper indentation. Consecutive page entries of the
+    /// same table that map physically contiguous memory with the same flags are summarized
+    /// in a single line, so large identity or physical memory mappings don't flood the output.
+    pub fn dump(&self, writer: &mut impl fmt::Write) -> fmt::Result {
+        let level_4_addr = self.kernel_level_4_frame.start_address();
+        writeln!(writer, "level 4 table at {:#x}", level_4_addr.as_u64())?;
+        dump_table(
+            writer,
+            self.kernel.phys_offset(),
+            level_4_addr,
+            PageTableLevel::Four,
+            0,
+        )
+    }
+}
+
+/// Consecutive page entries that map contiguous physical memory with the same flags.
+struct Run {
+    first_index: usize,
+    last_index: usize,
+    virt_start: VirtAddr,
+    phys_start: PhysAddr,
+    flags: PageTableFlags,
+}
+
+fn dump_table(
+    writer: &mut impl fmt::Write,
+    phys_offset: VirtAddr,
+    table_addr: PhysAddr,
+    level: PageTableLevel,
+    virt_base: u64,
+) -> fmt::Result {
+    let table: &PageTable = unsafe { &*(phys_offset + table_addr.as_u64()).as_ptr() };
+    let indent = 2 * (4 - level as usize);
+    let entry_size = level.entry_address_space_alignment();
+
+    let mut run: Option<Run> = None;
+    for (index, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            write_run(writer, run.take(), indent, entry_size)?;
+            continue;
+        }
+        let virt = VirtAddr::new_truncate(virt_base + index as u64 * entry_size);
+        let next_level = level
+            .next_lower_level()
+            .filter(|_| !flags.contains(PageTableFlags::HUGE_PAGE));
+        match next_level {
+            Some(next_level) => {
+                write_run(writer, run.take(), indent, entry_size)?;
+                writeln!(
+                    writer,
+                    "{:indent$}[{index:3}] {:#018x}: level {} table at {:#x} ({flags:?})",
+                    "",
+                    virt.as_u64(),
+                    next_level as u8,
+                    entry.addr().as_u64()
+                )?;
+                dump_table(writer, phys_offset, entry.addr(), next_level, virt.as_u64())?;
+            }
+            None => {
+                let continues_run = run.as_ref().is_some_and(|run| {
+                    let len = (run.last_index - run.first_index + 1) as u64;
+                    run.last_index + 1 == index
+                        && run.flags == flags
+                        && run.phys_start + len * entry_size == entry.addr()
+                });
+                if continues_run {
+                    run.as_mut().unwrap().last_index = index;
+                } else {
+                    write_run(writer, run.take(), indent, entry_size)?;
+                    run = Some(Run {
+                        first_index: index,
+                        last_index: index,
+                        virt_start: virt,
+                        phys_start: entry.addr(),
+                        flags,
+                    });
+                }
+            }
+        }
+    }
+    write_run(writer, run, indent, entry_size)
+}
+
+fn write_run(
+    writer: &mut impl fmt::Write,
+    run: Option<Run>,
+    indent: usize,
+    page_size: u64,
+) -> fmt::Result {
+    let Some(run) = run else {
+        return Ok(());
+    };
+    let count = (run.last_index - run.first_index + 1) as u64;
+    let size = match page_size {
+        0x1000 => "4KiB",
+        0x20_0000 => "2MiB",
+        _ => "1GiB",
+    };
+    write!(writer, "{:indent$}[{:3}", "", run.first_index)?;
+    if count > 1 {
+        write!(writer, "-{:3}", run.last_index)?;
+    }
+    writeln!(
+        writer,
+        "] {:#018x} -> {:#x}: {count} x {size} ({:?})",
+        run.virt_start.as_u64(),
+        run.phys_start.as_u64(),
+        run.flags
+    )
+}
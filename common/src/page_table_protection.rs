@@ -0,0 +1,171 @@
+use crate::PageTables;
+use x86_64::{
+    structures::paging::{
+        mapper::{MappedFrame, TranslateResult},
+        FrameAllocator, Mapper, Page, PageSize, PageTable, PageTableFlags, PhysFrame, Size2MiB,
+        Size4KiB, Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+/// Updates the mappings of all page table frames of the kernel address space in the physical
+/// memory mapping at the given offset.
+///
+/// If `read_only` is set, the page table frames are mapped read-only. The `cache_flags` (i.e.
+/// `WRITE_THROUGH` and `NO_CACHE`) are set both on these mappings and on the entries that
+/// point to the page tables, so that the page walks of the CPU use the same cache policy.
+///
+/// Huge pages that contain page tables are split into 4KiB pages. The kernel page tables must
+/// not be modified after calling this function.
+pub fn update_kernel_page_table_mappings(
+    page_tables: &mut PageTables,
+    physical_memory_offset: VirtAddr,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    read_only: bool,
+    cache_flags: PageTableFlags,
+) {
+    let remove = if read_only {
+        PageTableFlags::WRITABLE
+    } else {
+        PageTableFlags::empty()
+    };
+    let mut protector = Protector {
+        page_tables,
+        physical_memory_offset,
+        frame_allocator,
+        insert: cache_flags,
+        remove,
+    };
+    // splitting a huge page creates a new level 1 table, which needs to be protected too,
+    // so repeat until no page table was changed
+    loop {
+        let level_4_frame = protector.page_tables.kernel_level_4_frame;
+        if !protector.protect_table(level_4_frame, 4) {
+            break;
+        }
+    }
+}
+
+struct Protector<'a, A> {
+    page_tables: &'a mut PageTables,
+    physical_memory_offset: VirtAddr,
+    frame_allocator: &'a mut A,
+    /// The flags to set on the mappings of the page table frames.
+    insert: PageTableFlags,
+    /// The flags to clear on the mappings of the page table frames.
+    remove: PageTableFlags,
+}
+
+impl<A: FrameAllocator<Size4KiB>> Protector<'_, A> {
+    /// Protects the given page table and its child tables.
+    ///
+    /// Returns whether any mapping was changed.
+    fn protect_table(&mut self, frame: PhysFrame, level: u8) -> bool {
+        let mut changed = self.protect_frame(frame);
+        if level == 1 {
+            return changed;
+        }
+        for index in 0..512 {
+            let (flags, child) = self.entry(frame, index);
+            if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE)
+            {
+                continue;
+            }
+            let child = PhysFrame::containing_address(child);
+            if child == self.page_tables.kernel_level_4_frame {
+                // recursive entry
+                continue;
+            }
+            let cache_flags =
+                self.insert & (PageTableFlags::WRITE_THROUGH | PageTableFlags::NO_CACHE);
+            if !flags.contains(cache_flags) {
+                self.set_entry_flags(frame, index, flags | cache_flags);
+            }
+            changed |= self.protect_table(child, level - 1);
+        }
+        changed
+    }
+
+    /// Returns the flags and the address of the given page table entry.
+    fn entry(&self, frame: PhysFrame, index: usize) -> (PageTableFlags, PhysAddr) {
+        let addr = self.page_tables.kernel.phys_offset() + frame.start_address().as_u64();
+        let table: &PageTable = unsafe { &*addr.as_ptr() };
+        let entry = &table[index];
+        (entry.flags(), entry.addr())
+    }
+
+    /// Sets the flags of the given page table entry.
+    fn set_entry_flags(&mut self, frame: PhysFrame, index: usize, flags: PageTableFlags) {
+        let addr = self.page_tables.kernel.phys_offset() + frame.start_address().as_u64();
+        let table: &mut PageTable = unsafe { &mut *addr.as_mut_ptr() };
+        table[index].set_flags(flags);
+    }
+
+    /// Updates the flags of the mapping of the given frame in the physical memory mapping.
+    ///
+    /// Returns whether the mapping was changed.
+    fn protect_frame(&mut self, frame: PhysFrame) -> bool {
+        let addr = self.physical_memory_offset + frame.start_address().as_u64();
+        let kernel = &mut self.page_tables.kernel;
+        match kernel.translate(addr) {
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(_),
+                flags,
+                ..
+            } => {
+                let new_flags = (flags | self.insert) - self.remove;
+                if new_flags == flags {
+                    return false;
+                }
+                let page = Page::<Size4KiB>::containing_address(addr);
+                match unsafe { kernel.update_flags(page, new_flags) } {
+                    Ok(tlb) => tlb.ignore(),
+                    Err(err) => panic!("failed to update flags of page {:?}: {:?}", page, err),
+                }
+                true
+            }
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size2MiB(huge_frame),
+                flags,
+                ..
+            } => {
+                self.split_huge_page(Page::containing_address(addr), huge_frame, flags);
+                true
+            }
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size1GiB(_),
+                ..
+            } => panic!("page table frame {:?} is mapped by a 1GiB page", frame),
+            TranslateResult::NotMapped => false,
+            TranslateResult::InvalidFrameAddress(addr) => {
+                panic!("invalid frame address {:?} in kernel page table", addr)
+            }
+        }
+    }
+
+    /// Replaces the given 2MiB page by 4KiB pages with the same flags.
+    fn split_huge_page(
+        &mut self,
+        page: Page<Size2MiB>,
+        frame: PhysFrame<Size2MiB>,
+        flags: PageTableFlags,
+    ) {
+        let kernel = &mut self.page_tables.kernel;
+        match kernel.unmap(page) {
+            Ok((_, tlb)) => tlb.ignore(),
+            Err(err) => panic!("failed to unmap page {:?}: {:?}", page, err),
+        }
+        let flags = flags - PageTableFlags::HUGE_PAGE;
+        for offset in (0..Size2MiB::SIZE).step_by(Size4KiB::SIZE as usize) {
+            let page = Page::<Size4KiB>::containing_address(page.start_address() + offset);
+            let frame = PhysFrame::<Size4KiB>::containing_address(frame.start_address() + offset);
+            match unsafe { kernel.map_to(page, frame, flags, self.frame_allocator) } {
+                Ok(tlb) => tlb.ignore(),
+                Err(err) => panic!(
+                    "failed to map page {:?} to frame {:?}: {:?}",
+                    page, frame, err
+                ),
+            }
+        }
+    }
+}
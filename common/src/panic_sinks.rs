@@ -0,0 +1,73 @@
+use core::{
+    panic::PanicInfo,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+/// A function that the panic handler calls to signal a panic on an additional channel, e.g. a
+/// debug LED or a memory-mapped debug port.
+pub type PanicSink = fn(&PanicInfo);
+
+/// The maximum number of panic sinks that can be registered.
+pub const MAX_PANIC_SINKS: usize = 4;
+
+/// The registered sinks, stored as raw pointers because function pointers can't be atomics.
+static PANIC_SINKS: [AtomicPtr<()>; MAX_PANIC_SINKS] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+    [EMPTY; MAX_PANIC_SINKS]
+};
+/// Set while the sinks are running, so that a panic in a sink doesn't recurse.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Registers a function that is called by the panic handler.
+///
+/// Should be called during early initialization. The sinks are called in registration order
+/// after the panic message was logged. Returns an error if [`MAX_PANIC_SINKS`] sinks are
+/// already registered.
+pub fn register(sink: PanicSink) -> Result<(), &'static str> {
+    let sink = sink as *mut ();
+    for slot in &PANIC_SINKS {
+        if slot
+            .compare_exchange(ptr::null_mut(), sink, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            return Ok(());
+        }
+    }
+    Err("too many panic sinks registered")
+}
+
+/// Calls all registered panic sinks with the given panic info.
+///
+/// Should be called by the panic handler. If a sink panics itself, the sinks are not called
+/// again for the nested panic.
+pub fn run(info: &PanicInfo) {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    for slot in &PANIC_SINKS {
+        let sink = slot.load(Ordering::SeqCst);
+        if sink.is_null() {
+            break;
+        }
+        // only valid `PanicSink` pointers are stored in the slots
+        let sink = unsafe { core::mem::transmute::<*mut (), PanicSink>(sink) };
+        sink(info);
+    }
+    RUNNING.store(false, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panic_sink_limit() {
+        fn sink(_: &PanicInfo) {}
+        for _ in 0..MAX_PANIC_SINKS {
+            register(sink).unwrap();
+        }
+        assert_eq!(register(sink), Err("too many panic sinks registered"));
+    }
+}
@@ -0,0 +1,47 @@
+use crate::PageTables;
+use core::ptr;
+use x86_64::{
+    structures::paging::{mapper::TranslateResult, PageTableFlags, Translate},
+    VirtAddr,
+};
+use xmas_elf::{program::Type, ElfFile};
+
+/// Touches the first byte of every page of the loaded kernel segments.
+///
+/// The pages are looked up in the kernel page table and accessed through the physical memory
+/// mapping of the bootloader. Writable pages are written back with their current content, so
+/// the kernel image is not modified. Panics with the virtual address of the first page that is
+/// not mapped.
+pub fn touch_kernel_pages(kernel_slice: &[u8], image_offset: VirtAddr, page_tables: &PageTables) {
+    let elf = ElfFile::new(kernel_slice).unwrap();
+    let phys_offset = page_tables.kernel.phys_offset();
+    let mut touched = 0u64;
+    for header in elf.program_iter() {
+        if header.get_type() != Ok(Type::Load) || header.mem_size() == 0 {
+            continue;
+        }
+        let start = (image_offset + header.virtual_addr()).align_down(0x1000u64);
+        let end = image_offset + header.virtual_addr() + header.mem_size();
+        let mut page = start;
+        while page < end {
+            let (frame, offset, flags) = match page_tables.kernel.translate(page) {
+                TranslateResult::Mapped {
+                    frame,
+                    offset,
+                    flags,
+                } => (frame, offset, flags),
+                TranslateResult::NotMapped | TranslateResult::InvalidFrameAddress(_) => {
+                    panic!("kernel page {page:?} is not mapped")
+                }
+            };
+            let ptr: *mut u8 = (phys_offset + frame.start_address().as_u64() + offset).as_mut_ptr();
+            let value = unsafe { ptr::read_volatile(ptr) };
+            if flags.contains(PageTableFlags::WRITABLE) {
+                unsafe { ptr::write_volatile(ptr, value) };
+            }
+            touched += 1;
+            page += 0x1000u64;
+        }
+    }
+    log::info!("Touched {touched} kernel pages");
+}
@@ -0,0 +1,104 @@
+use crate::acpi::{self, ResetRegister};
+use bootloader_boot_config::PanicBehavior;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use x86_64::{
+    instructions::{port::Port, tables::lidt},
+    structures::DescriptorTablePointer,
+    PhysAddr, VirtAddr,
+};
+
+/// The configured [`PanicBehavior`], see the `PANIC_*` constants.
+static PANIC_BEHAVIOR: AtomicU8 = AtomicU8::new(PANIC_HALT);
+/// The address of the RSDP, or zero if it is not known (yet).
+static RSDP_ADDR: AtomicU64 = AtomicU64::new(0);
+
+const PANIC_HALT: u8 = 0;
+const PANIC_REBOOT: u8 = 1;
+const PANIC_TRIPLE_FAULT: u8 = 2;
+
+/// The command and status port of the 8042 keyboard controller.
+const KEYBOARD_CONTROLLER_PORT: u16 = 0x64;
+/// The keyboard controller command that pulses the CPU reset line.
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xfe;
+/// The status bit that is set while the input buffer of the keyboard controller is full.
+const KEYBOARD_CONTROLLER_INPUT_FULL: u8 = 1 << 1;
+
+/// Sets the action that [`handle_panic`] takes.
+///
+/// Should be called as soon as the boot config is loaded. Until then, panics halt the CPU.
+pub fn set_panic_behavior(behavior: PanicBehavior) {
+    let value = match behavior {
+        PanicBehavior::Reboot => PANIC_REBOOT,
+        PanicBehavior::TripleFault => PANIC_TRIPLE_FAULT,
+        _ => PANIC_HALT,
+    };
+    PANIC_BEHAVIOR.store(value, Ordering::SeqCst);
+}
+
+/// Remembers the RSDP address, so that a reboot can use the ACPI reset register.
+pub(crate) fn set_rsdp_addr(rsdp_addr: Option<PhysAddr>) {
+    RSDP_ADDR.store(rsdp_addr.map_or(0, PhysAddr::as_u64), Ordering::SeqCst);
+}
+
+/// Halts, reboots, or triple-faults, depending on the configured [`PanicBehavior`].
+///
+/// Should be called at the end of the panic handler, after the panic message was logged.
+pub fn handle_panic() -> ! {
+    match PANIC_BEHAVIOR.load(Ordering::SeqCst) {
+        PANIC_REBOOT => reboot(),
+        PANIC_TRIPLE_FAULT => triple_fault(),
+        _ => halt(),
+    }
+}
+
+/// Resets the machine.
+///
+/// Tries the ACPI reset register first (if the RSDP is known), then the 8042 keyboard
+/// controller. If the machine is still running after that, a triple fault is caused.
+pub fn reboot() -> ! {
+    x86_64::instructions::interrupts::disable();
+    let rsdp_addr = RSDP_ADDR.load(Ordering::SeqCst);
+    // the ACPI tables are identity-mapped in the bootloader address space
+    let reset_register = (rsdp_addr != 0)
+        .then(|| unsafe { acpi::find_reset_register(PhysAddr::new(rsdp_addr)) })
+        .flatten();
+    match reset_register {
+        Some(ResetRegister::Io { port, value }) => unsafe { Port::new(port).write(value) },
+        Some(ResetRegister::Memory { addr, value }) => unsafe {
+            (addr as *mut u8).write_volatile(value)
+        },
+        None => {}
+    }
+
+    let mut port = Port::<u8>::new(KEYBOARD_CONTROLLER_PORT);
+    // wait until the controller accepts commands, but don't wait forever
+    for _ in 0..0x10000 {
+        if unsafe { port.read() } & KEYBOARD_CONTROLLER_INPUT_FULL == 0 {
+            break;
+        }
+    }
+    unsafe { port.write(KEYBOARD_CONTROLLER_RESET) };
+
+    triple_fault()
+}
+
+/// Causes a triple fault by loading an empty IDT and raising an exception.
+pub fn triple_fault() -> ! {
+    x86_64::instructions::interrupts::disable();
+    let empty = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+    unsafe {
+        lidt(&empty);
+        core::arch::asm!("int3", options(nomem, nostack));
+    }
+    halt()
+}
+
+fn halt() -> ! {
+    loop {
+        x86_64::instructions::interrupts::disable();
+        x86_64::instructions::hlt();
+    }
+}
@@ -1,4 +1,12 @@
 use core::fmt;
+use x86_64::instructions::port::Port;
+
+/// The I/O port base address of the first serial port (COM1).
+const COM1: u16 = 0x3F8;
+/// The offset of the line status register.
+const LINE_STATUS: u16 = 5;
+/// Set in the line status register if a received byte is available.
+const LINE_STATUS_DATA_READY: u8 = 1;
 
 pub struct SerialPort {
     port: uart_16550::SerialPort,
@@ -9,10 +17,22 @@ impl SerialPort {
     ///
     /// unsafe because this function must only be called once
     pub unsafe fn init() -> Self {
-        let mut port = unsafe { uart_16550::SerialPort::new(0x3F8) };
+        let mut port = unsafe { uart_16550::SerialPort::new(COM1) };
         port.init();
         Self { port }
     }
+
+    /// Returns the next received byte, or `None` if no byte is available.
+    pub fn try_receive(&mut self) -> Option<u8> {
+        let mut line_status = Port::<u8>::new(COM1 + LINE_STATUS);
+        let data_ready = unsafe { line_status.read() } & LINE_STATUS_DATA_READY != 0;
+        data_ready.then(|| self.port.receive())
+    }
+
+    /// Waits for the next received byte.
+    pub fn receive(&mut self) -> u8 {
+        self.port.receive()
+    }
 }
 
 impl fmt::Write for SerialPort {
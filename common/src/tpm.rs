@@ -0,0 +1,311 @@
+use bootloader_boot_config::MeasuredBoot;
+use core::ptr;
+use sha2::{Digest, Sha256};
+
+/// Physical address of the locality 0 registers of a TPM 2.0.
+///
+/// Both the FIFO (TIS) and the CRB interface use this address. It is identity-mapped by both
+/// the BIOS and the UEFI implementations.
+const LOCALITY_0_BASE: u64 = 0xfed4_0000;
+
+/// Number of register polls before a TPM operation is considered as timed out.
+const POLL_LIMIT: usize = 10_000_000;
+
+const TPM_ST_SESSIONS: u16 = 0x8002;
+const TPM_CC_PCR_EXTEND: u32 = 0x0000_0182;
+const TPM_RS_PW: u32 = 0x4000_0009;
+const TPM_ALG_SHA256: u16 = 0x000b;
+
+/// Size of the `TPM2_PCR_Extend` command with a single SHA-256 digest.
+const PCR_EXTEND_COMMAND_LEN: usize = 65;
+/// Size of a response header (tag, size, and response code).
+const RESPONSE_HEADER_LEN: usize = 10;
+
+mod fifo {
+    pub const ACCESS: u64 = 0x00;
+    pub const STS: u64 = 0x18;
+    pub const DATA_FIFO: u64 = 0x24;
+
+    pub const ACCESS_VALID: u8 = 1 << 7;
+    pub const ACCESS_ACTIVE_LOCALITY: u8 = 1 << 5;
+    pub const ACCESS_REQUEST_USE: u8 = 1 << 1;
+
+    pub const STS_VALID: u32 = 1 << 7;
+    pub const STS_COMMAND_READY: u32 = 1 << 6;
+    pub const STS_GO: u32 = 1 << 5;
+    pub const STS_DATA_AVAIL: u32 = 1 << 4;
+    pub const STS_EXPECT: u32 = 1 << 3;
+}
+
+mod crb {
+    pub const LOC_CTRL: u64 = 0x08;
+    pub const LOC_STS: u64 = 0x0c;
+    pub const CTRL_REQ: u64 = 0x40;
+    pub const CTRL_STS: u64 = 0x44;
+    pub const CTRL_START: u64 = 0x4c;
+    pub const CTRL_CMD_SIZE: u64 = 0x58;
+    pub const CTRL_CMD_LADDR: u64 = 0x5c;
+    pub const CTRL_CMD_HADDR: u64 = 0x60;
+    pub const CTRL_RSP_SIZE: u64 = 0x64;
+    pub const CTRL_RSP_ADDR: u64 = 0x68;
+
+    pub const LOC_CTRL_REQUEST_ACCESS: u32 = 1 << 0;
+    pub const LOC_STS_GRANTED: u32 = 1 << 0;
+    pub const CTRL_REQ_CMD_READY: u32 = 1 << 0;
+    pub const CTRL_REQ_GO_IDLE: u32 = 1 << 1;
+    pub const CTRL_STS_ERROR: u32 = 1 << 0;
+    pub const CTRL_STS_IDLE: u32 = 1 << 1;
+}
+
+const INTERFACE_ID: u64 = 0x30;
+/// Only exists on the FIFO interface, on the CRB interface this offset is part of the data buffer.
+const DID_VID: u64 = 0xf00;
+
+/// Extends the configured PCRs with the SHA-256 digests of the kernel and the ramdisk.
+///
+/// Does nothing if measured boot is disabled or if no TPM 2.0 is present. Failures to
+/// communicate with the TPM are logged, but don't abort the boot.
+pub fn measure_boot_components(config: &MeasuredBoot, kernel: &[u8], ramdisk: Option<&[u8]>) {
+    if !config.enabled {
+        return;
+    }
+    let Some(mut tpm) = Tpm::detect() else {
+        log::debug!("No TPM 2.0 found, skipping measured boot");
+        return;
+    };
+
+    let mut measure = |pcr: u32, name: &str, data: &[u8]| {
+        let digest: [u8; 32] = Sha256::digest(data).into();
+        log::info!("Extending PCR {pcr} with SHA-256 digest of {name}: {digest:02x?}");
+        if let Err(err) = tpm.pcr_extend(pcr, &digest) {
+            log::warn!("Failed to extend PCR {pcr} with digest of {name}: {err}");
+        }
+    };
+    measure(config.kernel_pcr, "kernel", kernel);
+    if let Some(ramdisk) = ramdisk {
+        measure(config.ramdisk_pcr, "ramdisk", ramdisk);
+    }
+}
+
+/// The register interface used to talk to the TPM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interface {
+    /// The FIFO interface, as specified by the TIS and PTP specifications.
+    Fifo,
+    /// The command response buffer interface.
+    Crb,
+}
+
+/// A TPM 2.0 at locality 0.
+struct Tpm {
+    interface: Interface,
+}
+
+impl Tpm {
+    /// Checks whether a TPM is present and determines its register interface.
+    fn detect() -> Option<Self> {
+        let interface_id = read_u64(INTERFACE_ID);
+        let (interface, did_vid) = match interface_id & 0xf {
+            // TIS 1.3 devices report `0xf`, PTP FIFO devices report `0`
+            0x0 | 0xf => (Interface::Fifo, read_u32(DID_VID)),
+            // the CRB interface reports the vendor and device id in the upper half
+            0x1 => (Interface::Crb, (interface_id >> 32) as u32),
+            _ => return None,
+        };
+        if did_vid == 0 || did_vid == u32::MAX {
+            return None;
+        }
+        log::info!("Found TPM (vendor/device id {did_vid:#x}) with {interface:?} interface");
+        Some(Self { interface })
+    }
+
+    /// Extends the given PCR with the given SHA-256 digest using `TPM2_PCR_Extend`.
+    fn pcr_extend(&mut self, pcr: u32, digest: &[u8; 32]) -> Result<(), &'static str> {
+        let mut command = [0u8; PCR_EXTEND_COMMAND_LEN];
+        {
+            let mut writer = CommandWriter::new(&mut command);
+            writer.write(&TPM_ST_SESSIONS.to_be_bytes());
+            writer.write(&(PCR_EXTEND_COMMAND_LEN as u32).to_be_bytes());
+            writer.write(&TPM_CC_PCR_EXTEND.to_be_bytes());
+            writer.write(&pcr.to_be_bytes());
+            // authorization area: a single empty password session
+            writer.write(&9u32.to_be_bytes());
+            writer.write(&TPM_RS_PW.to_be_bytes());
+            writer.write(&0u16.to_be_bytes()); // nonce size
+            writer.write(&[0]); // session attributes
+            writer.write(&0u16.to_be_bytes()); // hmac size
+
+            // digest list with a single SHA-256 digest
+            writer.write(&1u32.to_be_bytes());
+            writer.write(&TPM_ALG_SHA256.to_be_bytes());
+            writer.write(digest);
+            assert!(writer.is_full());
+        }
+
+        let mut response = [0u8; RESPONSE_HEADER_LEN];
+        self.request_locality()?;
+        let result = match self.interface {
+            Interface::Fifo => self.execute_fifo(&command, &mut response),
+            Interface::Crb => self.execute_crb(&command, &mut response),
+        };
+        self.release_locality();
+        result?;
+
+        let response_code = u32::from_be_bytes(response[6..10].try_into().unwrap());
+        if response_code != 0 {
+            log::warn!("TPM2_PCR_Extend failed with response code {response_code:#x}");
+            return Err("TPM returned an error response code");
+        }
+        Ok(())
+    }
+
+    fn request_locality(&mut self) -> Result<(), &'static str> {
+        match self.interface {
+            Interface::Fifo => {
+                write_u8(fifo::ACCESS, fifo::ACCESS_REQUEST_USE);
+                let granted = fifo::ACCESS_VALID | fifo::ACCESS_ACTIVE_LOCALITY;
+                poll(|| read_u8(fifo::ACCESS) & granted == granted)
+            }
+            Interface::Crb => {
+                write_u32(crb::LOC_CTRL, crb::LOC_CTRL_REQUEST_ACCESS);
+                poll(|| read_u32(crb::LOC_STS) & crb::LOC_STS_GRANTED != 0)
+            }
+        }
+        .map_err(|()| "timeout while requesting TPM locality 0")
+    }
+
+    fn release_locality(&mut self) {
+        match self.interface {
+            // writing `activeLocality` relinquishes the locality
+            Interface::Fifo => write_u8(fifo::ACCESS, fifo::ACCESS_ACTIVE_LOCALITY),
+            // bit 1 of the locality control register relinquishes the locality
+            Interface::Crb => write_u32(crb::LOC_CTRL, 1 << 1),
+        }
+    }
+
+    fn execute_fifo(&mut self, command: &[u8], response: &mut [u8]) -> Result<(), &'static str> {
+        write_u32(fifo::STS, fifo::STS_COMMAND_READY);
+        poll(|| read_u32(fifo::STS) & fifo::STS_COMMAND_READY != 0)
+            .map_err(|()| "timeout while waiting for TPM to become ready")?;
+
+        for &byte in command {
+            poll(|| burst_count() > 0).map_err(|()| "timeout while sending TPM command")?;
+            write_u8(fifo::DATA_FIFO, byte);
+        }
+        poll(|| read_u32(fifo::STS) & fifo::STS_VALID != 0)
+            .map_err(|()| "timeout while waiting for TPM status")?;
+        if read_u32(fifo::STS) & fifo::STS_EXPECT != 0 {
+            return Err("TPM expects more command bytes than sent");
+        }
+
+        write_u32(fifo::STS, fifo::STS_GO);
+        let available = fifo::STS_VALID | fifo::STS_DATA_AVAIL;
+        poll(|| read_u32(fifo::STS) & available == available)
+            .map_err(|()| "timeout while waiting for TPM response")?;
+
+        for byte in response.iter_mut() {
+            poll(|| burst_count() > 0).map_err(|()| "timeout while reading TPM response")?;
+            *byte = read_u8(fifo::DATA_FIFO);
+        }
+
+        // drain the remaining response bytes (if any) and return to the idle state
+        while read_u32(fifo::STS) & available == available {
+            read_u8(fifo::DATA_FIFO);
+        }
+        write_u32(fifo::STS, fifo::STS_COMMAND_READY);
+        Ok(())
+    }
+
+    fn execute_crb(&mut self, command: &[u8], response: &mut [u8]) -> Result<(), &'static str> {
+        write_u32(crb::CTRL_REQ, crb::CTRL_REQ_CMD_READY);
+        poll(|| {
+            read_u32(crb::CTRL_REQ) & crb::CTRL_REQ_CMD_READY == 0
+                && read_u32(crb::CTRL_STS) & crb::CTRL_STS_IDLE == 0
+        })
+        .map_err(|()| "timeout while waiting for TPM to become ready")?;
+
+        let command_size = read_u32(crb::CTRL_CMD_SIZE) as usize;
+        let response_size = read_u32(crb::CTRL_RSP_SIZE) as usize;
+        if command_size < command.len() || response_size < response.len() {
+            return Err("TPM command/response buffer too small");
+        }
+        let command_addr = u64::from(read_u32(crb::CTRL_CMD_LADDR))
+            | u64::from(read_u32(crb::CTRL_CMD_HADDR)) << 32;
+        let response_addr = read_u64(crb::CTRL_RSP_ADDR);
+
+        for (i, &byte) in command.iter().enumerate() {
+            unsafe { ptr::write_volatile((command_addr as *mut u8).add(i), byte) };
+        }
+
+        write_u32(crb::CTRL_START, 1);
+        poll(|| read_u32(crb::CTRL_START) & 1 == 0)
+            .map_err(|()| "timeout while waiting for TPM response")?;
+        if read_u32(crb::CTRL_STS) & crb::CTRL_STS_ERROR != 0 {
+            return Err("TPM reported a fatal error");
+        }
+
+        for (i, byte) in response.iter_mut().enumerate() {
+            *byte = unsafe { ptr::read_volatile((response_addr as *const u8).add(i)) };
+        }
+
+        write_u32(crb::CTRL_REQ, crb::CTRL_REQ_GO_IDLE);
+        Ok(())
+    }
+}
+
+/// Helper for writing a TPM command into a fixed-size buffer.
+struct CommandWriter<'a> {
+    buf: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a> CommandWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, offset: 0 }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.buf[self.offset..][..bytes.len()].copy_from_slice(bytes);
+        self.offset += bytes.len();
+    }
+
+    fn is_full(&self) -> bool {
+        self.offset == self.buf.len()
+    }
+}
+
+fn burst_count() -> u32 {
+    (read_u32(fifo::STS) >> 8) & 0xffff
+}
+
+fn poll(mut condition: impl FnMut() -> bool) -> Result<(), ()> {
+    for _ in 0..POLL_LIMIT {
+        if condition() {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(())
+}
+
+fn read_u8(offset: u64) -> u8 {
+    unsafe { ptr::read_volatile((LOCALITY_0_BASE + offset) as *const u8) }
+}
+
+fn read_u32(offset: u64) -> u32 {
+    unsafe { ptr::read_volatile((LOCALITY_0_BASE + offset) as *const u32) }
+}
+
+fn read_u64(offset: u64) -> u64 {
+    let low = read_u32(offset);
+    let high = read_u32(offset + 4);
+    u64::from(low) | u64::from(high) << 32
+}
+
+fn write_u8(offset: u64, value: u8) {
+    unsafe { ptr::write_volatile((LOCALITY_0_BASE + offset) as *mut u8, value) }
+}
+
+fn write_u32(offset: u64, value: u32) {
+    unsafe { ptr::write_volatile((LOCALITY_0_BASE + offset) as *mut u32, value) }
+}
@@ -23,6 +23,19 @@ impl BiosBoot {
         self
     }
 
+    /// Add a flattened device tree blob to the image.
+    pub fn set_device_tree(&mut self, device_tree_path: &Path) -> &mut Self {
+        self.image_builder
+            .set_device_tree(device_tree_path.to_owned());
+        self
+    }
+
+    /// Add a key-value config blob for the kernel to the image.
+    pub fn set_boot_config_blob(&mut self, entries: &[(&str, &[u8])]) -> &mut Self {
+        self.image_builder.set_boot_config_blob(entries);
+        self
+    }
+
     /// Creates a configuration file (boot.json) that configures the runtime behavior of the bootloader.
     pub fn set_boot_config(&mut self, config: &BootConfig) -> &mut Self {
         self.image_builder.set_boot_config(config);
@@ -33,4 +46,20 @@ impl BiosBoot {
     pub fn create_disk_image(&self, out_path: &Path) -> anyhow::Result<()> {
         self.image_builder.create_bios_image(out_path)
     }
+
+    /// Prepare a folder for use with booting over BIOS PXE.
+    ///
+    /// This places the network bootstrap program under the path "bootloader.0". The
+    /// DHCP server should set the filename option to that path, otherwise the
+    /// bootloader won't be found. The kernel and the other files are loaded from the
+    /// same directory.
+    ///
+    /// The file names are fixed: the kernel is always loaded as `kernel-x86_64` from the
+    /// directory of the boot file (DHCP option 67), and there is no option to configure
+    /// another name. The network bootstrap program consists of the boot sector and the
+    /// second stage without its padding, which is below 64KiB. PXE ROMs that limit network
+    /// bootstrap programs to 32KiB are not supported.
+    pub fn create_pxe_tftp_folder(&self, out_path: &Path) -> anyhow::Result<()> {
+        self.image_builder.create_bios_tftp_folder(out_path)
+    }
 }
@@ -40,6 +40,12 @@ pub use bootloader_boot_config::BootConfig;
 const KERNEL_FILE_NAME: &str = "kernel-x86_64";
 const RAMDISK_FILE_NAME: &str = "ramdisk";
 const CONFIG_FILE_NAME: &str = "boot.json";
+const DEVICE_TREE_FILE_NAME: &str = "device-tree.dtb";
+const BOOT_CONFIG_BLOB_FILE_NAME: &str = "boot-config.bin";
+#[cfg(feature = "bios")]
+const BIOS_STAGE_3_NAME: &str = "boot-stage-3";
+#[cfg(feature = "bios")]
+const BIOS_STAGE_4_NAME: &str = "boot-stage-4";
 
 #[cfg(feature = "uefi")]
 const UEFI_BOOTLOADER: &[u8] = include_bytes!(env!("UEFI_BOOTLOADER_PATH"));
@@ -54,7 +60,7 @@ const BIOS_STAGE_4: &[u8] = include_bytes!(env!("BIOS_STAGE_4_PATH"));
 
 /// Allows creating disk images for a specified set of files.
 ///
-/// It can currently create `MBR` (BIOS), `GPT` (UEFI), and `TFTP` (UEFI) images.
+/// It can currently create `MBR` (BIOS), `GPT` (UEFI), and `TFTP` (BIOS and UEFI) images.
 pub struct DiskImageBuilder {
     files: BTreeMap<Cow<'static, str>, FileDataSource>,
 }
@@ -84,6 +90,31 @@ impl DiskImageBuilder {
         self.set_file_source(RAMDISK_FILE_NAME.into(), FileDataSource::File(path))
     }
 
+    /// Add or replace a flattened device tree blob to be included in the final image.
+    ///
+    /// The bootloader loads the blob and reports it in the `device_tree` field of the boot info.
+    pub fn set_device_tree(&mut self, path: PathBuf) -> &mut Self {
+        self.set_file_source(DEVICE_TREE_FILE_NAME.into(), FileDataSource::File(path))
+    }
+
+    /// Add or replace the key-value config blob that is passed to the kernel.
+    ///
+    /// The entries are encoded as described in [`bootloader_api::config_blob`]. The bootloader
+    /// loads the blob and reports it in the `boot_config_blob` field of the boot info.
+    pub fn set_boot_config_blob(&mut self, entries: &[(&str, &[u8])]) -> &mut Self {
+        let entries: Vec<(&[u8], &[u8])> = entries
+            .iter()
+            .map(|&(key, value)| (key.as_bytes(), value))
+            .collect();
+        let mut blob = vec![0; bootloader_api::config_blob::encoded_len(&entries)];
+        bootloader_api::config_blob::encode(&entries, &mut blob)
+            .expect("failed to encode the boot config blob");
+        self.set_file_source(
+            BOOT_CONFIG_BLOB_FILE_NAME.into(),
+            FileDataSource::Data(blob),
+        )
+    }
+
     /// Configures the runtime behavior of the bootloader.
     pub fn set_boot_config(&mut self, boot_config: &BootConfig) -> &mut Self {
         let json = serde_json::to_vec_pretty(boot_config).expect("failed to serialize BootConfig");
@@ -92,16 +123,16 @@ impl DiskImageBuilder {
 
     /// Add a file with the specified bytes to the disk image
     ///
-    /// Note that the bootloader only loads the kernel and ramdisk files into memory on boot.
-    /// Other files need to be loaded manually by the kernel.
+    /// Note that the bootloader only loads the kernel, ramdisk, device tree, and boot config
+    /// blob files into memory on boot. Other files need to be loaded manually by the kernel.
     pub fn set_file_contents(&mut self, destination: String, data: Vec<u8>) -> &mut Self {
         self.set_file_source(destination.into(), FileDataSource::Data(data))
     }
 
     /// Add a file with the specified source file to the disk image
     ///
-    /// Note that the bootloader only loads the kernel and ramdisk files into memory on boot.
-    /// Other files need to be loaded manually by the kernel.
+    /// Note that the bootloader only loads the kernel, ramdisk, device tree, and boot config
+    /// blob files into memory on boot. Other files need to be loaded manually by the kernel.
     pub fn set_file(&mut self, destination: String, file_path: PathBuf) -> &mut Self {
         self.set_file_source(destination.into(), FileDataSource::File(file_path))
     }
@@ -109,8 +140,6 @@ impl DiskImageBuilder {
     #[cfg(feature = "bios")]
     /// Create an MBR disk image for booting on BIOS systems.
     pub fn create_bios_image(&self, image_path: &Path) -> anyhow::Result<()> {
-        const BIOS_STAGE_3_NAME: &str = "boot-stage-3";
-        const BIOS_STAGE_4_NAME: &str = "boot-stage-4";
         let stage_3 = FileDataSource::Bytes(BIOS_STAGE_3);
         let stage_4 = FileDataSource::Bytes(BIOS_STAGE_4);
         let mut internal_files = BTreeMap::new();
@@ -133,6 +162,50 @@ impl DiskImageBuilder {
         Ok(())
     }
 
+    #[cfg(feature = "bios")]
+    /// Create a folder containing the needed files for BIOS TFTP/PXE booting.
+    ///
+    /// The network bootstrap program is placed at `bootloader.0`. All other files are loaded
+    /// from the directory of the network bootstrap program.
+    pub fn create_bios_tftp_folder(&self, tftp_path: &Path) -> anyhow::Result<()> {
+        use std::{fs, ops::Deref};
+
+        const BIOS_TFTP_BOOT_FILENAME: &str = "bootloader.0";
+        fs::create_dir_all(tftp_path)
+            .with_context(|| format!("failed to create out dir at {}", tftp_path.display()))?;
+
+        // the PXE ROM loads the network bootstrap program to 0x7c00, so the second stage
+        // directly follows the boot sector, like on disk
+        let to = tftp_path.join(BIOS_TFTP_BOOT_FILENAME);
+        let bootstrap_program = [BIOS_BOOT_SECTOR, bios_stage_2_without_padding()].concat();
+        fs::write(&to, bootstrap_program)
+            .with_context(|| format!("failed to write bootloader to {}", to.display()))?;
+
+        for (name, data) in [
+            (BIOS_STAGE_3_NAME, BIOS_STAGE_3),
+            (BIOS_STAGE_4_NAME, BIOS_STAGE_4),
+        ] {
+            let to = tftp_path.join(name);
+            fs::write(&to, data)
+                .with_context(|| format!("failed to write {name} to {}", to.display()))?;
+        }
+
+        for f in &self.files {
+            let to = tftp_path.join(f.0.deref());
+
+            let mut new_file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(to)?;
+
+            f.1.copy_to(&mut new_file)?;
+        }
+
+        Ok(())
+    }
+
     #[cfg(feature = "uefi")]
     /// Create a GPT disk image for booting on UEFI systems.
     pub fn create_uefi_image(&self, image_path: &Path) -> anyhow::Result<()> {
@@ -221,3 +294,18 @@ impl DiskImageBuilder {
         Ok(out_file)
     }
 }
+
+/// Strips the zero padding up to the end marker from the second stage.
+///
+/// On disk, the padding reserves the sectors up to the end marker. The PXE ROM downloads the
+/// whole network bootstrap program and many ROMs limit its size, so only the sectors up to the
+/// end of the last section are kept.
+#[cfg(feature = "bios")]
+fn bios_stage_2_without_padding() -> &'static [u8] {
+    let end_marker_start = BIOS_STAGE_2.len().saturating_sub(2);
+    let len = BIOS_STAGE_2[..end_marker_start]
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |i| i + 1);
+    &BIOS_STAGE_2[..len.next_multiple_of(512).min(BIOS_STAGE_2.len())]
+}
@@ -23,6 +23,19 @@ impl UefiBoot {
         self
     }
 
+    /// Add a flattened device tree blob to the image.
+    pub fn set_device_tree(&mut self, device_tree_path: &Path) -> &mut Self {
+        self.image_builder
+            .set_device_tree(device_tree_path.to_owned());
+        self
+    }
+
+    /// Add a key-value config blob for the kernel to the image.
+    pub fn set_boot_config_blob(&mut self, entries: &[(&str, &[u8])]) -> &mut Self {
+        self.image_builder.set_boot_config_blob(entries);
+        self
+    }
+
     /// Creates a configuration file (boot.json) that configures the runtime behavior of the bootloader.
     pub fn set_boot_config(&mut self, config: &BootConfig) -> &mut Self {
         self.image_builder.set_boot_config(config);
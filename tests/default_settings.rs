@@ -20,3 +20,10 @@ fn check_boot_info() {
         "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_check_boot_info"
     ));
 }
+
+#[test]
+fn check_entry_state() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_check_entry_state"
+    ));
+}
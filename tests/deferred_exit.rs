@@ -0,0 +1,8 @@
+use bootloader_test_runner::run_uefi_test_kernel;
+
+#[test]
+fn exit_boot_services() {
+    run_uefi_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFERRED_EXIT_exit_boot_services"
+    ));
+}
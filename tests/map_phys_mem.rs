@@ -13,3 +13,17 @@ fn access_phys_mem() {
         "CARGO_BIN_FILE_TEST_KERNEL_MAP_PHYS_MEM_access_phys_mem"
     ));
 }
+
+#[test]
+fn check_segment_alignment() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_MAP_PHYS_MEM_check_segment_alignment"
+    ));
+}
+
+#[test]
+fn check_global_pages() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_MAP_PHYS_MEM_check_global_pages"
+    ));
+}
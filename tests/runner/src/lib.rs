@@ -43,12 +43,29 @@ pub fn run_test_kernel_internal(
     {
         // create an MBR disk image for legacy BIOS booting
         let mbr_path = kernel_path.with_extension("mbr");
+        let tftp_path = kernel_path.with_extension("bios-tftp");
         image_builder.create_bios_image(mbr_path.as_path()).unwrap();
+        image_builder.create_bios_tftp_folder(&tftp_path).unwrap();
 
         run_test_kernel_on_bios(&mbr_path);
+        run_test_kernel_on_bios_pxe(&tftp_path);
     }
 }
 
+/// Runs the kernel only on UEFI, for kernels that test UEFI-specific config options.
+#[cfg(feature = "uefi")]
+pub fn run_uefi_test_kernel(kernel_binary_path: &str) {
+    let kernel_path = Path::new(kernel_binary_path);
+    let image_builder = DiskImageBuilder::new(kernel_path.to_owned());
+
+    let gpt_path = kernel_path.with_extension("gpt");
+    let tftp_path = kernel_path.with_extension("tftp");
+    image_builder.create_uefi_image(&gpt_path).unwrap();
+    image_builder.create_uefi_tftp_folder(&tftp_path).unwrap();
+    run_test_kernel_on_uefi(&gpt_path);
+    run_test_kernel_on_uefi_pxe(&tftp_path);
+}
+
 #[cfg(feature = "uefi")]
 pub fn run_test_kernel_on_uefi(out_gpt_path: &Path) {
     let ovmf_pure_efi = ovmf_prebuilt::ovmf_pure_efi();
@@ -70,6 +87,22 @@ pub fn run_test_kernel_on_bios(out_mbr_path: &Path) {
     run_qemu(args);
 }
 
+#[cfg(feature = "bios")]
+pub fn run_test_kernel_on_bios_pxe(out_tftp_path: &Path) {
+    let args = [
+        "-netdev",
+        &format!(
+            "user,id=net0,net=192.168.17.0/24,tftp={},bootfile=bootloader.0",
+            out_tftp_path.display()
+        ),
+        "-device",
+        "e1000,netdev=net0",
+        "-boot",
+        "n",
+    ];
+    run_qemu(args);
+}
+
 #[cfg(feature = "uefi")]
 pub fn run_test_kernel_on_uefi_pxe(out_tftp_path: &Path) {
     let ovmf_pure_efi = ovmf_prebuilt::ovmf_pure_efi();
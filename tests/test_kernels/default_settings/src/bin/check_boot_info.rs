@@ -36,6 +36,9 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     // the test kernel has no TLS template
     assert_eq!(boot_info.tls_template.into_option(), None);
 
+    // no handoff magic is configured by default
+    assert_eq!(boot_info.handoff_magic.into_option(), None);
+
     exit_qemu(QemuExitCode::Success);
 }
 
@@ -0,0 +1,35 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo};
+use test_kernel_default_settings::{exit_qemu, QemuExitCode};
+use x86_64::instructions::interrupts;
+
+entry_point!(kernel_main);
+
+/// A stack value that requires the 16-byte alignment guaranteed by the System V ABI.
+///
+/// The compiler relies on the ABI stack alignment when placing it, so a misaligned stack at
+/// the kernel entry point results in a misaligned address.
+#[repr(align(16))]
+struct Aligned([u8; 16]);
+
+fn kernel_main(_boot_info: &'static mut BootInfo) -> ! {
+    assert!(!interrupts::are_enabled());
+
+    let value = Aligned([0; 16]);
+    let addr = core::hint::black_box(value.0.as_ptr()) as usize;
+    assert_eq!(addr % 16, 0, "stack is not 16-byte aligned");
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let _ = writeln!(test_kernel_default_settings::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
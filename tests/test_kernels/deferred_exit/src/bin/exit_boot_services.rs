@@ -0,0 +1,75 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo};
+use core::ptr::addr_of_mut;
+use test_kernel_deferred_exit::{exit_qemu, QemuExitCode, BOOTLOADER_CONFIG};
+
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+
+/// Offset of the `BootServices` pointer in the UEFI system table.
+const SYSTEM_TABLE_BOOT_SERVICES: usize = 96;
+/// Offset of the `GetMemoryMap` function pointer in the UEFI boot services table.
+const BOOT_SERVICES_GET_MEMORY_MAP: usize = 56;
+/// Offset of the `ExitBootServices` function pointer in the UEFI boot services table.
+const BOOT_SERVICES_EXIT_BOOT_SERVICES: usize = 232;
+
+type GetMemoryMap = extern "efiapi" fn(
+    map_size: *mut usize,
+    map: *mut u8,
+    map_key: *mut usize,
+    descriptor_size: *mut usize,
+    descriptor_version: *mut u32,
+) -> usize;
+type ExitBootServices = extern "efiapi" fn(image_handle: u64, map_key: usize) -> usize;
+
+static mut MEMORY_MAP: [u8; 0x4000] = [0; 0x4000];
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    let deferred_exit = boot_info
+        .deferred_exit
+        .into_option()
+        .expect("boot services exit was not deferred");
+    assert_eq!(boot_info.physical_memory_offset.into_option(), Some(0));
+
+    // physical memory is identity-mapped, so the firmware tables can be accessed directly
+    let system_table = deferred_exit.system_table as *const u8;
+    let (get_memory_map, exit_boot_services) = unsafe {
+        let boot_services = *(system_table.add(SYSTEM_TABLE_BOOT_SERVICES) as *const *const u8);
+        (
+            *(boot_services.add(BOOT_SERVICES_GET_MEMORY_MAP) as *const GetMemoryMap),
+            *(boot_services.add(BOOT_SERVICES_EXIT_BOOT_SERVICES) as *const ExitBootServices),
+        )
+    };
+
+    // the memory map might change between the two calls, so `ExitBootServices` can fail once
+    for _ in 0..2 {
+        let map = unsafe { &mut *addr_of_mut!(MEMORY_MAP) };
+        let mut map_size = map.len();
+        let (mut map_key, mut descriptor_size, mut descriptor_version) = (0, 0, 0);
+        let status = get_memory_map(
+            &mut map_size,
+            map.as_mut_ptr(),
+            &mut map_key,
+            &mut descriptor_size,
+            &mut descriptor_version,
+        );
+        assert_eq!(status, 0, "GetMemoryMap failed");
+
+        if exit_boot_services(deferred_exit.image_handle, map_key) == 0 {
+            exit_qemu(QemuExitCode::Success);
+        }
+    }
+    panic!("ExitBootServices failed");
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+    use test_kernel_deferred_exit::serial;
+
+    let _ = writeln!(serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -0,0 +1,60 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo, BootloaderConfig};
+use test_kernel_map_phys_mem::{exit_qemu, QemuExitCode, BOOTLOADER_CONFIG};
+use x86_64::{
+    registers::control::{Cr3, Cr4, Cr4Flags},
+    structures::paging::{
+        mapper::TranslateResult, OffsetPageTable, PageTable, PageTableFlags, Translate,
+    },
+    VirtAddr,
+};
+
+const CONFIG: BootloaderConfig = {
+    let mut config = BOOTLOADER_CONFIG;
+    config.global_pages = true;
+    config
+};
+entry_point!(kernel_main, config = &CONFIG);
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    assert!(boot_info.global_pages_enabled);
+    assert!(Cr4::read().contains(Cr4Flags::PAGE_GLOBAL));
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+    let level_4_table = {
+        let phys = Cr3::read().0.start_address();
+        let virt = phys_mem_offset + phys.as_u64();
+        unsafe { &mut *virt.as_mut_ptr::<PageTable>() }
+    };
+    let page_table = unsafe { OffsetPageTable::new(level_4_table, phys_mem_offset) };
+
+    // a page of the kernel text segment
+    assert!(is_global(
+        &page_table,
+        VirtAddr::new(kernel_main as usize as u64)
+    ));
+    // a page of the physical memory mapping
+    assert!(is_global(&page_table, phys_mem_offset));
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+fn is_global(page_table: &OffsetPageTable, addr: VirtAddr) -> bool {
+    match page_table.translate(addr) {
+        TranslateResult::Mapped { flags, .. } => flags.contains(PageTableFlags::GLOBAL),
+        _ => panic!("address {addr:?} is not mapped"),
+    }
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+    use test_kernel_map_phys_mem::serial;
+
+    let _ = writeln!(serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
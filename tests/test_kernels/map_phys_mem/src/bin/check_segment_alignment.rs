@@ -0,0 +1,49 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo};
+use test_kernel_map_phys_mem::{exit_qemu, QemuExitCode, BOOTLOADER_CONFIG};
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{OffsetPageTable, PageTable, Translate},
+    VirtAddr,
+};
+
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+
+const ALIGN: u64 = 0x20_0000;
+
+/// Forces the linker to create a segment with an alignment of 2MiB.
+#[repr(C, align(0x200000))]
+struct Aligned([u8; 16]);
+
+static ALIGNED: Aligned = Aligned([42; 16]);
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+    let level_4_table = {
+        let phys = Cr3::read().0.start_address();
+        let virt = phys_mem_offset + phys.as_u64();
+        unsafe { &mut *virt.as_mut_ptr::<PageTable>() }
+    };
+    let page_table = unsafe { OffsetPageTable::new(level_4_table, phys_mem_offset) };
+
+    let virt = VirtAddr::from_ptr(&ALIGNED);
+    assert!(virt.is_aligned(ALIGN));
+    let phys = page_table.translate_addr(virt).unwrap();
+    assert!(phys.is_aligned(ALIGN));
+    assert_eq!(core::hint::black_box(&ALIGNED).0, [42; 16]);
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+    use test_kernel_map_phys_mem::serial;
+
+    let _ = writeln!(serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
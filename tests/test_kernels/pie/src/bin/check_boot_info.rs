@@ -33,6 +33,10 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     let rsdp = boot_info.rsdp_addr.into_option().unwrap();
     assert!(rsdp > 0x000E0000);
 
+    // the kernel is relocated, so its code lies above the reported base
+    assert_eq!(boot_info.kernel_load_bias, boot_info.kernel_image_offset);
+    assert!(kernel_main as usize as u64 >= boot_info.kernel_virtual_base);
+
     // the test kernel has no TLS template
     assert_eq!(boot_info.tls_template.into_option(), None);
 
@@ -2,15 +2,22 @@
 #![no_main]
 #![deny(unsafe_op_in_unsafe_fn)]
 
-use crate::memory_descriptor::UefiMemoryDescriptor;
-use bootloader_api::info::FrameBufferInfo;
+use crate::memory_descriptor::{DeferredExitDescriptor, UefiMemoryDescriptor, DEFERRED_EXIT_POOL};
+use bootloader_api::config::Mapping;
+use bootloader_api::info::{
+    BootEventId, BootTime, DeferredExit, FrameBufferInfo, Optional, VideoMode, VideoModes,
+};
 use bootloader_boot_config::BootConfig;
 use bootloader_x86_64_common::{
-    legacy_memory_region::LegacyFrameAllocator, Kernel, RawFrameBufferInfo, SystemInfo,
+    boot_events, identity_map_protection,
+    legacy_memory_region::{LegacyFrameAllocator, LegacyMemoryRegion},
+    reset, Kernel, RawFrameBufferInfo, SystemInfo,
 };
 use core::{
     cell::UnsafeCell,
-    ops::{Deref, DerefMut},
+    ffi::c_void,
+    mem,
+    ops::{Deref, DerefMut, Range},
     ptr, slice,
 };
 use uefi::{
@@ -30,7 +37,8 @@ use uefi::{
         ProtocolPointer,
     },
     table::boot::{
-        AllocateType, MemoryType, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
+        AllocateType, MemoryMap, MemoryType, OpenProtocolAttributes, OpenProtocolParams,
+        ScopedProtocol,
     },
     CStr16, CStr8,
 };
@@ -41,6 +49,9 @@ use x86_64::{
 
 mod memory_descriptor;
 
+/// The end of the memory that is addressable in real mode (1 MiB).
+const LOW_MEMORY_END: u64 = 0x10_0000;
+
 static SYSTEM_TABLE: RacyCell<Option<SystemTable<Boot>>> = RacyCell::new(None);
 
 struct RacyCell<T>(UnsafeCell<T>);
@@ -67,6 +78,8 @@ fn efi_main(image: Handle, st: SystemTable<Boot>) -> Status {
 }
 
 fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
+    boot_events::record(BootEventId::BOOTLOADER_START, 0);
+
     // temporarily clone the y table for printing panics
     unsafe {
         *SYSTEM_TABLE.get() = Some(st.unsafe_clone());
@@ -96,6 +109,8 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
         }
     };
 
+    reset::set_panic_behavior(config.panic_behavior);
+
     #[allow(deprecated)]
     if config.frame_buffer.minimum_framebuffer_height.is_none() {
         config.frame_buffer.minimum_framebuffer_height =
@@ -106,7 +121,10 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
         config.frame_buffer.minimum_framebuffer_width =
             kernel.config.frame_buffer.minimum_framebuffer_width;
     }
-    let framebuffer = init_logger(image, &st, &config);
+    // loaded before the logger is initialized, as it might contain the splash screen logo
+    let boot_config_blob = load_boot_config_blob(image, &mut st, boot_mode);
+    let (framebuffer, available_video_modes) =
+        init_logger(image, &st, &config, boot_config_blob.as_deref()).unzip();
 
     unsafe {
         *SYSTEM_TABLE.get() = None;
@@ -135,16 +153,23 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
             None => "Ramdisk not found.",
         }
     );
+    let device_tree = load_device_tree(image, &mut st, boot_mode);
+    if device_tree.is_some() {
+        log::info!("Loaded device tree");
+    }
+    if boot_config_blob.is_some() {
+        log::info!("Loaded boot config blob");
+    }
 
-    log::trace!("exiting boot services");
-    let (system_table, mut memory_map) = st.exit_boot_services();
-
-    memory_map.sort();
-
-    let mut frame_allocator =
-        LegacyFrameAllocator::new(memory_map.entries().copied().map(UefiMemoryDescriptor));
+    let boot_time = st.runtime_services().get_time().ok().map(|time| BootTime {
+        year: time.year(),
+        month: time.month(),
+        day: time.day(),
+        hour: time.hour(),
+        minute: time.minute(),
+        second: time.second(),
+    });
 
-    let page_tables = create_page_tables(&mut frame_allocator);
     let mut ramdisk_len = 0u64;
     let ramdisk_addr = if let Some(rd) = ramdisk {
         ramdisk_len = rd.len() as u64;
@@ -154,29 +179,180 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
     };
     let system_info = SystemInfo {
         framebuffer,
-        rsdp_addr: {
+        rsdp_addr: if kernel.config.detect_acpi {
             use uefi::table::cfg;
-            let mut config_entries = system_table.config_table().iter();
+            let mut config_entries = st.config_table().iter();
             // look for an ACPI2 RSDP first
             let acpi2_rsdp = config_entries.find(|entry| matches!(entry.guid, cfg::ACPI2_GUID));
             // if no ACPI2 RSDP is found, look for a ACPI1 RSDP
             let rsdp = acpi2_rsdp
                 .or_else(|| config_entries.find(|entry| matches!(entry.guid, cfg::ACPI_GUID)));
             rsdp.map(|entry| PhysAddr::new(entry.address as u64))
+        } else {
+            None
         },
         ramdisk_addr,
         ramdisk_len,
+        device_tree: device_tree.map(|blob| &*blob),
+        boot_config_blob: boot_config_blob.map(|blob| &*blob),
+        available_video_modes: available_video_modes.unwrap_or_default(),
+        boot_time,
+        // set after the frame allocator is created
+        ap_trampoline_addr: None,
+        vbe_info: None,
+        deferred_exit: None,
     };
 
+    match kernel.config.defer_exit_boot_services {
+        None => {
+            log::trace!("exiting boot services");
+            let (_, mut memory_map) = st.exit_boot_services();
+            memory_map.sort();
+
+            let frame_allocator =
+                LegacyFrameAllocator::new(memory_map.entries().copied().map(UefiMemoryDescriptor));
+            start_kernel(kernel, config, frame_allocator, system_info)
+        }
+        Some(pool_size) => {
+            let memory_map = prepare_deferred_exit(&st, &kernel, pool_size);
+            let system_info = SystemInfo {
+                // both types are `repr(transparent)` wrappers around the raw pointers
+                deferred_exit: Some(DeferredExit {
+                    system_table: unsafe {
+                        mem::transmute::<SystemTable<Boot>, *const c_void>(st) as u64
+                    },
+                    image_handle: unsafe { mem::transmute::<Handle, *mut c_void>(image) as u64 },
+                }),
+                ..system_info
+            };
+
+            let frame_allocator = LegacyFrameAllocator::new(
+                memory_map.entries().copied().map(DeferredExitDescriptor),
+            );
+            start_kernel(kernel, config, frame_allocator, system_info)
+        }
+    }
+}
+
+/// Allocates the pool for the bootloader allocations and queries the memory map without
+/// exiting the boot services.
+///
+/// See [`bootloader_api::BootloaderConfig::defer_exit_boot_services`] for the constraints.
+fn prepare_deferred_exit(
+    st: &SystemTable<Boot>,
+    kernel: &Kernel,
+    pool_size: u64,
+) -> MemoryMap<'static> {
+    assert!(
+        matches!(
+            kernel.config.mappings.physical_memory,
+            Some(Mapping::FixedAddress(0))
+        ),
+        "deferring the boot services exit requires physical memory to be identity-mapped"
+    );
+    log::info!("Deferring the boot services exit to the kernel");
+
+    let boot_services = st.boot_services();
+    let pool_pages = usize::try_from(pool_size.div_ceil(4096)).unwrap();
+    boot_services
+        .allocate_pages(AllocateType::AnyPages, DEFERRED_EXIT_POOL, pool_pages)
+        .expect("failed to allocate the pool for the deferred boot services exit");
+
+    // leave room for the descriptors that are added by the allocation of the buffer itself
+    let map_size = boot_services.memory_map_size();
+    let buffer_len = map_size.map_size + 8 * map_size.entry_size;
+    let buffer = boot_services
+        .allocate_pool(MemoryType::LOADER_DATA, buffer_len)
+        .expect("failed to allocate the memory map buffer");
+    let buffer = unsafe { slice::from_raw_parts_mut(buffer, buffer_len) };
+    let mut memory_map = boot_services
+        .memory_map(buffer)
+        .expect("failed to query the memory map");
+    memory_map.sort();
+    memory_map
+}
+
+/// Creates the page tables and jumps to the kernel.
+fn start_kernel<I, D>(
+    kernel: Kernel,
+    config: BootConfig,
+    mut frame_allocator: LegacyFrameAllocator<I, D>,
+    mut system_info: SystemInfo,
+) -> !
+where
+    I: ExactSizeIterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    // the allocator returns frames in ascending order, so the low memory region must be
+    // reserved before any other allocations
+    system_info.ap_trampoline_addr = kernel.config.ap_trampoline_size.and_then(|size| {
+        let range = frame_allocator
+            .allocate_contiguous_below(size.div_ceil(4096), PhysAddr::new(LOW_MEMORY_END));
+        if range.is_none() {
+            log::warn!("Failed to reserve {size:#x} bytes of low memory for the AP trampoline");
+        }
+        range.map(|range| range.start.start_address())
+    });
+
+    let mut page_tables = create_page_tables(&mut frame_allocator);
+    if config.protect_bootloader_code {
+        identity_map_protection::protect_bootloader_code(
+            &mut page_tables.bootloader,
+            &read_only_sections(),
+            &mut frame_allocator,
+        );
+    }
+
     bootloader_x86_64_common::load_and_switch_to_kernel(
         kernel,
         config,
         frame_allocator,
         page_tables,
         system_info,
+        None,
     );
 }
 
+/// The maximum number of PE sections that [`read_only_sections`] reports.
+const MAX_READ_ONLY_SECTIONS: usize = 8;
+
+/// Returns the physical address ranges of the non-writable sections of the bootloader image,
+/// i.e. its code and read-only data.
+///
+/// The sections are read from the PE section table of the loaded image. Unused array entries
+/// are empty ranges.
+fn read_only_sections() -> [Range<PhysAddr>; MAX_READ_ONLY_SECTIONS] {
+    /// The section flag for writable memory.
+    const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+    extern "C" {
+        /// The start of the loaded image, defined by the linker.
+        static __ImageBase: u8;
+    }
+
+    let base = unsafe { ptr::addr_of!(__ImageBase) };
+    let read_u16 = |offset: usize| unsafe { base.add(offset).cast::<u16>().read_unaligned() };
+    let read_u32 = |offset: usize| unsafe { base.add(offset).cast::<u32>().read_unaligned() };
+
+    // the offset of the PE signature, which is followed by the COFF header
+    let coff_header = read_u32(0x3c) as usize + 4;
+    let section_count = usize::from(read_u16(coff_header + 2));
+    let optional_header_size = usize::from(read_u16(coff_header + 16));
+    let section_table = coff_header + 20 + optional_header_size;
+
+    let empty = PhysAddr::zero()..PhysAddr::zero();
+    let mut sections = [(); MAX_READ_ONLY_SECTIONS].map(|()| empty.clone());
+    let read_only = (0..section_count)
+        .map(|i| section_table + i * 40)
+        .filter(|&header| read_u32(header + 36) & IMAGE_SCN_MEM_WRITE == 0);
+    for (section, header) in sections.iter_mut().zip(read_only) {
+        let start = base as u64 + u64::from(read_u32(header + 12));
+        let len = u64::from(read_u32(header + 8));
+        *section = PhysAddr::new(start)..PhysAddr::new(start + len);
+    }
+    sections
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum BootMode {
     Disk,
@@ -191,6 +367,22 @@ fn load_ramdisk(
     load_file_from_boot_method(image, st, "ramdisk\0", boot_mode)
 }
 
+fn load_device_tree(
+    image: Handle,
+    st: &mut SystemTable<Boot>,
+    boot_mode: BootMode,
+) -> Option<&'static mut [u8]> {
+    load_file_from_boot_method(image, st, "device-tree.dtb\0", boot_mode)
+}
+
+fn load_boot_config_blob(
+    image: Handle,
+    st: &mut SystemTable<Boot>,
+    boot_mode: BootMode,
+) -> Option<&'static mut [u8]> {
+    load_file_from_boot_method(image, st, "boot-config.bin\0", boot_mode)
+}
+
 fn load_config_file(
     image: Handle,
     st: &mut SystemTable<Boot>,
@@ -205,6 +397,8 @@ fn load_kernel(
     boot_mode: BootMode,
 ) -> Option<Kernel<'static>> {
     let kernel_slice = load_file_from_boot_method(image, st, "kernel-x86_64\0", boot_mode)?;
+    #[cfg(feature = "signed-kernel")]
+    let kernel_slice = bootloader_x86_64_common::kernel_signature::verify(kernel_slice);
     Some(Kernel::parse(kernel_slice))
 }
 
@@ -400,15 +594,9 @@ fn create_page_tables(
         let new_frame = frame_allocator
             .allocate_frame()
             .expect("Failed to allocate frame for new level 4 table");
-        let new_table: &mut PageTable = {
-            let ptr: *mut PageTable =
-                (phys_offset + new_frame.start_address().as_u64()).as_mut_ptr();
-            // create a new, empty page table
-            unsafe {
-                ptr.write(PageTable::new());
-                &mut *ptr
-            }
-        };
+        // create a new, empty page table
+        let new_table =
+            unsafe { bootloader_x86_64_common::init_level_4_table(new_frame, phys_offset) };
 
         // copy the first entry (we don't need to access more than 512 GiB; also, some UEFI
         // implementations seem to create an level 4 table entry 0 in all slots)
@@ -429,12 +617,9 @@ fn create_page_tables(
         // get an unused frame for new level 4 page table
         let frame: PhysFrame = frame_allocator.allocate_frame().expect("no unused frames");
         log::info!("New page table at: {:#?}", &frame);
-        // get the corresponding virtual address
-        let addr = phys_offset + frame.start_address().as_u64();
         // initialize a new page table
-        let ptr = addr.as_mut_ptr();
-        unsafe { *ptr = PageTable::new() };
-        let level_4_table = unsafe { &mut *ptr };
+        let level_4_table =
+            unsafe { bootloader_x86_64_common::init_level_4_table(frame, phys_offset) };
         (
             unsafe { OffsetPageTable::new(level_4_table, phys_offset) },
             frame,
@@ -452,7 +637,8 @@ fn init_logger(
     image_handle: Handle,
     st: &SystemTable<Boot>,
     config: &BootConfig,
-) -> Option<RawFrameBufferInfo> {
+    boot_config_blob: Option<&[u8]>,
+) -> Option<(RawFrameBufferInfo, VideoModes)> {
     let gop_handle = st
         .boot_services()
         .get_handle_for_protocol::<GraphicsOutput>()
@@ -470,6 +656,8 @@ fn init_logger(
             .ok()?
     };
 
+    let available_video_modes = query_video_modes(&gop);
+
     let mode = {
         let modes = gop.modes();
         match (
@@ -514,28 +702,63 @@ fn init_logger(
         },
         bytes_per_pixel: 4,
         stride: mode_info.stride(),
+        vbe_mode: Optional::None,
     };
 
     log::info!("UEFI boot");
 
-    bootloader_x86_64_common::init_logger(
+    let info = bootloader_x86_64_common::init_logger(
         slice,
         info,
         config.log_level,
+        config.log_colors,
         config.frame_buffer_logging,
         config.serial_logging,
+        config.frame_buffer.self_test,
+        config.splash,
+        boot_config_blob,
     );
 
-    Some(RawFrameBufferInfo {
-        addr: PhysAddr::new(framebuffer.as_mut_ptr() as u64),
-        info,
-    })
+    Some((
+        RawFrameBufferInfo {
+            addr: PhysAddr::new(framebuffer.as_mut_ptr() as u64),
+            info,
+        },
+        available_video_modes,
+    ))
+}
+
+/// Lists the GOP modes that provide a linear framebuffer.
+fn query_video_modes(gop: &GraphicsOutput) -> VideoModes {
+    let mut video_modes = VideoModes::new();
+    // don't use `gop.modes()` because it skips modes that can't be queried, so the mode
+    // index would be lost
+    for index in 0..gop.modes().len() as u32 {
+        let Ok(mode) = gop.query_mode(index) else {
+            continue;
+        };
+        let info = mode.info();
+        if info.pixel_format() == PixelFormat::BltOnly {
+            continue;
+        }
+        let (width, height) = info.resolution();
+        let video_mode = VideoMode {
+            width: width as u32,
+            height: height as u32,
+            // GOP pixels are always 32 bits wide
+            bits_per_pixel: 32,
+            mode_number: index,
+        };
+        if !video_modes.push(video_mode) {
+            break;
+        }
+    }
+    video_modes
 }
 
 #[cfg(target_os = "uefi")]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    use core::arch::asm;
     use core::fmt::Write;
 
     if let Some(st) = unsafe { &mut *SYSTEM_TABLE.get() } {
@@ -548,9 +771,9 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
             .get()
             .map(|l| l.force_unlock())
     };
+    // if the framebuffer was already handed to the kernel, this only logs to the serial port
     log::error!("{}", info);
+    bootloader_x86_64_common::panic_sinks::run(info);
 
-    loop {
-        unsafe { asm!("cli; hlt") };
-    }
+    bootloader_x86_64_common::reset::handle_panic()
 }
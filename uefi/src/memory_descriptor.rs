@@ -1,6 +1,6 @@
-use bootloader_api::info::MemoryRegionKind;
+use bootloader_api::info::{MemoryRegionAttributes, MemoryRegionKind};
 use bootloader_x86_64_common::legacy_memory_region::LegacyMemoryRegion;
-use uefi::table::boot::{MemoryDescriptor, MemoryType};
+use uefi::table::boot::{MemoryAttribute, MemoryDescriptor, MemoryType};
 use x86_64::PhysAddr;
 
 #[derive(Debug, Copy, Clone)]
@@ -24,6 +24,17 @@ impl LegacyMemoryRegion for UefiMemoryDescriptor {
         }
     }
 
+    fn attributes(&self) -> MemoryRegionAttributes {
+        MemoryRegionAttributes {
+            flags: if self.0.att.contains(MemoryAttribute::NON_VOLATILE) {
+                MemoryRegionAttributes::NON_VOLATILE
+            } else {
+                0
+            },
+            ..MemoryRegionAttributes::empty()
+        }
+    }
+
     fn usable_after_bootloader_exit(&self) -> bool {
         match self.0.ty {
             MemoryType::CONVENTIONAL => true,
@@ -44,3 +55,44 @@ impl LegacyMemoryRegion for UefiMemoryDescriptor {
         }
     }
 }
+
+/// The memory type of the pool that the bootloader allocates when the exit of the boot services
+/// is deferred to the kernel.
+///
+/// Uses the range reserved for OS loaders, so the firmware never merges the pool with other
+/// descriptors.
+pub const DEFERRED_EXIT_POOL: MemoryType = MemoryType::custom(0x8000_0000);
+
+/// A UEFI memory descriptor that was queried while the boot services are still active.
+///
+/// Only the [`DEFERRED_EXIT_POOL`] is usable, because the firmware might still allocate all
+/// other memory. Conventional memory is therefore reported as `UnknownUefi`, like the other
+/// memory types.
+#[derive(Debug, Copy, Clone)]
+pub struct DeferredExitDescriptor(pub MemoryDescriptor);
+
+impl LegacyMemoryRegion for DeferredExitDescriptor {
+    fn start(&self) -> PhysAddr {
+        UefiMemoryDescriptor(self.0).start()
+    }
+
+    fn len(&self) -> u64 {
+        UefiMemoryDescriptor(self.0).len()
+    }
+
+    fn kind(&self) -> MemoryRegionKind {
+        match self.0.ty {
+            DEFERRED_EXIT_POOL => MemoryRegionKind::Usable,
+            other => MemoryRegionKind::UnknownUefi(other.0),
+        }
+    }
+
+    fn attributes(&self) -> MemoryRegionAttributes {
+        UefiMemoryDescriptor(self.0).attributes()
+    }
+
+    fn usable_after_bootloader_exit(&self) -> bool {
+        // the boot services are still active when the kernel is started
+        false
+    }
+}